@@ -160,6 +160,9 @@ pub fn metadata_to_pydict<'a>(metadata: &'a Metadata, py: Python<'a>) -> &'a PyD
     )
     .wrap_err("could not make metadata a python dictionary item")
     .unwrap();
+    dict.set_item("dropped", metadata.dropped)
+        .wrap_err("could not make metadata a python dictionary item")
+        .unwrap();
     dict
 }
 
@@ -5,16 +5,23 @@ use dora_core::{
 };
 use eyre::{bail, eyre, Context};
 use shared_memory_server::{ShmemClient, ShmemConf};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::{
     net::{SocketAddr, TcpStream},
+    path::Path,
     time::Duration,
 };
 
 mod tcp;
+#[cfg(unix)]
+mod unix_domain;
 
 pub enum DaemonChannel {
     Shmem(ShmemClient<Timestamped<DaemonRequest>, DaemonReply>),
     Tcp(TcpStream),
+    #[cfg(unix)]
+    UnixDomain(UnixStream),
 }
 
 impl DaemonChannel {
@@ -25,6 +32,19 @@ impl DaemonChannel {
         Ok(DaemonChannel::Tcp(stream))
     }
 
+    #[cfg(unix)]
+    #[tracing::instrument(level = "trace")]
+    pub fn new_unix_socket(path: &Path) -> eyre::Result<Self> {
+        let stream = UnixStream::connect(path).wrap_err("failed to open Unix socket")?;
+        Ok(DaemonChannel::UnixDomain(stream))
+    }
+
+    #[cfg(not(unix))]
+    #[tracing::instrument(level = "trace")]
+    pub fn new_unix_socket(_path: &Path) -> eyre::Result<Self> {
+        bail!("unix domain sockets are only supported on Unix platforms")
+    }
+
     #[tracing::instrument(level = "trace")]
     pub unsafe fn new_shmem(daemon_control_region_id: &str) -> eyre::Result<Self> {
         let daemon_events_region = ShmemConf::new()
@@ -69,6 +89,8 @@ impl DaemonChannel {
         match self {
             DaemonChannel::Shmem(client) => client.request(request),
             DaemonChannel::Tcp(stream) => tcp::request(stream, request),
+            #[cfg(unix)]
+            DaemonChannel::UnixDomain(stream) => unix_domain::request(stream, request),
         }
     }
 }
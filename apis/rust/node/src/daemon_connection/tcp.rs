@@ -1,12 +1,12 @@
 use dora_core::daemon_messages::{DaemonReply, DaemonRequest, Timestamped};
 use eyre::{eyre, Context};
-use std::{
-    io::{Read, Write},
-    net::TcpStream,
-};
+use std::io::{Read, Write};
 
+/// Sends the request as a length-prefixed message and waits for the reply.
+///
+/// Works on any byte stream, e.g. TCP or Unix domain sockets.
 pub fn request(
-    connection: &mut TcpStream,
+    connection: &mut (impl Read + Write + Unpin),
     request: &Timestamped<DaemonRequest>,
 ) -> eyre::Result<DaemonReply> {
     send_message(connection, request)?;
@@ -19,7 +19,7 @@ pub fn request(
 }
 
 fn send_message(
-    connection: &mut TcpStream,
+    connection: &mut (impl Write + Unpin),
     message: &Timestamped<DaemonRequest>,
 ) -> eyre::Result<()> {
     let serialized = bincode::serialize(&message).wrap_err("failed to serialize DaemonRequest")?;
@@ -27,7 +27,7 @@ fn send_message(
     Ok(())
 }
 
-fn receive_reply(connection: &mut TcpStream) -> eyre::Result<Option<DaemonReply>> {
+fn receive_reply(connection: &mut (impl Read + Unpin)) -> eyre::Result<Option<DaemonReply>> {
     let raw = match tcp_receive(connection) {
         Ok(raw) => raw,
         Err(err) => match err.kind() {
@@ -0,0 +1,11 @@
+use super::tcp;
+use dora_core::daemon_messages::{DaemonReply, DaemonRequest, Timestamped};
+use std::os::unix::net::UnixStream;
+
+/// Unix domain sockets use the same message framing as TCP connections.
+pub fn request(
+    connection: &mut UnixStream,
+    request: &Timestamped<DaemonRequest>,
+) -> eyre::Result<DaemonReply> {
+    tcp::request(connection, request)
+}
@@ -50,6 +50,11 @@ impl EventStream {
             )?,
             DaemonCommunication::Tcp { socket_addr } => DaemonChannel::new_tcp(*socket_addr)
                 .wrap_err_with(|| format!("failed to connect event stream for node `{node_id}`"))?,
+            DaemonCommunication::UnixDomain { socket_file } => {
+                DaemonChannel::new_unix_socket(socket_file).wrap_err_with(|| {
+                    format!("failed to connect event stream for node `{node_id}`")
+                })?
+            }
         };
 
         let close_channel = match daemon_communication {
@@ -63,6 +68,11 @@ impl EventStream {
                 .wrap_err_with(|| {
                     format!("failed to connect event close channel for node `{node_id}`")
                 })?,
+            DaemonCommunication::UnixDomain { socket_file } => {
+                DaemonChannel::new_unix_socket(socket_file).wrap_err_with(|| {
+                    format!("failed to connect event close channel for node `{node_id}`")
+                })?
+            }
         };
 
         Self::init_on_channel(dataflow_id, node_id, channel, close_channel, clock)
@@ -137,6 +147,9 @@ impl EventStream {
                 NodeEvent::Reload { operator_id } => Event::Reload { operator_id },
                 NodeEvent::InputClosed { id } => Event::InputClosed { id },
                 NodeEvent::Input { id, metadata, data } => {
+                    if let Err(err) = metadata.check_version() {
+                        return Event::Error(format!("{err:?}"));
+                    }
                     let data = match data {
                         None => Ok(None),
                         Some(daemon_messages::DataMessage::Vec(v)) => Ok(Some(RawData::Vec(v))),
@@ -29,6 +29,10 @@ impl ControlChannel {
                 .wrap_err("failed to create shmem control channel")?,
             DaemonCommunication::Tcp { socket_addr } => DaemonChannel::new_tcp(*socket_addr)
                 .wrap_err("failed to connect control channel")?,
+            DaemonCommunication::UnixDomain { socket_file } => {
+                DaemonChannel::new_unix_socket(socket_file)
+                    .wrap_err("failed to connect control channel")?
+            }
         };
 
         Self::init_on_channel(dataflow_id, node_id, channel, clock)
@@ -63,6 +67,23 @@ impl ControlChannel {
         Ok(())
     }
 
+    pub fn report_ready(&mut self) -> eyre::Result<()> {
+        let reply = self
+            .channel
+            .request(&Timestamped {
+                inner: DaemonRequest::Ready,
+                timestamp: self.clock.new_timestamp(),
+            })
+            .wrap_err("failed to report readiness to dora-daemon")?;
+        match reply {
+            dora_core::daemon_messages::DaemonReply::Result(result) => result
+                .map_err(|e| eyre!(e))
+                .wrap_err("failed to receive ready reply from dora-daemon")?,
+            other => bail!("unexpected ready reply: {other:?}"),
+        }
+        Ok(())
+    }
+
     pub fn report_closed_outputs(&mut self, outputs: Vec<DataId>) -> eyre::Result<()> {
         let reply = self
             .channel
@@ -85,11 +106,13 @@ impl ControlChannel {
         output_id: DataId,
         metadata: Metadata,
         data: Option<DataMessage>,
+        wait_for_reply: bool,
     ) -> eyre::Result<()> {
         let request = DaemonRequest::SendMessage {
             output_id,
             metadata,
             data,
+            wait_for_reply,
         };
         let reply = self
             .channel
@@ -100,6 +123,9 @@ impl ControlChannel {
             .wrap_err("failed to send SendMessage request to dora-daemon")?;
         match reply {
             dora_core::daemon_messages::DaemonReply::Empty => Ok(()),
+            dora_core::daemon_messages::DaemonReply::Result(result) => result
+                .map_err(|e| eyre!(e))
+                .wrap_err("failed to send message"),
             other => bail!("unexpected SendMessage reply: {other:?}"),
         }
     }
@@ -36,6 +36,11 @@ impl DropStream {
             }
             DaemonCommunication::Tcp { socket_addr } => DaemonChannel::new_tcp(*socket_addr)
                 .wrap_err_with(|| format!("failed to connect drop stream for node `{node_id}`"))?,
+            DaemonCommunication::UnixDomain { socket_file } => {
+                DaemonChannel::new_unix_socket(socket_file).wrap_err_with(|| {
+                    format!("failed to connect drop stream for node `{node_id}`")
+                })?
+            }
         };
 
         Self::init_on_channel(dataflow_id, node_id, channel, hlc)
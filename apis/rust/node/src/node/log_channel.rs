@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use crate::daemon_connection::DaemonChannel;
+use dora_core::{
+    config::NodeId,
+    daemon_messages::{DaemonCommunication, DaemonReply, DaemonRequest, DataflowId, Timestamped},
+    logs::LogRecord,
+    message::uhlc::HLC,
+};
+use eyre::{bail, eyre, Context};
+
+/// Maximum serialized size of a log record. Larger records are truncated.
+const MAX_RECORD_LEN: u64 = 32 * 1024;
+
+/// Maximum serialized size of the records that are sent in a single request.
+///
+/// Must fit into the shared memory region that the daemon allocates for log
+/// records.
+const MAX_BATCH_LEN: u64 = 56 * 1024;
+
+/// Sends structured log records to the daemon.
+///
+/// Uses a separate connection so that records can be sent from a background
+/// thread, independent of the control channel of the node.
+pub(crate) struct LogChannel {
+    channel: DaemonChannel,
+    clock: Arc<HLC>,
+}
+
+impl LogChannel {
+    #[tracing::instrument(level = "trace", skip(clock))]
+    pub(crate) fn init(
+        dataflow_id: DataflowId,
+        node_id: &NodeId,
+        daemon_communication: &DaemonCommunication,
+        clock: Arc<HLC>,
+    ) -> eyre::Result<Self> {
+        let mut channel = match daemon_communication {
+            DaemonCommunication::Shmem {
+                daemon_logs_region_id,
+                ..
+            } => unsafe { DaemonChannel::new_shmem(daemon_logs_region_id) }
+                .wrap_err("failed to create shmem log channel")?,
+            DaemonCommunication::Tcp { socket_addr } => {
+                DaemonChannel::new_tcp(*socket_addr).wrap_err("failed to connect log channel")?
+            }
+            DaemonCommunication::UnixDomain { socket_file } => {
+                DaemonChannel::new_unix_socket(socket_file)
+                    .wrap_err("failed to connect log channel")?
+            }
+        };
+        channel.register(dataflow_id, node_id.clone(), clock.new_timestamp())?;
+
+        Ok(Self { channel, clock })
+    }
+
+    /// Sends the given records, split into as few requests as possible.
+    pub fn send_records(&mut self, records: Vec<LogRecord>) -> eyre::Result<()> {
+        let mut batch = Vec::new();
+        let mut batch_len = 0u64;
+        for mut record in records {
+            truncate(&mut record);
+            let len = serialized_len(&record);
+            if !batch.is_empty() && batch_len.saturating_add(len) > MAX_BATCH_LEN {
+                self.send_batch(std::mem::take(&mut batch))?;
+                batch_len = 0;
+            }
+            batch_len = batch_len.saturating_add(len);
+            batch.push(record);
+        }
+        if !batch.is_empty() {
+            self.send_batch(batch)?;
+        }
+        Ok(())
+    }
+
+    fn send_batch(&mut self, records: Vec<LogRecord>) -> eyre::Result<()> {
+        let reply = self
+            .channel
+            .request(&Timestamped {
+                inner: DaemonRequest::Logs(records),
+                timestamp: self.clock.new_timestamp(),
+            })
+            .wrap_err("failed to send log records to dora-daemon")?;
+        match reply {
+            DaemonReply::Result(result) => result
+                .map_err(|e| eyre!(e))
+                .wrap_err("failed to log records"),
+            other => bail!("unexpected Logs reply: {other:?}"),
+        }
+    }
+}
+
+fn serialized_len(record: &LogRecord) -> u64 {
+    bincode::serialized_size(record).unwrap_or(u64::MAX)
+}
+
+fn truncate(record: &mut LogRecord) {
+    if serialized_len(record) <= MAX_RECORD_LEN {
+        return;
+    }
+    record.fields.clear();
+    let overflow = serialized_len(record).saturating_sub(MAX_RECORD_LEN) as usize;
+    let mut end = record.message.len().saturating_sub(overflow + 3);
+    while !record.message.is_char_boundary(end) {
+        end -= 1;
+    }
+    record.message.truncate(end);
+    record.message.push_str("...");
+}
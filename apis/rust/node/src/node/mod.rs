@@ -16,21 +16,37 @@ use dora_core::{
 use eyre::{bail, WrapErr};
 use shared_memory_extended::{Shmem, ShmemConf};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeSet, HashMap, VecDeque},
     ops::{Deref, DerefMut},
-    sync::Arc,
-    time::Duration,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 #[cfg(feature = "tracing")]
-use dora_tracing::set_up_tracing;
+use dora_core::logs::{LogLevel, LogRecord};
+#[cfg(feature = "tracing")]
+use dora_tracing::{set_up_tracing_with_sink, sink::LogEvent};
 
 pub mod arrow_utils;
 mod control_channel;
 mod drop_stream;
+#[cfg(feature = "tracing")]
+mod log_channel;
 
 pub const ZERO_COPY_THRESHOLD: usize = 4096;
 
+/// Number of log records that can be queued for sending to the daemon.
+/// Further records are dropped until there is room again.
+#[cfg(feature = "tracing")]
+const LOG_QUEUE_SIZE: usize = 1024;
+
+/// Maximum time to wait for queued log records to be sent when the node
+/// is dropped.
+const LOG_FLUSH_TIMEOUT: Duration = Duration::from_millis(200);
+
 pub struct DoraNode {
     id: NodeId,
     dataflow_id: DataflowId,
@@ -41,6 +57,10 @@ pub struct DoraNode {
     sent_out_shared_memory: HashMap<DropToken, ShmemHandle>,
     drop_stream: DropStream,
     cache: VecDeque<ShmemHandle>,
+    /// Number of log records that are not forwarded to the daemon yet.
+    pending_log_records: Option<Arc<AtomicUsize>>,
+    /// Sending on these outputs waits until all receivers have room.
+    blocking_outputs: BTreeSet<DataId>,
 
     dataflow_descriptor: Descriptor,
 }
@@ -60,24 +80,71 @@ impl DoraNode {
                 .wrap_err("env variable DORA_NODE_CONFIG must be set")?;
             serde_yaml::from_str(&raw).context("failed to deserialize operator config")?
         };
+        let clock = Arc::new(uhlc::HLC::default());
+
+        // send `tracing` events as structured log records to the daemon
         #[cfg(feature = "tracing")]
-        set_up_tracing(&node_config.node_id.to_string())
-            .context("failed to set up tracing subscriber")?;
-        Self::init(node_config)
+        let pending_log_records = {
+            let (tx, rx) = flume::bounded(LOG_QUEUE_SIZE);
+            let pending = Arc::new(AtomicUsize::new(0));
+            let dropped = Arc::new(AtomicUsize::new(0));
+            let node_id = node_config.node_id.clone();
+            let record_clock = clock.clone();
+            let sink_pending = pending.clone();
+            let sink_dropped = dropped.clone();
+            let sink = move |event: LogEvent| {
+                sink_pending.fetch_add(1, Ordering::SeqCst);
+                let record = LogRecord {
+                    timestamp: record_clock.new_timestamp(),
+                    node_id: node_id.clone(),
+                    level: Some(event.level.into()),
+                    target: event.target,
+                    message: event.message,
+                    fields: event.fields,
+                };
+                // never block the logging thread
+                if tx.try_send(record).is_err() {
+                    sink_pending.fetch_sub(1, Ordering::SeqCst);
+                    sink_dropped.fetch_add(1, Ordering::SeqCst);
+                }
+            };
+            set_up_tracing_with_sink(&node_config.node_id.to_string(), sink)
+                .context("failed to set up tracing subscriber")?;
+
+            let log_channel = log_channel::LogChannel::init(
+                node_config.dataflow_id,
+                &node_config.node_id,
+                &node_config.daemon_communication,
+                clock.clone(),
+            );
+            forward_log_records(rx, log_channel, pending.clone(), dropped, clock.clone());
+            Some(pending)
+        };
+        #[cfg(not(feature = "tracing"))]
+        let pending_log_records = None;
+
+        Self::init_with_clock(node_config, clock, pending_log_records)
     }
 
     #[tracing::instrument]
     pub fn init(node_config: NodeConfig) -> eyre::Result<(Self, EventStream)> {
+        Self::init_with_clock(node_config, Arc::new(uhlc::HLC::default()), None)
+    }
+
+    fn init_with_clock(
+        node_config: NodeConfig,
+        clock: Arc<uhlc::HLC>,
+        pending_log_records: Option<Arc<AtomicUsize>>,
+    ) -> eyre::Result<(Self, EventStream)> {
         let NodeConfig {
             dataflow_id,
             node_id,
             run_config,
             daemon_communication,
             dataflow_descriptor,
+            blocking_outputs,
         } = node_config;
 
-        let clock = Arc::new(uhlc::HLC::default());
-
         let event_stream =
             EventStream::init(dataflow_id, &node_id, &daemon_communication, clock.clone())
                 .wrap_err("failed to init event stream")?;
@@ -97,6 +164,8 @@ impl DoraNode {
             sent_out_shared_memory: HashMap::new(),
             drop_stream,
             cache: VecDeque::new(),
+            pending_log_records,
+            blocking_outputs,
 
             dataflow_descriptor,
         };
@@ -201,9 +270,7 @@ impl DoraNode {
     ) -> eyre::Result<()> {
         self.handle_finished_drop_tokens()?;
 
-        if !self.node_config.outputs.contains(&output_id) {
-            eyre::bail!("unknown output");
-        }
+        check_output(&self.node_config, &output_id, &type_info)?;
         let metadata = Metadata::from_parameters(
             self.clock.new_timestamp(),
             type_info,
@@ -215,8 +282,9 @@ impl DoraNode {
             None => (None, None),
         };
 
+        let wait_for_reply = self.blocking_outputs.contains(&output_id);
         self.control_channel
-            .send_message(output_id.clone(), metadata, data)
+            .send_message(output_id.clone(), metadata, data, wait_for_reply)
             .wrap_err_with(|| format!("failed to send output {output_id}"))?;
 
         if let Some((shared_memory, drop_token)) = shmem {
@@ -241,6 +309,17 @@ impl DoraNode {
         Ok(())
     }
 
+    /// Signals that this node is ready, which starts the nodes that depend on
+    /// it.
+    ///
+    /// Only needed for nodes that set `ready_signal` in the dataflow. Other
+    /// nodes are ready as soon as they are initialized.
+    pub fn mark_ready(&mut self) -> eyre::Result<()> {
+        self.control_channel
+            .report_ready()
+            .wrap_err("failed to report readiness to daemon")
+    }
+
     pub fn id(&self) -> &NodeId {
         &self.id
     }
@@ -381,9 +460,78 @@ impl Drop for DoraNode {
         if let Err(err) = self.control_channel.report_outputs_done() {
             tracing::warn!("{err:?}")
         }
+
+        // give the log forwarding thread some time to send the remaining records
+        if let Some(pending) = &self.pending_log_records {
+            let start = Instant::now();
+            while pending.load(Ordering::SeqCst) > 0 && start.elapsed() < LOG_FLUSH_TIMEOUT {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
     }
 }
 
+/// Forwards the given log records to the daemon in a background thread.
+///
+/// All records that are queued at the same time are sent in a single batch.
+/// Falls back to printing the records to stderr if the log channel is not
+/// available.
+#[cfg(feature = "tracing")]
+fn forward_log_records(
+    records: flume::Receiver<LogRecord>,
+    channel: eyre::Result<log_channel::LogChannel>,
+    pending: Arc<AtomicUsize>,
+    dropped: Arc<AtomicUsize>,
+    clock: Arc<uhlc::HLC>,
+) {
+    // we cannot use `tracing` here because its events are sent to this thread
+    let mut channel = channel.map_err(|err| eprintln!("{err:?}")).ok();
+    std::thread::spawn(move || {
+        while let Ok(record) = records.recv() {
+            let mut batch = vec![record];
+            batch.extend(records.try_iter().take(LOG_QUEUE_SIZE));
+            let queued = batch.len();
+            let dropped = dropped.swap(0, Ordering::SeqCst);
+            if dropped > 0 {
+                batch.push(LogRecord {
+                    timestamp: clock.new_timestamp(),
+                    node_id: batch[0].node_id.clone(),
+                    level: Some(LogLevel::Warn),
+                    target: module_path!().to_owned(),
+                    message: format!(
+                        "dropped {dropped} log records because the log queue was full"
+                    ),
+                    fields: Default::default(),
+                });
+            }
+
+            let sent = match &mut channel {
+                Some(c) => match c.send_records(batch.clone()) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        eprintln!("{err:?}");
+                        channel = None;
+                        false
+                    }
+                },
+                None => false,
+            };
+            if !sent {
+                for record in batch {
+                    eprintln!(
+                        "{} {} {}: {}",
+                        record.timestamp.get_time(),
+                        record.level.map(|l| l.to_string()).unwrap_or_default(),
+                        record.target,
+                        record.message
+                    );
+                }
+            }
+            pending.fetch_sub(queued, Ordering::SeqCst);
+        }
+    });
+}
+
 pub struct DataSample {
     inner: DataSampleInner,
     len: usize,
@@ -473,3 +621,51 @@ impl DerefMut for ShmemHandle {
 
 unsafe impl Send for ShmemHandle {}
 unsafe impl Sync for ShmemHandle {}
+
+/// Checks that the output is declared and that the data matches its declared type.
+fn check_output(
+    node_config: &NodeRunConfig,
+    output_id: &DataId,
+    type_info: &ArrowTypeInfo,
+) -> eyre::Result<()> {
+    if !node_config.outputs.contains(output_id) {
+        eyre::bail!("unknown output");
+    }
+    if let Some(expected) = node_config.output_types.get(output_id) {
+        if &type_info.data_type != expected {
+            eyre::bail!(
+                "output `{output_id}` is declared as `{expected}` in the dataflow, \
+                but the sent data has type `{}`",
+                type_info.data_type
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::DataType;
+
+    #[test]
+    fn reject_mismatching_output_type() {
+        let image = DataId::from("image".to_owned());
+        let node_config = NodeRunConfig {
+            inputs: Default::default(),
+            outputs: [image.clone()].into(),
+            output_types: [(image.clone(), DataType::Float32)].into(),
+        };
+
+        let err = check_output(&node_config, &image, &ArrowTypeInfo::byte_array(4)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "output `image` is declared as `Float32` in the dataflow, \
+            but the sent data has type `UInt8`"
+        );
+
+        let mut type_info = ArrowTypeInfo::byte_array(4);
+        type_info.data_type = DataType::Float32;
+        check_output(&node_config, &image, &type_info).unwrap();
+    }
+}
@@ -6,9 +6,9 @@ use dora_core::{
 use eyre::Context;
 use notify::event::ModifyKind;
 use notify::{Config, Event as NotifyEvent, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::{path::PathBuf, sync::mpsc, time::Duration};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 pub fn attach_dataflow(
@@ -116,6 +116,7 @@ pub fn attach_dataflow(
     })
     .wrap_err("failed to set ctrl-c handler")?;
 
+    let mut reported_lost_machines = BTreeSet::new();
     loop {
         let control_request = match rx.recv_timeout(Duration::from_secs(1)) {
             Err(_err) => ControlRequest::Check {
@@ -131,6 +132,15 @@ pub fn attach_dataflow(
             serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
         match result {
             ControlRequestReply::DataflowStarted { uuid: _ } => (),
+            ControlRequestReply::DataflowDegraded {
+                uuid,
+                lost_machines,
+            } => {
+                if lost_machines != reported_lost_machines {
+                    warn!("dataflow {uuid} is degraded, lost machines: {lost_machines:?}");
+                    reported_lost_machines = lost_machines;
+                }
+            }
             ControlRequestReply::DataflowStopped { uuid, result } => {
                 info!("dataflow {uuid} stopped");
                 break result
@@ -1,6 +1,6 @@
 use dora_core::{
     config::OperatorId,
-    descriptor::{Descriptor, SINGLE_OPERATOR_DEFAULT_ID},
+    descriptor::{Descriptor, Node, SINGLE_OPERATOR_DEFAULT_ID},
 };
 use eyre::{eyre, Context};
 use std::{path::Path, process::Command};
@@ -14,9 +14,13 @@ pub fn build(dataflow: &Path) -> eyre::Result<()> {
     };
     let working_dir = dataflow_absolute.parent().unwrap();
 
+    build_nodes(&descriptor.nodes, working_dir)
+}
+
+fn build_nodes(nodes: &[Node], working_dir: &Path) -> eyre::Result<()> {
     let default_op_id = OperatorId::from(SINGLE_OPERATOR_DEFAULT_ID.to_string());
 
-    for node in descriptor.nodes {
+    for node in nodes {
         match node.kind()? {
             dora_core::descriptor::NodeKind::Standard(_) => {
                 run_build_command(node.build.as_deref(), working_dir).with_context(|| {
@@ -51,6 +55,12 @@ pub fn build(dataflow: &Path) -> eyre::Result<()> {
                     },
                 )?
             }
+            dora_core::descriptor::NodeKind::Subgraph(subgraph) => {
+                // only reached for descriptors that were not loaded through
+                // `Descriptor::read`, which inlines the sub-graph nodes
+                build_nodes(&subgraph.nodes, working_dir)
+                    .with_context(|| format!("failed to build sub-graph `{}`", node.id))?
+            }
         }
     }
 
@@ -1,14 +1,21 @@
 use crate::connect_to_coordinator;
-use communication_layer_request_reply::TcpRequestReplyConnection;
-use dora_core::topics::{ControlRequest, ControlRequestReply};
+use communication_layer_request_reply::{security::Security, TcpRequestReplyConnection};
+use dora_core::{
+    descriptor::{Descriptor, Severity, ValidationError, ValidationErrors},
+    topics::{ControlRequest, ControlRequestReply},
+};
 use eyre::{bail, Context};
 use std::{
     io::{IsTerminal, Write},
     net::IpAddr,
+    path::Path,
 };
 use termcolor::{Color, ColorChoice, ColorSpec, WriteColor};
 
-pub fn check_environment(coordinator_addr: Option<IpAddr>) -> eyre::Result<()> {
+pub fn check_environment(
+    coordinator_addr: Option<IpAddr>,
+    security: &Security,
+) -> eyre::Result<()> {
     let mut error_occured = false;
 
     let color_choice = if std::io::stdout().is_terminal() {
@@ -20,7 +27,7 @@ pub fn check_environment(coordinator_addr: Option<IpAddr>) -> eyre::Result<()> {
 
     // check whether coordinator is running
     write!(stdout, "Dora Coordinator: ")?;
-    let mut session = match connect_to_coordinator(coordinator_addr) {
+    let mut session = match connect_to_coordinator(coordinator_addr, security) {
         Ok(session) => {
             let _ = stdout.set_color(ColorSpec::new().set_fg(Some(Color::Green)));
             writeln!(stdout, "ok")?;
@@ -62,6 +69,113 @@ pub fn check_environment(coordinator_addr: Option<IpAddr>) -> eyre::Result<()> {
     Ok(())
 }
 
+/// Validates the given dataflow and prints the warnings of the graph analysis.
+///
+/// In `strict` mode, warnings are treated as errors.
+pub fn check_dataflow(dataflow: &Path, strict: bool) -> eyre::Result<()> {
+    let (result, warnings) = check_dataflow_with_warnings(dataflow)?;
+    for warning in &warnings {
+        eprintln!("warning: {warning}");
+    }
+    result?;
+    if strict && !warnings.is_empty() {
+        bail!("found {} warnings in strict mode", warnings.len());
+    }
+    Ok(())
+}
+
+/// Returns the validation result and the warnings of the graph analysis.
+fn check_dataflow_with_warnings(
+    dataflow: &Path,
+) -> eyre::Result<(eyre::Result<()>, Vec<ValidationError>)> {
+    let working_dir = dataflow
+        .canonicalize()
+        .context("failed to canonicalize dataflow path")?
+        .parent()
+        .ok_or_else(|| eyre::eyre!("dataflow path has no parent dir"))?
+        .to_owned();
+    let descriptor = Descriptor::blocking_read(dataflow)?;
+    let result = descriptor.check(&working_dir);
+    // the analysis fails for the same reasons as the check -> report only once
+    let warnings = descriptor.analyze().unwrap_or_default();
+    Ok((result, warnings))
+}
+
+/// A problem found in a dataflow descriptor.
+#[derive(Debug, serde::Serialize)]
+struct Diagnostic {
+    severity: Severity,
+    message: String,
+    /// 1-based line in the YAML file, if known.
+    line: Option<usize>,
+    /// 1-based column in the YAML file, if known.
+    column: Option<usize>,
+}
+
+impl Diagnostic {
+    /// Creates one diagnostic per problem, with the YAML location if known.
+    fn errors(err: &eyre::Report) -> Vec<Self> {
+        if let Some(errors) = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<ValidationErrors>())
+        {
+            return errors.0.iter().map(Self::from).collect();
+        }
+        vec![Self::error(err)]
+    }
+
+    fn error(err: &eyre::Report) -> Self {
+        let location = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<serde_yaml::Error>())
+            .and_then(|e| e.location());
+        Self {
+            severity: Severity::Error,
+            message: format!("{err:#}"),
+            line: location.as_ref().map(|l| l.line()),
+            column: location.as_ref().map(|l| l.column()),
+        }
+    }
+}
+
+impl From<&ValidationError> for Diagnostic {
+    fn from(error: &ValidationError) -> Self {
+        Self {
+            severity: error.severity,
+            message: error.message.clone(),
+            line: error.location.map(|l| l.line),
+            column: error.location.map(|l| l.column),
+        }
+    }
+}
+
+/// Checks the given dataflow and prints the found problems as JSON.
+pub fn check_dataflow_json(dataflow: &Path, strict: bool) -> eyre::Result<()> {
+    let (result, warnings) = match check_dataflow_with_warnings(dataflow) {
+        Ok(checked) => checked,
+        Err(err) => (Err(err), Vec::new()),
+    };
+    let diagnostics: Vec<_> = result
+        .err()
+        .iter()
+        .flat_map(Diagnostic::errors)
+        .chain(warnings.iter().map(Diagnostic::from))
+        .collect();
+    let output = serde_json::json!({
+        "file": dataflow,
+        "diagnostics": diagnostics,
+    });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+
+    let failed = diagnostics
+        .iter()
+        .any(|d| strict || d.severity == Severity::Error);
+    if failed {
+        bail!("dataflow check failed");
+    }
+    Ok(())
+}
+
 pub fn daemon_running(session: &mut TcpRequestReplyConnection) -> Result<bool, eyre::ErrReport> {
     let reply_raw = session
         .request(&serde_json::to_vec(&ControlRequest::DaemonConnected).unwrap())
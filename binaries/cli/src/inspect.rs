@@ -0,0 +1,123 @@
+use crate::table::{format_table, or_dash};
+use communication_layer_request_reply::TcpRequestReplyConnection;
+use dora_core::{
+    config::DataId,
+    topics::{ControlRequest, ControlRequestReply, NodeInfo},
+};
+use eyre::{bail, Context, Result};
+use std::{collections::BTreeSet, time::Duration};
+use uuid::Uuid;
+
+pub fn inspect(
+    session: &mut TcpRequestReplyConnection,
+    uuid: Option<Uuid>,
+    name: Option<String>,
+) -> Result<()> {
+    let reply_raw = session
+        .request(
+            &serde_json::to_vec(&ControlRequest::Inspect { uuid, name })
+                .wrap_err("failed to serialize Inspect request")?,
+        )
+        .wrap_err("failed to send Inspect request message")?;
+    let reply = serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+    let (uuid, name, nodes) = match reply {
+        ControlRequestReply::DataflowInfo { uuid, name, nodes } => (uuid, name, nodes),
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        other => bail!("unexpected reply to inspect request: {other:?}"),
+    };
+
+    match name {
+        Some(name) => println!("Dataflow `{name}` ({uuid}):"),
+        None => println!("Dataflow {uuid}:"),
+    }
+    print!("{}", format_nodes(&nodes));
+
+    Ok(())
+}
+
+fn format_nodes(nodes: &[NodeInfo]) -> String {
+    let rows: Vec<[String; 8]> = nodes
+        .iter()
+        .map(|node| {
+            [
+                node.id.to_string(),
+                or_dash(node.machine.clone()),
+                node.state.to_string(),
+                or_dash(node.pid.map(|pid| pid.to_string()).unwrap_or_default()),
+                or_dash(node.uptime.map(format_uptime).unwrap_or_default()),
+                node.restarts.to_string(),
+                or_dash(join(&node.open_inputs)),
+                or_dash(join(&node.open_outputs)),
+            ]
+        })
+        .collect();
+    format_table(
+        [
+            "NODE", "MACHINE", "STATE", "PID", "UPTIME", "RESTARTS", "INPUTS", "OUTPUTS",
+        ],
+        &rows,
+    )
+}
+
+fn join(ids: &BTreeSet<DataId>) -> String {
+    let ids: Vec<_> = ids.iter().map(|id| id.to_string()).collect();
+    ids.join(",")
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let secs = uptime.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, s) => format!("{h}h{m:02}m{s:02}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dora_core::topics::{NodeExitStatus, NodeState};
+
+    fn node(id: &str, state: NodeState) -> NodeInfo {
+        NodeInfo {
+            id: id.to_owned().into(),
+            machine: String::new(),
+            pid: None,
+            uptime: None,
+            restarts: 0,
+            open_inputs: BTreeSet::new(),
+            open_outputs: BTreeSet::new(),
+            state,
+        }
+    }
+
+    #[test]
+    fn node_table() {
+        let nodes = [
+            NodeInfo {
+                machine: "A".into(),
+                pid: Some(42),
+                uptime: Some(Duration::from_secs(3725)),
+                open_inputs: ["tick".to_owned().into()].into(),
+                open_outputs: ["a".to_owned().into(), "b".to_owned().into()].into(),
+                ..node("source", NodeState::Running)
+            },
+            NodeInfo {
+                restarts: 2,
+                ..node("flaky", NodeState::Restarting)
+            },
+            node("sink", NodeState::Exited(NodeExitStatus::ExitCode(1))),
+            node("later", NodeState::Pending),
+        ];
+        assert_eq!(
+            format_nodes(&nodes),
+            "\
+NODE    MACHINE  STATE                 PID  UPTIME    RESTARTS  INPUTS  OUTPUTS
+source  A        running               42   1h02m05s  0         tick    a,b
+flaky   -        restarting            -    -         2         -       -
+sink    -        exited (exit code 1)  -    -         0         -       -
+later   -        pending               -    -         0         -       -
+"
+        );
+    }
+}
@@ -1,6 +1,14 @@
 use communication_layer_request_reply::TcpRequestReplyConnection;
-use dora_core::topics::{ControlRequest, ControlRequestReply};
+use dora_core::{
+    config::NodeId,
+    logs::{LogFilter, LogRecord},
+    topics::{ControlRequest, ControlRequestReply, LogChunk, LogPosition},
+};
 use eyre::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
 use uuid::Uuid;
 
 use bat::{Input, PrettyPrinter};
@@ -30,12 +38,13 @@ pub fn logs(
         }
     };
 
+    let logs = format_records(&logs, None);
     PrettyPrinter::new()
         .header(false)
         .grid(false)
         .line_numbers(false)
         .paging_mode(bat::PagingMode::QuitIfOneScreen)
-        .inputs(vec![Input::from_bytes(&logs)
+        .inputs(vec![Input::from_bytes(logs.as_bytes())
             .name("Logs")
             .title(format!("Logs from {node}.").as_str())])
         .print()
@@ -43,3 +52,148 @@ pub fn logs(
 
     Ok(())
 }
+
+/// Prints the logs of the given node, or of all nodes if `node` is `None`.
+///
+/// The lines of different nodes are prefixed with the node ID.
+pub fn read_logs(
+    session: &mut TcpRequestReplyConnection,
+    mut uuid: Option<Uuid>,
+    mut name: Option<String>,
+    node: Option<NodeId>,
+    start: LogPosition,
+    filter: LogFilter,
+) -> Result<()> {
+    let prefix_lines = node.is_none();
+    let mut offsets = BTreeMap::new();
+    loop {
+        let reply = request(
+            session,
+            &ControlRequest::ReadLogs {
+                uuid,
+                name: name.clone(),
+                node: node.clone(),
+                offsets: offsets.clone(),
+                start,
+                filter: filter.clone(),
+            },
+        )?;
+        let chunks = match reply {
+            ControlRequestReply::LogChunks {
+                uuid: resolved,
+                chunks,
+                running: _,
+            } => {
+                // stick to the same dataflow, even if the name is reused
+                uuid = Some(resolved);
+                name = None;
+                chunks
+            }
+            other => bail!("unexpected reply to read logs request: {other:?}"),
+        };
+
+        let mut progressed = false;
+        for LogChunk { node, data, offset } in chunks {
+            if !data.is_empty() {
+                print_chunk(&node, &data, prefix_lines).wrap_err("failed to print logs")?;
+            }
+            // filtered lines are skipped without returning any data
+            if offsets.insert(node, offset) != Some(offset) {
+                progressed = true;
+            }
+        }
+
+        // there might be more output than fits into a single reply
+        if !progressed {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Like [`read_logs`], but keeps printing new output as it is written until
+/// the dataflow is finished.
+///
+/// The daemons push new output to the coordinator, which answers the next
+/// request as soon as output is available.
+pub fn follow_logs(
+    session: &mut TcpRequestReplyConnection,
+    uuid: Option<Uuid>,
+    name: Option<String>,
+    node: Option<NodeId>,
+    start: LogPosition,
+    filter: LogFilter,
+) -> Result<()> {
+    let prefix_lines = node.is_none();
+    let reply = request(
+        session,
+        &ControlRequest::FollowLogs {
+            uuid,
+            name,
+            node,
+            start,
+            filter,
+        },
+    )?;
+    let subscription = match reply {
+        ControlRequestReply::LogSubscription { subscription, .. } => subscription,
+        other => bail!("unexpected reply to follow logs request: {other:?}"),
+    };
+    loop {
+        match request(session, &ControlRequest::NextLogs { subscription })? {
+            ControlRequestReply::LogChunks {
+                chunks, running, ..
+            } => {
+                for LogChunk { node, data, .. } in chunks {
+                    print_chunk(&node, &data, prefix_lines).wrap_err("failed to print logs")?;
+                }
+                if !running {
+                    break;
+                }
+            }
+            other => bail!("unexpected reply to next logs request: {other:?}"),
+        }
+    }
+    Ok(())
+}
+
+fn request(
+    session: &mut TcpRequestReplyConnection,
+    request: &ControlRequest,
+) -> Result<ControlRequestReply> {
+    let reply_raw = session
+        .request(&serde_json::to_vec(request).wrap_err("failed to serialize log request")?)
+        .wrap_err("failed to send log request")?;
+    match serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")? {
+        ControlRequestReply::Error(err) => bail!("{err}"),
+        reply => Ok(reply),
+    }
+}
+
+fn print_chunk(node: &NodeId, data: &[u8], prefix_lines: bool) -> io::Result<()> {
+    let output = format_records(data, prefix_lines.then_some(node));
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(output.as_bytes())?;
+    stdout.flush()
+}
+
+/// Formats the given JSON lines log records as text.
+///
+/// Lines that are not valid log records are kept as they are.
+fn format_records(data: &[u8], prefix: Option<&NodeId>) -> String {
+    let mut output = String::new();
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        let text = match serde_json::from_slice::<LogRecord>(line) {
+            Ok(record) => record.to_string(),
+            Err(_) => String::from_utf8_lossy(line).into_owned(),
+        };
+        for line in text.lines() {
+            if let Some(node) = prefix {
+                output.push_str(&format!("[{node}] "));
+            }
+            output.push_str(line);
+            output.push('\n');
+        }
+    }
+    output
+}
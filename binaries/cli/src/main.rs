@@ -1,21 +1,27 @@
 use attach::attach_dataflow;
 use clap::Parser;
-use communication_layer_request_reply::{RequestReplyLayer, TcpLayer, TcpRequestReplyConnection};
-use dora_coordinator::Event;
+use communication_layer_request_reply::{
+    security::{Security, SecurityConfig, TlsConfig},
+    RequestReplyLayer, TcpLayer, TcpRequestReplyConnection,
+};
+use dora_coordinator::{Event, MachineFailureConfig, MachineFailurePolicy};
 use dora_core::{
+    config::NodeId,
     descriptor::Descriptor,
+    logs::{LogFilter, LogLevel},
     topics::{
-        control_socket_addr, ControlRequest, ControlRequestReply, DataflowId,
+        control_socket_addr, ControlRequest, ControlRequestReply, DataflowId, LogPosition,
         DORA_COORDINATOR_PORT_CONTROL, DORA_COORDINATOR_PORT_DEFAULT,
     },
 };
-use dora_daemon::Daemon;
+use dora_daemon::{Daemon, DropPolicy, LogConfig, LogFormat, PeerBufferConfig, RetentionPolicy};
 #[cfg(feature = "tracing")]
 use dora_tracing::set_up_tracing;
 use duration_str::parse;
 use eyre::{bail, Context};
 use std::net::SocketAddr;
 use std::{
+    ffi::OsString,
     net::{IpAddr, Ipv4Addr},
     path::PathBuf,
     time::Duration,
@@ -27,7 +33,10 @@ mod attach;
 mod build;
 mod check;
 mod graph;
+mod inspect;
 mod logs;
+mod stats;
+mod table;
 mod template;
 mod up;
 
@@ -36,6 +45,94 @@ mod up;
 struct Args {
     #[clap(subcommand)]
     command: Command,
+    #[clap(flatten)]
+    security: SecurityArgs,
+}
+
+/// Authentication of the connections between CLI, coordinator and daemons.
+///
+/// All parties need the same settings.
+#[derive(Debug, Clone, Default, clap::Args)]
+struct SecurityArgs {
+    /// YAML file with TLS and token settings
+    #[clap(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    security_config: Option<PathBuf>,
+    /// PEM file of the CA that signed all certificates (enables mutual TLS)
+    #[clap(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    tls_ca: Option<PathBuf>,
+    /// PEM file of the certificate of this machine
+    #[clap(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    tls_cert: Option<PathBuf>,
+    /// PEM file of the private key of `--tls-cert`
+    #[clap(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    tls_key: Option<PathBuf>,
+    /// Name to check server certificates against [default: IP address of the server]
+    #[clap(long, global = true)]
+    tls_server_name: Option<String>,
+    /// File with a shared token that connecting parties need to present
+    #[clap(long, global = true, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    auth_token_file: Option<PathBuf>,
+}
+
+impl SecurityArgs {
+    /// Combines the config file with the flags, which take precedence.
+    fn config(&self) -> eyre::Result<SecurityConfig> {
+        let mut config = match &self.security_config {
+            Some(path) => SecurityConfig::read(path)?,
+            None => SecurityConfig::default(),
+        };
+        match (&self.tls_ca, &self.tls_cert, &self.tls_key) {
+            (None, None, None) => {}
+            (Some(ca), Some(cert), Some(key)) => {
+                config.tls = Some(TlsConfig {
+                    ca: ca.clone(),
+                    cert: cert.clone(),
+                    key: key.clone(),
+                    server_name: None,
+                })
+            }
+            _ => bail!("`--tls-ca`, `--tls-cert` and `--tls-key` must be used together"),
+        }
+        if let Some(server_name) = &self.tls_server_name {
+            match &mut config.tls {
+                Some(tls) => tls.server_name = Some(server_name.clone()),
+                None => bail!("`--tls-server-name` requires TLS to be enabled"),
+            }
+        }
+        if let Some(token_file) = &self.auth_token_file {
+            config.token_file = Some(token_file.clone());
+        }
+        Ok(config)
+    }
+
+    fn load(&self) -> eyre::Result<Security> {
+        self.config()?
+            .load()
+            .wrap_err("failed to load security settings")
+    }
+
+    /// Arguments to pass the same settings to spawned `dora` processes.
+    fn to_args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        let paths = [
+            ("--security-config", &self.security_config),
+            ("--tls-ca", &self.tls_ca),
+            ("--tls-cert", &self.tls_cert),
+            ("--tls-key", &self.tls_key),
+            ("--auth-token-file", &self.auth_token_file),
+        ];
+        for (flag, value) in paths {
+            if let Some(value) = value {
+                args.push(flag.into());
+                args.push(value.into());
+            }
+        }
+        if let Some(server_name) = &self.tls_server_name {
+            args.push("--tls-server-name".into());
+            args.push(server_name.into());
+        }
+        args
+    }
 }
 
 /// dora-rs cli client
@@ -48,6 +145,14 @@ enum Command {
         dataflow: Option<PathBuf>,
         #[clap(long)]
         coordinator_addr: Option<IpAddr>,
+        /// Output format. `json` only checks the dataflow and reports the problems
+        /// with their YAML locations, e.g. for editor integrations.
+        #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+        /// Fail on graph warnings too, e.g. unused outputs or feedback cycles that
+        /// can deadlock under `block_sender` backpressure
+        #[clap(long, action)]
+        strict: bool,
     },
     /// Generate a visualization of the given graph using mermaid.js. Use --open to open browser.
     Graph {
@@ -67,6 +172,12 @@ enum Command {
         #[clap(value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
         dataflow: PathBuf,
     },
+    /// Print the JSON schema of the dataflow descriptor format.
+    Schema {
+        /// Write the schema to the given file instead of stdout
+        #[clap(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
     /// Generate a new project, node or operator. Choose the language between Rust, Python, C or C++.
     New {
         #[clap(flatten)]
@@ -108,6 +219,10 @@ enum Command {
         /// Enable hot reloading (Python only)
         #[clap(long, action)]
         hot_reload: bool,
+        /// Override the value of a dataflow variable (also applies to included sub-graphs)
+        #[clap(long = "set", value_name = "KEY=VALUE")]
+        #[arg(value_parser = parse_variable)]
+        variables: Vec<(String, String)>,
     },
     /// Stop the given dataflow UUID. If no id is provided, you will be able to choose between the running dataflows.
     Stop {
@@ -137,13 +252,53 @@ enum Command {
         #[clap(value_name = "UUID_OR_NAME")]
         dataflow: Option<String>,
         /// Show logs for the given node
-        #[clap(value_name = "NAME")]
-        node: String,
+        #[clap(value_name = "NAME", required_unless_present = "all")]
+        node: Option<String>,
+        /// Show the interleaved logs of all nodes, prefixed by the node ID
+        #[clap(long)]
+        all: bool,
+        /// Keep printing new log output until the dataflow is finished
+        #[clap(long, short)]
+        follow: bool,
+        /// Only show the last N lines
+        #[clap(long, value_name = "N", conflicts_with = "since")]
+        tail: Option<usize>,
+        /// Only show log output of the given duration, e.g. `10m`
+        ///
+        /// The duration is measured with the clock of the machine that runs the node.
+        #[clap(long, value_name = "DURATION", value_parser = parse)]
+        since: Option<Duration>,
+        /// Only show log records of the given level or more severe ones
+        /// (requires daemons started with `--structured-logs`)
+        #[clap(long, value_name = "LEVEL")]
+        level: Option<LogLevel>,
+        /// Only show log records whose target starts with the given string
+        /// (requires daemons started with `--structured-logs`)
+        #[clap(long, value_name = "TARGET")]
+        target: Option<String>,
+        #[clap(long)]
+        coordinator_addr: Option<IpAddr>,
+    },
+    /// Show the status of all nodes of a running dataflow.
+    Inspect {
+        /// Identifier of the dataflow
+        #[clap(value_name = "UUID_OR_NAME")]
+        dataflow: Option<String>,
+        #[clap(long)]
+        coordinator_addr: Option<IpAddr>,
+    },
+    /// Show live message statistics of all inputs and outputs of a running dataflow.
+    Stats {
+        /// Identifier of the dataflow
+        #[clap(value_name = "UUID_OR_NAME")]
+        dataflow: Option<String>,
+        /// Refresh interval
+        #[clap(long, value_parser = parse, default_value = "1s")]
+        interval: Duration,
         #[clap(long)]
         coordinator_addr: Option<IpAddr>,
     },
     // Metrics,
-    // Stats,
     // Get,
     // Upgrade,
     /// Run daemon
@@ -158,6 +313,43 @@ enum Command {
         addr: SocketAddr,
         #[clap(long)]
         coordinator_addr: Option<SocketAddr>,
+        /// Number of messages that are buffered per remote machine.
+        #[clap(long, default_value_t = PeerBufferConfig::default().capacity)]
+        peer_buffer_size: usize,
+        /// Messages to drop when the buffer of an unreachable remote machine
+        /// is full [possible values: drop-oldest, drop-newest]
+        #[clap(long, default_value = "drop-oldest")]
+        peer_drop_policy: DropPolicy,
+        /// Start a new node log segment once the current one exceeds this
+        /// size in MiB (0 disables size-based rotation)
+        #[clap(long, value_name = "MIB", default_value_t = 64)]
+        log_segment_size: u64,
+        /// Start a new node log segment once the current one is older than
+        /// the given duration, e.g. `1h`
+        #[clap(long, value_name = "DURATION", value_parser = parse)]
+        log_segment_age: Option<Duration>,
+        /// Number of rotated log segments to keep per node
+        #[clap(long, value_name = "N", default_value_t = LogConfig::default().max_segments)]
+        log_max_segments: usize,
+        /// Keep rotated log segments uncompressed
+        #[clap(long)]
+        log_no_compress: bool,
+        /// Maximum time between writing log output and syncing it to disk
+        #[clap(long, value_name = "DURATION", value_parser = parse, default_value = "1s")]
+        log_sync_interval: Duration,
+        /// Store node logs as JSON lines (`log_<node>.jsonl`) instead of plain
+        /// text, which allows filtering them by level and target
+        #[clap(long)]
+        structured_logs: bool,
+        /// Remove the `out/<dataflow>` directories of dataflows that finished
+        /// on this machine and were not modified for the given duration, e.g.
+        /// `7d`
+        #[clap(long, value_name = "DURATION", value_parser = parse)]
+        retention_max_age: Option<Duration>,
+        /// Only keep the `out/<dataflow>` directories of the N most recently
+        /// finished dataflows of this machine
+        #[clap(long, value_name = "N")]
+        retention_max_count: Option<usize>,
 
         #[clap(long, hide = true)]
         run_dataflow: Option<PathBuf>,
@@ -170,6 +362,27 @@ enum Command {
             IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), DORA_COORDINATOR_PORT_DEFAULT)
         )]
         addr: SocketAddr,
+        /// Directory in which the coordinator persists its state, so that it
+        /// can re-adopt running dataflows after a restart
+        #[clap(long)]
+        state_dir: Option<PathBuf>,
+        /// Warn if a daemon sent no heartbeat for this duration
+        #[clap(long, value_parser = parse, default_value = "15s")]
+        machine_warn_timeout: Duration,
+        /// Consider a machine lost if its daemon sent no heartbeat for this
+        /// duration
+        #[clap(long, value_parser = parse, default_value = "30s")]
+        machine_lost_timeout: Duration,
+        /// What to do with the dataflows of a lost machine; inputs closed by
+        /// `close-inputs` stay closed if the machine rejoins later
+        /// [possible values: degrade, stop-dataflow, close-inputs]
+        #[clap(long, default_value = "degrade")]
+        machine_failure_policy: MachineFailurePolicy,
+        /// Serve the control API as HTTP/JSON REST endpoints on this port or
+        /// address; a plain port listens on 127.0.0.1 only. Requires TLS or an
+        /// auth token
+        #[clap(long, value_name = "[IP:]PORT", value_parser = parse_http_addr)]
+        http_addr: Option<SocketAddr>,
     },
 }
 
@@ -195,6 +408,12 @@ enum Kind {
     CustomNode,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 enum Lang {
     Rust,
@@ -229,22 +448,23 @@ fn run() -> eyre::Result<()> {
         }
     };
 
+    let security_args = args.security;
+    let security = security_args.load()?;
+
     match args.command {
         Command::Check {
             dataflow,
             coordinator_addr,
-        } => match dataflow {
-            Some(dataflow) => {
-                let working_dir = dataflow
-                    .canonicalize()
-                    .context("failed to canonicalize dataflow path")?
-                    .parent()
-                    .ok_or_else(|| eyre::eyre!("dataflow path has no parent dir"))?
-                    .to_owned();
-                Descriptor::blocking_read(&dataflow)?.check(&working_dir)?;
-                check::check_environment(coordinator_addr)?
+            format,
+            strict,
+        } => match (dataflow, format) {
+            (Some(dataflow), OutputFormat::Text) => {
+                check::check_dataflow(&dataflow, strict)?;
+                check::check_environment(coordinator_addr, &security)?
             }
-            None => check::check_environment(coordinator_addr)?,
+            (Some(dataflow), OutputFormat::Json) => check::check_dataflow_json(&dataflow, strict)?,
+            (None, OutputFormat::Text) => check::check_environment(coordinator_addr, &security)?,
+            (None, OutputFormat::Json) => bail!("`--format json` requires a `--dataflow` path"),
         },
         Command::Graph {
             dataflow,
@@ -256,6 +476,14 @@ fn run() -> eyre::Result<()> {
         Command::Build { dataflow } => {
             build::build(&dataflow)?;
         }
+        Command::Schema { output } => {
+            let schema = Descriptor::json_schema();
+            match output {
+                Some(path) => std::fs::write(&path, schema)
+                    .wrap_err_with(|| format!("failed to write schema to `{}`", path.display()))?,
+                None => println!("{schema}"),
+            }
+        }
         Command::New {
             args,
             internal_create_with_path_dependencies,
@@ -264,28 +492,102 @@ fn run() -> eyre::Result<()> {
             config,
             coordinator_addr,
         } => {
-            up::up(config.as_deref(), coordinator_addr)?;
+            up::up(config.as_deref(), coordinator_addr, &security_args)?;
         }
         Command::Logs {
             dataflow,
             node,
+            all,
+            follow,
+            tail,
+            since,
+            level,
+            target,
             coordinator_addr,
         } => {
-            let mut session = connect_to_coordinator(coordinator_addr)
+            // a single positional argument is parsed as node, but is the dataflow with `--all`
+            let (dataflow, node) = match (all, dataflow, node) {
+                (true, None, dataflow) => (dataflow, None),
+                (true, Some(_), Some(_)) => bail!("`--all` cannot be combined with a node"),
+                (_, dataflow, node) => (dataflow, node),
+            };
+            let mut session = connect_to_coordinator(coordinator_addr, &security)
                 .wrap_err("failed to connect to dora coordinator")?;
-            let uuids = query_running_dataflows(&mut *session)
-                .wrap_err("failed to query running dataflows")?;
-            if let Some(dataflow) = dataflow {
+            let (uuid, name) = if let Some(dataflow) = dataflow {
                 let uuid = Uuid::parse_str(&dataflow).ok();
                 let name = if uuid.is_some() { None } else { Some(dataflow) };
-                logs::logs(&mut *session, uuid, name, node)?
+                (uuid, name)
             } else {
+                let uuids = query_running_dataflows(&mut *session)
+                    .wrap_err("failed to query running dataflows")?;
                 let uuid = match &uuids[..] {
                     [] => bail!("No dataflows are running"),
                     [uuid] => uuid.clone(),
                     _ => inquire::Select::new("Choose dataflow to show logs:", uuids).prompt()?,
                 };
-                logs::logs(&mut *session, Some(uuid.uuid), None, node)?
+                (Some(uuid.uuid), None)
+            };
+            let filter = LogFilter { level, target };
+            match node {
+                Some(node) if !follow && tail.is_none() && since.is_none() && filter.is_empty() => {
+                    logs::logs(&mut *session, uuid, name, node)?
+                }
+                node => {
+                    let start = match (tail, since) {
+                        (Some(lines), _) => LogPosition::Tail(lines),
+                        (None, Some(since)) => LogPosition::Since(since),
+                        (None, None) => LogPosition::Offset(0),
+                    };
+                    let node = node.map(NodeId::from);
+                    if follow {
+                        logs::follow_logs(&mut *session, uuid, name, node, start, filter)?
+                    } else {
+                        logs::read_logs(&mut *session, uuid, name, node, start, filter)?
+                    }
+                }
+            }
+        }
+        Command::Inspect {
+            dataflow,
+            coordinator_addr,
+        } => {
+            let mut session = connect_to_coordinator(coordinator_addr, &security)
+                .wrap_err("failed to connect to dora coordinator")?;
+            if let Some(dataflow) = dataflow {
+                let uuid = Uuid::parse_str(&dataflow).ok();
+                let name = if uuid.is_some() { None } else { Some(dataflow) };
+                inspect::inspect(&mut *session, uuid, name)?
+            } else {
+                let uuids = query_running_dataflows(&mut *session)
+                    .wrap_err("failed to query running dataflows")?;
+                let uuid = match &uuids[..] {
+                    [] => bail!("No dataflows are running"),
+                    [uuid] => uuid.clone(),
+                    _ => inquire::Select::new("Choose dataflow to inspect:", uuids).prompt()?,
+                };
+                inspect::inspect(&mut *session, Some(uuid.uuid), None)?
+            }
+        }
+        Command::Stats {
+            dataflow,
+            interval,
+            coordinator_addr,
+        } => {
+            let mut session = connect_to_coordinator(coordinator_addr, &security)
+                .wrap_err("failed to connect to dora coordinator")?;
+            if let Some(dataflow) = dataflow {
+                let uuid = Uuid::parse_str(&dataflow).ok();
+                let name = if uuid.is_some() { None } else { Some(dataflow) };
+                stats::stats(&mut *session, uuid, name, interval)?
+            } else {
+                let uuids = query_running_dataflows(&mut *session)
+                    .wrap_err("failed to query running dataflows")?;
+                let uuid = match &uuids[..] {
+                    [] => bail!("No dataflows are running"),
+                    [uuid] => uuid.clone(),
+                    _ => inquire::Select::new("Choose dataflow:", uuids).prompt()?,
+                };
+                stats::stats(&mut *session, Some(uuid.uuid), None, interval)?
             }
         }
         Command::Start {
@@ -294,9 +596,12 @@ fn run() -> eyre::Result<()> {
             coordinator_addr,
             attach,
             hot_reload,
+            variables,
         } => {
+            let variables = variables.into_iter().collect();
             let dataflow_descriptor =
-                Descriptor::blocking_read(&dataflow).wrap_err("Failed to read yaml dataflow")?;
+                Descriptor::blocking_read_with_variables(&dataflow, &variables)
+                    .wrap_err("Failed to read yaml dataflow")?;
             let working_dir = dataflow
                 .canonicalize()
                 .context("failed to canonicalize dataflow path")?
@@ -307,7 +612,7 @@ fn run() -> eyre::Result<()> {
                 .check(&working_dir)
                 .wrap_err("Could not validate yaml")?;
 
-            let mut session = connect_to_coordinator(coordinator_addr)
+            let mut session = connect_to_coordinator(coordinator_addr, &security)
                 .wrap_err("failed to connect to dora coordinator")?;
             let dataflow_id = start_dataflow(
                 dataflow_descriptor.clone(),
@@ -326,19 +631,21 @@ fn run() -> eyre::Result<()> {
                 )?
             }
         }
-        Command::List { coordinator_addr } => match connect_to_coordinator(coordinator_addr) {
-            Ok(mut session) => list(&mut *session)?,
-            Err(_) => {
-                bail!("No dora coordinator seems to be running.");
+        Command::List { coordinator_addr } => {
+            match connect_to_coordinator(coordinator_addr, &security) {
+                Ok(mut session) => list(&mut *session)?,
+                Err(_) => {
+                    bail!("No dora coordinator seems to be running.");
+                }
             }
-        },
+        }
         Command::Stop {
             uuid,
             name,
             grace_duration,
             coordinator_addr,
         } => {
-            let mut session = connect_to_coordinator(coordinator_addr)
+            let mut session = connect_to_coordinator(coordinator_addr, &security)
                 .wrap_err("could not connect to dora coordinator")?;
             match (uuid, name) {
                 (Some(uuid), _) => stop_dataflow(uuid, grace_duration, &mut *session)?,
@@ -349,15 +656,35 @@ fn run() -> eyre::Result<()> {
         Command::Destroy {
             config,
             coordinator_addr,
-        } => up::destroy(config.as_deref(), coordinator_addr)?,
-        Command::Coordinator { addr } => {
+        } => up::destroy(config.as_deref(), coordinator_addr, &security)?,
+        Command::Coordinator {
+            addr,
+            state_dir,
+            machine_warn_timeout,
+            machine_lost_timeout,
+            machine_failure_policy,
+            http_addr,
+        } => {
+            let machine_failure = MachineFailureConfig {
+                warn_timeout: machine_warn_timeout,
+                lost_timeout: machine_lost_timeout,
+                policy: machine_failure_policy,
+            };
+            machine_failure.validate()?;
             let rt = Builder::new_multi_thread()
                 .enable_all()
                 .build()
                 .context("tokio runtime failed")?;
             rt.block_on(async {
-                let (_port, task) =
-                    dora_coordinator::start(addr, futures::stream::empty::<Event>()).await?;
+                let (_port, task) = dora_coordinator::start(
+                    addr,
+                    futures::stream::empty::<Event>(),
+                    security,
+                    state_dir,
+                    machine_failure,
+                    http_addr,
+                )
+                .await?;
                 task.await
             })
             .context("failed to run dora-coordinator")?
@@ -366,6 +693,16 @@ fn run() -> eyre::Result<()> {
             coordinator_addr,
             addr,
             machine_id,
+            peer_buffer_size,
+            peer_drop_policy,
+            log_segment_size,
+            log_segment_age,
+            log_max_segments,
+            log_no_compress,
+            log_sync_interval,
+            structured_logs,
+            retention_max_age,
+            retention_max_count,
             run_dataflow,
         } => {
             let rt = Builder::new_multi_thread()
@@ -391,7 +728,36 @@ fn run() -> eyre::Result<()> {
                             let localhost = Ipv4Addr::new(127, 0, 0, 1);
                             (localhost, DORA_COORDINATOR_PORT_DEFAULT).into()
                     });
-                        Daemon::run(coordination_addr, machine_id.unwrap_or_default(), addr).await
+                        let peer_buffer = PeerBufferConfig {
+                            capacity: peer_buffer_size,
+                            drop_policy: peer_drop_policy,
+                        };
+                        let log_config = LogConfig {
+                            max_segment_size: (log_segment_size > 0)
+                                .then_some(log_segment_size * 1024 * 1024),
+                            max_segment_age: log_segment_age,
+                            max_segments: log_max_segments,
+                            compress: !log_no_compress,
+                            sync_interval: log_sync_interval,
+                            format: if structured_logs {
+                                LogFormat::Json
+                            } else {
+                                LogFormat::Text
+                            },
+                            retention: RetentionPolicy {
+                                max_age: retention_max_age,
+                                max_count: retention_max_count,
+                            },
+                        };
+                        Daemon::run(
+                            coordination_addr,
+                            machine_id.unwrap_or_default(),
+                            addr,
+                            peer_buffer,
+                            log_config,
+                            security,
+                        )
+                        .await
                     }
                 }
             })
@@ -403,6 +769,23 @@ fn run() -> eyre::Result<()> {
     Ok(())
 }
 
+fn parse_variable(s: &str) -> eyre::Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| eyre::eyre!("expected `KEY=VALUE` (got `{s}`)"))?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Parses `IP:PORT` or a plain `PORT`, which listens on localhost only.
+fn parse_http_addr(s: &str) -> eyre::Result<SocketAddr> {
+    match s.parse::<u16>() {
+        Ok(port) => Ok((Ipv4Addr::LOCALHOST, port).into()),
+        Err(_) => s
+            .parse()
+            .wrap_err_with(|| format!("expected `PORT` or `IP:PORT` (got `{s}`)")),
+    }
+}
+
 fn start_dataflow(
     dataflow: Descriptor,
     name: Option<String>,
@@ -531,13 +914,15 @@ fn query_running_dataflows(
 
 fn connect_to_coordinator(
     coordinator_addr: Option<IpAddr>,
+    security: &Security,
 ) -> std::io::Result<Box<TcpRequestReplyConnection>> {
+    let mut layer = TcpLayer::with_security(security.clone());
     if let Some(coordinator_addr) = coordinator_addr {
-        TcpLayer::new().connect(SocketAddr::new(
+        layer.connect(SocketAddr::new(
             coordinator_addr,
             DORA_COORDINATOR_PORT_CONTROL,
         ))
     } else {
-        TcpLayer::new().connect(control_socket_addr())
+        layer.connect(control_socket_addr())
     }
 }
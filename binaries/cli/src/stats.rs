@@ -0,0 +1,174 @@
+use crate::table::{or_dash, print_table};
+use communication_layer_request_reply::TcpRequestReplyConnection;
+use dora_core::{
+    config::{DataId, NodeId},
+    topics::{ControlRequest, ControlRequestReply, DataflowStats, InputStats},
+};
+use eyre::{bail, Context, Result};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Shows the edge statistics of the given dataflow, refreshed at the given
+/// interval until the dataflow is stopped.
+pub fn stats(
+    session: &mut TcpRequestReplyConnection,
+    uuid: Option<Uuid>,
+    name: Option<String>,
+    interval: Duration,
+) -> Result<()> {
+    let mut previous: Option<(Instant, DataflowStats)> = None;
+    loop {
+        let reply_raw = session
+            .request(
+                &serde_json::to_vec(&ControlRequest::Stats {
+                    uuid,
+                    name: name.clone(),
+                })
+                .wrap_err("failed to serialize Stats request")?,
+            )
+            .wrap_err("failed to send Stats request message")?;
+        let reply = serde_json::from_slice(&reply_raw).wrap_err("failed to parse reply")?;
+        let (uuid, name, stats) = match reply {
+            ControlRequestReply::DataflowStats { uuid, name, stats } => (uuid, name, stats),
+            ControlRequestReply::Error(err) => bail!("{err}"),
+            other => bail!("unexpected reply to stats request: {other:?}"),
+        };
+        let now = Instant::now();
+
+        // clear the terminal and move the cursor to the top left
+        print!("\x1b[2J\x1b[H");
+        match name {
+            Some(name) => println!("Dataflow `{name}` ({uuid}):\n"),
+            None => println!("Dataflow {uuid}:\n"),
+        }
+        let rates = previous
+            .as_ref()
+            .map(|(time, previous)| Rates::new(previous, now - *time));
+        print_outputs(&stats, rates.as_ref());
+        println!();
+        print_inputs(&stats, rates.as_ref());
+
+        previous = Some((now, stats));
+        std::thread::sleep(interval);
+    }
+}
+
+/// Snapshot of the previous refresh, used to calculate rates.
+struct Rates<'a> {
+    outputs: BTreeMap<(&'a NodeId, &'a DataId), (u64, u64)>,
+    inputs: BTreeMap<(&'a NodeId, &'a DataId), &'a InputStats>,
+    elapsed: f64,
+}
+
+impl<'a> Rates<'a> {
+    fn new(previous: &'a DataflowStats, elapsed: Duration) -> Self {
+        Self {
+            outputs: previous
+                .outputs
+                .iter()
+                .map(|s| ((&s.node, &s.output), (s.messages, s.bytes)))
+                .collect(),
+            inputs: previous
+                .inputs
+                .iter()
+                .map(|s| ((&s.node, &s.input), s))
+                .collect(),
+            elapsed: elapsed.as_secs_f64(),
+        }
+    }
+
+    fn per_sec(&self, current: u64, previous: u64) -> f64 {
+        current.saturating_sub(previous) as f64 / self.elapsed
+    }
+}
+
+fn print_outputs(stats: &DataflowStats, rates: Option<&Rates>) {
+    let rows: Vec<[String; 5]> = stats
+        .outputs
+        .iter()
+        .map(|s| {
+            let previous = rates.and_then(|r| Some((r, r.outputs.get(&(&s.node, &s.output))?)));
+            let (messages_per_sec, bytes_per_sec) = match previous {
+                Some((r, (messages, bytes))) => (
+                    format!("{:.1}", r.per_sec(s.messages, *messages)),
+                    format!("{}/s", format_bytes(r.per_sec(s.bytes, *bytes))),
+                ),
+                None => Default::default(),
+            };
+            [
+                format!("{}/{}", s.node, s.output),
+                or_dash(s.machine.clone()),
+                or_dash(messages_per_sec),
+                or_dash(bytes_per_sec),
+                s.messages.to_string(),
+            ]
+        })
+        .collect();
+    print_table(["OUTPUT", "MACHINE", "MSG/S", "BYTES/S", "TOTAL"], &rows);
+}
+
+fn print_inputs(stats: &DataflowStats, rates: Option<&Rates>) {
+    let rows: Vec<[String; 7]> = stats
+        .inputs
+        .iter()
+        .map(|s| {
+            let previous = rates.and_then(|r| Some((r, *r.inputs.get(&(&s.node, &s.input))?)));
+            let (messages_per_sec, bytes_per_sec, latency) = match previous {
+                Some((r, p)) => (
+                    format!("{:.1}", r.per_sec(s.messages, p.messages)),
+                    format!("{}/s", format_bytes(r.per_sec(s.bytes, p.bytes))),
+                    average_latency(
+                        s.total_latency.saturating_sub(p.total_latency),
+                        s.delivered.saturating_sub(p.delivered),
+                    ),
+                ),
+                None => (
+                    String::new(),
+                    String::new(),
+                    average_latency(s.total_latency, s.delivered),
+                ),
+            };
+            [
+                format!("{}/{}", s.node, s.input),
+                or_dash(s.machine.clone()),
+                or_dash(messages_per_sec),
+                or_dash(bytes_per_sec),
+                s.messages.to_string(),
+                s.dropped.to_string(),
+                or_dash(latency),
+            ]
+        })
+        .collect();
+    print_table(
+        [
+            "INPUT", "MACHINE", "MSG/S", "BYTES/S", "TOTAL", "DROPPED", "LATENCY",
+        ],
+        &rows,
+    );
+}
+
+fn average_latency(total: Duration, delivered: u64) -> String {
+    match u32::try_from(delivered) {
+        Ok(0) => String::new(),
+        Ok(delivered) => format!("{:.2?}", total / delivered),
+        Err(_) => format!("{:.2?}", total.div_f64(delivered as f64)),
+    }
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -1,19 +1,25 @@
-use crate::{check::daemon_running, connect_to_coordinator};
+use crate::{check::daemon_running, connect_to_coordinator, SecurityArgs};
+use communication_layer_request_reply::security::Security;
 use dora_core::topics::ControlRequest;
 use eyre::Context;
 use std::{fs, net::IpAddr, path::Path, process::Command, time::Duration};
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 struct UpConfig {}
 
-pub(crate) fn up(config_path: Option<&Path>, coordinator_addr: Option<IpAddr>) -> eyre::Result<()> {
+pub(crate) fn up(
+    config_path: Option<&Path>,
+    coordinator_addr: Option<IpAddr>,
+    security_args: &SecurityArgs,
+) -> eyre::Result<()> {
     let UpConfig {} = parse_dora_config(config_path)?;
-    let mut session = match connect_to_coordinator(coordinator_addr) {
+    let security = security_args.load()?;
+    let mut session = match connect_to_coordinator(coordinator_addr, &security) {
         Ok(session) => session,
         Err(_) => {
-            start_coordinator().wrap_err("failed to start dora-coordinator")?;
+            start_coordinator(security_args).wrap_err("failed to start dora-coordinator")?;
 
             loop {
-                match connect_to_coordinator(coordinator_addr) {
+                match connect_to_coordinator(coordinator_addr, &security) {
                     Ok(session) => break session,
                     Err(_) => {
                         // sleep a bit until the coordinator accepts connections
@@ -25,7 +31,7 @@ pub(crate) fn up(config_path: Option<&Path>, coordinator_addr: Option<IpAddr>) -
     };
 
     if !daemon_running(&mut *session)? {
-        start_daemon().wrap_err("failed to start dora-daemon")?;
+        start_daemon(security_args).wrap_err("failed to start dora-daemon")?;
 
         // wait a bit until daemon is connected
         let mut i = 0;
@@ -48,9 +54,10 @@ pub(crate) fn up(config_path: Option<&Path>, coordinator_addr: Option<IpAddr>) -
 pub(crate) fn destroy(
     config_path: Option<&Path>,
     coordinator_addr: Option<IpAddr>,
+    security: &Security,
 ) -> Result<(), eyre::ErrReport> {
     let UpConfig {} = parse_dora_config(config_path)?;
-    match connect_to_coordinator(coordinator_addr) {
+    match connect_to_coordinator(coordinator_addr, security) {
         Ok(mut session) => {
             // send destroy command to dora-coordinator
             session
@@ -80,10 +87,11 @@ fn parse_dora_config(config_path: Option<&Path>) -> Result<UpConfig, eyre::ErrRe
     Ok(config)
 }
 
-fn start_coordinator() -> eyre::Result<()> {
+fn start_coordinator(security_args: &SecurityArgs) -> eyre::Result<()> {
     let mut cmd =
         Command::new(std::env::current_exe().wrap_err("failed to get current executable path")?);
     cmd.arg("coordinator");
+    cmd.args(security_args.to_args());
     cmd.spawn().wrap_err("failed to run `dora coordinator`")?;
 
     println!("started dora coordinator");
@@ -91,10 +99,11 @@ fn start_coordinator() -> eyre::Result<()> {
     Ok(())
 }
 
-fn start_daemon() -> eyre::Result<()> {
+fn start_daemon(security_args: &SecurityArgs) -> eyre::Result<()> {
     let mut cmd =
         Command::new(std::env::current_exe().wrap_err("failed to get current executable path")?);
     cmd.arg("daemon");
+    cmd.args(security_args.to_args());
     cmd.spawn().wrap_err("failed to run `dora daemon`")?;
 
     println!("started dora daemon");
@@ -2,6 +2,7 @@ use crate::{
     tcp_utils::{tcp_receive, tcp_send},
     Event,
 };
+use communication_layer_request_reply::security::{MaybeTlsStream, Security};
 use dora_core::topics::{ControlRequest, ControlRequestReply};
 use eyre::{eyre, Context};
 use futures::{
@@ -12,7 +13,7 @@ use futures::{
 use futures_concurrency::future::Race;
 use std::{io::ErrorKind, net::SocketAddr};
 use tokio::{
-    net::{TcpListener, TcpStream},
+    net::TcpListener,
     sync::{mpsc, oneshot},
     task::JoinHandle,
 };
@@ -21,11 +22,17 @@ use tokio_stream::wrappers::ReceiverStream;
 pub(crate) async fn control_events(
     control_listen_addr: SocketAddr,
     tasks: &FuturesUnordered<JoinHandle<()>>,
+    security: Security,
 ) -> eyre::Result<impl Stream<Item = Event>> {
     let (tx, rx) = mpsc::channel(10);
 
     let (finish_tx, mut finish_rx) = mpsc::channel(1);
-    tasks.push(tokio::spawn(listen(control_listen_addr, tx, finish_tx)));
+    tasks.push(tokio::spawn(listen(
+        control_listen_addr,
+        tx,
+        finish_tx,
+        security,
+    )));
     tasks.push(tokio::spawn(async move {
         while let Some(()) = finish_rx.recv().await {}
     }));
@@ -37,6 +44,7 @@ async fn listen(
     control_listen_addr: SocketAddr,
     tx: mpsc::Sender<ControlEvent>,
     _finish_tx: mpsc::Sender<()>,
+    security: Security,
 ) {
     let result = TcpListener::bind(control_listen_addr)
         .await
@@ -62,7 +70,14 @@ async fn listen(
         match connection.wrap_err("failed to connect") {
             Ok((connection, _)) => {
                 let tx = tx.clone();
-                tokio::spawn(handle_requests(connection, tx, _finish_tx.clone()));
+                let security = security.clone();
+                let finish_tx = _finish_tx.clone();
+                tokio::spawn(async move {
+                    match security.accept(connection).await {
+                        Ok(connection) => handle_requests(connection, tx, finish_tx).await,
+                        Err(err) => tracing::warn!("rejected control connection: {err}"),
+                    }
+                });
             }
             Err(err) => {
                 if tx.blocking_send(err.into()).is_err() {
@@ -74,7 +89,7 @@ async fn listen(
 }
 
 async fn handle_requests(
-    mut connection: TcpStream,
+    mut connection: MaybeTlsStream,
     tx: mpsc::Sender<ControlEvent>,
     _finish_tx: mpsc::Sender<()>,
 ) {
@@ -135,7 +150,7 @@ async fn handle_requests(
     }
 }
 
-async fn handle_request(
+pub(crate) async fn handle_request(
     request: ControlRequest,
     tx: &mpsc::Sender<ControlEvent>,
 ) -> eyre::Result<ControlRequestReply> {
@@ -0,0 +1,456 @@
+//! Optional HTTP/JSON interface to the coordinator.
+//!
+//! Exposes the `ControlRequest`s of the TCP control protocol as REST
+//! endpoints:
+//!
+//! - `GET /dataflows`: list the running dataflows
+//! - `POST /dataflows?name=<name>&working_dir=<path>`: start the dataflow
+//!   that is given as YAML or JSON descriptor in the request body
+//! - `GET /dataflows/<uuid>`: check the status of a dataflow
+//! - `POST /dataflows/<uuid or name>/stop?grace_secs=<secs>`: stop a dataflow
+//! - `GET /dataflows/<uuid or name>/logs/<node>`: retrieve the logs of a node
+//! - `GET /dataflows/<uuid or name>/nodes`: status of all nodes of a dataflow
+//! - `GET /dataflows/<uuid or name>/stats`: message statistics of all edges
+//! - `GET /machines`: list the connected machines
+//! - `GET /events`: server-sent events stream of dataflow status changes
+//!
+//! Replies are the JSON-serialized `ControlRequestReply`s.
+//!
+//! Since the API allows to spawn arbitrary processes, it is only served with
+//! authentication: If TLS is configured, the API is served over HTTPS and
+//! clients need a certificate signed by the configured CA. Otherwise, an auth
+//! token is required and the API is only served on loopback addresses, so
+//! that the token is never sent over the network in plain text. If a token is
+//! configured, requests need to pass it as `Authorization: Bearer <token>`
+//! header.
+
+use crate::{control::handle_request, dataflow_result, ControlEvent, Event, RunningDataflow};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Router,
+};
+use communication_layer_request_reply::security::{MaybeTlsStream, Security};
+use dora_core::{
+    descriptor::Descriptor,
+    topics::{ControlRequest, ControlRequestReply},
+};
+use eyre::{bail, Context};
+use futures::{stream::FuturesUnordered, Stream, StreamExt};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::Infallible,
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+use uuid::Uuid;
+
+/// Connections that don't finish the TLS handshake in time are closed.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+struct HttpState {
+    tx: mpsc::Sender<ControlEvent>,
+    status_tx: broadcast::Sender<ControlRequestReply>,
+    security: Security,
+}
+
+pub(crate) async fn http_events(
+    listen_addr: SocketAddr,
+    tasks: &FuturesUnordered<JoinHandle<()>>,
+    security: Security,
+    status_tx: broadcast::Sender<ControlRequestReply>,
+) -> eyre::Result<impl Stream<Item = Event>> {
+    if !security.tls_enabled() {
+        if !security.token_enabled() {
+            bail!("the HTTP API requires TLS or an auth token to be configured");
+        }
+        if !listen_addr.ip().is_loopback() {
+            bail!(
+                "the HTTP API can only listen on loopback addresses without TLS, \
+                to not send the auth token in plain text (got {listen_addr})"
+            );
+        }
+    }
+    let listener = TcpListener::bind(listen_addr)
+        .await
+        .wrap_err_with(|| format!("failed to listen for HTTP requests on {listen_addr}"))?;
+    let local_addr = listener
+        .local_addr()
+        .wrap_err("failed to get HTTP listen address")?;
+    let connections =
+        hyper::server::accept::from_stream(accept_connections(listener, security.clone()));
+
+    let scheme = if security.tls_enabled() {
+        "https"
+    } else {
+        "http"
+    };
+
+    let (tx, rx) = mpsc::channel(10);
+
+    let app = Router::new()
+        .route("/dataflows", get(list).post(start))
+        .route("/dataflows/:dataflow", get(check))
+        .route("/dataflows/:dataflow/stop", post(stop))
+        .route("/dataflows/:dataflow/logs/:node", get(logs))
+        .route("/dataflows/:dataflow/nodes", get(inspect))
+        .route("/dataflows/:dataflow/stats", get(stats))
+        .route("/machines", get(machines))
+        .route("/events", get(events))
+        .with_state(HttpState {
+            tx: tx.clone(),
+            status_tx,
+            security,
+        });
+    let server = axum::Server::builder(connections).serve(app.into_make_service());
+    tracing::info!("listening for {scheme} requests on {local_addr}");
+
+    tasks.push(tokio::spawn(async move {
+        let coordinator_stopped = async move { tx.closed().await };
+        if let Err(err) = server.with_graceful_shutdown(coordinator_stopped).await {
+            tracing::error!("HTTP server failed: {err}");
+        }
+    }));
+
+    Ok(ReceiverStream::new(rx).map(Event::Control))
+}
+
+/// Accepts connections and performs the TLS handshake, if enabled.
+///
+/// Handshakes run in separate tasks, so that slow clients don't block other
+/// connections.
+fn accept_connections(
+    listener: TcpListener,
+    security: Security,
+) -> impl Stream<Item = io::Result<MaybeTlsStream>> {
+    let (tx, rx) = mpsc::channel(16);
+    tokio::spawn(async move {
+        loop {
+            let stream = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        tracing::warn!("failed to accept HTTP connection: {err}");
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                },
+                // the HTTP server stopped
+                () = tx.closed() => break,
+            };
+            let security = security.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, security.accept_tls(stream)).await
+                {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(err)) => tracing::debug!("HTTP TLS handshake failed: {err}"),
+                    Err(_) => tracing::debug!("HTTP TLS handshake timed out"),
+                }
+            });
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// Tracks the last published status of each dataflow to send out
+/// `/events` updates only when the status actually changed.
+pub(crate) struct StatusChanges {
+    tx: broadcast::Sender<ControlRequestReply>,
+    /// Lost machines of each running dataflow.
+    published: HashMap<Uuid, BTreeSet<String>>,
+}
+
+impl StatusChanges {
+    pub fn new(tx: broadcast::Sender<ControlRequestReply>) -> Self {
+        Self {
+            tx,
+            published: HashMap::new(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        running_dataflows: &HashMap<Uuid, RunningDataflow>,
+        dataflow_results: &HashMap<Uuid, BTreeMap<String, Result<(), String>>>,
+    ) {
+        for (uuid, dataflow) in running_dataflows {
+            if self.published.get(uuid) == Some(&dataflow.lost_machines) {
+                continue;
+            }
+            let status = if dataflow.lost_machines.is_empty() {
+                ControlRequestReply::DataflowStarted { uuid: *uuid }
+            } else {
+                ControlRequestReply::DataflowDegraded {
+                    uuid: *uuid,
+                    lost_machines: dataflow.lost_machines.clone(),
+                }
+            };
+            self.published.insert(*uuid, dataflow.lost_machines.clone());
+            // an error only means that there are currently no subscribers
+            let _ = self.tx.send(status);
+        }
+
+        let stopped: Vec<_> = self
+            .published
+            .keys()
+            .filter(|uuid| !running_dataflows.contains_key(uuid))
+            .copied()
+            .collect();
+        for uuid in stopped {
+            self.published.remove(&uuid);
+            let result = dataflow_results
+                .get(&uuid)
+                .map(|results| dataflow_result(results, uuid))
+                .unwrap_or(Ok(()));
+            let _ = self
+                .tx
+                .send(ControlRequestReply::DataflowStopped { uuid, result });
+        }
+    }
+}
+
+impl HttpState {
+    async fn request(&self, headers: &HeaderMap, request: ControlRequest) -> Response {
+        if let Err(response) = self.authorize(headers) {
+            return response;
+        }
+        let reply = handle_request(request, &self.tx)
+            .await
+            .unwrap_or_else(|err| ControlRequestReply::Error(format!("{err}")));
+        match reply {
+            ControlRequestReply::Logs(logs) => logs.into_response(),
+            reply => json_response(&reply),
+        }
+    }
+
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), Response> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if self.security.check_token(token) {
+            Ok(())
+        } else {
+            Err((StatusCode::UNAUTHORIZED, "invalid or missing auth token").into_response())
+        }
+    }
+}
+
+fn json_response(reply: &ControlRequestReply) -> Response {
+    let status = match reply {
+        ControlRequestReply::Error(_) => StatusCode::BAD_REQUEST,
+        ControlRequestReply::CoordinatorStopped => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    match serde_json::to_vec(reply) {
+        Ok(body) => (status, [(header::CONTENT_TYPE, "application/json")], body).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn list(State(state): State<HttpState>, headers: HeaderMap) -> Response {
+    state.request(&headers, ControlRequest::List).await
+}
+
+async fn machines(State(state): State<HttpState>, headers: HeaderMap) -> Response {
+    state
+        .request(&headers, ControlRequest::ConnectedMachines)
+        .await
+}
+
+#[derive(Deserialize)]
+struct StartParams {
+    name: Option<String>,
+    /// Defaults to the working directory of the coordinator.
+    working_dir: Option<PathBuf>,
+}
+
+async fn start(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Query(params): Query<StartParams>,
+    body: Bytes,
+) -> Response {
+    let inner = || {
+        let dataflow = Descriptor::parse(body.to_vec())?;
+        let local_working_dir = match params.working_dir {
+            Some(dir) => dir,
+            None => std::env::current_dir().wrap_err("failed to get current working dir")?,
+        };
+        eyre::Ok(ControlRequest::Start {
+            dataflow,
+            name: params.name,
+            local_working_dir,
+        })
+    };
+    match inner() {
+        Ok(request) => state.request(&headers, request).await,
+        Err(err) => json_response(&ControlRequestReply::Error(format!("{err:?}"))),
+    }
+}
+
+async fn check(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Path(dataflow): Path<Uuid>,
+) -> Response {
+    let request = ControlRequest::Check {
+        dataflow_uuid: dataflow,
+    };
+    state.request(&headers, request).await
+}
+
+#[derive(Deserialize)]
+struct StopParams {
+    grace_secs: Option<f64>,
+}
+
+async fn stop(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Path(dataflow): Path<String>,
+    Query(params): Query<StopParams>,
+) -> Response {
+    let grace_duration = params.grace_secs.map(Duration::from_secs_f64);
+    let request = match dataflow.parse() {
+        Ok(dataflow_uuid) => ControlRequest::Stop {
+            dataflow_uuid,
+            grace_duration,
+        },
+        Err(_) => ControlRequest::StopByName {
+            name: dataflow,
+            grace_duration,
+        },
+    };
+    state.request(&headers, request).await
+}
+
+async fn logs(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Path((dataflow, node)): Path<(String, String)>,
+) -> Response {
+    let (uuid, name) = match dataflow.parse() {
+        Ok(uuid) => (Some(uuid), None),
+        Err(_) => (None, Some(dataflow)),
+    };
+    state
+        .request(&headers, ControlRequest::Logs { uuid, name, node })
+        .await
+}
+
+async fn inspect(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Path(dataflow): Path<String>,
+) -> Response {
+    let (uuid, name) = match dataflow.parse() {
+        Ok(uuid) => (Some(uuid), None),
+        Err(_) => (None, Some(dataflow)),
+    };
+    state
+        .request(&headers, ControlRequest::Inspect { uuid, name })
+        .await
+}
+
+async fn stats(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Path(dataflow): Path<String>,
+) -> Response {
+    let (uuid, name) = match dataflow.parse() {
+        Ok(uuid) => (Some(uuid), None),
+        Err(_) => (None, Some(dataflow)),
+    };
+    state
+        .request(&headers, ControlRequest::Stats { uuid, name })
+        .await
+}
+
+async fn events(State(state): State<HttpState>, headers: HeaderMap) -> Response {
+    if let Err(response) = state.authorize(&headers) {
+        return response;
+    }
+    let status_changes = BroadcastStream::new(state.status_tx.subscribe()).filter_map(|status| {
+        let event = match status {
+            Ok(status) => serde_json::to_string(&status)
+                .map(|data| sse::Event::default().event("status").data(data))
+                .map_err(|err| tracing::warn!("failed to serialize status event: {err}"))
+                .ok(),
+            Err(err) => {
+                tracing::warn!("dropped status events for slow HTTP client: {err}");
+                None
+            }
+        };
+        futures::future::ready(event.map(Ok::<_, Infallible>))
+    });
+    Sse::new(status_changes)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish_status_changes_once() {
+        let (tx, mut rx) = broadcast::channel(8);
+        let mut status_changes = StatusChanges::new(tx);
+
+        let uuid = Uuid::nil();
+        let mut running_dataflows = HashMap::from([(
+            uuid,
+            RunningDataflow {
+                name: None,
+                uuid,
+                machines: ["A".to_owned()].into(),
+                pending_machines: BTreeSet::new(),
+                init_success: true,
+                nodes: Vec::new(),
+                unconfirmed_machines: BTreeSet::new(),
+                lost_machines: BTreeSet::new(),
+                reply_senders: Vec::new(),
+            },
+        )]);
+        let mut dataflow_results = HashMap::new();
+
+        status_changes.update(&running_dataflows, &dataflow_results);
+        status_changes.update(&running_dataflows, &dataflow_results);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ControlRequestReply::DataflowStarted { .. })
+        ));
+        assert!(rx.try_recv().is_err());
+
+        running_dataflows.remove(&uuid);
+        dataflow_results.insert(
+            uuid,
+            BTreeMap::from([("A".to_owned(), Err("failed".into()))]),
+        );
+        status_changes.update(&running_dataflows, &dataflow_results);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(ControlRequestReply::DataflowStopped { result: Err(_), .. })
+        ));
+    }
+}
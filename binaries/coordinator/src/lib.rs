@@ -2,23 +2,31 @@ use crate::{
     run::spawn_dataflow,
     tcp_utils::{tcp_receive, tcp_send},
 };
+use communication_layer_request_reply::security::{MaybeTlsStream, Security};
 pub use control::ControlEvent;
 use dora_core::{
     config::{NodeId, OperatorId},
     coordinator_messages::RegisterResult,
     daemon_messages::{DaemonCoordinatorEvent, DaemonCoordinatorReply, Timestamped},
     descriptor::{Descriptor, ResolvedNode},
+    logs::LogFilter,
     message::uhlc::{self, HLC},
-    topics::{control_socket_addr, ControlRequest, ControlRequestReply, DataflowId},
+    topics::{
+        control_socket_addr, ControlRequest, ControlRequestReply, DataflowId, DataflowStats,
+        LogChunk, LogPosition, NodeInfo, NodeState,
+    },
 };
 use eyre::{bail, eyre, ContextCompat, WrapErr};
 use futures::{stream::FuturesUnordered, Future, Stream, StreamExt};
 use futures_concurrency::stream::Merge;
+use log_subscriptions::LogSubscription;
 use run::SpawnedDataflow;
+use state::{PersistedState, StateStore};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     net::SocketAddr,
     path::PathBuf,
+    str::FromStr,
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -28,17 +36,106 @@ use tokio::{
     task::JoinHandle,
 };
 use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
-use uuid::Uuid;
+use uuid::{NoContext, Timestamp, Uuid};
 
 mod control;
+mod http;
 mod listener;
+mod log_subscriptions;
 mod run;
+mod state;
 mod tcp_utils;
 
+/// How long restored dataflows wait for their daemons to register again
+/// after a coordinator restart.
+const RECOVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of finished dataflows whose nodes and results are kept,
+/// e.g. for `dora logs` and `dora list`.
+const MAX_ARCHIVED_DATAFLOWS: usize = 100;
+
+/// How the coordinator handles daemons that stop sending heartbeats.
+#[derive(Debug, Clone, Copy)]
+pub struct MachineFailureConfig {
+    /// Log a warning if a daemon sent no heartbeat for this duration.
+    pub warn_timeout: Duration,
+    /// Mark the machine as lost if its daemon sent no heartbeat for this
+    /// duration.
+    pub lost_timeout: Duration,
+    pub policy: MachineFailurePolicy,
+}
+
+impl MachineFailureConfig {
+    /// Checks that a machine is warned about before it is considered lost.
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.warn_timeout >= self.lost_timeout {
+            bail!(
+                "machine warn timeout ({:?}) must be shorter than the machine lost timeout ({:?})",
+                self.warn_timeout,
+                self.lost_timeout
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Default for MachineFailureConfig {
+    fn default() -> Self {
+        Self {
+            warn_timeout: Duration::from_secs(15),
+            lost_timeout: Duration::from_secs(30),
+            policy: MachineFailurePolicy::default(),
+        }
+    }
+}
+
+/// What to do with the dataflows of a lost machine.
+///
+/// Affected dataflows are reported as degraded in any case.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MachineFailurePolicy {
+    /// Keep the nodes on the remaining machines running.
+    #[default]
+    Degrade,
+    /// Stop the whole dataflow.
+    StopDataflow,
+    /// Close all inputs that are fed by nodes of the lost machine.
+    ///
+    /// Closed inputs are not reopened if the machine rejoins later, e.g.
+    /// after a network partition. Its nodes keep running, but their outputs
+    /// are no longer delivered to the nodes on the other machines.
+    CloseInputs,
+}
+
+impl FromStr for MachineFailurePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "degrade" => Ok(Self::Degrade),
+            "stop-dataflow" | "stop_dataflow" => Ok(Self::StopDataflow),
+            "close-inputs" | "close_inputs" => Ok(Self::CloseInputs),
+            other => Err(format!(
+                "unknown machine failure policy `{other}` \
+                (expected `degrade`, `stop-dataflow` or `close-inputs`)"
+            )),
+        }
+    }
+}
+
 pub async fn start(
     bind: SocketAddr,
     external_events: impl Stream<Item = Event> + Unpin,
+    security: Security,
+    state_dir: Option<PathBuf>,
+    machine_failure: MachineFailureConfig,
+    http_addr: Option<SocketAddr>,
 ) -> Result<(u16, impl Future<Output = eyre::Result<()>>), eyre::ErrReport> {
+    machine_failure.validate()?;
+    let state = state_dir
+        .map(|dir| StateStore::open(&dir))
+        .transpose()
+        .wrap_err("failed to load coordinator state")?;
     let listener = listener::create_listener(bind).await?;
     let port = listener
         .local_addr()
@@ -50,7 +147,16 @@ pub async fn start(
     let ctrlc_events = set_up_ctrlc_handler()?;
 
     let future = async move {
-        start_inner(listener, &tasks, (ctrlc_events, external_events).merge()).await?;
+        start_inner(
+            listener,
+            &tasks,
+            (ctrlc_events, external_events).merge(),
+            security,
+            state,
+            machine_failure,
+            http_addr,
+        )
+        .await?;
 
         tracing::debug!("coordinator main loop finished, waiting on spawned tasks");
         while let Some(join_result) = tasks.next().await {
@@ -99,10 +205,27 @@ fn resolve_name(
     }
 }
 
+fn resolve_uuid_or_name(
+    uuid: Option<Uuid>,
+    name: Option<String>,
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &HashMap<Uuid, ArchivedDataflow>,
+) -> eyre::Result<Uuid> {
+    match (uuid, name) {
+        (Some(uuid), _) => Ok(uuid),
+        (None, Some(name)) => resolve_name(name, running_dataflows, archived_dataflows),
+        (None, None) => Err(eyre!("no dataflow UUID or name given")),
+    }
+}
+
 async fn start_inner(
     listener: TcpListener,
     tasks: &FuturesUnordered<JoinHandle<()>>,
     external_events: impl Stream<Item = Event> + Unpin,
+    security: Security,
+    state: Option<(StateStore, PersistedState)>,
+    machine_failure: MachineFailureConfig,
+    http_addr: Option<SocketAddr>,
 ) -> eyre::Result<()> {
     let clock = Arc::new(HLC::default());
 
@@ -116,10 +239,24 @@ async fn start_inner(
     let mut daemon_events_tx = Some(daemon_events_tx);
     let daemon_events = ReceiverStream::new(daemon_events);
 
-    let control_events = control::control_events(control_socket_addr(), tasks)
+    let control_events = control::control_events(control_socket_addr(), tasks, security.clone())
         .await
         .wrap_err("failed to create control events")?;
 
+    let mut status_changes = None;
+    let http_events = match http_addr {
+        Some(addr) => {
+            let (status_tx, _) = tokio::sync::broadcast::channel(64);
+            let events = http::http_events(addr, tasks, security.clone(), status_tx.clone())
+                .await
+                .wrap_err("failed to start HTTP server")?;
+            status_changes = Some(http::StatusChanges::new(status_tx));
+            Some(events)
+        }
+        None => None,
+    };
+    let http_events = futures::stream::iter(http_events).flatten();
+
     let daemon_heartbeat_interval =
         tokio_stream::wrappers::IntervalStream::new(tokio::time::interval(Duration::from_secs(3)))
             .map(|_| Event::DaemonHeartbeatInterval);
@@ -128,6 +265,7 @@ async fn start_inner(
     let (abortable_events, abort_handle) = futures::stream::abortable(
         (
             control_events,
+            http_events,
             new_daemon_connections,
             external_events,
             daemon_heartbeat_interval,
@@ -137,15 +275,45 @@ async fn start_inner(
 
     let mut events = (abortable_events, daemon_events).merge();
 
-    let mut running_dataflows: HashMap<Uuid, RunningDataflow> = HashMap::new();
-    let mut dataflow_results: HashMap<Uuid, BTreeMap<String, Result<(), String>>> = HashMap::new();
-    let mut archived_dataflows: HashMap<Uuid, ArchivedDataflow> = HashMap::new();
+    let (mut state_store, persisted) = match state {
+        Some((store, persisted)) => (Some(store), persisted),
+        None => (None, PersistedState::default()),
+    };
+    let mut running_dataflows: HashMap<Uuid, RunningDataflow> = persisted
+        .running_dataflows
+        .into_iter()
+        .map(|(uuid, dataflow)| (uuid, RunningDataflow::restore(uuid, dataflow)))
+        .collect();
+    let mut dataflow_results: HashMap<Uuid, BTreeMap<String, Result<(), String>>> =
+        persisted.dataflow_results.into_iter().collect();
+    let mut archived_dataflows: HashMap<Uuid, ArchivedDataflow> = persisted
+        .archived_dataflows
+        .into_iter()
+        .map(|(uuid, dataflow)| {
+            let dataflow = ArchivedDataflow {
+                name: dataflow.name,
+                nodes: dataflow.nodes,
+            };
+            (uuid, dataflow)
+        })
+        .collect();
     let mut daemon_connections: HashMap<_, DaemonConnection> = HashMap::new();
+    let mut log_subscriptions: HashMap<Uuid, LogSubscription> = HashMap::new();
+
+    let mut recovery_deadline = None;
+    if !running_dataflows.is_empty() {
+        tracing::info!(
+            "restored {} running dataflows, waiting for their daemons to register again",
+            running_dataflows.len()
+        );
+        recovery_deadline = Some(Instant::now() + RECOVERY_TIMEOUT);
+    }
 
     while let Some(event) = events.next().await {
         if event.log() {
             tracing::trace!("Handling event {event:?}");
         }
+        let mut state_changed = false;
         match event {
             Event::NewDaemonConnection(connection) => {
                 connection.set_nodelay(true)?;
@@ -155,6 +323,7 @@ async fn start_inner(
                         connection,
                         events_tx,
                         clock.clone(),
+                        security.clone(),
                     ));
                     tasks.push(task);
                 } else {
@@ -172,6 +341,7 @@ async fn start_inner(
                     mut connection,
                     dora_version: daemon_version,
                     listen_port,
+                    running_dataflows: reported_dataflows,
                 } => {
                     let coordinator_version: &&str = &env!("CARGO_PKG_VERSION");
                     let version_check = if &daemon_version == coordinator_version {
@@ -211,6 +381,14 @@ async fn start_inner(
                                     "closing previous connection `{machine_id}` on new register"
                                 );
                             }
+                            reconcile_machine(
+                                &machine_id,
+                                &reported_dataflows,
+                                &mut running_dataflows,
+                                &mut archived_dataflows,
+                                &mut dataflow_results,
+                            );
+                            state_changed = true;
                         }
                         (Err(err), _) => {
                             tracing::warn!("failed to register daemon connection for machine `{machine_id}`: {err}");
@@ -231,6 +409,7 @@ async fn start_inner(
                             let dataflow = entry.get_mut();
                             dataflow.pending_machines.remove(&machine_id);
                             dataflow.init_success &= success;
+                            state_changed = true;
                             if dataflow.pending_machines.is_empty() {
                                 let message = serde_json::to_vec(&Timestamped {
                                     inner: DaemonCoordinatorEvent::AllNodesReady {
@@ -266,45 +445,82 @@ async fn start_inner(
                         }
                     }
                 }
-                DataflowEvent::DataflowFinishedOnMachine { machine_id, result } => {
-                    match running_dataflows.entry(uuid) {
-                        std::collections::hash_map::Entry::Occupied(mut entry) => {
-                            // Archive finished dataflow
-                            if archived_dataflows.get(&uuid).is_none() {
-                                archived_dataflows
-                                    .insert(uuid, ArchivedDataflow::from(entry.get()));
-                            }
-                            entry.get_mut().machines.remove(&machine_id);
-                            match &result {
-                                Ok(()) => {
-                                    tracing::info!("dataflow `{uuid}` finished successfully on machine `{machine_id}`");
-                                }
-                                Err(err) => {
-                                    tracing::error!("{err:?}");
-                                }
-                            }
-                            dataflow_results
-                                .entry(uuid)
-                                .or_default()
-                                .insert(machine_id, result.map_err(|err| format!("{err:?}")));
-                            if entry.get_mut().machines.is_empty() {
-                                let finished_dataflow = entry.remove();
-                                let reply = ControlRequestReply::DataflowStopped {
-                                    uuid,
-                                    result: dataflow_results
-                                        .get(&uuid)
-                                        .map(|r| dataflow_result(r, uuid))
-                                        .unwrap_or(Ok(())),
-                                };
-                                for sender in finished_dataflow.reply_senders {
-                                    let _ = sender.send(Ok(reply.clone()));
-                                }
-                            }
-                        }
-                        std::collections::hash_map::Entry::Vacant(_) => {
-                            tracing::warn!("dataflow not running on DataflowFinishedOnMachine");
-                        }
+                DataflowEvent::NodeReadyOnMachine {
+                    machine_id,
+                    node_id,
+                } => match running_dataflows.get(&uuid) {
+                    Some(dataflow) => {
+                        let event = DaemonCoordinatorEvent::NodeReady {
+                            dataflow_id: uuid,
+                            node_id: node_id.clone(),
+                        };
+                        notify_other_machines(
+                            dataflow,
+                            &machine_id,
+                            event,
+                            &mut daemon_connections,
+                            &clock,
+                        )
+                        .await
+                        .wrap_err_with(|| format!("failed to send NodeReady({uuid}/{node_id})"))?;
                     }
+                    None => {
+                        tracing::warn!("dataflow not running on NodeReadyOnMachine");
+                    }
+                },
+                DataflowEvent::NodeFailedOnMachine {
+                    machine_id,
+                    node_id,
+                } => match running_dataflows.get(&uuid) {
+                    Some(dataflow) => {
+                        tracing::warn!(
+                            "node `{uuid}/{node_id}` exited before it was ready, \
+                            giving up on its dependents"
+                        );
+                        let event = DaemonCoordinatorEvent::NodeFailed {
+                            dataflow_id: uuid,
+                            node_id: node_id.clone(),
+                        };
+                        notify_other_machines(
+                            dataflow,
+                            &machine_id,
+                            event,
+                            &mut daemon_connections,
+                            &clock,
+                        )
+                        .await
+                        .wrap_err_with(|| format!("failed to send NodeFailed({uuid}/{node_id})"))?;
+                    }
+                    None => {
+                        tracing::warn!("dataflow not running on NodeFailedOnMachine");
+                    }
+                },
+                DataflowEvent::LogOutput {
+                    machine_id,
+                    subscription,
+                    output,
+                    finished,
+                } => {
+                    if let Some(s) = log_subscriptions.get_mut(&subscription) {
+                        s.push(&machine_id, output, finished);
+                    }
+                    close_log_subscriptions(
+                        &mut log_subscriptions,
+                        &mut daemon_connections,
+                        &clock,
+                    )
+                    .await;
+                }
+                DataflowEvent::DataflowFinishedOnMachine { machine_id, result } => {
+                    dataflow_finished_on_machine(
+                        uuid,
+                        machine_id,
+                        result,
+                        &mut running_dataflows,
+                        &mut archived_dataflows,
+                        &mut dataflow_results,
+                    );
+                    state_changed = true;
                 }
             },
 
@@ -344,12 +560,19 @@ async fn start_inner(
                             let reply = inner.await.map(|dataflow| {
                                 let uuid = dataflow.uuid;
                                 running_dataflows.insert(uuid, dataflow);
+                                state_changed = true;
                                 ControlRequestReply::DataflowStarted { uuid }
                             });
                             let _ = reply_sender.send(reply);
                         }
                         ControlRequest::Check { dataflow_uuid } => {
                             let status = match &running_dataflows.get(&dataflow_uuid) {
+                                Some(dataflow) if !dataflow.lost_machines.is_empty() => {
+                                    ControlRequestReply::DataflowDegraded {
+                                        uuid: dataflow_uuid,
+                                        lost_machines: dataflow.lost_machines.clone(),
+                                    }
+                                }
                                 Some(_) => ControlRequestReply::DataflowStarted {
                                     uuid: dataflow_uuid,
                                 },
@@ -427,7 +650,13 @@ async fn start_inner(
                             let dataflow_uuid = if let Some(uuid) = uuid {
                                 uuid
                             } else if let Some(name) = name {
-                                resolve_name(name, &running_dataflows, &archived_dataflows)?
+                                match resolve_name(name, &running_dataflows, &archived_dataflows) {
+                                    Ok(uuid) => uuid,
+                                    Err(err) => {
+                                        let _ = reply_sender.send(Err(err));
+                                        continue;
+                                    }
+                                }
                             } else {
                                 bail!("No uuid")
                             };
@@ -444,6 +673,132 @@ async fn start_inner(
                             .map(ControlRequestReply::Logs);
                             let _ = reply_sender.send(reply);
                         }
+                        ControlRequest::ReadLogs {
+                            uuid,
+                            name,
+                            node,
+                            offsets,
+                            start,
+                            filter,
+                        } => {
+                            let reply = match resolve_uuid_or_name(
+                                uuid,
+                                name,
+                                &running_dataflows,
+                                &archived_dataflows,
+                            ) {
+                                Ok(uuid) => {
+                                    read_logs(
+                                        &running_dataflows,
+                                        &archived_dataflows,
+                                        uuid,
+                                        node,
+                                        offsets,
+                                        start,
+                                        filter,
+                                        &mut daemon_connections,
+                                        clock.new_timestamp(),
+                                    )
+                                    .await
+                                }
+                                Err(err) => Err(err),
+                            };
+                            let _ = reply_sender.send(reply);
+                        }
+                        ControlRequest::FollowLogs {
+                            uuid,
+                            name,
+                            node,
+                            start,
+                            filter,
+                        } => {
+                            let reply = match resolve_uuid_or_name(
+                                uuid,
+                                name,
+                                &running_dataflows,
+                                &archived_dataflows,
+                            ) {
+                                Ok(uuid) => follow_logs(
+                                    &running_dataflows,
+                                    &archived_dataflows,
+                                    uuid,
+                                    node,
+                                    start,
+                                    filter,
+                                    &mut daemon_connections,
+                                    &clock,
+                                )
+                                .await
+                                .map(
+                                    |(subscription_id, subscription)| {
+                                        log_subscriptions.insert(subscription_id, subscription);
+                                        ControlRequestReply::LogSubscription {
+                                            uuid,
+                                            subscription: subscription_id,
+                                        }
+                                    },
+                                ),
+                                Err(err) => Err(err),
+                            };
+                            let _ = reply_sender.send(reply);
+                        }
+                        ControlRequest::NextLogs { subscription } => {
+                            match log_subscriptions.get_mut(&subscription) {
+                                Some(s) => s.next(reply_sender),
+                                None => {
+                                    let _ = reply_sender.send(Err(eyre!(
+                                        "no log subscription `{subscription}`, it might have \
+                                        timed out"
+                                    )));
+                                }
+                            }
+                            close_log_subscriptions(
+                                &mut log_subscriptions,
+                                &mut daemon_connections,
+                                &clock,
+                            )
+                            .await;
+                        }
+                        ControlRequest::Inspect { uuid, name } => {
+                            let reply = match resolve_uuid_or_name(
+                                uuid,
+                                name,
+                                &running_dataflows,
+                                &archived_dataflows,
+                            ) {
+                                Ok(uuid) => {
+                                    inspect_dataflow(
+                                        &running_dataflows,
+                                        uuid,
+                                        &mut daemon_connections,
+                                        clock.new_timestamp(),
+                                    )
+                                    .await
+                                }
+                                Err(err) => Err(err),
+                            };
+                            let _ = reply_sender.send(reply);
+                        }
+                        ControlRequest::Stats { uuid, name } => {
+                            let reply = match resolve_uuid_or_name(
+                                uuid,
+                                name,
+                                &running_dataflows,
+                                &archived_dataflows,
+                            ) {
+                                Ok(uuid) => {
+                                    dataflow_stats(
+                                        &running_dataflows,
+                                        uuid,
+                                        &mut daemon_connections,
+                                        clock.new_timestamp(),
+                                    )
+                                    .await
+                                }
+                                Err(err) => Err(err),
+                            };
+                            let _ = reply_sender.send(reply);
+                        }
                         ControlRequest::Destroy => {
                             tracing::info!("Received destroy command");
 
@@ -452,6 +807,7 @@ async fn start_inner(
                                 &mut daemon_connections,
                                 &abort_handle,
                                 &mut daemon_events_tx,
+                                &mut state_store,
                                 &clock,
                             )
                             .await
@@ -491,13 +847,13 @@ async fn start_inner(
             Event::DaemonHeartbeatInterval => {
                 let mut disconnected = BTreeSet::new();
                 for (machine_id, connection) in &mut daemon_connections {
-                    if connection.last_heartbeat.elapsed() > Duration::from_secs(15) {
+                    if connection.last_heartbeat.elapsed() > machine_failure.warn_timeout {
                         tracing::warn!(
                             "no heartbeat message from machine `{machine_id}` since {:?}",
                             connection.last_heartbeat.elapsed()
                         )
                     }
-                    if connection.last_heartbeat.elapsed() > Duration::from_secs(30) {
+                    if connection.last_heartbeat.elapsed() > machine_failure.lost_timeout {
                         disconnected.insert(machine_id.clone());
                         continue;
                     }
@@ -518,8 +874,64 @@ async fn start_inner(
                 }
                 if !disconnected.is_empty() {
                     tracing::error!("Disconnecting daemons that failed watchdog: {disconnected:?}");
-                    for machine_id in disconnected {
-                        daemon_connections.remove(&machine_id);
+                    for machine_id in &disconnected {
+                        daemon_connections.remove(machine_id);
+                    }
+                    handle_lost_machines(
+                        &disconnected,
+                        machine_failure.policy,
+                        &mut running_dataflows,
+                        &mut archived_dataflows,
+                        &mut dataflow_results,
+                        &mut daemon_connections,
+                        &clock,
+                    )
+                    .await;
+                    state_changed = true;
+                }
+
+                for subscription in log_subscriptions.values_mut() {
+                    let lost: Vec<_> = subscription
+                        .machines()
+                        .iter()
+                        .filter(|m| !daemon_connections.contains_key(*m))
+                        .cloned()
+                        .collect();
+                    for machine_id in lost {
+                        subscription.machine_lost(&machine_id);
+                    }
+                }
+                close_log_subscriptions(&mut log_subscriptions, &mut daemon_connections, &clock)
+                    .await;
+
+                if recovery_deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    recovery_deadline = None;
+                    let missing: Vec<_> = running_dataflows
+                        .iter()
+                        .flat_map(|(uuid, dataflow)| {
+                            dataflow
+                                .unconfirmed_machines
+                                .iter()
+                                .map(|m| (*uuid, m.clone()))
+                        })
+                        .collect();
+                    for (uuid, machine_id) in missing {
+                        tracing::warn!(
+                            "machine `{machine_id}` did not register again after coordinator \
+                            restart -> marking dataflow `{uuid}` as finished on it"
+                        );
+                        let err = eyre!(
+                            "machine `{machine_id}` did not register again after coordinator restart"
+                        );
+                        dataflow_finished_on_machine(
+                            uuid,
+                            machine_id,
+                            Err(err),
+                            &mut running_dataflows,
+                            &mut archived_dataflows,
+                            &mut dataflow_results,
+                        );
+                        state_changed = true;
                     }
                 }
             }
@@ -530,6 +942,7 @@ async fn start_inner(
                     &mut daemon_connections,
                     &abort_handle,
                     &mut daemon_events_tx,
+                    &mut state_store,
                     &clock,
                 )
                 .await?;
@@ -540,6 +953,23 @@ async fn start_inner(
                 }
             }
         }
+
+        if state_changed {
+            if let Some(store) = &state_store {
+                store.save(PersistedState::new(
+                    &running_dataflows,
+                    &archived_dataflows,
+                    &dataflow_results,
+                ));
+            }
+            if let Some(status_changes) = &mut status_changes {
+                status_changes.update(&running_dataflows, &dataflow_results);
+            }
+        }
+    }
+
+    if let Some(store) = state_store {
+        store.close().await;
     }
 
     tracing::info!("stopped");
@@ -589,6 +1019,297 @@ async fn stop_dataflow_by_uuid(
     Ok(())
 }
 
+fn dataflow_finished_on_machine(
+    uuid: Uuid,
+    machine_id: String,
+    result: eyre::Result<()>,
+    running_dataflows: &mut HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &mut HashMap<Uuid, ArchivedDataflow>,
+    dataflow_results: &mut HashMap<Uuid, BTreeMap<String, Result<(), String>>>,
+) {
+    match running_dataflows.entry(uuid) {
+        std::collections::hash_map::Entry::Occupied(mut entry) => {
+            // Archive finished dataflow
+            if archived_dataflows.get(&uuid).is_none() {
+                archived_dataflows.insert(uuid, ArchivedDataflow::from(entry.get()));
+            }
+            entry.get_mut().machines.remove(&machine_id);
+            entry.get_mut().unconfirmed_machines.remove(&machine_id);
+            match &result {
+                Ok(()) => {
+                    tracing::info!(
+                        "dataflow `{uuid}` finished successfully on machine `{machine_id}`"
+                    );
+                }
+                Err(err) => {
+                    tracing::error!("{err:?}");
+                }
+            }
+            dataflow_results
+                .entry(uuid)
+                .or_default()
+                .insert(machine_id, result.map_err(|err| format!("{err:?}")));
+            if entry.get_mut().machines.is_empty() {
+                let finished_dataflow = entry.remove();
+                let reply = ControlRequestReply::DataflowStopped {
+                    uuid,
+                    result: dataflow_results
+                        .get(&uuid)
+                        .map(|r| dataflow_result(r, uuid))
+                        .unwrap_or(Ok(())),
+                };
+                for sender in finished_dataflow.reply_senders {
+                    let _ = sender.send(Ok(reply.clone()));
+                }
+            }
+        }
+        std::collections::hash_map::Entry::Vacant(_) => {
+            tracing::warn!("dataflow not running on DataflowFinishedOnMachine");
+        }
+    }
+    limit_archived_dataflows(running_dataflows, archived_dataflows, dataflow_results);
+}
+
+/// Forgets the oldest finished dataflows once more than
+/// [`MAX_ARCHIVED_DATAFLOWS`] are archived.
+///
+/// Dataflow IDs are UUIDv7, so they are ordered by their start time.
+fn limit_archived_dataflows(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &mut HashMap<Uuid, ArchivedDataflow>,
+    dataflow_results: &mut HashMap<Uuid, BTreeMap<String, Result<(), String>>>,
+) {
+    let excess = archived_dataflows
+        .len()
+        .saturating_sub(MAX_ARCHIVED_DATAFLOWS);
+    if excess == 0 {
+        return;
+    }
+    // dataflows that still run on some machines are archived already
+    let mut finished: Vec<_> = archived_dataflows
+        .keys()
+        .filter(|uuid| !running_dataflows.contains_key(uuid))
+        .copied()
+        .collect();
+    finished.sort_unstable();
+    for uuid in finished.into_iter().take(excess) {
+        archived_dataflows.remove(&uuid);
+        dataflow_results.remove(&uuid);
+    }
+}
+
+/// Compares the dataflows that a (re-)registering daemon reports as running
+/// with the dataflows that the coordinator expects on that machine.
+///
+/// Dataflows that the daemon no longer runs are marked as finished on the
+/// machine, e.g. because they stopped while the coordinator was down.
+fn reconcile_machine(
+    machine_id: &str,
+    reported_dataflows: &[Uuid],
+    running_dataflows: &mut HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &mut HashMap<Uuid, ArchivedDataflow>,
+    dataflow_results: &mut HashMap<Uuid, BTreeMap<String, Result<(), String>>>,
+) {
+    let mut stopped = Vec::new();
+    for (uuid, dataflow) in running_dataflows.iter_mut() {
+        if dataflow.lost_machines.contains(machine_id) && reported_dataflows.contains(uuid) {
+            // the machine is back, e.g. after a network partition
+            dataflow.lost_machines.remove(machine_id);
+            dataflow.machines.insert(machine_id.to_owned());
+            if let Some(results) = dataflow_results.get_mut(uuid) {
+                results.remove(machine_id);
+            }
+            tracing::info!("lost machine `{machine_id}` rejoined dataflow `{uuid}`");
+            continue;
+        }
+        if !dataflow.machines.contains(machine_id) {
+            continue;
+        }
+        let unconfirmed = dataflow.unconfirmed_machines.remove(machine_id);
+        if reported_dataflows.contains(uuid) {
+            if unconfirmed {
+                tracing::info!("re-adopted dataflow `{uuid}` on machine `{machine_id}`");
+            }
+        } else {
+            stopped.push(*uuid);
+        }
+    }
+    for uuid in reported_dataflows {
+        if !running_dataflows.contains_key(uuid) {
+            tracing::warn!("machine `{machine_id}` reports unknown dataflow `{uuid}` as running");
+        }
+    }
+
+    for uuid in stopped {
+        let err = eyre!("dataflow `{uuid}` is no longer running on machine `{machine_id}`");
+        dataflow_finished_on_machine(
+            uuid,
+            machine_id.to_owned(),
+            Err(err),
+            running_dataflows,
+            archived_dataflows,
+            dataflow_results,
+        );
+    }
+}
+
+/// Marks the dataflows of the given machines as degraded and applies the
+/// configured [`MachineFailurePolicy`] to them.
+async fn handle_lost_machines(
+    lost_machines: &BTreeSet<String>,
+    policy: MachineFailurePolicy,
+    running_dataflows: &mut HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &mut HashMap<Uuid, ArchivedDataflow>,
+    dataflow_results: &mut HashMap<Uuid, BTreeMap<String, Result<(), String>>>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) {
+    let affected: Vec<Uuid> = running_dataflows
+        .iter()
+        .filter(|(_, dataflow)| !dataflow.machines.is_disjoint(lost_machines))
+        .map(|(uuid, _)| *uuid)
+        .collect();
+    for uuid in affected {
+        let Some(dataflow) = running_dataflows.get_mut(&uuid) else {
+            continue;
+        };
+        let lost: Vec<String> = dataflow
+            .machines
+            .intersection(lost_machines)
+            .cloned()
+            .collect();
+        dataflow.lost_machines.extend(lost.iter().cloned());
+        let lost_nodes: BTreeSet<NodeId> = dataflow
+            .nodes
+            .iter()
+            .filter(|node| lost_machines.contains(&node.deploy.machine))
+            .map(|node| node.id.clone())
+            .collect();
+
+        // the lost machines will never report a result
+        for machine_id in lost {
+            tracing::warn!("dataflow `{uuid}` is degraded: lost machine `{machine_id}`");
+            let err = eyre!("lost connection to machine `{machine_id}`");
+            dataflow_finished_on_machine(
+                uuid,
+                machine_id,
+                Err(err),
+                running_dataflows,
+                archived_dataflows,
+                dataflow_results,
+            );
+        }
+
+        // the dataflow is finished if it ran on lost machines only
+        let Some(dataflow) = running_dataflows.get(&uuid) else {
+            continue;
+        };
+        let result = match policy {
+            MachineFailurePolicy::Degrade => Ok(()),
+            MachineFailurePolicy::StopDataflow => {
+                stop_dataflow(
+                    dataflow,
+                    uuid,
+                    daemon_connections,
+                    clock.new_timestamp(),
+                    None,
+                )
+                .await
+            }
+            MachineFailurePolicy::CloseInputs => {
+                send_nodes_lost(
+                    dataflow,
+                    uuid,
+                    lost_nodes,
+                    daemon_connections,
+                    clock.new_timestamp(),
+                )
+                .await
+            }
+        };
+        if let Err(err) = result {
+            tracing::warn!(
+                "{:?}",
+                err.wrap_err(format!(
+                    "failed to apply machine failure policy to dataflow `{uuid}`"
+                ))
+            );
+        }
+    }
+}
+
+async fn send_nodes_lost(
+    dataflow: &RunningDataflow,
+    uuid: Uuid,
+    nodes: BTreeSet<NodeId>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<()> {
+    let message = serde_json::to_vec(&Timestamped {
+        inner: DaemonCoordinatorEvent::NodesLost {
+            dataflow_id: uuid,
+            nodes,
+        },
+        timestamp,
+    })?;
+
+    for machine_id in &dataflow.machines {
+        let daemon_connection = daemon_connections
+            .get_mut(machine_id)
+            .wrap_err("no daemon connection")?;
+        tcp_send(&mut daemon_connection.stream, &message)
+            .await
+            .wrap_err("failed to send NodesLost message to daemon")?;
+    }
+    Ok(())
+}
+
+/// Sends the given event to all machines of the dataflow except the given one,
+/// e.g. to notify machines that might run dependent nodes.
+async fn notify_other_machines(
+    dataflow: &RunningDataflow,
+    machine_id: &str,
+    event: DaemonCoordinatorEvent,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) -> eyre::Result<()> {
+    let message = serde_json::to_vec(&Timestamped {
+        inner: event,
+        timestamp: clock.new_timestamp(),
+    })
+    .wrap_err("failed to serialize message")?;
+
+    for other_machine in dataflow.machines.iter().filter(|m| *m != machine_id) {
+        let Some(connection) = daemon_connections.get_mut(other_machine) else {
+            tracing::warn!("no daemon connection found for machine `{other_machine}`");
+            continue;
+        };
+        tcp_send(&mut connection.stream, &message)
+            .await
+            .wrap_err_with(|| format!("failed to send message to machine {other_machine}"))?;
+    }
+    Ok(())
+}
+
+async fn send_to_machine(
+    machine_id: &str,
+    event: DaemonCoordinatorEvent,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) -> eyre::Result<()> {
+    let message = serde_json::to_vec(&Timestamped {
+        inner: event,
+        timestamp: clock.new_timestamp(),
+    })
+    .wrap_err("failed to serialize message")?;
+    let connection = daemon_connections
+        .get_mut(machine_id)
+        .wrap_err_with(|| format!("no daemon connection found for machine `{machine_id}`"))?;
+    tcp_send(&mut connection.stream, &message)
+        .await
+        .wrap_err_with(|| format!("failed to send message to machine {machine_id}"))
+}
+
 fn format_error(machine: &str, err: &str) -> String {
     let mut error = err
         .lines()
@@ -623,7 +1344,7 @@ fn dataflow_result(
 }
 
 struct DaemonConnection {
-    stream: TcpStream,
+    stream: MaybeTlsStream,
     listen_socket: SocketAddr,
     last_heartbeat: Instant,
 }
@@ -633,6 +1354,7 @@ async fn handle_destroy(
     daemon_connections: &mut HashMap<String, DaemonConnection>,
     abortable_events: &futures::stream::AbortHandle,
     daemon_events_tx: &mut Option<mpsc::Sender<Event>>,
+    state_store: &mut Option<StateStore>,
     clock: &HLC,
 ) -> Result<(), eyre::ErrReport> {
     abortable_events.abort();
@@ -648,11 +1370,15 @@ async fn handle_destroy(
     }
     destroy_daemons(daemon_connections, clock.new_timestamp()).await?;
     *daemon_events_tx = None;
+    // all dataflows were stopped, so there is nothing to recover on restart
+    if let Some(store) = state_store.take() {
+        store.clear().await;
+    }
     Ok(())
 }
 
 async fn send_heartbeat_message(
-    connection: &mut TcpStream,
+    connection: &mut MaybeTlsStream,
     timestamp: uhlc::Timestamp,
 ) -> eyre::Result<()> {
     let message = serde_json::to_vec(&Timestamped {
@@ -675,10 +1401,30 @@ struct RunningDataflow {
     pending_machines: BTreeSet<String>,
     init_success: bool,
     nodes: Vec<ResolvedNode>,
+    /// Machines that did not register again since the coordinator restarted.
+    unconfirmed_machines: BTreeSet<String>,
+    /// Machines that stopped sending heartbeats while running the dataflow.
+    lost_machines: BTreeSet<String>,
 
     reply_senders: Vec<tokio::sync::oneshot::Sender<eyre::Result<ControlRequestReply>>>,
 }
 
+impl RunningDataflow {
+    fn restore(uuid: Uuid, dataflow: state::PersistedDataflow) -> Self {
+        Self {
+            name: dataflow.name,
+            uuid,
+            unconfirmed_machines: dataflow.machines.clone(),
+            machines: dataflow.machines,
+            pending_machines: dataflow.pending_machines,
+            init_success: dataflow.init_success,
+            nodes: dataflow.nodes,
+            lost_machines: dataflow.lost_machines,
+            reply_senders: Vec::new(),
+        }
+    }
+}
+
 struct ArchivedDataflow {
     name: Option<String>,
     nodes: Vec<ResolvedNode>,
@@ -852,6 +1598,299 @@ async fn retrieve_logs(
     reply_logs.map_err(|err| eyre!(err))
 }
 
+#[allow(clippy::too_many_arguments)]
+async fn read_logs(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &HashMap<Uuid, ArchivedDataflow>,
+    dataflow_id: Uuid,
+    node: Option<NodeId>,
+    offsets: BTreeMap<NodeId, u64>,
+    start: LogPosition,
+    filter: LogFilter,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<ControlRequestReply> {
+    let running = running_dataflows.get(&dataflow_id);
+    let nodes = dataflow_nodes(running_dataflows, archived_dataflows, dataflow_id, &node)?;
+
+    let machines = log_positions(nodes, node.as_ref(), &offsets, start);
+
+    let mut chunks = Vec::new();
+    for (machine_id, nodes) in machines {
+        let replies = query_machines(
+            &BTreeSet::from([machine_id.clone()]),
+            DaemonCoordinatorEvent::ReadLogs {
+                dataflow_id,
+                nodes,
+                filter: filter.clone(),
+            },
+            daemon_connections,
+            timestamp,
+        )
+        .await?;
+        for (machine_id, reply) in replies {
+            match reply {
+                DaemonCoordinatorReply::LogChunks(result) => {
+                    chunks.extend(result.map_err(|err| eyre!(err)).wrap_err_with(|| {
+                        format!("failed to read logs on machine `{machine_id}`")
+                    })?)
+                }
+                other => bail!("unexpected reply after sending read logs: {other:?}"),
+            }
+        }
+    }
+
+    Ok(ControlRequestReply::LogChunks {
+        uuid: dataflow_id,
+        chunks,
+        running: running.is_some(),
+    })
+}
+
+/// Starts pushing the log output of the given node, or of all nodes if `node`
+/// is `None`, from the daemons to the coordinator.
+#[allow(clippy::too_many_arguments)]
+async fn follow_logs(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &HashMap<Uuid, ArchivedDataflow>,
+    dataflow_id: Uuid,
+    node: Option<NodeId>,
+    start: LogPosition,
+    filter: LogFilter,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) -> eyre::Result<(Uuid, LogSubscription)> {
+    let nodes = dataflow_nodes(running_dataflows, archived_dataflows, dataflow_id, &node)?;
+    let machines = log_positions(nodes, node.as_ref(), &BTreeMap::new(), start);
+
+    let subscription_id = Uuid::new_v7(Timestamp::now(NoContext));
+    let subscription = LogSubscription::new(dataflow_id, machines.keys().cloned().collect());
+    for (machine_id, nodes) in machines {
+        let event = DaemonCoordinatorEvent::FollowLogs {
+            dataflow_id,
+            subscription: subscription_id,
+            nodes,
+            filter: filter.clone(),
+        };
+        send_to_machine(&machine_id, event, daemon_connections, clock)
+            .await
+            .wrap_err("failed to follow logs")?;
+    }
+    Ok((subscription_id, subscription))
+}
+
+/// Removes log subscriptions that ended or that are no longer read and lets
+/// their machines stop pushing output.
+async fn close_log_subscriptions(
+    log_subscriptions: &mut HashMap<Uuid, LogSubscription>,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    clock: &HLC,
+) {
+    let closed: Vec<_> = log_subscriptions
+        .iter()
+        .filter(|(_, s)| s.is_closed() || s.is_abandoned())
+        .map(|(id, _)| *id)
+        .collect();
+    for subscription_id in closed {
+        let Some(subscription) = log_subscriptions.remove(&subscription_id) else {
+            continue;
+        };
+        for machine_id in subscription.machines() {
+            let event = DaemonCoordinatorEvent::UnfollowLogs {
+                subscription: subscription_id,
+            };
+            if let Err(err) = send_to_machine(machine_id, event, daemon_connections, clock).await {
+                tracing::warn!("failed to stop log subscription: {err:?}");
+            }
+        }
+    }
+}
+
+/// Returns the nodes of the given running or archived dataflow, after
+/// checking that it contains `node`.
+fn dataflow_nodes<'a>(
+    running_dataflows: &'a HashMap<Uuid, RunningDataflow>,
+    archived_dataflows: &'a HashMap<Uuid, ArchivedDataflow>,
+    dataflow_id: Uuid,
+    node: &Option<NodeId>,
+) -> eyre::Result<&'a Vec<ResolvedNode>> {
+    let nodes = match (
+        running_dataflows.get(&dataflow_id),
+        archived_dataflows.get(&dataflow_id),
+    ) {
+        (Some(dataflow), _) => &dataflow.nodes,
+        (None, Some(dataflow)) => &dataflow.nodes,
+        (None, None) => bail!("No dataflow found with UUID `{dataflow_id}`"),
+    };
+    if let Some(node) = node {
+        if !nodes.iter().any(|n| &n.id == node) {
+            bail!("dataflow `{dataflow_id}` has no node `{node}`");
+        }
+    }
+    Ok(nodes)
+}
+
+/// Groups the log read positions of the given node, or of all nodes if `node`
+/// is `None`, by machine.
+///
+/// Nodes in `offsets` continue at their offset, all others start at `start`.
+fn log_positions(
+    nodes: &[ResolvedNode],
+    node: Option<&NodeId>,
+    offsets: &BTreeMap<NodeId, u64>,
+    start: LogPosition,
+) -> BTreeMap<String, BTreeMap<NodeId, LogPosition>> {
+    let mut machines: BTreeMap<String, BTreeMap<NodeId, LogPosition>> = BTreeMap::new();
+    for n in nodes.iter().filter(|n| node.map_or(true, |id| &n.id == id)) {
+        let position = offsets
+            .get(&n.id)
+            .map(|offset| LogPosition::Offset(*offset))
+            .unwrap_or(start);
+        machines
+            .entry(n.deploy.machine.clone())
+            .or_default()
+            .insert(n.id.clone(), position);
+    }
+    machines
+}
+
+async fn inspect_dataflow(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    dataflow_id: Uuid,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<ControlRequestReply> {
+    let Some(dataflow) = running_dataflows.get(&dataflow_id) else {
+        bail!("no running dataflow with UUID `{dataflow_id}`")
+    };
+
+    let replies = query_machines(
+        &dataflow.machines,
+        DaemonCoordinatorEvent::Inspect { dataflow_id },
+        daemon_connections,
+        timestamp,
+    )
+    .await?;
+    let mut reported = BTreeMap::new();
+    for (machine_id, reply) in replies {
+        let result = match reply {
+            DaemonCoordinatorReply::InspectResult(result) => result,
+            other => Err(format!("unexpected reply after sending inspect: {other:?}")),
+        };
+        match result {
+            Ok(nodes) => reported.extend(nodes.into_iter().map(|node| (node.id.clone(), node))),
+            Err(err) => {
+                tracing::warn!("failed to inspect dataflow on machine `{machine_id}`: {err}")
+            }
+        }
+    }
+
+    let nodes = dataflow
+        .nodes
+        .iter()
+        .map(|node| {
+            reported.remove(&node.id).unwrap_or_else(|| NodeInfo {
+                id: node.id.clone(),
+                machine: node.deploy.machine.clone(),
+                state: NodeState::Unknown,
+                pid: None,
+                uptime: None,
+                restarts: 0,
+                open_inputs: BTreeSet::new(),
+                open_outputs: BTreeSet::new(),
+            })
+        })
+        .collect();
+
+    Ok(ControlRequestReply::DataflowInfo {
+        uuid: dataflow_id,
+        name: dataflow.name.clone(),
+        nodes,
+    })
+}
+
+async fn dataflow_stats(
+    running_dataflows: &HashMap<Uuid, RunningDataflow>,
+    dataflow_id: Uuid,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<ControlRequestReply> {
+    let Some(dataflow) = running_dataflows.get(&dataflow_id) else {
+        bail!("no running dataflow with UUID `{dataflow_id}`")
+    };
+
+    let replies = query_machines(
+        &dataflow.machines,
+        DaemonCoordinatorEvent::Stats { dataflow_id },
+        daemon_connections,
+        timestamp,
+    )
+    .await?;
+    let mut stats = DataflowStats::default();
+    for (machine_id, reply) in replies {
+        let result = match reply {
+            DaemonCoordinatorReply::StatsResult(result) => result,
+            other => Err(format!("unexpected reply after sending stats: {other:?}")),
+        };
+        match result {
+            Ok(machine_stats) => {
+                stats.outputs.extend(machine_stats.outputs);
+                stats.inputs.extend(machine_stats.inputs);
+            }
+            Err(err) => {
+                tracing::warn!("failed to get dataflow stats from machine `{machine_id}`: {err}")
+            }
+        }
+    }
+
+    Ok(ControlRequestReply::DataflowStats {
+        uuid: dataflow_id,
+        name: dataflow.name.clone(),
+        stats,
+    })
+}
+
+/// Sends the given event to the daemons of all given machines and collects
+/// their replies.
+///
+/// Machines that are not connected or fail to reply are skipped with a
+/// warning.
+async fn query_machines(
+    machines: &BTreeSet<String>,
+    event: DaemonCoordinatorEvent,
+    daemon_connections: &mut HashMap<String, DaemonConnection>,
+    timestamp: uhlc::Timestamp,
+) -> eyre::Result<Vec<(String, DaemonCoordinatorReply)>> {
+    let message = serde_json::to_vec(&Timestamped {
+        inner: event,
+        timestamp,
+    })?;
+
+    let mut replies = Vec::new();
+    for machine_id in machines {
+        let Some(daemon_connection) = daemon_connections.get_mut(machine_id) else {
+            continue;
+        };
+        let reply = async {
+            tcp_send(&mut daemon_connection.stream, &message)
+                .await
+                .wrap_err("failed to send message to daemon")?;
+            let reply_raw = tcp_receive(&mut daemon_connection.stream)
+                .await
+                .wrap_err("failed to receive reply from daemon")?;
+            serde_json::from_slice(&reply_raw).wrap_err("failed to deserialize reply from daemon")
+        };
+        match reply.await {
+            Ok(reply) => replies.push((machine_id.clone(), reply)),
+            Err(err) => tracing::warn!(
+                "{:?}",
+                err.wrap_err(format!("failed to query machine `{machine_id}`"))
+            ),
+        }
+    }
+    Ok(replies)
+}
+
 async fn start_dataflow(
     dataflow: Descriptor,
     working_dir: PathBuf,
@@ -875,6 +1914,8 @@ async fn start_dataflow(
         init_success: true,
         machines,
         nodes,
+        unconfirmed_machines: BTreeSet::new(),
+        lost_machines: BTreeSet::new(),
         reply_senders: Vec::new(),
     })
 }
@@ -945,6 +1986,20 @@ pub enum DataflowEvent {
         machine_id: String,
         success: bool,
     },
+    NodeReadyOnMachine {
+        machine_id: String,
+        node_id: NodeId,
+    },
+    NodeFailedOnMachine {
+        machine_id: String,
+        node_id: NodeId,
+    },
+    LogOutput {
+        machine_id: String,
+        subscription: Uuid,
+        output: Result<Vec<LogChunk>, String>,
+        finished: bool,
+    },
 }
 
 #[derive(Debug)]
@@ -952,8 +2007,9 @@ pub enum DaemonEvent {
     Register {
         dora_version: String,
         machine_id: String,
-        connection: TcpStream,
+        connection: MaybeTlsStream,
         listen_port: u16,
+        running_dataflows: Vec<Uuid>,
     },
 }
 
@@ -995,4 +2051,115 @@ mod test {
         let new_error = super::format_error(machine, err);
         assert_eq!(old_error, new_error)
     }
+
+    #[test]
+    fn validate_machine_failure_config() {
+        use super::*;
+
+        assert!(MachineFailureConfig::default().validate().is_ok());
+        let config = |warn, lost| MachineFailureConfig {
+            warn_timeout: Duration::from_secs(warn),
+            lost_timeout: Duration::from_secs(lost),
+            policy: MachineFailurePolicy::Degrade,
+        };
+        assert!(config(30, 30).validate().is_err());
+        assert!(config(60, 30).validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn lost_machine_degrades_dataflow() {
+        use super::*;
+
+        let uuid = Uuid::nil();
+        let mut running_dataflows = HashMap::from([(
+            uuid,
+            RunningDataflow {
+                name: None,
+                uuid,
+                machines: ["A".to_owned(), "B".to_owned()].into(),
+                pending_machines: BTreeSet::new(),
+                init_success: true,
+                nodes: Vec::new(),
+                unconfirmed_machines: BTreeSet::new(),
+                lost_machines: BTreeSet::new(),
+                reply_senders: Vec::new(),
+            },
+        )]);
+        let mut archived_dataflows = HashMap::new();
+        let mut dataflow_results = HashMap::new();
+
+        handle_lost_machines(
+            &["B".to_owned()].into(),
+            MachineFailurePolicy::Degrade,
+            &mut running_dataflows,
+            &mut archived_dataflows,
+            &mut dataflow_results,
+            &mut HashMap::new(),
+            &HLC::default(),
+        )
+        .await;
+        let dataflow = &running_dataflows[&uuid];
+        assert_eq!(dataflow.machines, ["A".to_owned()].into());
+        assert_eq!(dataflow.lost_machines, ["B".to_owned()].into());
+        assert!(dataflow_results[&uuid]["B"].is_err());
+
+        // machine B comes back and still runs the dataflow
+        reconcile_machine(
+            "B",
+            &[uuid],
+            &mut running_dataflows,
+            &mut archived_dataflows,
+            &mut dataflow_results,
+        );
+        let dataflow = &running_dataflows[&uuid];
+        assert_eq!(dataflow.machines.len(), 2);
+        assert!(dataflow.lost_machines.is_empty());
+        assert!(!dataflow_results[&uuid].contains_key("B"));
+    }
+
+    #[test]
+    fn limit_archived_dataflows() {
+        use super::*;
+
+        let archived = || ArchivedDataflow {
+            name: None,
+            nodes: Vec::new(),
+        };
+        let uuids: Vec<_> = (0..=MAX_ARCHIVED_DATAFLOWS as u128 + 1)
+            .map(Uuid::from_u128)
+            .collect();
+        let mut archived_dataflows: HashMap<_, _> =
+            uuids.iter().map(|&uuid| (uuid, archived())).collect();
+        let mut dataflow_results: HashMap<_, _> = uuids
+            .iter()
+            .map(|&uuid| (uuid, BTreeMap::from([("A".to_owned(), Ok(()))])))
+            .collect();
+        // the oldest dataflow still runs on another machine
+        let running_dataflows = HashMap::from([(
+            uuids[0],
+            RunningDataflow {
+                name: None,
+                uuid: uuids[0],
+                machines: ["B".to_owned()].into(),
+                pending_machines: BTreeSet::new(),
+                init_success: true,
+                nodes: Vec::new(),
+                unconfirmed_machines: BTreeSet::new(),
+                lost_machines: BTreeSet::new(),
+                reply_senders: Vec::new(),
+            },
+        )]);
+
+        super::limit_archived_dataflows(
+            &running_dataflows,
+            &mut archived_dataflows,
+            &mut dataflow_results,
+        );
+        assert_eq!(archived_dataflows.len(), MAX_ARCHIVED_DATAFLOWS);
+        assert!(archived_dataflows.contains_key(&uuids[0]));
+        assert!(!archived_dataflows.contains_key(&uuids[1]));
+        assert!(!archived_dataflows.contains_key(&uuids[2]));
+        assert!(!dataflow_results.contains_key(&uuids[1]));
+        assert!(dataflow_results.contains_key(&uuids[3]));
+    }
 }
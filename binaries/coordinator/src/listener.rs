@@ -1,4 +1,8 @@
-use crate::{tcp_utils::tcp_receive, DaemonEvent, DataflowEvent, Event};
+use crate::{
+    tcp_utils::{tcp_receive, tcp_send},
+    DaemonEvent, DataflowEvent, Event,
+};
+use communication_layer_request_reply::security::Security;
 use dora_core::{coordinator_messages, daemon_messages::Timestamped, message::uhlc::HLC};
 use eyre::{eyre, Context};
 use std::{io::ErrorKind, net::SocketAddr, sync::Arc};
@@ -18,14 +22,43 @@ pub async fn create_listener(bind: SocketAddr) -> eyre::Result<TcpListener> {
 }
 
 pub async fn handle_connection(
-    mut connection: TcpStream,
+    connection: TcpStream,
     events_tx: mpsc::Sender<Event>,
     clock: Arc<HLC>,
+    security: Security,
 ) {
+    let mut connection = match security.accept(connection).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::warn!("rejected daemon connection: {err}");
+            return;
+        }
+    };
+    // number of messages received on this connection, acknowledged to the
+    // daemon once they are handled
+    let mut received = 0;
     loop {
+        if received > 0 {
+            let ack = coordinator_messages::EventsAck { received };
+            let serialized = match serde_json::to_vec(&ack) {
+                Ok(serialized) => serialized,
+                Err(err) => {
+                    tracing::error!("failed to serialize EventsAck: {err}");
+                    break;
+                }
+            };
+            if let Err(err) = tcp_send(&mut connection, &serialized).await {
+                tracing::debug!("failed to acknowledge daemon events: {err}");
+                break;
+            }
+        }
+
         // receive the next message and parse it
         let raw = match tcp_receive(&mut connection).await {
-            Ok(data) => data,
+            Ok(data) => {
+                received += 1;
+                data
+            }
             Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
                 break;
             }
@@ -53,12 +86,14 @@ pub async fn handle_connection(
                 machine_id,
                 dora_version,
                 listen_port,
+                running_dataflows,
             } => {
                 let event = DaemonEvent::Register {
                     dora_version,
                     machine_id,
                     connection,
                     listen_port,
+                    running_dataflows,
                 };
                 let _ = events_tx.send(Event::Daemon(event)).await;
                 break;
@@ -79,6 +114,36 @@ pub async fn handle_connection(
                         break;
                     }
                 }
+                coordinator_messages::DaemonEvent::NodeReady {
+                    dataflow_id,
+                    node_id,
+                } => {
+                    let event = Event::Dataflow {
+                        uuid: dataflow_id,
+                        event: DataflowEvent::NodeReadyOnMachine {
+                            machine_id,
+                            node_id,
+                        },
+                    };
+                    if events_tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
+                coordinator_messages::DaemonEvent::NodeFailed {
+                    dataflow_id,
+                    node_id,
+                } => {
+                    let event = Event::Dataflow {
+                        uuid: dataflow_id,
+                        event: DataflowEvent::NodeFailedOnMachine {
+                            machine_id,
+                            node_id,
+                        },
+                    };
+                    if events_tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
                 coordinator_messages::DaemonEvent::AllNodesFinished {
                     dataflow_id,
                     result,
@@ -94,12 +159,45 @@ pub async fn handle_connection(
                         break;
                     }
                 }
+                coordinator_messages::DaemonEvent::LogOutput {
+                    dataflow_id,
+                    subscription,
+                    output,
+                    finished,
+                } => {
+                    let event = Event::Dataflow {
+                        uuid: dataflow_id,
+                        event: DataflowEvent::LogOutput {
+                            machine_id,
+                            subscription,
+                            output,
+                            finished,
+                        },
+                    };
+                    if events_tx.send(event).await.is_err() {
+                        break;
+                    }
+                }
                 coordinator_messages::DaemonEvent::Heartbeat => {
                     let event = Event::DaemonHeartbeat { machine_id };
                     if events_tx.send(event).await.is_err() {
                         break;
                     }
                 }
+                coordinator_messages::DaemonEvent::PeerDisconnected {
+                    peer_machine_id,
+                    error,
+                } => {
+                    tracing::warn!(
+                        "machine `{machine_id}` lost connection to machine \
+                        `{peer_machine_id}`: {error}"
+                    );
+                }
+                coordinator_messages::DaemonEvent::PeerReconnected { peer_machine_id } => {
+                    tracing::info!(
+                        "machine `{machine_id}` reconnected to machine `{peer_machine_id}`"
+                    );
+                }
             },
         };
     }
@@ -0,0 +1,197 @@
+use dora_core::topics::{ControlRequestReply, LogChunk};
+use eyre::eyre;
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Subscriptions are dropped if no new output is requested for this long.
+const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of buffered bytes per subscription.
+const MAX_BUFFERED: usize = 16 * 1024 * 1024;
+
+type ReplySender = oneshot::Sender<eyre::Result<ControlRequestReply>>;
+
+/// Log output that the daemons push for a `dora logs --follow` request.
+///
+/// The output is buffered until it is requested through a `NextLogs` control
+/// request. Requests that arrive before new output is available wait for it.
+pub struct LogSubscription {
+    dataflow_id: Uuid,
+    /// Machines that might push more output.
+    machines: BTreeSet<String>,
+    chunks: Vec<LogChunk>,
+    buffered: usize,
+    error: Option<String>,
+    /// Reply sender of a `NextLogs` request that waits for new output.
+    waiting: Option<ReplySender>,
+    last_request: Instant,
+    closed: bool,
+}
+
+impl LogSubscription {
+    pub fn new(dataflow_id: Uuid, machines: BTreeSet<String>) -> Self {
+        Self {
+            dataflow_id,
+            machines,
+            chunks: Vec::new(),
+            buffered: 0,
+            error: None,
+            waiting: None,
+            last_request: Instant::now(),
+            closed: false,
+        }
+    }
+
+    /// Machines that still push output.
+    pub fn machines(&self) -> &BTreeSet<String> {
+        &self.machines
+    }
+
+    /// Adds output that the given machine pushed.
+    pub fn push(
+        &mut self,
+        machine_id: &str,
+        output: Result<Vec<LogChunk>, String>,
+        finished: bool,
+    ) {
+        match output {
+            Ok(chunks) => {
+                self.buffered += chunks.iter().map(|c| c.data.len()).sum::<usize>();
+                self.chunks.extend(chunks);
+                if self.buffered > MAX_BUFFERED {
+                    self.error = Some("log output is written faster than it is read".into());
+                }
+            }
+            Err(err) => {
+                self.error = Some(format!(
+                    "failed to read logs on machine `{machine_id}`: {err}"
+                ))
+            }
+        }
+        if finished {
+            self.machines.remove(machine_id);
+        }
+        self.reply_if_ready();
+    }
+
+    /// The given machine disconnected, so no more output will follow from it.
+    pub fn machine_lost(&mut self, machine_id: &str) {
+        if self.machines.remove(machine_id) {
+            self.reply_if_ready();
+        }
+    }
+
+    /// Answers the given request once new output is available.
+    pub fn next(&mut self, reply_sender: ReplySender) {
+        self.waiting = Some(reply_sender);
+        self.last_request = Instant::now();
+        self.reply_if_ready();
+    }
+
+    /// Whether the subscription ended, either because all output was sent or
+    /// because of an error.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Whether the client stopped requesting new output.
+    pub fn is_abandoned(&self) -> bool {
+        self.waiting.is_none() && self.last_request.elapsed() > SUBSCRIPTION_TIMEOUT
+    }
+
+    fn reply_if_ready(&mut self) {
+        if self.closed {
+            return;
+        }
+        let finished = self.machines.is_empty();
+        if self.error.is_none() && self.chunks.is_empty() && !finished {
+            return;
+        }
+        let Some(reply_sender) = self.waiting.take() else {
+            return;
+        };
+        let reply = match self.error.take() {
+            Some(err) => {
+                self.closed = true;
+                Err(eyre!(err))
+            }
+            None => {
+                self.closed = finished;
+                self.buffered = 0;
+                Ok(ControlRequestReply::LogChunks {
+                    uuid: self.dataflow_id,
+                    chunks: std::mem::take(&mut self.chunks),
+                    running: !finished,
+                })
+            }
+        };
+        self.last_request = Instant::now();
+        let _ = reply_sender.send(reply);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(data: &str) -> LogChunk {
+        LogChunk {
+            node: "node".to_owned().into(),
+            data: data.into(),
+            offset: 0,
+        }
+    }
+
+    fn reply(rx: &mut oneshot::Receiver<eyre::Result<ControlRequestReply>>) -> (usize, bool) {
+        match rx.try_recv() {
+            Ok(Ok(ControlRequestReply::LogChunks {
+                chunks, running, ..
+            })) => (chunks.len(), running),
+            other => panic!("unexpected reply: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn requests_wait_for_pushed_output() {
+        let machines = BTreeSet::from(["A".to_owned(), "B".to_owned()]);
+        let mut subscription = LogSubscription::new(Uuid::new_v4(), machines);
+
+        let (tx, mut rx) = oneshot::channel();
+        subscription.next(tx);
+        assert!(rx.try_recv().is_err());
+        subscription.push("A", Ok(vec![chunk("a\n")]), false);
+        assert_eq!(reply(&mut rx), (1, true));
+
+        // output is buffered until the next request
+        subscription.push("A", Ok(vec![chunk("b\n")]), true);
+        subscription.push("B", Ok(vec![chunk("c\n")]), false);
+        let (tx, mut rx) = oneshot::channel();
+        subscription.next(tx);
+        assert_eq!(reply(&mut rx), (2, true));
+
+        let (tx, mut rx) = oneshot::channel();
+        subscription.next(tx);
+        subscription.machine_lost("B");
+        assert_eq!(reply(&mut rx), (0, false));
+        assert!(subscription.is_closed());
+    }
+
+    #[test]
+    fn errors_close_the_subscription() {
+        let machines = BTreeSet::from(["A".to_owned(), "B".to_owned()]);
+        let mut subscription = LogSubscription::new(Uuid::new_v4(), machines);
+
+        subscription.push("A", Err("no such file".into()), true);
+        assert!(!subscription.is_closed());
+        let (tx, mut rx) = oneshot::channel();
+        subscription.next(tx);
+        assert!(matches!(rx.try_recv(), Ok(Err(_))));
+        assert!(subscription.is_closed());
+        // the other machine is asked to stop pushing
+        assert_eq!(subscription.machines().len(), 1);
+    }
+}
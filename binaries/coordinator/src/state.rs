@@ -0,0 +1,264 @@
+use crate::{ArchivedDataflow, RunningDataflow};
+use dora_core::descriptor::ResolvedNode;
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{sync::watch, task::JoinHandle};
+use uuid::Uuid;
+
+const STATE_FILE: &str = "coordinator-state.json";
+
+/// Stores the coordinator state in a JSON file, so that a restarted
+/// coordinator can re-adopt the dataflows that are still running.
+///
+/// The file is written by a background task, so that the coordinator event
+/// loop never waits for the disk. Updates that arrive while a write is in
+/// progress are coalesced, so only the latest state is written.
+pub struct StateStore {
+    updates: watch::Sender<Option<Arc<Update>>>,
+    writer: JoinHandle<()>,
+}
+
+enum Update {
+    Save(PersistedState),
+    Clear,
+}
+
+impl StateStore {
+    /// Opens the store in the given directory and loads the previously
+    /// persisted state, if any.
+    ///
+    /// A state file that cannot be parsed, e.g. because it was written by an
+    /// incompatible version, is renamed to `coordinator-state.json.corrupt`.
+    pub fn open(dir: &Path) -> eyre::Result<(Self, PersistedState)> {
+        std::fs::create_dir_all(dir)
+            .wrap_err_with(|| format!("failed to create state dir `{}`", dir.display()))?;
+        let path = dir.join(STATE_FILE);
+        let state = match std::fs::read(&path) {
+            Ok(raw) => match serde_json::from_slice(&raw) {
+                Ok(state) => state,
+                Err(err) => {
+                    // keep the file for inspection, but don't refuse to start
+                    let corrupt = path.with_extension("json.corrupt");
+                    tracing::error!(
+                        "failed to parse `{}`, moving it to `{}` and starting \
+                        with an empty state: {err}",
+                        path.display(),
+                        corrupt.display()
+                    );
+                    std::fs::rename(&path, &corrupt)
+                        .wrap_err_with(|| format!("failed to move `{}` aside", path.display()))?;
+                    PersistedState::default()
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => PersistedState::default(),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| format!("failed to read `{}`", path.display()))
+            }
+        };
+        let (updates, rx) = watch::channel(None);
+        let writer = tokio::spawn(write_updates(path, rx));
+        Ok((Self { updates, writer }, state))
+    }
+
+    /// Writes the given state to disk in the background.
+    pub fn save(&self, state: PersistedState) {
+        self.updates
+            .send_replace(Some(Arc::new(Update::Save(state))));
+    }
+
+    /// Removes the state file, e.g. after all dataflows were stopped on
+    /// `dora destroy`.
+    pub async fn clear(self) {
+        self.updates.send_replace(Some(Arc::new(Update::Clear)));
+        self.close().await
+    }
+
+    /// Waits until the latest state is written.
+    pub async fn close(self) {
+        drop(self.updates);
+        if let Err(err) = self.writer.await {
+            tracing::warn!("coordinator state writer failed: {err}");
+        }
+    }
+}
+
+async fn write_updates(path: PathBuf, mut updates: watch::Receiver<Option<Arc<Update>>>) {
+    // the latest value is still seen after the sender was dropped
+    while updates.changed().await.is_ok() {
+        let Some(update) = updates.borrow_and_update().clone() else {
+            continue;
+        };
+        let path = path.clone();
+        let result = tokio::task::spawn_blocking(move || match &*update {
+            Update::Save(state) => write_state(&path, state),
+            Update::Clear => remove_state(&path),
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                tracing::warn!("{:?}", err.wrap_err("failed to persist coordinator state"))
+            }
+            Err(err) => tracing::warn!("failed to persist coordinator state: {err}"),
+        }
+    }
+}
+
+/// Writes the state to a temporary file first, which is synced to disk and
+/// then replaces the previous state file. This way, a crash while writing
+/// never leaves a truncated state file behind.
+fn write_state(path: &Path, state: &PersistedState) -> eyre::Result<()> {
+    let serialized =
+        serde_json::to_vec_pretty(state).wrap_err("failed to serialize coordinator state")?;
+    let tmp = path.with_extension("json.tmp");
+    let mut file = std::fs::File::create(&tmp)
+        .wrap_err_with(|| format!("failed to create `{}`", tmp.display()))?;
+    file.write_all(&serialized)
+        .and_then(|()| file.sync_all())
+        .wrap_err_with(|| format!("failed to write `{}`", tmp.display()))?;
+    std::fs::rename(&tmp, path).wrap_err_with(|| format!("failed to replace `{}`", path.display()))
+}
+
+fn remove_state(path: &Path) -> eyre::Result<()> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).wrap_err_with(|| format!("failed to remove `{}`", path.display())),
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PersistedState {
+    pub running_dataflows: BTreeMap<Uuid, PersistedDataflow>,
+    pub archived_dataflows: BTreeMap<Uuid, PersistedArchivedDataflow>,
+    pub dataflow_results: BTreeMap<Uuid, BTreeMap<String, Result<(), String>>>,
+}
+
+impl PersistedState {
+    pub fn new(
+        running_dataflows: &HashMap<Uuid, RunningDataflow>,
+        archived_dataflows: &HashMap<Uuid, ArchivedDataflow>,
+        dataflow_results: &HashMap<Uuid, BTreeMap<String, Result<(), String>>>,
+    ) -> Self {
+        Self {
+            running_dataflows: running_dataflows
+                .iter()
+                .map(|(uuid, d)| {
+                    let dataflow = PersistedDataflow {
+                        name: d.name.clone(),
+                        machines: d.machines.clone(),
+                        pending_machines: d.pending_machines.clone(),
+                        init_success: d.init_success,
+                        nodes: d.nodes.clone(),
+                        lost_machines: d.lost_machines.clone(),
+                    };
+                    (*uuid, dataflow)
+                })
+                .collect(),
+            archived_dataflows: archived_dataflows
+                .iter()
+                .map(|(uuid, d)| {
+                    let dataflow = PersistedArchivedDataflow {
+                        name: d.name.clone(),
+                        nodes: d.nodes.clone(),
+                    };
+                    (*uuid, dataflow)
+                })
+                .collect(),
+            dataflow_results: dataflow_results
+                .iter()
+                .map(|(uuid, r)| (*uuid, r.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedDataflow {
+    pub name: Option<String>,
+    pub machines: BTreeSet<String>,
+    pub pending_machines: BTreeSet<String>,
+    pub init_success: bool,
+    pub nodes: Vec<ResolvedNode>,
+    #[serde(default)]
+    pub lost_machines: BTreeSet<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PersistedArchivedDataflow {
+    pub name: Option<String>,
+    pub nodes: Vec<ResolvedNode>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn save_and_reload() {
+        let dir = std::env::temp_dir().join(format!(
+            "dora-coordinator-state-{}",
+            Uuid::new_v7(uuid::Timestamp::now(uuid::NoContext))
+        ));
+        let (store, state) = StateStore::open(&dir).unwrap();
+        assert!(state.running_dataflows.is_empty());
+
+        let uuid = Uuid::new_v7(uuid::Timestamp::now(uuid::NoContext));
+        let mut state = PersistedState::default();
+        state.running_dataflows.insert(
+            uuid,
+            PersistedDataflow {
+                name: Some("test".into()),
+                machines: ["A".to_owned(), "B".to_owned()].into(),
+                pending_machines: BTreeSet::new(),
+                init_success: true,
+                nodes: Vec::new(),
+                lost_machines: BTreeSet::new(),
+            },
+        );
+        state
+            .dataflow_results
+            .insert(uuid, [("A".to_owned(), Err("failed".to_owned()))].into());
+        store.save(state);
+        store.close().await;
+
+        let (store, reloaded) = StateStore::open(&dir).unwrap();
+        let dataflow = &reloaded.running_dataflows[&uuid];
+        assert_eq!(dataflow.name.as_deref(), Some("test"));
+        assert_eq!(dataflow.machines.len(), 2);
+        assert_eq!(
+            reloaded.dataflow_results[&uuid]["A"],
+            Err("failed".to_owned())
+        );
+
+        store.clear().await;
+        let (_, cleared) = StateStore::open(&dir).unwrap();
+        assert!(cleared.running_dataflows.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn move_corrupt_state_aside() {
+        let dir = std::env::temp_dir().join(format!(
+            "dora-coordinator-state-{}",
+            Uuid::new_v7(uuid::Timestamp::now(uuid::NoContext))
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(STATE_FILE), "{ invalid").unwrap();
+
+        let (_store, state) = StateStore::open(&dir).unwrap();
+        assert!(state.running_dataflows.is_empty());
+        assert!(!dir.join(STATE_FILE).exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.join("coordinator-state.json.corrupt")).unwrap(),
+            "{ invalid"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,26 @@
 use crate::{
     tcp_utils::{tcp_receive, tcp_send},
-    DaemonCoordinatorEvent,
+    DaemonCoordinatorEvent, Event,
 };
+use communication_layer_request_reply::security::{MaybeTlsStream, Security};
 use dora_core::{
-    coordinator_messages::{CoordinatorRequest, RegisterResult},
-    daemon_messages::{DaemonCoordinatorReply, Timestamped},
+    coordinator_messages::{CoordinatorRequest, DaemonEvent, EventsAck, RegisterResult},
+    daemon_messages::{DaemonCoordinatorReply, DataflowId, Timestamped},
     message::uhlc::HLC,
 };
 use eyre::{eyre, Context};
-use std::{io::ErrorKind, net::SocketAddr};
+use futures::{future::RemoteHandle, FutureExt};
+use std::{collections::VecDeque, io::ErrorKind, net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{
+    io::WriteHalf,
     net::TcpStream,
-    sync::{mpsc, oneshot},
+    sync::{mpsc, oneshot, watch},
 };
-use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+
+/// Delay before the first reconnect attempt, doubled after each failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub struct CoordinatorEvent {
@@ -21,23 +28,345 @@ pub struct CoordinatorEvent {
     pub reply_tx: oneshot::Sender<Option<DaemonCoordinatorReply>>,
 }
 
-pub async fn register(
+/// The registration of the daemon at the coordinator.
+///
+/// Keeps everything that is needed to register again after the connection
+/// to the coordinator was lost.
+pub struct CoordinatorLink {
     addr: SocketAddr,
-    machine_id: String,
     listen_port: u16,
-    clock: &HLC,
-) -> eyre::Result<impl Stream<Item = Timestamped<CoordinatorEvent>>> {
-    let mut stream = TcpStream::connect(addr)
+    security: Security,
+    /// Forwards the events of the registered connection to the event loop.
+    /// Dropping it stops the forwarding.
+    forward_task: Option<RemoteHandle<()>>,
+}
+
+impl CoordinatorLink {
+    pub fn new(addr: SocketAddr, listen_port: u16, security: Security) -> Self {
+        Self {
+            addr,
+            listen_port,
+            security,
+            forward_task: None,
+        }
+    }
+
+    pub async fn connect(&self) -> eyre::Result<MaybeTlsStream> {
+        connect(self.addr, &self.security).await
+    }
+
+    /// Registers the daemon on the given connection and forwards the events
+    /// sent by the coordinator to the daemon event loop.
+    ///
+    /// An [`Event::CoordinatorDisconnected`] is sent when the connection is
+    /// closed.
+    pub async fn register(
+        &mut self,
+        stream: MaybeTlsStream,
+        machine_id: String,
+        running_dataflows: Vec<DataflowId>,
+        clock: Arc<HLC>,
+        events_tx: mpsc::Sender<Timestamped<Event>>,
+    ) -> eyre::Result<()> {
+        let mut events = register(
+            stream,
+            machine_id,
+            self.listen_port,
+            running_dataflows,
+            &clock,
+        )
+        .await?;
+        tracing::info!("Connected to dora-coordinator at {:?}", self.addr);
+
+        let (task, handle) = async move {
+            while let Some(Timestamped { inner, timestamp }) = events.next().await {
+                let event = Timestamped {
+                    inner: Event::Coordinator(inner),
+                    timestamp,
+                };
+                if events_tx.send(event).await.is_err() {
+                    return;
+                }
+            }
+            let _ = events_tx
+                .send(Timestamped {
+                    inner: Event::CoordinatorDisconnected,
+                    timestamp: clock.new_timestamp(),
+                })
+                .await;
+        }
+        .remote_handle();
+        tokio::spawn(task);
+        self.forward_task = Some(handle);
+        Ok(())
+    }
+
+    pub fn is_registered(&self) -> bool {
+        self.forward_task.is_some()
+    }
+
+    /// Stops forwarding the events of the lost connection and spawns a task
+    /// that connects to the coordinator again.
+    ///
+    /// The task retries with exponential backoff until it succeeds and then
+    /// sends the new connections as [`Event::CoordinatorReconnected`].
+    pub fn reconnect(&mut self, events_tx: mpsc::Sender<Timestamped<Event>>, clock: Arc<HLC>) {
+        self.forward_task = None;
+        let addr = self.addr;
+        let security = self.security.clone();
+        tokio::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let result = async {
+                    let register = connect(addr, &security).await?;
+                    let events = connect(addr, &security).await?;
+                    eyre::Ok((register, events))
+                };
+                match result.await {
+                    Ok((register, events)) => {
+                        let event = Timestamped {
+                            inner: Event::CoordinatorReconnected { register, events },
+                            timestamp: clock.new_timestamp(),
+                        };
+                        let _ = events_tx.send(event).await;
+                        break;
+                    }
+                    Err(err) => {
+                        tracing::debug!("failed to reconnect to dora-coordinator: {err:?}");
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Maximum number of messages that are kept for (re)sending to the
+/// coordinator.
+const MAX_QUEUED_MESSAGES: usize = 1024;
+
+/// Connection for reporting daemon events to the coordinator.
+///
+/// Sent messages are kept until the coordinator acknowledges them. Messages
+/// that are unacknowledged when the connection is lost are queued together
+/// with the messages that could not be sent and sent out again after
+/// reconnecting. The coordinator might thus see some messages twice.
+pub struct CoordinatorConnection {
+    writer: Option<WriteHalf<MaybeTlsStream>>,
+    /// Number of messages that the coordinator acknowledged on the current
+    /// connection.
+    acked: watch::Receiver<u64>,
+    /// Messages sent on the current connection that are not acknowledged
+    /// yet, oldest first. Droppable messages are only counted.
+    in_flight: VecDeque<Option<Timestamped<CoordinatorRequest>>>,
+    /// Number of messages sent on the current connection.
+    sent: u64,
+    unsent: VecDeque<Timestamped<CoordinatorRequest>>,
+    /// Stops reading the acknowledgements of the current connection.
+    _ack_task: Option<RemoteHandle<()>>,
+}
+
+impl CoordinatorConnection {
+    pub fn new(stream: MaybeTlsStream) -> Self {
+        let mut connection = Self {
+            writer: None,
+            acked: watch::channel(0).1,
+            in_flight: VecDeque::new(),
+            sent: 0,
+            unsent: VecDeque::new(),
+            _ack_task: None,
+        };
+        connection.connect(stream);
+        connection
+    }
+
+    /// Sends the given message, or queues it if the connection is down.
+    pub async fn send(&mut self, message: Timestamped<CoordinatorRequest>) -> eyre::Result<()> {
+        let serialized =
+            serde_json::to_vec(&message).wrap_err("failed to serialize CoordinatorRequest")?;
+        if self.try_send(&serialized).await {
+            self.in_flight.push_back(Some(message));
+        } else {
+            self.unsent.push_back(message);
+        }
+        self.enforce_limit();
+        Ok(())
+    }
+
+    /// Sends the given message if connected. Otherwise the message is
+    /// dropped, which is fine for periodic messages such as heartbeats.
+    pub async fn send_droppable(
+        &mut self,
+        message: Timestamped<CoordinatorRequest>,
+    ) -> eyre::Result<()> {
+        let serialized =
+            serde_json::to_vec(&message).wrap_err("failed to serialize CoordinatorRequest")?;
+        if self.try_send(&serialized).await {
+            self.in_flight.push_back(None);
+        }
+        Ok(())
+    }
+
+    async fn try_send(&mut self, message: &[u8]) -> bool {
+        self.remove_acked();
+        let Some(writer) = &mut self.writer else {
+            return false;
+        };
+        match tcp_send(writer, message).await {
+            Ok(()) => {
+                self.sent += 1;
+                true
+            }
+            Err(err) => {
+                tracing::warn!("failed to send message to dora-coordinator: {err}");
+                self.disconnect();
+                false
+            }
+        }
+    }
+
+    /// Forgets the messages that the coordinator acknowledged.
+    fn remove_acked(&mut self) {
+        let acked = *self.acked.borrow();
+        let unacked = self.sent.saturating_sub(acked) as usize;
+        let acked_in_flight = self.in_flight.len().saturating_sub(unacked);
+        self.in_flight.drain(..acked_in_flight);
+    }
+
+    /// Drops the oldest messages if too many are queued.
+    ///
+    /// `AllNodesFinished` messages are kept since the coordinator cannot
+    /// recover the dataflow result otherwise.
+    fn enforce_limit(&mut self) {
+        let mut excess = (self.in_flight.iter().flatten().count() + self.unsent.len())
+            .saturating_sub(MAX_QUEUED_MESSAGES);
+        if excess == 0 {
+            return;
+        }
+        for message in &mut self.in_flight {
+            if excess > 0 && message.as_ref().is_some_and(|m| !is_finished_message(m)) {
+                // keep the entry since it is still counted by the coordinator
+                *message = None;
+                excess -= 1;
+            }
+        }
+        self.unsent.retain(|message| {
+            if excess > 0 && !is_finished_message(message) {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+        tracing::warn!("too many messages for dora-coordinator queued, dropping the oldest");
+    }
+
+    /// Dataflows whose `AllNodesFinished` message is not acknowledged yet.
+    ///
+    /// They are announced as running when registering again, so that the
+    /// coordinator receives their actual result afterwards.
+    pub fn unreported_finished_dataflows(&self) -> impl Iterator<Item = DataflowId> + '_ {
+        self.in_flight
+            .iter()
+            .flatten()
+            .chain(&self.unsent)
+            .filter_map(|message| match &message.inner {
+                CoordinatorRequest::Event {
+                    event: DaemonEvent::AllNodesFinished { dataflow_id, .. },
+                    ..
+                } => Some(*dataflow_id),
+                _ => None,
+            })
+    }
+
+    /// Closes the connection and queues all unacknowledged messages for
+    /// sending them again.
+    pub fn disconnect(&mut self) {
+        self.remove_acked();
+        self.writer = None;
+        self._ack_task = None;
+        let mut unacked: VecDeque<_> = self.in_flight.drain(..).flatten().collect();
+        unacked.append(&mut self.unsent);
+        self.unsent = unacked;
+    }
+
+    /// Switches to the given connection and sends out all queued messages.
+    pub async fn reconnect(&mut self, stream: MaybeTlsStream) -> eyre::Result<()> {
+        self.disconnect();
+        self.connect(stream);
+        for message in std::mem::take(&mut self.unsent) {
+            self.send(message).await?;
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, stream: MaybeTlsStream) {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (acked_tx, acked) = watch::channel(0);
+        let (task, handle) = async move {
+            loop {
+                let ack = match tcp_receive(&mut reader).await {
+                    Ok(raw) => serde_json::from_slice::<EventsAck>(&raw),
+                    Err(err) => {
+                        tracing::debug!("stopped receiving acks from dora-coordinator: {err}");
+                        break;
+                    }
+                };
+                match ack {
+                    Ok(ack) => acked_tx.send_replace(ack.received),
+                    Err(err) => {
+                        tracing::warn!("failed to deserialize ack from dora-coordinator: {err}");
+                        break;
+                    }
+                };
+            }
+        }
+        .remote_handle();
+        tokio::spawn(task);
+        self.writer = Some(writer);
+        self.acked = acked;
+        self.sent = 0;
+        self._ack_task = Some(handle);
+    }
+}
+
+fn is_finished_message(message: &Timestamped<CoordinatorRequest>) -> bool {
+    matches!(
+        message.inner,
+        CoordinatorRequest::Event {
+            event: DaemonEvent::AllNodesFinished { .. },
+            ..
+        }
+    )
+}
+
+async fn connect(addr: SocketAddr, security: &Security) -> eyre::Result<MaybeTlsStream> {
+    let stream = TcpStream::connect(addr)
         .await
         .wrap_err("failed to connect to dora-coordinator")?;
     stream
         .set_nodelay(true)
         .wrap_err("failed to set TCP_NODELAY")?;
+    security
+        .connect(stream)
+        .await
+        .wrap_err("failed to secure connection to dora-coordinator")
+}
+
+async fn register(
+    mut stream: MaybeTlsStream,
+    machine_id: String,
+    listen_port: u16,
+    running_dataflows: Vec<DataflowId>,
+    clock: &HLC,
+) -> eyre::Result<impl Stream<Item = Timestamped<CoordinatorEvent>>> {
     let register = serde_json::to_vec(&Timestamped {
         inner: CoordinatorRequest::Register {
             dora_version: env!("CARGO_PKG_VERSION").to_owned(),
             machine_id,
             listen_port,
+            running_dataflows,
         },
         timestamp: clock.new_timestamp(),
     })?;
@@ -54,8 +383,6 @@ pub async fn register(
         tracing::warn!("failed to update timestamp after register: {err}");
     }
 
-    tracing::info!("Connected to dora-coordinator at {:?}", addr);
-
     let (tx, rx) = mpsc::channel(1);
     tokio::spawn(async move {
         loop {
@@ -71,9 +398,10 @@ pub async fn register(
                 },
                 Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
                 Err(err) => {
+                    // the stream is out of sync after a failed read
                     let err = eyre!(err).wrap_err("failed to receive incoming event");
                     tracing::warn!("{err:?}");
-                    continue;
+                    break;
                 }
             };
             let Timestamped {
@@ -125,3 +453,153 @@ pub async fn register(
 
     Ok(ReceiverStream::new(rx))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn event(event: DaemonEvent) -> Timestamped<CoordinatorRequest> {
+        Timestamped {
+            inner: CoordinatorRequest::Event {
+                machine_id: "A".into(),
+                event,
+            },
+            timestamp: HLC::default().new_timestamp(),
+        }
+    }
+
+    #[tokio::test]
+    async fn queue_messages_while_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let security = Security::default();
+        let dataflow_id = DataflowId::nil();
+
+        let mut connection = CoordinatorConnection::new(connect(addr, &security).await.unwrap());
+        connection.disconnect();
+        let finished = DaemonEvent::AllNodesFinished {
+            dataflow_id,
+            result: Ok(()),
+        };
+        connection.send(event(finished)).await.unwrap();
+        connection
+            .send_droppable(event(DaemonEvent::Heartbeat))
+            .await
+            .unwrap();
+        assert_eq!(
+            connection
+                .unreported_finished_dataflows()
+                .collect::<Vec<_>>(),
+            [dataflow_id]
+        );
+
+        let (reconnected, (mut receiver, _)) = tokio::join!(
+            async {
+                let stream = connect(addr, &security).await.unwrap();
+                connection.reconnect(stream).await
+            },
+            async {
+                // skip the first, disconnected stream
+                let _ = listener.accept().await.unwrap();
+                listener.accept().await.unwrap()
+            }
+        );
+        reconnected.unwrap();
+
+        let received: Timestamped<CoordinatorRequest> =
+            serde_json::from_slice(&tcp_receive(&mut receiver).await.unwrap()).unwrap();
+        assert!(matches!(
+            received.inner,
+            CoordinatorRequest::Event {
+                event: DaemonEvent::AllNodesFinished { .. },
+                ..
+            }
+        ));
+        // kept until the coordinator acknowledges it
+        assert_eq!(connection.unreported_finished_dataflows().count(), 1);
+        ack(&mut receiver, 1).await;
+        connection.acked.changed().await.unwrap();
+        connection.remove_acked();
+        assert_eq!(connection.unreported_finished_dataflows().count(), 0);
+    }
+
+    async fn ack(stream: &mut TcpStream, received: u64) {
+        let ack = serde_json::to_vec(&EventsAck { received }).unwrap();
+        tcp_send(stream, &ack).await.unwrap();
+    }
+
+    async fn receive_finished(stream: &mut TcpStream) -> DataflowId {
+        let received: Timestamped<CoordinatorRequest> =
+            serde_json::from_slice(&tcp_receive(stream).await.unwrap()).unwrap();
+        match received.inner {
+            CoordinatorRequest::Event {
+                event: DaemonEvent::AllNodesFinished { dataflow_id, .. },
+                ..
+            } => dataflow_id,
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn resend_unacknowledged_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let security = Security::default();
+        let finished = |dataflow_id| {
+            event(DaemonEvent::AllNodesFinished {
+                dataflow_id,
+                result: Ok(()),
+            })
+        };
+        let (first, second) = (DataflowId::from_u128(1), DataflowId::from_u128(2));
+
+        let (stream, accepted) = tokio::join!(connect(addr, &security), listener.accept());
+        let (mut receiver, _) = accepted.unwrap();
+        let mut connection = CoordinatorConnection::new(stream.unwrap());
+        connection.send(finished(first)).await.unwrap();
+        connection.send(finished(second)).await.unwrap();
+        assert_eq!(receive_finished(&mut receiver).await, first);
+        assert_eq!(receive_finished(&mut receiver).await, second);
+        ack(&mut receiver, 1).await;
+        connection.acked.changed().await.unwrap();
+
+        // the connection is lost before the second message is acknowledged
+        drop(receiver);
+        connection.disconnect();
+        assert_eq!(
+            connection
+                .unreported_finished_dataflows()
+                .collect::<Vec<_>>(),
+            [second]
+        );
+        let (stream, accepted) = tokio::join!(connect(addr, &security), listener.accept());
+        let (mut receiver, _) = accepted.unwrap();
+        connection.reconnect(stream.unwrap()).await.unwrap();
+        assert_eq!(receive_finished(&mut receiver).await, second);
+    }
+
+    #[tokio::test]
+    async fn limit_queued_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut connection =
+            CoordinatorConnection::new(connect(addr, &Security::default()).await.unwrap());
+        connection.disconnect();
+
+        let finished = DaemonEvent::AllNodesFinished {
+            dataflow_id: DataflowId::nil(),
+            result: Ok(()),
+        };
+        connection.send(event(finished)).await.unwrap();
+        for _ in 0..MAX_QUEUED_MESSAGES {
+            let ready = DaemonEvent::NodeReady {
+                dataflow_id: DataflowId::nil(),
+                node_id: "node".to_owned().into(),
+            };
+            connection.send(event(ready)).await.unwrap();
+        }
+        assert_eq!(connection.unsent.len(), MAX_QUEUED_MESSAGES);
+        assert_eq!(connection.unreported_finished_dataflows().count(), 1);
+    }
+}
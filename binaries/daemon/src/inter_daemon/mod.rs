@@ -0,0 +1,346 @@
+use crate::Event;
+use aligned_vec::AVec;
+use communication_layer_request_reply::security::Security;
+use dora_core::{
+    config::{DataId, NodeId, RemoteCommunicationConfig},
+    daemon_messages::{DataflowId, InterDaemonEvent, Timestamped},
+    message::{uhlc::HLC, Metadata},
+};
+use eyre::Context;
+use futures::Future;
+use std::{
+    borrow::Cow,
+    collections::{btree_map, BTreeMap},
+    net::SocketAddr,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::sync::{mpsc, oneshot};
+use transfer::OutgoingTransfer;
+use uuid::{NoContext, Timestamp, Uuid};
+
+pub use tcp::spawn_listener_loop;
+pub use transfer::{IncomingTransfer, OutputData, ShmemHandle};
+
+mod tcp;
+mod transfer;
+mod zenoh;
+
+/// Outbound buffer of each TCP connection to another daemon.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerBufferConfig {
+    /// Maximum number of queued messages. An output that is split into
+    /// chunks counts as a single message.
+    pub capacity: usize,
+    /// Which messages to drop when the buffer is full while the peer is
+    /// unreachable.
+    pub drop_policy: DropPolicy,
+}
+
+impl Default for PeerBufferConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 100,
+            drop_policy: DropPolicy::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    #[default]
+    DropOldest,
+    DropNewest,
+}
+
+impl FromStr for DropPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" | "drop_oldest" => Ok(Self::DropOldest),
+            "drop-newest" | "drop_newest" => Ok(Self::DropNewest),
+            other => Err(format!(
+                "unknown drop policy `{other}` (expected `drop-oldest` or `drop-newest`)"
+            )),
+        }
+    }
+}
+
+/// Link state change of a connection to another daemon.
+#[derive(Debug)]
+pub enum PeerEvent {
+    Disconnected { machine_id: String, error: String },
+    Reconnected { machine_id: String },
+}
+
+/// Connections to the daemons of other machines.
+///
+/// Dataflows use direct TCP connections by default, which are shared between
+/// all dataflows. Dataflows with a `zenoh` remote communication config get
+/// their own zenoh session instead.
+pub struct InterDaemonConnections {
+    machine_id: String,
+    tcp: BTreeMap<String, tcp::InterDaemonConnection>,
+    zenoh: BTreeMap<DataflowId, zenoh::ZenohConnection>,
+    buffer_config: PeerBufferConfig,
+    security: Security,
+    /// Receives link state changes of the TCP connections.
+    peer_events_tx: mpsc::Sender<Timestamped<Event>>,
+    /// Forwards events of other daemons to the event loop. Not set when
+    /// running a single dataflow locally.
+    events_tx: Option<flume::Sender<Timestamped<InterDaemonEvent>>>,
+}
+
+impl InterDaemonConnections {
+    pub fn new(
+        machine_id: String,
+        events_tx: Option<flume::Sender<Timestamped<InterDaemonEvent>>>,
+        buffer_config: PeerBufferConfig,
+        security: Security,
+        peer_events_tx: mpsc::Sender<Timestamped<Event>>,
+    ) -> Self {
+        Self {
+            machine_id,
+            tcp: BTreeMap::new(),
+            zenoh: BTreeMap::new(),
+            buffer_config,
+            security,
+            peer_events_tx,
+            events_tx,
+        }
+    }
+
+    /// Sets up the connections to the other machines of the given dataflow.
+    pub async fn open(
+        &mut self,
+        dataflow_id: DataflowId,
+        config: &RemoteCommunicationConfig,
+        machine_listen_ports: BTreeMap<String, SocketAddr>,
+        working_dir: &Path,
+    ) -> eyre::Result<()> {
+        match config {
+            RemoteCommunicationConfig::Tcp => {
+                for (machine_id, socket) in machine_listen_ports {
+                    let connection = || {
+                        tcp::InterDaemonConnection::new(
+                            machine_id.clone(),
+                            socket,
+                            self.buffer_config,
+                            self.security.clone(),
+                            self.peer_events_tx.clone(),
+                        )
+                    };
+                    match self.tcp.entry(machine_id.clone()) {
+                        btree_map::Entry::Vacant(entry) => {
+                            entry.insert(connection());
+                        }
+                        btree_map::Entry::Occupied(mut entry) => {
+                            if entry.get().socket() != socket {
+                                entry.insert(connection());
+                            }
+                        }
+                    }
+                }
+            }
+            RemoteCommunicationConfig::Zenoh(config) => {
+                // there are no other machines when running a dataflow locally
+                let Some(events_tx) = self.events_tx.clone() else {
+                    return Ok(());
+                };
+                let zenoh_config = match &config.config {
+                    Some(path) => {
+                        let path = working_dir.join(path);
+                        ::zenoh::config::Config::from_file(&path).map_err(|err| {
+                            eyre::eyre!("failed to read zenoh config `{}`: {err}", path.display())
+                        })?
+                    }
+                    None => ::zenoh::config::Config::default(),
+                };
+                let peers = machine_listen_ports
+                    .into_keys()
+                    .filter(|machine_id| *machine_id != self.machine_id)
+                    .collect();
+                let connection = zenoh::ZenohConnection::open(
+                    zenoh_config,
+                    config.prefix.clone(),
+                    dataflow_id,
+                    &self.machine_id,
+                    peers,
+                    events_tx,
+                )
+                .await
+                .wrap_err("failed to open zenoh session")?;
+                self.zenoh.insert(dataflow_id, connection);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns a future that resolves once the dataflow-specific connections
+    /// to all other machines are established, if there are any.
+    ///
+    /// The dataflow must not be reported as ready before.
+    pub fn handshake(
+        &self,
+        dataflow_id: DataflowId,
+    ) -> Option<impl Future<Output = eyre::Result<()>> + Send + 'static> {
+        self.zenoh
+            .get(&dataflow_id)
+            .map(|connection| connection.handshake())
+    }
+
+    /// Closes the dataflow-specific connections, e.g. zenoh sessions.
+    pub fn close(&mut self, dataflow_id: DataflowId) {
+        if let Some(connection) = self.zenoh.remove(&dataflow_id) {
+            connection.close();
+        }
+    }
+
+    pub async fn send(
+        &mut self,
+        dataflow_id: DataflowId,
+        target_machines: &[String],
+        event: &Timestamped<InterDaemonEvent>,
+    ) -> eyre::Result<()> {
+        let message = bincode::serialize(event).wrap_err("failed to serialize InterDaemonEvent")?;
+        // receivers wait for `InputsClosed`, so it must not be dropped
+        let droppable = !matches!(event.inner, InterDaemonEvent::InputsClosed { .. });
+        self.send_message(
+            dataflow_id,
+            target_machines,
+            OutgoingMessage::Event(message),
+            droppable,
+        )
+        .await
+    }
+
+    /// Sends the given message to the given machines.
+    ///
+    /// On TCP connections, the message is queued as a single unit, which is
+    /// either sent or dropped as a whole.
+    async fn send_message(
+        &mut self,
+        dataflow_id: DataflowId,
+        target_machines: &[String],
+        message: OutgoingMessage,
+        droppable: bool,
+    ) -> eyre::Result<()> {
+        match self.zenoh.get(&dataflow_id) {
+            Some(connection) => {
+                for index in 0..message.message_count() {
+                    let serialized = message.serialized(index)?.into_owned();
+                    connection.send(target_machines, serialized).await?;
+                }
+                Ok(())
+            }
+            None => {
+                tcp::send_message(target_machines, &mut self.tcp, Arc::new(message), droppable)
+                    .await
+            }
+        }
+    }
+
+    /// Forwards an output to the given machines.
+    ///
+    /// Outputs that are larger than [`transfer::CHUNK_SIZE`] are split into
+    /// chunks, which the receiving daemons reassemble in shared memory. The
+    /// chunks are serialized from `data` while sending, so the data may still
+    /// be in use when this function returns. In this case, the returned
+    /// receiver is closed once the data is no longer needed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn send_output(
+        &mut self,
+        dataflow_id: DataflowId,
+        target_machines: &[String],
+        node_id: NodeId,
+        output_id: DataId,
+        metadata: Metadata,
+        data: Option<OutputData>,
+        clock: &HLC,
+    ) -> eyre::Result<Option<oneshot::Receiver<()>>> {
+        match data {
+            Some(data) if data.as_slice().len() > transfer::CHUNK_SIZE => {
+                let transfer_id = Uuid::new_v7(Timestamp::now(NoContext));
+                let header = Timestamped {
+                    inner: InterDaemonEvent::LargeOutput {
+                        dataflow_id,
+                        node_id,
+                        output_id,
+                        metadata,
+                        transfer_id,
+                        len: data.as_slice().len(),
+                    },
+                    timestamp: clock.new_timestamp(),
+                };
+                let header =
+                    bincode::serialize(&header).wrap_err("failed to serialize InterDaemonEvent")?;
+                let (transfer, done) = OutgoingTransfer::new(
+                    header,
+                    dataflow_id,
+                    transfer_id,
+                    data,
+                    clock.new_timestamp(),
+                );
+                self.send_message(
+                    dataflow_id,
+                    target_machines,
+                    OutgoingMessage::Transfer(transfer),
+                    true,
+                )
+                .await?;
+                Ok(Some(done))
+            }
+            data => {
+                let event = Timestamped {
+                    inner: InterDaemonEvent::Output {
+                        dataflow_id,
+                        node_id,
+                        output_id,
+                        metadata,
+                        data: data.map(|data| match data {
+                            OutputData::Vec(data) => data,
+                            OutputData::SharedMemory(..) => AVec::from_slice(1, data.as_slice()),
+                        }),
+                    },
+                    timestamp: clock.new_timestamp(),
+                };
+                self.send(dataflow_id, target_machines, &event).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Messages that are queued, sent, or dropped together.
+pub enum OutgoingMessage {
+    /// A serialized `InterDaemonEvent`.
+    Event(Vec<u8>),
+    /// A large output, which is sent as a header followed by its chunks.
+    Transfer(OutgoingTransfer),
+}
+
+impl OutgoingMessage {
+    pub fn message_count(&self) -> usize {
+        match self {
+            OutgoingMessage::Event(_) => 1,
+            OutgoingMessage::Transfer(transfer) => transfer.message_count(),
+        }
+    }
+
+    /// Returns the serialized message with the given index.
+    pub fn serialized(&self, index: usize) -> eyre::Result<Cow<'_, [u8]>> {
+        match self {
+            OutgoingMessage::Event(message) if index == 0 => Ok(Cow::Borrowed(message)),
+            OutgoingMessage::Event(_) => eyre::bail!("event has no message {index}"),
+            OutgoingMessage::Transfer(transfer) => transfer.message(index),
+        }
+    }
+
+    /// Whether the message with the given index is an output chunk, which the
+    /// receiver acknowledges.
+    pub fn is_chunk(&self, index: usize) -> bool {
+        matches!(self, OutgoingMessage::Transfer(_)) && index > 0
+    }
+}
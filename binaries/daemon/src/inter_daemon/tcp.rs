@@ -0,0 +1,600 @@
+use super::{
+    transfer::{CHUNK_WINDOW, TRANSFER_TIMEOUT},
+    DropPolicy, OutgoingMessage, PeerBufferConfig, PeerEvent,
+};
+use crate::{
+    tcp_utils::{tcp_receive, tcp_send},
+    Event,
+};
+use communication_layer_request_reply::security::{MaybeTlsStream, Security};
+use dora_core::{
+    daemon_messages::{InterDaemonEvent, Timestamped},
+    message::uhlc::HLC,
+};
+use eyre::{Context, ContextCompat};
+use futures::{future::RemoteHandle, FutureExt};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::ErrorKind,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, Notify},
+};
+
+/// Delay before the first reconnect attempt, doubled after each failure.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Connection to the daemon of another machine.
+///
+/// Messages are queued in a bounded outbox, which is sent out by a background
+/// task. The task reconnects with exponential backoff when the connection
+/// fails and reports link state changes as [`PeerEvent`]s.
+pub struct InterDaemonConnection {
+    socket: SocketAddr,
+    outbox: Arc<Outbox>,
+    /// Stops the sender task on drop.
+    _sender: RemoteHandle<()>,
+}
+
+impl InterDaemonConnection {
+    pub fn new(
+        machine_id: String,
+        socket: SocketAddr,
+        config: PeerBufferConfig,
+        security: Security,
+        events_tx: mpsc::Sender<Timestamped<Event>>,
+    ) -> Self {
+        let outbox = Arc::new(Outbox::new(config));
+        let (task, handle) =
+            sender_loop(machine_id, socket, outbox.clone(), security, events_tx).remote_handle();
+        tokio::spawn(task);
+        Self {
+            socket,
+            outbox,
+            _sender: handle,
+        }
+    }
+
+    pub fn socket(&self) -> SocketAddr {
+        self.socket
+    }
+}
+
+#[tracing::instrument(skip(inter_daemon_connections, message))]
+pub async fn send_message(
+    target_machines: &[String],
+    inter_daemon_connections: &mut BTreeMap<String, InterDaemonConnection>,
+    message: Arc<OutgoingMessage>,
+    droppable: bool,
+) -> eyre::Result<()> {
+    for target_machine in target_machines {
+        inter_daemon_connections
+            .get(target_machine)
+            .wrap_err_with(|| format!("unknown target machine `{target_machine}`"))?
+            .outbox
+            .push(message.clone(), droppable)
+            .await;
+    }
+
+    Ok(())
+}
+
+struct Outbox {
+    state: Mutex<OutboxState>,
+    config: PeerBufferConfig,
+    /// Wakes the sender task when a message is queued.
+    queued: Notify,
+    /// Wakes a waiting `push` when a message was taken out of the queue or
+    /// when the link went down.
+    space: Notify,
+}
+
+struct OutboxState {
+    queue: VecDeque<Outgoing>,
+    link_down: bool,
+}
+
+struct Outgoing {
+    message: Arc<OutgoingMessage>,
+    droppable: bool,
+}
+
+impl Outbox {
+    fn new(config: PeerBufferConfig) -> Self {
+        Self {
+            state: Mutex::new(OutboxState {
+                queue: VecDeque::new(),
+                link_down: false,
+            }),
+            config,
+            queued: Notify::new(),
+            space: Notify::new(),
+        }
+    }
+
+    /// Queues the given message.
+    ///
+    /// Waits for free space while the link is up, which slows down the sender
+    /// to the speed of the connection. While the link is down, droppable
+    /// messages are dropped according to the drop policy instead. Messages
+    /// that are not droppable, e.g. `InputsClosed`, replace the oldest
+    /// droppable message in this case. They are only dropped if the outbox is
+    /// completely filled with non-droppable messages.
+    async fn push(&self, mut message: Arc<OutgoingMessage>, droppable: bool) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                let outgoing = Outgoing { message, droppable };
+                if state.queue.len() < self.config.capacity {
+                    state.queue.push_back(outgoing);
+                    self.queued.notify_one();
+                    return;
+                }
+                if state.link_down {
+                    let drop_oldest =
+                        !droppable || self.config.drop_policy == DropPolicy::DropOldest;
+                    let oldest_droppable = state.queue.iter().position(|m| m.droppable);
+                    match oldest_droppable.filter(|_| drop_oldest) {
+                        Some(index) => {
+                            state.queue.remove(index);
+                            state.queue.push_back(outgoing);
+                            self.queued.notify_one();
+                            tracing::debug!("outbox full and peer link down -> dropped message");
+                        }
+                        None if droppable => {
+                            tracing::debug!("outbox full and peer link down -> dropped message");
+                        }
+                        None => {
+                            tracing::warn!(
+                                "outbox full of undroppable messages and peer link down \
+                                -> dropped undroppable message"
+                            );
+                        }
+                    }
+                    return;
+                }
+                message = outgoing.message;
+            }
+            self.space.notified().await;
+        }
+    }
+
+    async fn pop(&self) -> Outgoing {
+        loop {
+            let next = self.state.lock().unwrap().queue.pop_front();
+            if let Some(next) = next {
+                self.space.notify_one();
+                return next;
+            }
+            self.queued.notified().await;
+        }
+    }
+
+    /// Returns `true` if the link state changed.
+    fn set_link_down(&self, down: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let changed = state.link_down != down;
+        state.link_down = down;
+        if down {
+            // apply the drop policy instead of waiting for space
+            self.space.notify_one();
+        }
+        changed
+    }
+}
+
+async fn sender_loop(
+    machine_id: String,
+    socket: SocketAddr,
+    outbox: Arc<Outbox>,
+    security: Security,
+    events_tx: mpsc::Sender<Timestamped<Event>>,
+) {
+    let clock = HLC::default();
+    let report = |event: PeerEvent| {
+        let event = Timestamped {
+            inner: Event::Peer(event),
+            timestamp: clock.new_timestamp(),
+        };
+        let events_tx = events_tx.clone();
+        async move {
+            let _ = events_tx.send(event).await;
+        }
+    };
+
+    let mut connection: Option<MaybeTlsStream> = None;
+    let mut backoff = INITIAL_BACKOFF;
+    // partially sent messages and the index of the next message to send
+    let mut pending = None;
+    loop {
+        let (next, mut sent) = match pending.take() {
+            Some(next) => next,
+            None => (outbox.pop().await, 0),
+        };
+        let stream = match &mut connection {
+            Some(stream) => stream,
+            None => match connect(socket, &security).await {
+                Ok(stream) => {
+                    backoff = INITIAL_BACKOFF;
+                    if outbox.set_link_down(false) {
+                        report(PeerEvent::Reconnected {
+                            machine_id: machine_id.clone(),
+                        })
+                        .await;
+                    }
+                    connection.insert(stream)
+                }
+                Err(err) => {
+                    if outbox.set_link_down(true) {
+                        report(PeerEvent::Disconnected {
+                            machine_id: machine_id.clone(),
+                            error: format!("{err:?}"),
+                        })
+                        .await;
+                    }
+                    pending = Some((next, sent));
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            },
+        };
+        if let Err(err) = send_messages(stream, &next.message, &mut sent).await {
+            tracing::warn!("failed to send event to machine `{machine_id}`: {err:?}");
+            connection = None;
+            // continue with the failed message on a new connection
+            pending = Some((next, sent));
+        }
+    }
+}
+
+/// Sends the messages of `outgoing`, starting at index `sent`.
+///
+/// At most [`CHUNK_WINDOW`] chunks are sent before the receiver acknowledges
+/// them. All acknowledgements are awaited before returning, so none are left
+/// over for the next message.
+async fn send_messages(
+    stream: &mut MaybeTlsStream,
+    outgoing: &OutgoingMessage,
+    sent: &mut usize,
+) -> eyre::Result<()> {
+    let mut unacked = 0;
+    while *sent < outgoing.message_count() {
+        let is_chunk = outgoing.is_chunk(*sent);
+        if is_chunk && unacked == CHUNK_WINDOW {
+            receive_ack(stream).await?;
+            unacked -= 1;
+        }
+        let message = match outgoing.serialized(*sent) {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::warn!("skipping rest of message: {err:?}");
+                break;
+            }
+        };
+        tcp_send(stream, &message).await?;
+        *sent += 1;
+        if is_chunk {
+            unacked += 1;
+        }
+    }
+    for _ in 0..unacked {
+        receive_ack(stream).await?;
+    }
+    Ok(())
+}
+
+async fn receive_ack(stream: &mut MaybeTlsStream) -> eyre::Result<()> {
+    tokio::time::timeout(TRANSFER_TIMEOUT, tcp_receive(stream))
+        .await
+        .wrap_err("timed out waiting for chunk acknowledgement")?
+        .wrap_err("failed to receive chunk acknowledgement")?;
+    Ok(())
+}
+
+async fn connect(socket: SocketAddr, security: &Security) -> eyre::Result<MaybeTlsStream> {
+    let connection = TcpStream::connect(socket)
+        .await
+        .wrap_err_with(|| format!("failed to connect to {socket}"))?;
+    connection
+        .set_nodelay(true)
+        .wrap_err("failed to set nodelay")?;
+    security
+        .connect(connection)
+        .await
+        .wrap_err_with(|| format!("failed to secure connection to {socket}"))
+}
+
+pub async fn spawn_listener_loop(
+    bind: SocketAddr,
+    machine_id: String,
+    events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    security: Security,
+) -> eyre::Result<u16> {
+    let socket = match TcpListener::bind(bind).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            return Err(eyre::Report::new(err).wrap_err("failed to create local TCP listener"))
+        }
+    };
+    let listen_port = socket
+        .local_addr()
+        .wrap_err("failed to get local addr of socket")?
+        .port();
+
+    tokio::spawn(async move {
+        listener_loop(socket, events_tx, security).await;
+        tracing::debug!("inter-daemon listener loop finished for machine `{machine_id}`");
+    });
+
+    Ok(listen_port)
+}
+
+async fn listener_loop(
+    listener: TcpListener,
+    events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    security: Security,
+) {
+    loop {
+        match listener
+            .accept()
+            .await
+            .wrap_err("failed to accept new connection")
+        {
+            Err(err) => {
+                tracing::info!("{err}");
+            }
+            Ok((connection, _)) => {
+                tokio::spawn(handle_connection_loop(
+                    connection,
+                    events_tx.clone(),
+                    security.clone(),
+                ));
+            }
+        }
+    }
+}
+
+async fn handle_connection_loop(
+    connection: TcpStream,
+    events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    security: Security,
+) {
+    if let Err(err) = connection.set_nodelay(true) {
+        tracing::warn!("failed to set nodelay for connection: {err}");
+    }
+    let mut connection = match security.accept(connection).await {
+        Ok(connection) => connection,
+        Err(err) => {
+            tracing::warn!("rejected inter-daemon connection: {err}");
+            return;
+        }
+    };
+
+    loop {
+        match receive_message(&mut connection).await {
+            Ok(Some(message)) => {
+                let is_chunk = matches!(message.inner, InterDaemonEvent::OutputChunk { .. });
+                if events_tx.send_async(message).await.is_err() {
+                    break;
+                }
+                // allows the sender to continue with the next chunk
+                if is_chunk {
+                    if let Err(err) = tcp_send(&mut connection, &[]).await {
+                        tracing::warn!("failed to acknowledge output chunk: {err}");
+                        break;
+                    }
+                }
+            }
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!("{err:?}");
+                break;
+            }
+        }
+    }
+}
+
+async fn receive_message(
+    connection: &mut MaybeTlsStream,
+) -> eyre::Result<Option<Timestamped<InterDaemonEvent>>> {
+    let raw = match tcp_receive(connection).await {
+        Ok(raw) => raw,
+        Err(err) => match err.kind() {
+            ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset => return Ok(None),
+            _other => {
+                return Err(err)
+                    .context("unexpected I/O error while trying to receive InterDaemonEvent")
+            }
+        },
+    };
+    bincode::deserialize(&raw)
+        .wrap_err("failed to deserialize DaemonRequest")
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inter_daemon::{
+        transfer::{OutgoingTransfer, CHUNK_SIZE},
+        OutputData,
+    };
+    use aligned_vec::AVec;
+    use dora_core::daemon_messages::DataflowId;
+    use tokio::sync::oneshot;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn drop_oldest_while_link_down() {
+        let outbox = Outbox::new(PeerBufferConfig {
+            capacity: 2,
+            drop_policy: DropPolicy::DropOldest,
+        });
+        outbox.set_link_down(true);
+        for i in 0..3u8 {
+            outbox.push(event(i), true).await;
+        }
+        outbox.push(event(10), false).await;
+
+        assert_eq!(received(&outbox).await, [vec![2], vec![10]]);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_while_link_down() {
+        let outbox = Outbox::new(PeerBufferConfig {
+            capacity: 2,
+            drop_policy: DropPolicy::DropNewest,
+        });
+        outbox.set_link_down(true);
+        for i in 0..3u8 {
+            outbox.push(event(i), true).await;
+        }
+
+        assert_eq!(received(&outbox).await, [vec![0], vec![1]]);
+    }
+
+    #[tokio::test]
+    async fn transfers_are_dropped_as_a_whole() {
+        let outbox = Outbox::new(PeerBufferConfig {
+            capacity: 2,
+            drop_policy: DropPolicy::DropOldest,
+        });
+        outbox.set_link_down(true);
+        let mut done = Vec::new();
+        for i in 0..3u8 {
+            let (transfer, transfer_done) = transfer(vec![i], CHUNK_SIZE + 1);
+            outbox.push(Arc::new(transfer), true).await;
+            done.push(transfer_done);
+        }
+
+        // the data of the dropped transfer is released
+        assert_eq!(
+            done[0].try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        );
+        assert_eq!(received(&outbox).await, [vec![1], vec![2]]);
+    }
+
+    #[tokio::test]
+    async fn undroppable_messages_are_bounded() {
+        let outbox = Outbox::new(PeerBufferConfig {
+            capacity: 2,
+            drop_policy: DropPolicy::DropNewest,
+        });
+        outbox.set_link_down(true);
+        outbox.push(event(0), true).await;
+        for i in 1..4u8 {
+            outbox.push(event(i), false).await;
+        }
+
+        // the droppable message is replaced, the last undroppable one dropped
+        assert_eq!(received(&outbox).await, [vec![1], vec![2]]);
+    }
+
+    /// Returns the first message of each queued outgoing message.
+    async fn received(outbox: &Outbox) -> Vec<Vec<u8>> {
+        let mut received = Vec::new();
+        while !outbox.state.lock().unwrap().queue.is_empty() {
+            let outgoing = outbox.pop().await.message;
+            received.push(outgoing.serialized(0).unwrap().into_owned());
+        }
+        received
+    }
+
+    fn event(i: u8) -> Arc<OutgoingMessage> {
+        Arc::new(OutgoingMessage::Event(vec![i]))
+    }
+
+    fn transfer(header: Vec<u8>, len: usize) -> (OutgoingMessage, oneshot::Receiver<()>) {
+        let (transfer, done) = OutgoingTransfer::new(
+            header,
+            DataflowId::nil(),
+            Uuid::nil(),
+            OutputData::Vec(AVec::from_slice(128, &vec![0; len])),
+            HLC::default().new_timestamp(),
+        );
+        (OutgoingMessage::Transfer(transfer), done)
+    }
+
+    #[tokio::test]
+    async fn reconnect_after_peer_restart() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let socket = listener.local_addr().unwrap();
+        // peer is not reachable at first
+        drop(listener);
+
+        let (events_tx, mut events_rx) = mpsc::channel(10);
+        let connection = InterDaemonConnection::new(
+            "B".into(),
+            socket,
+            PeerBufferConfig::default(),
+            Security::default(),
+            events_tx,
+        );
+        let mut connections = BTreeMap::from([("B".to_owned(), connection)]);
+        send_message(
+            &["B".into()],
+            &mut connections,
+            Arc::new(OutgoingMessage::Event(vec![42])),
+            true,
+        )
+        .await
+        .unwrap();
+        match events_rx.recv().await.map(|e| e.inner) {
+            Some(Event::Peer(PeerEvent::Disconnected { machine_id, .. })) => {
+                assert_eq!(machine_id, "B")
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        let listener = TcpListener::bind(socket).await.unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(tcp_receive(&mut stream).await.unwrap(), [42]);
+        match events_rx.recv().await.map(|e| e.inner) {
+            Some(Event::Peer(PeerEvent::Reconnected { machine_id })) => {
+                assert_eq!(machine_id, "B")
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn limit_chunks_in_flight() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (events_tx, _events_rx) = mpsc::channel(10);
+        let connection = InterDaemonConnection::new(
+            "B".into(),
+            listener.local_addr().unwrap(),
+            PeerBufferConfig::default(),
+            Security::default(),
+            events_tx,
+        );
+        let mut connections = BTreeMap::from([("B".to_owned(), connection)]);
+        let (transfer, _done) = transfer(b"header".to_vec(), CHUNK_SIZE * (CHUNK_WINDOW + 1));
+        send_message(&["B".into()], &mut connections, Arc::new(transfer), true)
+            .await
+            .unwrap();
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        assert_eq!(tcp_receive(&mut stream).await.unwrap(), b"header");
+        for _ in 0..CHUNK_WINDOW {
+            tcp_receive(&mut stream).await.unwrap();
+        }
+        let next = tokio::time::timeout(Duration::from_millis(200), tcp_receive(&mut stream));
+        assert!(
+            next.await.is_err(),
+            "sent more chunks than the window allows"
+        );
+
+        tcp_send(&mut stream, &[]).await.unwrap();
+        tcp_receive(&mut stream).await.unwrap();
+    }
+}
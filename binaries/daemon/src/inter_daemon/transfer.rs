@@ -0,0 +1,307 @@
+use aligned_vec::{AVec, ConstAlign};
+use dora_core::{
+    config::{DataId, NodeId},
+    daemon_messages::{DataMessage, DataflowId, DropToken, OutputChunkRef, Timestamped},
+    message::{uhlc, Metadata},
+};
+use eyre::{bail, Context};
+use shared_memory_server::{Shmem, ShmemConf};
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+/// Outputs larger than this are sent to other daemons in chunks of this size.
+///
+/// Each chunk is a separate message, so that other inter-daemon messages are
+/// not limited in size by large outputs.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Maximum number of chunks that are sent to another daemon before it
+/// acknowledges them.
+pub const CHUNK_WINDOW: usize = 8;
+
+/// Incomplete transfers are discarded if no chunk was received for this
+/// duration, e.g. because the sending daemon was lost.
+pub const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Data of an output that is sent to other daemons.
+pub enum OutputData {
+    Vec(AVec<u8, ConstAlign<128>>),
+    /// Shared memory region of the sending node and the data length.
+    SharedMemory(ShmemHandle, usize),
+}
+
+impl OutputData {
+    pub fn as_slice(&self) -> &[u8] {
+        match self {
+            OutputData::Vec(data) => data,
+            OutputData::SharedMemory(memory, len) => &memory.as_slice()[..*len],
+        }
+    }
+}
+
+/// An output that is sent to other daemons in chunks.
+///
+/// The chunks are serialized one at a time while sending, directly from the
+/// output data. The receiver returned by [`new`](Self::new) is closed when
+/// the transfer is dropped, i.e. once it was sent to all machines or
+/// discarded.
+pub struct OutgoingTransfer {
+    /// Serialized `LargeOutput` event
+    header: Vec<u8>,
+    dataflow_id: DataflowId,
+    transfer_id: Uuid,
+    data: OutputData,
+    timestamp: uhlc::Timestamp,
+    _done: oneshot::Sender<()>,
+}
+
+impl OutgoingTransfer {
+    pub fn new(
+        header: Vec<u8>,
+        dataflow_id: DataflowId,
+        transfer_id: Uuid,
+        data: OutputData,
+        timestamp: uhlc::Timestamp,
+    ) -> (Self, oneshot::Receiver<()>) {
+        let (done, done_rx) = oneshot::channel();
+        let transfer = Self {
+            header,
+            dataflow_id,
+            transfer_id,
+            data,
+            timestamp,
+            _done: done,
+        };
+        (transfer, done_rx)
+    }
+
+    /// Number of messages, i.e. the header and the chunks.
+    pub fn message_count(&self) -> usize {
+        1 + self.data.as_slice().len().div_ceil(CHUNK_SIZE)
+    }
+
+    /// Returns the header for index 0 and serializes the corresponding chunk
+    /// otherwise.
+    pub fn message(&self, index: usize) -> eyre::Result<Cow<'_, [u8]>> {
+        let Some(chunk_index) = index.checked_sub(1) else {
+            return Ok(Cow::Borrowed(&self.header));
+        };
+        let offset = chunk_index * CHUNK_SIZE;
+        let data = self.data.as_slice();
+        let chunk = data
+            .get(offset..)
+            .map(|rest| &rest[..rest.len().min(CHUNK_SIZE)])
+            .ok_or_else(|| eyre::eyre!("transfer has no chunk {chunk_index}"))?;
+        let event = Timestamped {
+            inner: OutputChunkRef {
+                dataflow_id: self.dataflow_id,
+                transfer_id: self.transfer_id,
+                offset,
+                data: chunk,
+            },
+            timestamp: self.timestamp,
+        };
+        bincode::serialize(&event)
+            .map(Cow::Owned)
+            .wrap_err("failed to serialize output chunk")
+    }
+}
+
+/// An output that is received in chunks from another daemon.
+///
+/// The chunks are written directly into a shared memory region, which is
+/// then passed to the local receivers without further copies.
+pub struct IncomingTransfer {
+    pub node_id: NodeId,
+    pub output_id: DataId,
+    pub metadata: Metadata,
+    memory: ShmemHandle,
+    len: usize,
+    /// Which chunks were already received, by chunk index.
+    received: Vec<bool>,
+    missing: usize,
+    last_chunk: Instant,
+}
+
+impl IncomingTransfer {
+    pub fn new(
+        node_id: NodeId,
+        output_id: DataId,
+        metadata: Metadata,
+        len: usize,
+    ) -> eyre::Result<Self> {
+        let memory = ShmemConf::new()
+            .size(len)
+            .writable(true)
+            .create()
+            .wrap_err("failed to allocate shared memory for remote output")?;
+        let chunks = len.div_ceil(CHUNK_SIZE);
+        Ok(Self {
+            node_id,
+            output_id,
+            metadata,
+            memory: ShmemHandle(memory),
+            len,
+            received: vec![false; chunks],
+            missing: chunks,
+            last_chunk: Instant::now(),
+        })
+    }
+
+    /// Copies the given chunk into the shared memory region.
+    ///
+    /// Returns `true` once all chunks were received. Duplicate chunks, e.g.
+    /// resent after a reconnect, are written again but counted only once.
+    pub fn write_chunk(&mut self, offset: usize, data: &[u8]) -> eyre::Result<bool> {
+        let expected_len = self.len.saturating_sub(offset).min(CHUNK_SIZE);
+        if offset % CHUNK_SIZE != 0 || offset >= self.len || data.len() != expected_len {
+            bail!(
+                "invalid chunk at offset {offset} with length {} for output length {}",
+                data.len(),
+                self.len
+            );
+        }
+        let memory = unsafe { self.memory.0.as_slice_mut() };
+        memory[offset..][..data.len()].copy_from_slice(data);
+        self.last_chunk = Instant::now();
+
+        let received = &mut self.received[offset / CHUNK_SIZE];
+        if !*received {
+            *received = true;
+            self.missing -= 1;
+        }
+        Ok(self.missing == 0)
+    }
+
+    /// Whether no chunk was received for [`TRANSFER_TIMEOUT`].
+    pub fn is_stale(&self) -> bool {
+        self.last_chunk.elapsed() > TRANSFER_TIMEOUT
+    }
+
+    /// Returns the data message that refers to the received data and the
+    /// region, which needs to be kept alive until the drop token is released.
+    pub fn finish(self) -> (NodeId, DataId, Metadata, DataMessage, ShmemHandle) {
+        let data = DataMessage::SharedMemory {
+            shared_memory_id: self.memory.0.get_os_id().to_owned(),
+            len: self.len,
+            drop_token: DropToken::generate(),
+        };
+        (
+            self.node_id,
+            self.output_id,
+            self.metadata,
+            data,
+            self.memory,
+        )
+    }
+}
+
+pub struct ShmemHandle(Shmem);
+
+impl ShmemHandle {
+    pub fn open(os_id: &str) -> eyre::Result<Self> {
+        let memory = ShmemConf::new()
+            .os_id(os_id)
+            .open()
+            .wrap_err("failed to map shared memory output")?;
+        Ok(Self(memory))
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { self.0.as_slice() }
+    }
+}
+
+unsafe impl Send for ShmemHandle {}
+unsafe impl Sync for ShmemHandle {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dora_core::{
+        daemon_messages::InterDaemonEvent,
+        message::{uhlc::HLC, ArrowTypeInfo},
+    };
+
+    #[test]
+    fn reassemble_chunks() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let mut transfer = IncomingTransfer::new(
+            NodeId::from("source".to_owned()),
+            "output".to_owned().into(),
+            Metadata::new(HLC::default().new_timestamp(), ArrowTypeInfo::empty()),
+            data.len(),
+        )
+        .unwrap();
+
+        let chunks: Vec<_> = data.chunks(CHUNK_SIZE).enumerate().collect();
+        // chunks may arrive out of order
+        for (index, chunk) in chunks.iter().rev() {
+            let complete = transfer.write_chunk(index * CHUNK_SIZE, chunk).unwrap();
+            assert_eq!(complete, *index == 0);
+        }
+        assert!(transfer.write_chunk(data.len() - 5, &[0; 10]).is_err());
+        assert!(transfer.write_chunk(CHUNK_SIZE, &[0; 10]).is_err());
+
+        let (_, _, _, message, memory) = transfer.finish();
+        match message {
+            DataMessage::SharedMemory { len, .. } => assert_eq!(len, data.len()),
+            DataMessage::Vec(_) => panic!("expected shared memory message"),
+        }
+        assert_eq!(&memory.as_slice()[..data.len()], &data[..]);
+    }
+
+    #[test]
+    fn serialize_chunks_from_borrowed_data() {
+        let output: Vec<u8> = (0..CHUNK_SIZE + 10).map(|i| i as u8).collect();
+        let (transfer, mut done) = OutgoingTransfer::new(
+            b"header".to_vec(),
+            DataflowId::nil(),
+            Uuid::nil(),
+            OutputData::Vec(AVec::from_slice(128, &output)),
+            HLC::default().new_timestamp(),
+        );
+        assert_eq!(transfer.message_count(), 3);
+        assert_eq!(&transfer.message(0).unwrap()[..], b"header");
+
+        let chunk: Timestamped<InterDaemonEvent> =
+            bincode::deserialize(&transfer.message(2).unwrap()).unwrap();
+        match chunk.inner {
+            InterDaemonEvent::OutputChunk { offset, data, .. } => {
+                assert_eq!(offset, CHUNK_SIZE);
+                assert_eq!(data, &output[CHUNK_SIZE..]);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert!(transfer.message(3).is_err());
+
+        assert!(done.try_recv().is_err());
+        drop(transfer);
+        assert_eq!(done.try_recv(), Err(oneshot::error::TryRecvError::Closed));
+    }
+
+    #[test]
+    fn count_duplicate_chunks_once() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let mut transfer = IncomingTransfer::new(
+            NodeId::from("source".to_owned()),
+            "output".to_owned().into(),
+            Metadata::new(HLC::default().new_timestamp(), ArrowTypeInfo::empty()),
+            data.len(),
+        )
+        .unwrap();
+        let chunks: Vec<_> = data.chunks(CHUNK_SIZE).collect();
+
+        assert!(!transfer.write_chunk(0, chunks[0]).unwrap());
+        assert!(!transfer.write_chunk(CHUNK_SIZE, chunks[1]).unwrap());
+        // resent chunks must not complete the transfer
+        assert!(!transfer.write_chunk(CHUNK_SIZE, chunks[1]).unwrap());
+        assert!(!transfer.write_chunk(0, chunks[0]).unwrap());
+        assert!(!transfer.is_stale());
+        assert!(transfer.write_chunk(2 * CHUNK_SIZE, chunks[2]).unwrap());
+    }
+}
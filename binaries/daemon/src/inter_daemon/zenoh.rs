@@ -0,0 +1,343 @@
+use communication_layer_pub_sub::{zenoh::ZenohCommunicationLayer, CommunicationLayer, Publisher};
+use dora_core::daemon_messages::{DataflowId, InterDaemonEvent, Timestamped};
+use eyre::{bail, eyre, Context};
+use futures::Future;
+use std::{
+    collections::{btree_map, BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{oneshot, watch};
+use zenoh::prelude::{sync::SyncResolve, SessionDeclarations, SplitBuffer};
+
+/// Maximum time to wait for the handshake replies of the other machines.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Interval in which handshake requests are repeated until they are answered.
+const HANDSHAKE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+const EVENTS_TOPIC: &str = "events";
+const HANDSHAKE_TOPIC: &str = "handshake";
+const PING: u8 = 0;
+const PONG: u8 = 1;
+
+/// Zenoh session of a dataflow.
+///
+/// Each daemon subscribes to the `<prefix>/<dataflow_id>/<machine_id>/*` key
+/// expression and publishes the events for other machines to their `events`
+/// topic. Samples on the `handshake` topic are used to check that the
+/// subscriptions of all machines are propagated (see
+/// [`handshake`](Self::handshake)).
+pub struct ZenohConnection {
+    inner: Arc<Mutex<Inner>>,
+    dataflow_id: DataflowId,
+    machine_id: String,
+    /// The other machines of the dataflow.
+    peers: BTreeSet<String>,
+    /// Machines that answered a handshake request of this daemon.
+    answered: watch::Receiver<BTreeSet<String>>,
+    /// Stops the receive task.
+    stop: oneshot::Sender<()>,
+}
+
+struct Inner {
+    layer: ZenohCommunicationLayer,
+    publishers: BTreeMap<String, Box<dyn Publisher>>,
+}
+
+impl Inner {
+    fn publish(&mut self, topic: &str, message: &[u8]) -> eyre::Result<()> {
+        let publisher = match self.publishers.entry(topic.to_owned()) {
+            btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            btree_map::Entry::Vacant(entry) => {
+                let publisher = self
+                    .layer
+                    .publisher(topic)
+                    .map_err(|err| eyre!(err))
+                    .wrap_err_with(|| format!("failed to create zenoh publisher for `{topic}`"))?;
+                entry.insert(publisher)
+            }
+        };
+        publisher
+            .publish(message)
+            .map_err(|err| eyre!(err))
+            .wrap_err_with(|| format!("failed to publish to `{topic}`"))
+    }
+}
+
+impl ZenohConnection {
+    pub async fn open(
+        config: ::zenoh::config::Config,
+        prefix: String,
+        dataflow_id: DataflowId,
+        machine_id: &str,
+        peers: BTreeSet<String>,
+        events_tx: flume::Sender<Timestamped<InterDaemonEvent>>,
+    ) -> eyre::Result<Self> {
+        let key_expr = format!("{prefix}/{}/*", topic(dataflow_id, machine_id));
+        let handshake_suffix = format!("/{HANDSHAKE_TOPIC}");
+        // zenoh's sync API blocks the current thread
+        let (layer, subscriber) = tokio::task::spawn_blocking(move || {
+            let layer = ZenohCommunicationLayer::init(config, prefix).map_err(|err| eyre!(err))?;
+            let subscriber = layer
+                .session()
+                .declare_subscriber(&key_expr)
+                .reliable()
+                .res_sync()
+                .map_err(|err| eyre!(err))
+                .wrap_err_with(|| format!("failed to subscribe to `{key_expr}`"))?;
+            eyre::Ok((layer, subscriber))
+        })
+        .await??;
+        let inner = Arc::new(Mutex::new(Inner {
+            layer,
+            publishers: BTreeMap::new(),
+        }));
+
+        let (answered_tx, answered) = watch::channel(BTreeSet::new());
+        let (stop, mut stop_rx) = oneshot::channel();
+        let receive = {
+            // the session is closed once the last reference is dropped, which
+            // blocks, so the task must not keep it alive
+            let inner = Arc::downgrade(&inner);
+            let machine_id = machine_id.to_owned();
+            async move {
+                loop {
+                    let sample = tokio::select! {
+                        sample = subscriber.recv_async() => match sample {
+                            Ok(sample) => sample,
+                            Err(_) => break,
+                        },
+                        _ = &mut stop_rx => break,
+                    };
+                    let raw = sample.value.payload.contiguous();
+                    if sample.key_expr.as_str().ends_with(&handshake_suffix) {
+                        match raw.split_first() {
+                            Some((&PING, from)) => {
+                                let from = String::from_utf8_lossy(from).into_owned();
+                                let pong = handshake_message(PONG, &machine_id);
+                                let topic =
+                                    format!("{}/{HANDSHAKE_TOPIC}", topic(dataflow_id, &from));
+                                let Some(inner) = inner.upgrade() else {
+                                    break;
+                                };
+                                let result = tokio::task::spawn_blocking(move || {
+                                    inner
+                                        .lock()
+                                        .map_err(|_| eyre!("zenoh connection poisoned"))?
+                                        .publish(&topic, &pong)
+                                })
+                                .await;
+                                if let Err(err) = result.map_err(eyre::Report::new).and_then(|r| r)
+                                {
+                                    tracing::warn!("failed to answer zenoh handshake: {err:?}");
+                                }
+                            }
+                            Some((&PONG, from)) => {
+                                let from = String::from_utf8_lossy(from).into_owned();
+                                answered_tx.send_modify(|answered| {
+                                    answered.insert(from);
+                                });
+                            }
+                            _ => tracing::warn!("received invalid zenoh handshake message"),
+                        }
+                        continue;
+                    }
+                    match bincode::deserialize(&raw) {
+                        Ok(event) => {
+                            if events_tx.send_async(event).await.is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) => tracing::warn!("failed to deserialize InterDaemonEvent: {err}"),
+                    }
+                }
+                // dropping the subscriber undeclares it
+            }
+        };
+        tokio::spawn(receive);
+
+        Ok(Self {
+            inner,
+            dataflow_id,
+            machine_id: machine_id.to_owned(),
+            peers,
+            answered,
+            stop,
+        })
+    }
+
+    /// Checks that all other machines of the dataflow receive the events of
+    /// this machine and vice versa.
+    ///
+    /// Zenoh propagates subscriptions asynchronously, so messages that are
+    /// published right after opening the session might be lost otherwise.
+    /// The handshake request is repeated until each other machine answered
+    /// it, which requires the subscriptions of both machines to be known.
+    pub fn handshake(&self) -> impl Future<Output = eyre::Result<()>> + Send + 'static {
+        let inner = Arc::downgrade(&self.inner);
+        let dataflow_id = self.dataflow_id;
+        let peers = self.peers.clone();
+        let ping = handshake_message(PING, &self.machine_id);
+        let mut answered = self.answered.clone();
+        async move {
+            let start = Instant::now();
+            loop {
+                let missing: Vec<_> = peers.difference(&answered.borrow()).cloned().collect();
+                if missing.is_empty() {
+                    return Ok(());
+                }
+                if start.elapsed() > HANDSHAKE_TIMEOUT {
+                    bail!("no zenoh handshake reply from machines {missing:?}");
+                }
+                let inner = inner
+                    .upgrade()
+                    .ok_or_else(|| eyre!("zenoh connection was closed"))?;
+                let ping = ping.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut inner = inner
+                        .lock()
+                        .map_err(|_| eyre!("zenoh connection poisoned"))?;
+                    for machine in missing {
+                        let topic = format!("{}/{HANDSHAKE_TOPIC}", topic(dataflow_id, &machine));
+                        inner.publish(&topic, &ping)?;
+                    }
+                    eyre::Ok(())
+                })
+                .await??;
+                // time out to repeat the requests
+                let _ = tokio::time::timeout(HANDSHAKE_RETRY_INTERVAL, answered.changed()).await;
+            }
+        }
+    }
+
+    pub async fn send(&self, target_machines: &[String], message: Vec<u8>) -> eyre::Result<()> {
+        let inner = self.inner.clone();
+        let topics: Vec<_> = target_machines
+            .iter()
+            .map(|machine| format!("{}/{EVENTS_TOPIC}", topic(self.dataflow_id, machine)))
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            let mut inner = inner
+                .lock()
+                .map_err(|_| eyre!("zenoh connection poisoned"))?;
+            for topic in topics {
+                inner.publish(&topic, &message)?;
+            }
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Stops the receive task and closes the session in the background.
+    pub fn close(self) {
+        let Self { inner, stop, .. } = self;
+        let _ = stop.send(());
+        // dropping the layer blocks until pending messages are sent out
+        tokio::task::spawn_blocking(move || drop(inner));
+    }
+}
+
+fn topic(dataflow_id: DataflowId, machine_id: &str) -> String {
+    // key expressions must not contain empty chunks
+    let machine_id = if machine_id.is_empty() {
+        "_"
+    } else {
+        machine_id
+    };
+    format!("{dataflow_id}/{machine_id}")
+}
+
+fn handshake_message(kind: u8, machine_id: &str) -> Vec<u8> {
+    let mut message = vec![kind];
+    message.extend_from_slice(machine_id.as_bytes());
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dora_core::{config::NodeId, message::uhlc::HLC};
+    use uuid::{NoContext, Timestamp, Uuid};
+
+    /// Uses a local peer-to-peer session, without multicast scouting.
+    #[tokio::test]
+    async fn send_to_other_machine() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let endpoint = format!("tcp/127.0.0.1:{port}");
+        let config = |listen: bool| {
+            let mut config = ::zenoh::config::Config::default();
+            config.scouting.multicast.set_enabled(Some(false)).unwrap();
+            if listen {
+                config.listen.endpoints = vec![endpoint.parse().unwrap()];
+            } else {
+                config.connect.endpoints = vec![endpoint.parse().unwrap()];
+            }
+            config
+        };
+
+        let dataflow_id = Uuid::new_v7(Timestamp::now(NoContext));
+        let (a_tx, _a_rx) = flume::unbounded();
+        let (b_tx, b_rx) = flume::unbounded();
+        let peers = |machine: &str| [machine.to_owned()].into();
+        let a = ZenohConnection::open(
+            config(true),
+            "dora-test".into(),
+            dataflow_id,
+            "A",
+            peers("B"),
+            a_tx,
+        )
+        .await
+        .unwrap();
+        let b = ZenohConnection::open(
+            config(false),
+            "dora-test".into(),
+            dataflow_id,
+            "B",
+            peers("A"),
+            b_tx,
+        )
+        .await
+        .unwrap();
+        let (a_ready, b_ready) = tokio::join!(a.handshake(), b.handshake());
+        a_ready.unwrap();
+        b_ready.unwrap();
+
+        let inputs = [(NodeId::from("sink".to_owned()), "input".to_owned().into())].into();
+        let event = Timestamped {
+            inner: InterDaemonEvent::InputsClosed {
+                dataflow_id,
+                inputs,
+            },
+            timestamp: HLC::default().new_timestamp(),
+        };
+        let message = bincode::serialize(&event).unwrap();
+
+        // the first message must arrive once the handshake is done
+        a.send(&["B".to_owned()], message).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(5), b_rx.recv_async())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        match received.map(|e| e.inner) {
+            Some(InterDaemonEvent::InputsClosed {
+                dataflow_id: id,
+                inputs,
+            }) => {
+                assert_eq!(id, dataflow_id);
+                assert_eq!(inputs.len(), 1);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        a.close();
+        b.close();
+        // the receive task stops and drops its event sender
+        let closed = tokio::time::timeout(Duration::from_secs(5), b_rx.recv_async()).await;
+        assert!(matches!(closed, Ok(Err(flume::RecvError::Disconnected))));
+    }
+}
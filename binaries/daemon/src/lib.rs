@@ -1,41 +1,45 @@
-use aligned_vec::{AVec, ConstAlign};
-use coordinator::CoordinatorEvent;
-use dora_core::config::{Input, OperatorId};
+use communication_layer_request_reply::security::{MaybeTlsStream, Security};
+use coordinator::{CoordinatorConnection, CoordinatorEvent, CoordinatorLink};
+use dora_core::config::{Input, OperatorId, QueuePolicy};
 use dora_core::coordinator_messages::CoordinatorRequest;
 use dora_core::daemon_messages::{DataMessage, InterDaemonEvent, Timestamped};
+use dora_core::descriptor::RestartMode;
 use dora_core::message::uhlc::{self, HLC};
 use dora_core::message::{ArrowTypeInfo, Metadata, MetadataParameters};
 use dora_core::{
-    config::{DataId, InputMapping, NodeId},
+    config::{DataId, InputMapping, NodeId, TimerSpec},
     coordinator_messages::DaemonEvent,
     daemon_messages::{
         self, DaemonCoordinatorEvent, DaemonCoordinatorReply, DaemonReply, DataflowId, DropToken,
         SpawnDataflowNodes,
     },
     descriptor::{CoreNodeKind, Descriptor, ResolvedNode},
+    logs::LogFilter,
+    topics::{DataflowStats, LogChunk, LogPosition, NodeExitStatus, NodeInfo, NodeState},
 };
 
 use eyre::{bail, eyre, Context, ContextCompat};
 use futures::{future, stream, FutureExt, TryFutureExt};
 use futures_concurrency::stream::Merge;
-use inter_daemon::InterDaemonConnection;
+pub use inter_daemon::{DropPolicy, PeerBufferConfig};
+use inter_daemon::{IncomingTransfer, InterDaemonConnections, OutputData, PeerEvent, ShmemHandle};
+pub use log::{LogConfig, LogFormat, RetentionPolicy};
+use log::{LogFollower, LogIndex};
 use pending::PendingNodes;
-use shared_memory_server::ShmemConf;
+use rand::Rng;
+use stats::OutputStats;
 use std::sync::Arc;
 use std::time::Instant;
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, BTreeSet, HashMap},
-    io,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     net::SocketAddr,
     path::{Path, PathBuf},
     time::Duration,
 };
 use sysinfo::Pid;
-use tcp_utils::tcp_send;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
-use tokio::net::TcpStream;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot::Sender;
 use tokio::sync::{mpsc, oneshot};
@@ -49,6 +53,7 @@ mod log;
 mod node_communication;
 mod pending;
 mod spawn;
+mod stats;
 mod tcp_utils;
 
 #[cfg(feature = "telemetry")]
@@ -56,17 +61,39 @@ use dora_tracing::telemetry::serialize_context;
 #[cfg(feature = "telemetry")]
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use crate::node_communication::{InputQueue, QueueGauge, DEFAULT_QUEUE_SIZE};
 use crate::pending::DataflowStatus;
 
+/// Time for which the log indexes of a finished dataflow are kept.
+///
+/// Afterwards, [`LogPosition::Since`] reads all log output of the dataflow.
+const LOG_INDEX_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Interval for applying the `max_age` retention policy while no dataflow
+/// finishes.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+
 pub struct Daemon {
     running: HashMap<DataflowId, RunningDataflow>,
     working_dir: HashMap<DataflowId, PathBuf>,
+    /// Kept for [`LOG_INDEX_TTL`] after the dataflow finished to look up
+    /// logs by time.
+    log_indexes: HashMap<(DataflowId, NodeId), Arc<LogIndex>>,
+    /// Finished dataflows, by the time their log indexes expire.
+    log_index_expiry: VecDeque<(Instant, DataflowId)>,
+    /// Log subscriptions of the coordinator.
+    log_followers: HashMap<Uuid, LogFollowerHandle>,
+    log_config: LogConfig,
+    last_retention: Instant,
 
     events_tx: mpsc::Sender<Timestamped<Event>>,
 
-    coordinator_connection: Option<TcpStream>,
+    coordinator_connection: Option<CoordinatorConnection>,
+    /// Used to register again after the connection to the coordinator was
+    /// lost. Not set when running a single dataflow locally.
+    coordinator_link: Option<CoordinatorLink>,
     last_coordinator_heartbeat: Instant,
-    inter_daemon_connections: BTreeMap<String, InterDaemonConnection>,
+    inter_daemon_connections: InterDaemonConnections,
     machine_id: String,
 
     /// used for testing and examples
@@ -82,6 +109,9 @@ impl Daemon {
         coordinator_addr: SocketAddr,
         machine_id: String,
         bind_addr: SocketAddr,
+        peer_buffer: PeerBufferConfig,
+        log_config: LogConfig,
+        security: Security,
     ) -> eyre::Result<()> {
         let clock = Arc::new(HLC::default());
 
@@ -89,32 +119,29 @@ impl Daemon {
 
         // spawn listen loop
         let (events_tx, events_rx) = flume::bounded(10);
-        let listen_port =
-            inter_daemon::spawn_listener_loop(bind_addr, machine_id.clone(), events_tx).await?;
+        let listen_port = inter_daemon::spawn_listener_loop(
+            bind_addr,
+            machine_id.clone(),
+            events_tx.clone(),
+            security.clone(),
+        )
+        .await?;
         let daemon_events = events_rx.into_stream().map(|e| Timestamped {
             inner: Event::Daemon(e.inner),
             timestamp: e.timestamp,
         });
 
-        // connect to the coordinator
-        let coordinator_events =
-            coordinator::register(coordinator_addr, machine_id.clone(), listen_port, &clock)
-                .await
-                .wrap_err("failed to connect to dora-coordinator")?
-                .map(
-                    |Timestamped {
-                         inner: event,
-                         timestamp,
-                     }| Timestamped {
-                        inner: Event::Coordinator(event),
-                        timestamp,
-                    },
-                );
+        let coordinator_link =
+            CoordinatorLink::new(coordinator_addr, listen_port, security.clone());
 
         Self::run_general(
-            (coordinator_events, ctrlc_events, daemon_events).merge(),
-            Some(coordinator_addr),
+            (ctrlc_events, daemon_events).merge(),
+            Some(coordinator_link),
             machine_id,
+            Some(events_tx),
+            peer_buffer,
+            log_config,
+            security,
             None,
             clock,
         )
@@ -164,6 +191,10 @@ impl Daemon {
             Box::pin(coordinator_events),
             None,
             "".to_string(),
+            None,
+            PeerBufferConfig::default(),
+            LogConfig::default(),
+            Security::default(),
             Some(exit_when_done),
             clock,
         );
@@ -195,34 +226,63 @@ impl Daemon {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn run_general(
         external_events: impl Stream<Item = Timestamped<Event>> + Unpin,
-        coordinator_addr: Option<SocketAddr>,
+        mut coordinator_link: Option<CoordinatorLink>,
         machine_id: String,
+        inter_daemon_events_tx: Option<flume::Sender<Timestamped<InterDaemonEvent>>>,
+        peer_buffer: PeerBufferConfig,
+        log_config: LogConfig,
+        security: Security,
         exit_when_done: Option<BTreeSet<(Uuid, NodeId)>>,
         clock: Arc<HLC>,
     ) -> eyre::Result<BTreeMap<Uuid, BTreeMap<NodeId, eyre::Report>>> {
-        let coordinator_connection = match coordinator_addr {
-            Some(addr) => {
-                let stream = TcpStream::connect(addr)
+        let (dora_events_tx, dora_events_rx) = mpsc::channel(5);
+
+        let coordinator_connection = match &mut coordinator_link {
+            Some(link) => {
+                let stream = link
+                    .connect()
+                    .await
+                    .wrap_err("failed to connect to dora-coordinator")?;
+                link.register(
+                    stream,
+                    machine_id.clone(),
+                    Vec::new(), // no dataflows are running yet
+                    clock.clone(),
+                    dora_events_tx.clone(),
+                )
+                .await
+                .wrap_err("failed to register at dora-coordinator")?;
+                let stream = link
+                    .connect()
                     .await
                     .wrap_err("failed to connect to dora-coordinator")?;
-                stream
-                    .set_nodelay(true)
-                    .wrap_err("failed to set TCP_NODELAY")?;
-                Some(stream)
+                Some(CoordinatorConnection::new(stream))
             }
             None => None,
         };
 
-        let (dora_events_tx, dora_events_rx) = mpsc::channel(5);
         let daemon = Self {
             running: HashMap::new(),
             working_dir: HashMap::new(),
-            events_tx: dora_events_tx,
+            log_indexes: HashMap::new(),
+            log_index_expiry: VecDeque::new(),
+            log_followers: HashMap::new(),
+            log_config,
+            last_retention: Instant::now(),
+            events_tx: dora_events_tx.clone(),
             coordinator_connection,
+            coordinator_link,
             last_coordinator_heartbeat: Instant::now(),
-            inter_daemon_connections: BTreeMap::new(),
+            inter_daemon_connections: InterDaemonConnections::new(
+                machine_id.clone(),
+                inter_daemon_events_tx,
+                peer_buffer,
+                security,
+                dora_events_tx,
+            ),
             machine_id,
             exit_when_done,
             dataflow_errors: BTreeMap::new(),
@@ -267,6 +327,7 @@ impl Daemon {
                 Event::Daemon(event) => {
                     self.handle_inter_daemon_event(event).await?;
                 }
+                Event::Peer(event) => self.handle_peer_event(event).await?,
                 Event::Node {
                     dataflow_id: dataflow,
                     node_id,
@@ -278,21 +339,29 @@ impl Daemon {
                 },
                 Event::HeartbeatInterval => {
                     if let Some(connection) = &mut self.coordinator_connection {
-                        let msg = serde_json::to_vec(&Timestamped {
-                            inner: CoordinatorRequest::Event {
-                                machine_id: self.machine_id.clone(),
-                                event: DaemonEvent::Heartbeat,
-                            },
-                            timestamp: self.clock.new_timestamp(),
-                        })?;
-                        tcp_send(connection, &msg)
-                            .await
-                            .wrap_err("failed to send watchdog message to dora-coordinator")?;
+                        connection
+                            .send_droppable(Timestamped {
+                                inner: CoordinatorRequest::Event {
+                                    machine_id: self.machine_id.clone(),
+                                    event: DaemonEvent::Heartbeat,
+                                },
+                                timestamp: self.clock.new_timestamp(),
+                            })
+                            .await?;
 
                         if self.last_coordinator_heartbeat.elapsed() > Duration::from_secs(20) {
-                            bail!("lost connection to coordinator")
+                            self.handle_coordinator_lost();
                         }
                     }
+                    for dataflow in self.running.values_mut() {
+                        dataflow.discard_stale_transfers();
+                    }
+                    self.remove_expired_log_indexes();
+                    self.apply_periodic_retention();
+                }
+                Event::CoordinatorDisconnected => self.handle_coordinator_lost(),
+                Event::CoordinatorReconnected { register, events } => {
+                    self.handle_coordinator_reconnected(register, events).await;
                 }
                 Event::CtrlC => {
                     for dataflow in self.running.values_mut() {
@@ -318,25 +387,23 @@ impl Daemon {
                 machine_listen_ports,
                 dataflow_descriptor,
             }) => {
-                match dataflow_descriptor.communication.remote {
-                    dora_core::config::RemoteCommunicationConfig::Tcp => {}
-                }
-                for (machine_id, socket) in machine_listen_ports {
-                    match self.inter_daemon_connections.entry(machine_id) {
-                        std::collections::btree_map::Entry::Vacant(entry) => {
-                            entry.insert(InterDaemonConnection::new(socket));
-                        }
-                        std::collections::btree_map::Entry::Occupied(mut entry) => {
-                            if entry.get().socket() != socket {
-                                entry.insert(InterDaemonConnection::new(socket));
-                            }
-                        }
-                    }
-                }
-
                 let result = self
-                    .spawn_dataflow(dataflow_id, working_dir, nodes, dataflow_descriptor)
-                    .await;
+                    .inter_daemon_connections
+                    .open(
+                        dataflow_id,
+                        &dataflow_descriptor.communication.remote,
+                        machine_listen_ports,
+                        &working_dir,
+                    )
+                    .await
+                    .wrap_err("failed to set up connections to remote daemons");
+                let result = match result {
+                    Ok(()) => {
+                        self.spawn_dataflow(dataflow_id, working_dir, nodes, dataflow_descriptor)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                };
                 if let Err(err) = &result {
                     tracing::error!("{err:?}");
                 }
@@ -373,6 +440,65 @@ impl Daemon {
                 });
                 RunStatus::Continue
             }
+            DaemonCoordinatorEvent::NodeReady {
+                dataflow_id,
+                node_id,
+            } => {
+                self.handle_node_ready(dataflow_id, node_id).await?;
+                let _ = reply_tx.send(None).map_err(|_| {
+                    error!("could not send `NodeReady` reply from daemon to coordinator")
+                });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::NodeFailed {
+                dataflow_id,
+                node_id,
+            } => {
+                match self.running.get_mut(&dataflow_id) {
+                    Some(dataflow) => {
+                        Self::give_up_dependents(
+                            dataflow,
+                            &node_id,
+                            &mut self.coordinator_connection,
+                            &self.clock,
+                        )
+                        .await?;
+                        self.finish_dataflow_if_done(dataflow_id).await?;
+                    }
+                    None => {
+                        tracing::warn!(
+                            "node `{node_id}` failed, but dataflow `{dataflow_id}` is unknown"
+                        );
+                    }
+                }
+                let _ = reply_tx.send(None).map_err(|_| {
+                    error!("could not send `NodeFailed` reply from daemon to coordinator")
+                });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::NodesLost { dataflow_id, nodes } => {
+                match self.running.get_mut(&dataflow_id) {
+                    Some(dataflow) => {
+                        tracing::warn!(
+                            "closing inputs from lost nodes {nodes:?} of dataflow `{dataflow_id}`"
+                        );
+                        send_input_closed_events(
+                            dataflow,
+                            &mut self.inter_daemon_connections,
+                            |OutputId(source_id, _)| nodes.contains(source_id),
+                            &self.clock,
+                        )
+                        .await?;
+                    }
+                    None => {
+                        tracing::warn!("received NodesLost for unknown dataflow `{dataflow_id}`");
+                    }
+                }
+                let _ = reply_tx.send(None).map_err(|_| {
+                    error!("could not send `NodesLost` reply from daemon to coordinator")
+                });
+                RunStatus::Continue
+            }
             DaemonCoordinatorEvent::Logs {
                 dataflow_id,
                 node_id,
@@ -380,15 +506,14 @@ impl Daemon {
                 match self.working_dir.get(&dataflow_id) {
                     Some(working_dir) => {
                         let working_dir = working_dir.clone();
+                        let format = self.log_config.format;
                         tokio::spawn(async move {
                             let logs = async {
-                                let mut file =
-                                    File::open(log::log_path(&working_dir, &dataflow_id, &node_id))
-                                        .await
-                                        .wrap_err(format!(
-                                            "Could not open log file: {:#?}",
-                                            log::log_path(&working_dir, &dataflow_id, &node_id)
-                                        ))?;
+                                let path =
+                                    log::log_path(&working_dir, &dataflow_id, &node_id, format);
+                                let mut file = File::open(&path)
+                                    .await
+                                    .wrap_err(format!("Could not open log file: {path:#?}"))?;
 
                                 let mut contents = vec![];
                                 file.read_to_end(&mut contents)
@@ -416,6 +541,110 @@ impl Daemon {
                 }
                 RunStatus::Continue
             }
+            DaemonCoordinatorEvent::ReadLogs {
+                dataflow_id,
+                nodes,
+                filter,
+            } => {
+                let format = self.log_config.format;
+                let working_dir = match self.working_dir.get(&dataflow_id) {
+                    Some(working_dir) => format.check_filter(&filter).map(|()| working_dir.clone()),
+                    None => Err(eyre!("no dataflow with ID `{dataflow_id}`")),
+                };
+                let working_dir = match working_dir {
+                    Ok(working_dir) => working_dir,
+                    Err(err) => {
+                        let _ = reply_tx
+                            .send(Some(DaemonCoordinatorReply::LogChunks(Err(format!(
+                                "{err:?}"
+                            )))))
+                            .map_err(|_| {
+                                error!("could not send logs reply from daemon to coordinator")
+                            });
+                        return Ok(RunStatus::Continue);
+                    }
+                };
+                let nodes: Vec<_> = nodes
+                    .into_iter()
+                    .map(|(node_id, position)| {
+                        let index = self
+                            .log_indexes
+                            .get(&(dataflow_id, node_id.clone()))
+                            .cloned();
+                        // the last line of a running node might still be incomplete
+                        let running = self
+                            .running
+                            .get(&dataflow_id)
+                            .is_some_and(|dataflow| dataflow.running_nodes.contains_key(&node_id));
+                        (node_id, position, index, running)
+                    })
+                    .collect();
+                tokio::spawn(async move {
+                    let chunks = async {
+                        let mut chunks = Vec::new();
+                        for (node, position, index, running) in nodes {
+                            let path = log::log_path(&working_dir, &dataflow_id, &node, format);
+                            let (data, offset) =
+                                log::read_log(&path, position, index.as_deref(), running, &filter)
+                                    .await
+                                    .wrap_err_with(|| format!("failed to read logs of `{node}`"))?;
+                            chunks.push(LogChunk { node, data, offset });
+                        }
+                        Result::<_, eyre::Report>::Ok(chunks)
+                    }
+                    .await
+                    .map_err(|err| format!("{err:?}"));
+                    let _ = reply_tx
+                        .send(Some(DaemonCoordinatorReply::LogChunks(chunks)))
+                        .map_err(|_| {
+                            error!("could not send logs reply from daemon to coordinator")
+                        });
+                });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::FollowLogs {
+                dataflow_id,
+                subscription,
+                nodes,
+                filter,
+            } => {
+                self.follow_logs(dataflow_id, subscription, nodes, filter)
+                    .await?;
+                let _ = reply_tx.send(None).map_err(|_| {
+                    error!("could not send `FollowLogs` reply from daemon to coordinator")
+                });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::UnfollowLogs { subscription } => {
+                // the follower stops once its finish sender is dropped
+                self.log_followers.remove(&subscription);
+                let _ = reply_tx.send(None).map_err(|_| {
+                    error!("could not send `UnfollowLogs` reply from daemon to coordinator")
+                });
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::Stats { dataflow_id } => {
+                let result = self
+                    .running
+                    .get(&dataflow_id)
+                    .map(|dataflow| dataflow.stats(&self.machine_id))
+                    .ok_or_else(|| format!("no running dataflow with ID `{dataflow_id}`"));
+                let _ = reply_tx
+                    .send(Some(DaemonCoordinatorReply::StatsResult(result)))
+                    .map_err(|_| error!("could not send stats reply from daemon to coordinator"));
+                RunStatus::Continue
+            }
+            DaemonCoordinatorEvent::Inspect { dataflow_id } => {
+                let result = self
+                    .running
+                    .get(&dataflow_id)
+                    .map(|dataflow| dataflow.inspect(&self.machine_id))
+                    .ok_or_else(|| format!("no running dataflow with ID `{dataflow_id}`"));
+                let _ = reply_tx
+                    .send(Some(DaemonCoordinatorReply::InspectResult(result)))
+                    .map_err(|_| error!("could not send inspect reply from daemon to coordinator"));
+                RunStatus::Continue
+            }
             DaemonCoordinatorEvent::ReloadDataflow {
                 dataflow_id,
                 node_id,
@@ -472,6 +701,99 @@ impl Daemon {
         Ok(status)
     }
 
+    /// Keeps the dataflows running and tries to register at the coordinator
+    /// again.
+    fn handle_coordinator_lost(&mut self) {
+        let Some(link) = &mut self.coordinator_link else {
+            return;
+        };
+        if !link.is_registered() {
+            // already reconnecting
+            return;
+        }
+        tracing::warn!("lost connection to dora-coordinator, trying to reconnect");
+        if let Some(connection) = &mut self.coordinator_connection {
+            connection.disconnect();
+        }
+        link.reconnect(self.events_tx.clone(), self.clock.clone());
+    }
+
+    async fn handle_coordinator_reconnected(
+        &mut self,
+        register: MaybeTlsStream,
+        events: MaybeTlsStream,
+    ) {
+        let Some(link) = &mut self.coordinator_link else {
+            return;
+        };
+        // announce the running dataflows so that the coordinator can
+        // rebuild its state
+        let mut running_dataflows: Vec<_> = self.running.keys().copied().collect();
+        if let Some(connection) = &self.coordinator_connection {
+            running_dataflows.extend(connection.unreported_finished_dataflows());
+        }
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            link.register(
+                register,
+                self.machine_id.clone(),
+                running_dataflows,
+                self.clock.clone(),
+                self.events_tx.clone(),
+            ),
+        )
+        .await
+        .wrap_err("timeout")
+        .and_then(|r| r);
+        match result {
+            Ok(()) => {
+                self.last_coordinator_heartbeat = Instant::now();
+                if let Some(connection) = &mut self.coordinator_connection {
+                    if let Err(err) = connection.reconnect(events).await {
+                        tracing::warn!("{err:?}");
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!(
+                    "{:?}",
+                    err.wrap_err("failed to register at dora-coordinator again")
+                );
+                link.reconnect(self.events_tx.clone(), self.clock.clone());
+            }
+        }
+    }
+
+    async fn handle_peer_event(&mut self, event: PeerEvent) -> eyre::Result<()> {
+        let event = match event {
+            PeerEvent::Disconnected { machine_id, error } => {
+                tracing::warn!("lost connection to machine `{machine_id}`: {error}");
+                DaemonEvent::PeerDisconnected {
+                    peer_machine_id: machine_id,
+                    error,
+                }
+            }
+            PeerEvent::Reconnected { machine_id } => {
+                tracing::info!("reconnected to machine `{machine_id}`");
+                DaemonEvent::PeerReconnected {
+                    peer_machine_id: machine_id,
+                }
+            }
+        };
+        if let Some(connection) = &mut self.coordinator_connection {
+            connection
+                .send_droppable(Timestamped {
+                    inner: CoordinatorRequest::Event {
+                        machine_id: self.machine_id.clone(),
+                        event,
+                    },
+                    timestamp: self.clock.new_timestamp(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn handle_inter_daemon_event(&mut self, event: InterDaemonEvent) -> eyre::Result<()> {
         match event {
             InterDaemonEvent::Output {
@@ -482,6 +804,7 @@ impl Daemon {
                 data,
             } => {
                 let inner = async {
+                    metadata.check_version()?;
                     let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
                         format!("send out failed: no running dataflow with ID `{dataflow_id}`")
                     })?;
@@ -490,10 +813,8 @@ impl Daemon {
                         output_id.clone(),
                         dataflow,
                         &metadata,
-                        data.map(DataMessage::Vec),
-                        &self.clock,
-                    )
-                    .await?;
+                        data.map(DataMessage::Vec).as_ref(),
+                    );
                     Result::<_, eyre::Report>::Ok(())
                 };
                 if let Err(err) = inner
@@ -504,6 +825,94 @@ impl Daemon {
                 }
                 Ok(())
             }
+            InterDaemonEvent::LargeOutput {
+                dataflow_id,
+                node_id,
+                output_id,
+                metadata,
+                transfer_id,
+                len,
+            } => {
+                let inner = async {
+                    metadata.check_version()?;
+                    let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
+                        format!("send out failed: no running dataflow with ID `{dataflow_id}`")
+                    })?;
+                    // chunks of a previous transfer might have been dropped while the
+                    // connection was down
+                    dataflow.incoming_transfers.retain(|id, transfer| {
+                        let stale = transfer.node_id == node_id && transfer.output_id == output_id;
+                        if stale {
+                            tracing::warn!("discarding incomplete remote output transfer `{id}`");
+                        }
+                        !stale
+                    });
+                    let transfer = IncomingTransfer::new(node_id, output_id, metadata, len)?;
+                    dataflow.incoming_transfers.insert(transfer_id, transfer);
+                    Result::<_, eyre::Report>::Ok(())
+                };
+                if let Err(err) = inner
+                    .await
+                    .wrap_err("failed to start receiving large remote output")
+                {
+                    tracing::warn!("{err:?}")
+                }
+                Ok(())
+            }
+            InterDaemonEvent::OutputChunk {
+                dataflow_id,
+                transfer_id,
+                offset,
+                data,
+            } => {
+                let inner = async {
+                    let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
+                        format!("send out failed: no running dataflow with ID `{dataflow_id}`")
+                    })?;
+                    let transfer = dataflow
+                        .incoming_transfers
+                        .get_mut(&transfer_id)
+                        .wrap_err_with(|| format!("unknown output transfer `{transfer_id}`"))?;
+                    let complete = match transfer.write_chunk(offset, &data) {
+                        Ok(complete) => complete,
+                        Err(err) => {
+                            dataflow.incoming_transfers.remove(&transfer_id);
+                            return Err(err);
+                        }
+                    };
+                    if complete {
+                        let transfer = dataflow
+                            .incoming_transfers
+                            .remove(&transfer_id)
+                            .expect("transfer was just accessed");
+                        let (node_id, output_id, metadata, data, memory) = transfer.finish();
+                        let drop_token = data.drop_token();
+                        send_output_to_local_receivers(
+                            node_id.clone(),
+                            output_id,
+                            dataflow,
+                            &metadata,
+                            Some(&data),
+                        );
+                        if let Some(token) = drop_token {
+                            // the region is owned by this daemon -> free it once
+                            // all local receivers are done with it
+                            dataflow.received_outputs.insert(token, memory);
+                            dataflow
+                                .release_drop_token(token, node_id, &self.clock)
+                                .await?;
+                        }
+                    }
+                    Result::<_, eyre::Report>::Ok(())
+                };
+                if let Err(err) = inner
+                    .await
+                    .wrap_err("failed to receive chunk of large remote output")
+                {
+                    tracing::warn!("{err:?}")
+                }
+                Ok(())
+            }
             InterDaemonEvent::InputsClosed {
                 dataflow_id,
                 inputs,
@@ -536,7 +945,8 @@ impl Daemon {
         nodes: Vec<ResolvedNode>,
         dataflow_descriptor: Descriptor,
     ) -> eyre::Result<()> {
-        let dataflow = RunningDataflow::new(dataflow_id, self.machine_id.clone());
+        let dataflow =
+            RunningDataflow::new(dataflow_id, self.machine_id.clone(), dataflow_descriptor);
         let dataflow = match self.running.entry(dataflow_id) {
             std::collections::hash_map::Entry::Vacant(entry) => {
                 self.working_dir.insert(dataflow_id, working_dir.clone());
@@ -547,12 +957,60 @@ impl Daemon {
             }
         };
 
-        for node in nodes {
+        if let Some(handshake) = self.inter_daemon_connections.handshake(dataflow_id) {
+            dataflow.pending_nodes.set_remote_links_pending();
+            let events_tx = self.events_tx.clone();
+            let clock = self.clock.clone();
+            tokio::spawn(async move {
+                let result = handshake.await;
+                let event = Timestamped {
+                    inner: DoraEvent::RemoteLinksReady {
+                        dataflow_id,
+                        result,
+                    }
+                    .into(),
+                    timestamp: clock.new_timestamp(),
+                };
+                let _ = events_tx.send(event).await;
+            });
+        }
+
+        for node in nodes.iter().filter(|n| n.deploy.machine != self.machine_id) {
+            dataflow
+                .remote_dependencies
+                .extend(node.depends_on.iter().cloned());
+        }
+        dataflow.ready_signal_nodes = nodes
+            .iter()
+            .filter(|n| n.deploy.machine == self.machine_id && n.ready_signal)
+            .map(|n| n.id.clone())
+            .collect();
+
+        // set up all input queues first, so that spawned nodes know which of
+        // their outputs have `block_sender` receivers
+        for node in &nodes {
             let local = node.deploy.machine == self.machine_id;
 
-            let inputs = node_inputs(&node);
+            let inputs = node_inputs(node);
             for (input_id, input) in inputs {
                 if local {
+                    let size = input.queue_size.unwrap_or(DEFAULT_QUEUE_SIZE);
+                    let gauge = (input.queue_policy == QueuePolicy::BlockSender)
+                        .then(|| Arc::new(QueueGauge::new(size)));
+                    if let Some(gauge) = &gauge {
+                        dataflow
+                            .queue_gauges
+                            .insert((node.id.clone(), input_id.clone()), gauge.clone());
+                    }
+                    let queue = InputQueue {
+                        size,
+                        policy: input.queue_policy,
+                        gauge,
+                        stats: Default::default(),
+                    };
+                    dataflow
+                        .input_queues
+                        .insert((node.id.clone(), input_id.clone()), queue);
                     dataflow
                         .open_inputs
                         .entry(node.id.clone())
@@ -566,10 +1024,10 @@ impl Daemon {
                                 .or_default()
                                 .insert((node.id.clone(), input_id));
                         }
-                        InputMapping::Timer { interval } => {
+                        InputMapping::Timer(timer) => {
                             dataflow
                                 .timers
-                                .entry(interval)
+                                .entry(timer)
                                 .or_default()
                                 .insert((node.id.clone(), input_id));
                         }
@@ -584,38 +1042,47 @@ impl Daemon {
                         .insert((node.id.clone(), input_id));
                 }
             }
+        }
+
+        for node in nodes {
+            let local = node.deploy.machine == self.machine_id;
             if local {
+                dataflow
+                    .open_outputs
+                    .insert(node.id.clone(), node_outputs(&node));
                 dataflow.pending_nodes.insert(node.id.clone());
+                if node.restart.mode != RestartMode::Never {
+                    dataflow.restartable_nodes.insert(
+                        node.id.clone(),
+                        RestartableNode {
+                            node: node.clone(),
+                            restarts: 0,
+                            restarting: false,
+                        },
+                    );
+                }
 
-                let node_id = node.id.clone();
-                match spawn::spawn_node(
-                    dataflow_id,
-                    &working_dir,
+                if !node.depends_on.is_empty() {
+                    // spawned once all dependencies are ready
+                    dataflow.deferred_nodes.push(node);
+                    continue;
+                }
+                let log_index = self
+                    .log_indexes
+                    .entry((dataflow_id, node.id.clone()))
+                    .or_default()
+                    .clone();
+                Self::spawn_local_node(
+                    dataflow,
                     node,
-                    self.events_tx.clone(),
-                    dataflow_descriptor.clone(),
-                    self.clock.clone(),
+                    &working_dir,
+                    log_index,
+                    self.log_config,
+                    &self.events_tx,
+                    &mut self.coordinator_connection,
+                    &self.clock,
                 )
-                .await
-                .wrap_err_with(|| format!("failed to spawn node `{node_id}`"))
-                {
-                    Ok(pid) => {
-                        dataflow
-                            .running_nodes
-                            .insert(node_id.clone(), RunningNode { pid });
-                    }
-                    Err(err) => {
-                        tracing::error!("{err:?}");
-                        dataflow
-                            .pending_nodes
-                            .handle_node_stop(
-                                &node_id,
-                                &mut self.coordinator_connection,
-                                &self.clock,
-                            )
-                            .await?;
-                    }
-                }
+                .await?;
             } else {
                 dataflow.pending_nodes.set_external_nodes(true);
             }
@@ -624,6 +1091,188 @@ impl Daemon {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
+    async fn spawn_local_node(
+        dataflow: &mut RunningDataflow,
+        node: ResolvedNode,
+        working_dir: &Path,
+        log_index: Arc<LogIndex>,
+        log_config: LogConfig,
+        events_tx: &mpsc::Sender<Timestamped<Event>>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
+        clock: &Arc<HLC>,
+    ) -> eyre::Result<()> {
+        let node_id = node.id.clone();
+        match spawn::spawn_node(
+            dataflow.id,
+            working_dir,
+            node,
+            events_tx.clone(),
+            dataflow.descriptor.clone(),
+            dataflow.node_input_queues(&node_id),
+            dataflow.blocking_outputs(&node_id),
+            log_index,
+            log_config,
+            clock.clone(),
+        )
+        .await
+        .wrap_err_with(|| format!("failed to spawn node `{node_id}`"))
+        {
+            Ok(pid) => {
+                dataflow
+                    .running_nodes
+                    .insert(node_id.clone(), RunningNode::new(pid));
+            }
+            Err(err) => {
+                tracing::error!("{err:?}");
+                Self::stop_pending_node(dataflow, &node_id, coordinator_connection, clock).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports a stopped node to the pending nodes and gives up on all deferred
+    /// nodes that depend on it if it never became ready.
+    async fn stop_pending_node(
+        dataflow: &mut RunningDataflow,
+        node_id: &NodeId,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
+        clock: &HLC,
+    ) -> eyre::Result<()> {
+        dataflow
+            .pending_nodes
+            .handle_node_stop(node_id, coordinator_connection, clock)
+            .await?;
+        if !dataflow.ready_nodes.contains(node_id) {
+            Self::report_failed_dependency(dataflow, node_id, coordinator_connection, clock)
+                .await?;
+            Self::give_up_dependents(dataflow, node_id, coordinator_connection, clock).await?;
+        }
+        Ok(())
+    }
+
+    /// Gives up on all deferred nodes that depend on the given node, which
+    /// exited before it was ready.
+    ///
+    /// The given node might run on another machine.
+    async fn give_up_dependents(
+        dataflow: &mut RunningDataflow,
+        node_id: &NodeId,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
+        clock: &HLC,
+    ) -> eyre::Result<()> {
+        for dependent in dataflow.remove_dependents(node_id) {
+            tracing::warn!(
+                "not starting node `{dependent}` because its dependency \
+                `{node_id}` exited before it was ready"
+            );
+            dataflow
+                .pending_nodes
+                .handle_node_stop(&dependent, coordinator_connection, clock)
+                .await?;
+            Self::report_failed_dependency(dataflow, &dependent, coordinator_connection, clock)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Notifies the other machines through the coordinator if they run nodes
+    /// that depend on the given local node, which exited before it was ready.
+    async fn report_failed_dependency(
+        dataflow: &RunningDataflow,
+        node_id: &NodeId,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
+        clock: &HLC,
+    ) -> eyre::Result<()> {
+        if !dataflow.remote_dependencies.contains(node_id) {
+            return Ok(());
+        }
+        if let Some(connection) = coordinator_connection {
+            connection
+                .send(Timestamped {
+                    inner: CoordinatorRequest::Event {
+                        machine_id: dataflow.machine_id.clone(),
+                        event: DaemonEvent::NodeFailed {
+                            dataflow_id: dataflow.id,
+                            node_id: node_id.clone(),
+                        },
+                    },
+                    timestamp: clock.new_timestamp(),
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Marks the given node as ready and spawns all deferred nodes whose
+    /// dependencies are ready now.
+    async fn handle_node_ready(
+        &mut self,
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    ) -> eyre::Result<()> {
+        let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+            tracing::warn!("node `{node_id}` is ready, but dataflow `{dataflow_id}` is unknown");
+            return Ok(());
+        };
+        if !dataflow.ready_nodes.insert(node_id.clone()) {
+            // restarted node
+            return Ok(());
+        }
+
+        if dataflow.remote_dependencies.contains(&node_id) {
+            if let Some(connection) = &mut self.coordinator_connection {
+                connection
+                    .send(Timestamped {
+                        inner: CoordinatorRequest::Event {
+                            machine_id: self.machine_id.clone(),
+                            event: DaemonEvent::NodeReady {
+                                dataflow_id,
+                                node_id: node_id.clone(),
+                            },
+                        },
+                        timestamp: self.clock.new_timestamp(),
+                    })
+                    .await?;
+            }
+        }
+
+        let (ready, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut dataflow.deferred_nodes)
+            .into_iter()
+            .partition(|node| node.depends_on.is_subset(&dataflow.ready_nodes));
+        dataflow.deferred_nodes = waiting;
+        if ready.is_empty() {
+            return Ok(());
+        }
+        let working_dir = self
+            .working_dir
+            .get(&dataflow_id)
+            .wrap_err_with(|| format!("no working dir for dataflow `{dataflow_id}`"))?;
+        for node in ready {
+            tracing::info!(
+                "dependencies of node `{dataflow_id}/{}` are ready, spawning it",
+                node.id
+            );
+            let log_index = self
+                .log_indexes
+                .entry((dataflow_id, node.id.clone()))
+                .or_default()
+                .clone();
+            Self::spawn_local_node(
+                dataflow,
+                node,
+                working_dir,
+                log_index,
+                self.log_config,
+                &self.events_tx,
+                &mut self.coordinator_connection,
+                &self.clock,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     async fn handle_node_event(
         &mut self,
         event: DaemonNodeEvent,
@@ -643,10 +1292,24 @@ impl Daemon {
                     Err(err) => {
                         let _ = reply_sender.send(DaemonReply::Result(Err(err)));
                     }
+                    Ok(dataflow) if dataflow.is_restarted(&node_id) => {
+                        tracing::debug!("restarted node `{node_id}` is ready");
+                        Self::subscribe(dataflow, node_id.clone(), event_sender, &self.clock).await;
+                        let _ = reply_sender.send(DaemonReply::Result(Ok(())));
+                    }
                     Ok(dataflow) => {
                         tracing::debug!("node `{node_id}` is ready");
                         Self::subscribe(dataflow, node_id.clone(), event_sender, &self.clock).await;
 
+                        // nodes with a `ready_signal` must not wait for the
+                        // nodes that depend on them
+                        let ready_signal = dataflow.ready_signal_nodes.contains(&node_id);
+                        let reply_sender = if ready_signal {
+                            let _ = reply_sender.send(DaemonReply::Result(Ok(())));
+                            None
+                        } else {
+                            Some(reply_sender)
+                        };
                         let status = dataflow
                             .pending_nodes
                             .handle_node_subscription(
@@ -665,9 +1328,21 @@ impl Daemon {
                             }
                             DataflowStatus::Pending => {}
                         }
+                        // nodes with a `ready_signal` are ready once they report it
+                        if !ready_signal {
+                            self.handle_node_ready(dataflow_id, node_id).await?;
+                        }
                     }
                 }
             }
+            DaemonNodeEvent::Ready { reply_sender } => {
+                tracing::debug!("node `{node_id}` signaled that it is ready");
+                let result = self
+                    .handle_node_ready(dataflow_id, node_id)
+                    .await
+                    .map_err(|err| format!("{err:?}"));
+                let _ = reply_sender.send(DaemonReply::Result(result));
+            }
             DaemonNodeEvent::SubscribeDrop {
                 event_sender,
                 reply_sender,
@@ -688,6 +1363,9 @@ impl Daemon {
                         .running
                         .get_mut(&dataflow_id)
                         .wrap_err_with(|| format!("failed to get downstream nodes: no running dataflow with ID `{dataflow_id}`"))?;
+                    if let Some(open_outputs) = dataflow.open_outputs.get_mut(&node_id) {
+                        open_outputs.retain(|output| !outputs.contains(output));
+                    }
                     send_input_closed_events(
                         dataflow,
                         &mut self.inter_daemon_connections,
@@ -704,6 +1382,8 @@ impl Daemon {
             }
             DaemonNodeEvent::OutputsDone { reply_sender } => {
                 let result = match self.running.get_mut(&dataflow_id) {
+                    // keep the downstream inputs open in case the node is restarted
+                    Some(dataflow) if dataflow.may_restart(&node_id) => Ok(()),
                     Some(dataflow) => {
                         Self::handle_outputs_done(dataflow, &mut self.inter_daemon_connections, &node_id, &self.clock)
                     .await
@@ -719,9 +1399,34 @@ impl Daemon {
                 output_id,
                 metadata,
                 data,
+                reply_sender,
             } => {
-                self.send_out(dataflow_id, node_id, output_id, metadata, data)
-                    .await?
+                self.send_out(
+                    dataflow_id,
+                    node_id.clone(),
+                    output_id.clone(),
+                    metadata,
+                    data,
+                )
+                .await?;
+                if let Some(reply_sender) = reply_sender {
+                    let full_queues = match self.running.get(&dataflow_id) {
+                        Some(dataflow) => dataflow.full_queues(&node_id, &output_id),
+                        None => Vec::new(),
+                    };
+                    let reply = DaemonReply::Result(Ok(()));
+                    if full_queues.is_empty() {
+                        let _ = reply_sender.send(reply);
+                    } else {
+                        // block the sender until all receivers have space again
+                        tokio::spawn(async move {
+                            for gauge in full_queues {
+                                gauge.wait_for_space().await;
+                            }
+                            let _ = reply_sender.send(reply);
+                        });
+                    }
+                }
             }
             DaemonNodeEvent::ReportDrop { tokens } => {
                 let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
@@ -753,6 +1458,7 @@ impl Daemon {
                         .get_mut(&dataflow_id)
                         .wrap_err_with(|| format!("no running dataflow with ID `{dataflow_id}`"))?;
                     dataflow.subscribe_channels.remove(&node_id);
+                    dataflow.close_queues(&node_id);
                     Result::<_, eyre::Error>::Ok(())
                 };
 
@@ -798,15 +1504,18 @@ impl Daemon {
         let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
             format!("send out failed: no running dataflow with ID `{dataflow_id}`")
         })?;
-        let data_bytes = send_output_to_local_receivers(
+        dataflow
+            .output_stats
+            .entry(OutputId(node_id.clone(), output_id.clone()))
+            .or_default()
+            .record_message(data.as_ref());
+        send_output_to_local_receivers(
             node_id.clone(),
             output_id.clone(),
             dataflow,
             &metadata,
-            data,
-            &self.clock,
-        )
-        .await?;
+            data.as_ref(),
+        );
 
         let output_id = OutputId(node_id, output_id);
         let remote_receivers: Vec<_> = dataflow
@@ -814,27 +1523,78 @@ impl Daemon {
             .get(&output_id)
             .map(|m| m.keys().cloned().collect())
             .unwrap_or_default();
-        if !remote_receivers.is_empty() {
-            let event = Timestamped {
-                inner: InterDaemonEvent::Output {
+        let OutputId(node_id, output_id) = output_id;
+        let drop_token = data.as_ref().and_then(|d| d.drop_token());
+        let result = if !remote_receivers.is_empty() {
+            let data = match data {
+                Some(DataMessage::SharedMemory {
+                    shared_memory_id,
+                    len,
+                    ..
+                }) => Some(OutputData::SharedMemory(
+                    ShmemHandle::open(&shared_memory_id)?,
+                    len,
+                )),
+                Some(DataMessage::Vec(v)) => Some(OutputData::Vec(v)),
+                None => None,
+            };
+            self.inter_daemon_connections
+                .send_output(
                     dataflow_id,
-                    node_id: output_id.0,
-                    output_id: output_id.1,
+                    &remote_receivers,
+                    node_id.clone(),
+                    output_id,
                     metadata,
-                    data: data_bytes,
-                },
-                timestamp: self.clock.new_timestamp(),
-            };
-            inter_daemon::send_inter_daemon_event(
-                &remote_receivers,
-                &mut self.inter_daemon_connections,
-                &event,
-            )
-            .await
-            .wrap_err("failed to forward output to remote receivers")?;
+                    data,
+                    &self.clock,
+                )
+                .await
+                .wrap_err("failed to forward output to remote receivers")
+        } else {
+            Ok(None)
+        };
+        let (result, transfer_done) = match result {
+            Ok(done) => (Ok(()), done),
+            Err(err) => (Err(err), None),
+        };
+
+        // the sender may reuse the shared memory region once the token is
+        // released, so keep it until the data was sent to remote receivers
+        if let Some(token) = drop_token {
+            let dataflow = self.running.get_mut(&dataflow_id).wrap_err_with(|| {
+                format!("send out failed: no running dataflow with ID `{dataflow_id}`")
+            })?;
+            if let Some(transfer_done) = transfer_done {
+                dataflow
+                    .pending_drop_tokens
+                    .entry(token)
+                    .or_insert_with(|| DropTokenInformation {
+                        owner: node_id.clone(),
+                        pending_nodes: Default::default(),
+                        pending_transfers: 0,
+                    })
+                    .pending_transfers += 1;
+                let events_tx = self.events_tx.clone();
+                let clock = self.clock.clone();
+                tokio::spawn(async move {
+                    let _ = transfer_done.await;
+                    let event = Timestamped {
+                        inner: DoraEvent::RemoteTransferFinished {
+                            dataflow_id,
+                            drop_token: token,
+                        }
+                        .into(),
+                        timestamp: clock.new_timestamp(),
+                    };
+                    let _ = events_tx.send(event).await;
+                });
+            }
+            dataflow
+                .release_drop_token(token, node_id, &self.clock)
+                .await?;
         }
 
-        Ok(())
+        result
     }
 
     async fn subscribe(
@@ -886,7 +1646,7 @@ impl Daemon {
     #[tracing::instrument(skip(dataflow, inter_daemon_connections, clock), fields(uuid = %dataflow.id), level = "trace")]
     async fn handle_outputs_done(
         dataflow: &mut RunningDataflow,
-        inter_daemon_connections: &mut BTreeMap<String, InterDaemonConnection>,
+        inter_daemon_connections: &mut InterDaemonConnections,
         node_id: &NodeId,
         clock: &HLC,
     ) -> eyre::Result<()> {
@@ -898,6 +1658,9 @@ impl Daemon {
         )
         .await?;
         dataflow.drop_channels.remove(node_id);
+        if let Some(open_outputs) = dataflow.open_outputs.get_mut(node_id) {
+            open_outputs.clear();
+        }
         Ok(())
     }
 
@@ -906,10 +1669,14 @@ impl Daemon {
             format!("failed to get downstream nodes: no running dataflow with ID `{dataflow_id}`")
         })?;
 
-        dataflow
-            .pending_nodes
-            .handle_node_stop(node_id, &mut self.coordinator_connection, &self.clock)
-            .await?;
+        Self::stop_pending_node(
+            dataflow,
+            node_id,
+            &mut self.coordinator_connection,
+            &self.clock,
+        )
+        .await?;
+        dataflow.close_queues(node_id);
 
         Self::handle_outputs_done(
             dataflow,
@@ -920,8 +1687,17 @@ impl Daemon {
         .await?;
 
         dataflow.running_nodes.remove(node_id);
-        if dataflow.running_nodes.is_empty() {
-            let result = match self.dataflow_errors.get(&dataflow.id) {
+        self.finish_dataflow_if_done(dataflow_id).await
+    }
+
+    /// Reports the dataflow as finished once no local nodes are running or
+    /// waiting for their dependencies anymore.
+    async fn finish_dataflow_if_done(&mut self, dataflow_id: Uuid) -> eyre::Result<()> {
+        let Some(dataflow) = self.running.get(&dataflow_id) else {
+            return Ok(());
+        };
+        if dataflow.running_nodes.is_empty() && dataflow.deferred_nodes.is_empty() {
+            let result = match self.dataflow_errors.get(&dataflow_id) {
                 None => Ok(()),
                 Some(errors) => {
                     let mut output = "some nodes failed:".to_owned();
@@ -937,30 +1713,219 @@ impl Daemon {
                 self.machine_id
             );
             if let Some(connection) = &mut self.coordinator_connection {
-                let msg = serde_json::to_vec(&Timestamped {
-                    inner: CoordinatorRequest::Event {
-                        machine_id: self.machine_id.clone(),
-                        event: DaemonEvent::AllNodesFinished {
-                            dataflow_id,
-                            result,
+                connection
+                    .send(Timestamped {
+                        inner: CoordinatorRequest::Event {
+                            machine_id: self.machine_id.clone(),
+                            event: DaemonEvent::AllNodesFinished {
+                                dataflow_id,
+                                result,
+                            },
                         },
-                    },
-                    timestamp: self.clock.new_timestamp(),
-                })?;
-                tcp_send(connection, &msg)
-                    .await
-                    .wrap_err("failed to report dataflow finish to dora-coordinator")?;
+                        timestamp: self.clock.new_timestamp(),
+                    })
+                    .await?;
+            }
+            self.running.remove(&dataflow_id);
+            self.inter_daemon_connections.close(dataflow_id);
+            self.finish_log_followers(dataflow_id);
+            self.log_index_expiry
+                .push_back((Instant::now() + LOG_INDEX_TTL, dataflow_id));
+            if let Some(working_dir) = self.working_dir.get(&dataflow_id).cloned() {
+                let running: HashSet<_> = self.running.keys().copied().collect();
+                let retention = self.log_config.retention;
+                let machine_id = self.machine_id.clone();
+                tokio::task::spawn_blocking(move || {
+                    if let Err(err) = log::mark_finished(&working_dir, &dataflow_id, &machine_id) {
+                        tracing::warn!(
+                            "failed to mark dataflow `{dataflow_id}` as finished: {err:?}"
+                        );
+                    }
+                    if let Err(err) =
+                        log::apply_retention(&working_dir, &machine_id, &retention, &running)
+                    {
+                        tracing::warn!("failed to apply output retention policy: {err:?}");
+                    }
+                });
+            }
+            #[cfg(unix)]
+            {
+                let socket_dir = node_communication::unix_domain::socket_dir(&dataflow_id);
+                if socket_dir.exists() {
+                    if let Err(err) = std::fs::remove_dir_all(&socket_dir) {
+                        tracing::warn!("failed to remove `{}`: {err}", socket_dir.display());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts pushing the log output of the given local nodes to the
+    /// coordinator.
+    async fn follow_logs(
+        &mut self,
+        dataflow_id: DataflowId,
+        subscription: Uuid,
+        nodes: BTreeMap<NodeId, LogPosition>,
+        filter: LogFilter,
+    ) -> eyre::Result<()> {
+        let format = self.log_config.format;
+        let working_dir = match self.working_dir.get(&dataflow_id) {
+            Some(working_dir) => format.check_filter(&filter).map(|()| working_dir.clone()),
+            None => Err(eyre!("no dataflow with ID `{dataflow_id}`")),
+        };
+        let working_dir = match working_dir {
+            Ok(working_dir) => working_dir,
+            Err(err) => {
+                let event = DoraEvent::LogOutput {
+                    dataflow_id,
+                    subscription,
+                    output: Err(err),
+                    finished: true,
+                };
+                return self.handle_dora_event(event).await.map(|_| ());
+            }
+        };
+        let nodes = nodes
+            .into_iter()
+            .map(|(node_id, position)| {
+                let index = self
+                    .log_indexes
+                    .entry((dataflow_id, node_id.clone()))
+                    .or_default()
+                    .clone();
+                (node_id, position, index)
+            })
+            .collect();
+        let mut follower = LogFollower::new(working_dir, dataflow_id, format, nodes, filter);
+
+        let (finish_tx, mut finish_rx) = oneshot::channel();
+        if self.running.contains_key(&dataflow_id) {
+            self.log_followers.insert(
+                subscription,
+                LogFollowerHandle {
+                    dataflow_id,
+                    finish: finish_tx,
+                },
+            );
+        } else {
+            // only the existing output is sent
+            let _ = finish_tx.send(());
+        }
+
+        let events_tx = self.events_tx.clone();
+        let clock = self.clock.clone();
+        tokio::spawn(async move {
+            let send = |output, finished| {
+                let event = Timestamped {
+                    inner: DoraEvent::LogOutput {
+                        dataflow_id,
+                        subscription,
+                        output,
+                        finished,
+                    }
+                    .into(),
+                    timestamp: clock.new_timestamp(),
+                };
+                events_tx.send(event)
+            };
+            loop {
+                // the last line of a running node might still be incomplete
+                match follower.read(true).await {
+                    Ok(chunks) if !chunks.is_empty() => {
+                        if send(Ok(chunks), false).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        let _ = send(Err(err), true).await;
+                        break;
+                    }
+                }
+                tokio::select! {
+                    () = follower.changed() => {}
+                    result = &mut finish_rx => {
+                        if result.is_err() {
+                            // unsubscribed
+                            break;
+                        }
+                        let output = async {
+                            let mut output = Vec::new();
+                            loop {
+                                let chunks = follower.read(false).await?;
+                                if chunks.is_empty() {
+                                    break eyre::Ok(output);
+                                }
+                                output.extend(chunks);
+                            }
+                        };
+                        let _ = send(output.await, true).await;
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Lets the log followers of the given dataflow send the remaining
+    /// output and finish.
+    fn finish_log_followers(&mut self, dataflow_id: DataflowId) {
+        let finished: Vec<_> = self
+            .log_followers
+            .iter()
+            .filter(|(_, follower)| follower.dataflow_id == dataflow_id)
+            .map(|(subscription, _)| *subscription)
+            .collect();
+        for subscription in finished {
+            if let Some(follower) = self.log_followers.remove(&subscription) {
+                let _ = follower.finish.send(());
             }
-            self.running.remove(&dataflow_id);
         }
-        Ok(())
+    }
+
+    /// Applies the retention policy to the `out/` directories of all known
+    /// working directories, so that `max_age` also removes old output while
+    /// no dataflow finishes.
+    fn apply_periodic_retention(&mut self) {
+        let retention = self.log_config.retention;
+        if retention.max_age.is_none() || self.last_retention.elapsed() < RETENTION_INTERVAL {
+            return;
+        }
+        self.last_retention = Instant::now();
+
+        let working_dirs: BTreeSet<_> = self.working_dir.values().cloned().collect();
+        let running: HashSet<_> = self.running.keys().copied().collect();
+        let machine_id = self.machine_id.clone();
+        tokio::task::spawn_blocking(move || {
+            for working_dir in working_dirs {
+                if let Err(err) =
+                    log::apply_retention(&working_dir, &machine_id, &retention, &running)
+                {
+                    tracing::warn!("failed to apply output retention policy: {err:?}");
+                }
+            }
+        });
+    }
+
+    fn remove_expired_log_indexes(&mut self) {
+        while let Some(&(expiry, dataflow_id)) = self.log_index_expiry.front() {
+            if expiry > Instant::now() {
+                break;
+            }
+            self.log_index_expiry.pop_front();
+            self.log_indexes.retain(|(id, _), _| *id != dataflow_id);
+        }
     }
 
     async fn handle_dora_event(&mut self, event: DoraEvent) -> eyre::Result<RunStatus> {
         match event {
             DoraEvent::Timer {
                 dataflow_id,
-                interval,
+                timer,
                 metadata,
             } => {
                 let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
@@ -968,7 +1933,7 @@ impl Daemon {
                     return Ok(RunStatus::Continue);
                 };
 
-                let Some(subscribers) = dataflow.timers.get(&interval) else {
+                let Some(subscribers) = dataflow.timers.get(&timer) else {
                     return Ok(RunStatus::Continue);
                 };
 
@@ -1050,13 +2015,13 @@ impl Daemon {
                 node_id,
                 exit_status,
             } => {
-                let node_error = match exit_status {
+                let node_error = match &exit_status {
                     NodeExitStatus::Success => {
                         tracing::info!("node {dataflow_id}/{node_id} finished successfully");
                         None
                     }
                     NodeExitStatus::IoError(err) => {
-                        let err = eyre!(err).wrap_err(format!(
+                        let err = eyre!(err.clone()).wrap_err(format!(
                             "
     I/O error while waiting for node `{dataflow_id}/{node_id}. 
 
@@ -1118,6 +2083,32 @@ impl Daemon {
                     }
                 };
 
+                let restart_backoff = self
+                    .running
+                    .get_mut(&dataflow_id)
+                    .and_then(|dataflow| dataflow.prepare_restart(&node_id, node_error.is_some()));
+                if let Some(backoff) = restart_backoff {
+                    tracing::info!("restarting node `{dataflow_id}/{node_id}` in {backoff:?}");
+                    let events_tx = self.events_tx.clone();
+                    let clock = self.clock.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(backoff).await;
+                        let event = Timestamped {
+                            inner: DoraEvent::RestartNode {
+                                dataflow_id,
+                                node_id,
+                            }
+                            .into(),
+                            timestamp: clock.new_timestamp(),
+                        };
+                        let _ = events_tx.send(event).await;
+                    });
+                    return Ok(RunStatus::Continue);
+                }
+
+                if let Some(dataflow) = self.running.get_mut(&dataflow_id) {
+                    dataflow.exited_nodes.insert(node_id.clone(), exit_status);
+                }
                 if let Some(err) = node_error {
                     self.dataflow_errors
                         .entry(dataflow_id)
@@ -1125,31 +2116,173 @@ impl Daemon {
                         .insert(node_id.clone(), err);
                 }
 
-                self.handle_node_stop(dataflow_id, &node_id).await?;
-
-                if let Some(exit_when_done) = &mut self.exit_when_done {
-                    exit_when_done.remove(&(dataflow_id, node_id));
-                    if exit_when_done.is_empty() {
-                        tracing::info!(
-                            "exiting daemon because all required dataflows are finished"
-                        );
-                        return Ok(RunStatus::Exit);
+                return self.handle_node_exit(dataflow_id, node_id).await;
+            }
+            DoraEvent::RestartNode {
+                dataflow_id,
+                node_id,
+            } => return self.restart_node(dataflow_id, node_id).await,
+            DoraEvent::LogOutput {
+                dataflow_id,
+                subscription,
+                output,
+                finished,
+            } => {
+                let finished = finished || output.is_err();
+                if finished {
+                    self.log_followers.remove(&subscription);
+                }
+                if let Some(connection) = &mut self.coordinator_connection {
+                    connection
+                        .send(Timestamped {
+                            inner: CoordinatorRequest::Event {
+                                machine_id: self.machine_id.clone(),
+                                event: DaemonEvent::LogOutput {
+                                    dataflow_id,
+                                    subscription,
+                                    output: output.map_err(|err| format!("{err:?}")),
+                                    finished,
+                                },
+                            },
+                            timestamp: self.clock.new_timestamp(),
+                        })
+                        .await?;
+                }
+            }
+            DoraEvent::RemoteLinksReady {
+                dataflow_id,
+                result,
+            } => {
+                let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+                    tracing::warn!("remote links ready for unknown dataflow `{dataflow_id}`");
+                    return Ok(RunStatus::Continue);
+                };
+                let status = dataflow
+                    .pending_nodes
+                    .handle_remote_links_ready(
+                        result,
+                        &mut self.coordinator_connection,
+                        &self.clock,
+                    )
+                    .await?;
+                match status {
+                    DataflowStatus::AllNodesReady => {
+                        tracing::info!("all nodes are ready, starting dataflow `{dataflow_id}`");
+                        dataflow.start(&self.events_tx, &self.clock).await?;
                     }
+                    DataflowStatus::Pending => {}
                 }
             }
+            DoraEvent::RemoteTransferFinished {
+                dataflow_id,
+                drop_token,
+            } => {
+                let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+                    return Ok(RunStatus::Continue);
+                };
+                if let Some(info) = dataflow.pending_drop_tokens.get_mut(&drop_token) {
+                    info.pending_transfers = info.pending_transfers.saturating_sub(1);
+                    dataflow.check_drop_token(drop_token, &self.clock).await?;
+                }
+            }
+        }
+        Ok(RunStatus::Continue)
+    }
+
+    async fn restart_node(
+        &mut self,
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    ) -> eyre::Result<RunStatus> {
+        let Some(dataflow) = self.running.get_mut(&dataflow_id) else {
+            tracing::warn!("cannot restart node `{node_id}`: unknown dataflow `{dataflow_id}`");
+            return Ok(RunStatus::Continue);
+        };
+        if dataflow.stop_sent {
+            // the dataflow was stopped while waiting for the restart
+            return self.handle_node_exit(dataflow_id, node_id).await;
+        }
+        let restartable = dataflow
+            .restartable_nodes
+            .get(&node_id)
+            .wrap_err_with(|| format!("node `{node_id}` has no restart policy"))?;
+        let working_dir = self
+            .working_dir
+            .get(&dataflow_id)
+            .wrap_err_with(|| format!("no working dir for dataflow `{dataflow_id}`"))?;
+
+        let result = spawn::spawn_node(
+            dataflow_id,
+            working_dir,
+            restartable.node.clone(),
+            self.events_tx.clone(),
+            dataflow.descriptor.clone(),
+            dataflow.node_input_queues(&node_id),
+            dataflow.blocking_outputs(&node_id),
+            self.log_indexes
+                .entry((dataflow_id, node_id.clone()))
+                .or_default()
+                .clone(),
+            self.log_config,
+            self.clock.clone(),
+        )
+        .await
+        .wrap_err_with(|| format!("failed to restart node `{node_id}`"));
+        if let Some(restartable) = dataflow.restartable_nodes.get_mut(&node_id) {
+            restartable.restarting = false;
+        }
+        match result {
+            Ok(pid) => {
+                dataflow
+                    .running_nodes
+                    .insert(node_id, RunningNode::new(pid));
+                Ok(RunStatus::Continue)
+            }
+            Err(err) => {
+                tracing::error!("{err:?}");
+                self.dataflow_errors
+                    .entry(dataflow_id)
+                    .or_default()
+                    .insert(node_id.clone(), err);
+                self.handle_node_exit(dataflow_id, node_id).await
+            }
+        }
+    }
+
+    /// Handles the final exit of a node, i.e. when it is not restarted.
+    async fn handle_node_exit(
+        &mut self,
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    ) -> eyre::Result<RunStatus> {
+        self.handle_node_stop(dataflow_id, &node_id).await?;
+
+        if let Some(exit_when_done) = &mut self.exit_when_done {
+            exit_when_done.remove(&(dataflow_id, node_id));
+            if !self.running.contains_key(&dataflow_id) {
+                // the dataflow finished without starting all nodes (see `depends_on`)
+                exit_when_done.retain(|(id, _)| *id != dataflow_id);
+            }
+            if exit_when_done.is_empty() {
+                tracing::info!("exiting daemon because all required dataflows are finished");
+                return Ok(RunStatus::Exit);
+            }
         }
         Ok(RunStatus::Continue)
     }
 }
 
-async fn send_output_to_local_receivers(
+/// Sends the output to all local receivers.
+///
+/// Shared memory outputs stay pending until
+/// [`RunningDataflow::release_drop_token`] is called for their drop token.
+fn send_output_to_local_receivers(
     node_id: NodeId,
     output_id: DataId,
     dataflow: &mut RunningDataflow,
     metadata: &dora_core::message::Metadata,
-    data: Option<DataMessage>,
-    clock: &HLC,
-) -> Result<Option<AVec<u8, ConstAlign<128>>>, eyre::ErrReport> {
+    data: Option<&DataMessage>,
+) {
     let timestamp = metadata.timestamp();
     let empty_set = BTreeSet::new();
     let output_id = OutputId(node_id, output_id);
@@ -1157,24 +2290,37 @@ async fn send_output_to_local_receivers(
     let OutputId(node_id, _) = output_id;
     let mut closed = Vec::new();
     for (receiver_id, input_id) in local_receivers {
+        if let Some(queue) = dataflow
+            .input_queues
+            .get(&(receiver_id.clone(), input_id.clone()))
+        {
+            queue.stats.record_message(data);
+        }
         if let Some(channel) = dataflow.subscribe_channels.get(receiver_id) {
             let item = daemon_messages::NodeEvent::Input {
                 id: input_id.clone(),
                 metadata: metadata.clone(),
-                data: data.clone(),
+                data: data.cloned(),
             };
             match channel.send(Timestamped {
                 inner: item,
                 timestamp,
             }) {
                 Ok(()) => {
-                    if let Some(token) = data.as_ref().and_then(|d| d.drop_token()) {
+                    if let Some(gauge) = dataflow
+                        .queue_gauges
+                        .get(&(receiver_id.clone(), input_id.clone()))
+                    {
+                        gauge.push();
+                    }
+                    if let Some(token) = data.and_then(|d| d.drop_token()) {
                         dataflow
                             .pending_drop_tokens
                             .entry(token)
                             .or_insert_with(|| DropTokenInformation {
                                 owner: node_id.clone(),
                                 pending_nodes: Default::default(),
+                                pending_transfers: 0,
                             })
                             .pending_nodes
                             .insert(receiver_id.clone());
@@ -1189,35 +2335,6 @@ async fn send_output_to_local_receivers(
     for id in closed {
         dataflow.subscribe_channels.remove(id);
     }
-    let (data_bytes, drop_token) = match data {
-        None => (None, None),
-        Some(DataMessage::SharedMemory {
-            shared_memory_id,
-            len,
-            drop_token,
-        }) => {
-            let memory = ShmemConf::new()
-                .os_id(shared_memory_id)
-                .open()
-                .wrap_err("failed to map shared memory output")?;
-            let data = Some(AVec::from_slice(1, &unsafe { memory.as_slice() }[..len]));
-            (data, Some(drop_token))
-        }
-        Some(DataMessage::Vec(v)) => (Some(v), None),
-    };
-    if let Some(token) = drop_token {
-        // insert token into `pending_drop_tokens` even if there are no local subscribers
-        dataflow
-            .pending_drop_tokens
-            .entry(token)
-            .or_insert_with(|| DropTokenInformation {
-                owner: node_id.clone(),
-                pending_nodes: Default::default(),
-            });
-        // check if all local subscribers are finished with the token
-        dataflow.check_drop_token(token, clock).await?;
-    }
-    Ok(data_bytes)
 }
 
 fn node_inputs(node: &ResolvedNode) -> BTreeMap<DataId, Input> {
@@ -1241,6 +2358,13 @@ fn runtime_node_inputs(n: &dora_core::descriptor::RuntimeNode) -> BTreeMap<DataI
         .collect()
 }
 
+fn node_outputs(node: &ResolvedNode) -> BTreeSet<DataId> {
+    match &node.kind {
+        CoreNodeKind::Custom(n) => n.run_config.outputs.clone(),
+        CoreNodeKind::Runtime(n) => runtime_node_outputs(n),
+    }
+}
+
 fn runtime_node_outputs(n: &dora_core::descriptor::RuntimeNode) -> BTreeSet<DataId> {
     n.operators
         .iter()
@@ -1254,9 +2378,29 @@ fn runtime_node_outputs(n: &dora_core::descriptor::RuntimeNode) -> BTreeSet<Data
         .collect()
 }
 
+fn runtime_node_output_types(
+    n: &dora_core::descriptor::RuntimeNode,
+) -> BTreeMap<DataId, arrow_schema::DataType> {
+    n.operators
+        .iter()
+        .flat_map(|operator| {
+            operator
+                .config
+                .output_types
+                .iter()
+                .map(|(output_id, data_type)| {
+                    (
+                        DataId::from(format!("{}/{output_id}", operator.id)),
+                        data_type.clone(),
+                    )
+                })
+        })
+        .collect()
+}
+
 async fn send_input_closed_events<F>(
     dataflow: &mut RunningDataflow,
-    inter_daemon_connections: &mut BTreeMap<String, InterDaemonConnection>,
+    inter_daemon_connections: &mut InterDaemonConnections,
     mut filter: F,
     clock: &HLC,
 ) -> eyre::Result<()>
@@ -1289,13 +2433,10 @@ where
                 },
                 timestamp: clock.new_timestamp(),
             };
-            inter_daemon::send_inter_daemon_event(
-                &[target_machine],
-                inter_daemon_connections,
-                &event,
-            )
-            .await
-            .wrap_err("failed to sent InputClosed event to remote receiver")?;
+            inter_daemon_connections
+                .send(dataflow.id, &[target_machine], &event)
+                .await
+                .wrap_err("failed to sent InputClosed event to remote receiver")?;
         }
     }
     Ok(())
@@ -1331,23 +2472,74 @@ fn close_input(
 #[derive(Debug, Clone)]
 struct RunningNode {
     pid: u32,
+    started: Instant,
+}
+
+impl RunningNode {
+    fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            started: Instant::now(),
+        }
+    }
+}
+
+/// Information needed to restart a node according to its restart policy.
+struct RestartableNode {
+    node: ResolvedNode,
+    restarts: u32,
+    /// Set while waiting for the restart backoff.
+    restarting: bool,
+}
+
+struct LogFollowerHandle {
+    dataflow_id: DataflowId,
+    /// Signals that the dataflow finished, dropped to unsubscribe.
+    finish: oneshot::Sender<()>,
 }
 
 pub struct RunningDataflow {
     id: Uuid,
+    machine_id: String,
+    descriptor: Descriptor,
     /// Local nodes that are not started yet
     pending_nodes: PendingNodes,
 
     subscribe_channels: HashMap<NodeId, UnboundedSender<Timestamped<daemon_messages::NodeEvent>>>,
     drop_channels: HashMap<NodeId, UnboundedSender<Timestamped<daemon_messages::NodeDropEvent>>>,
     mappings: HashMap<OutputId, BTreeSet<InputId>>,
-    timers: BTreeMap<Duration, BTreeSet<InputId>>,
+    timers: BTreeMap<TimerSpec, BTreeSet<InputId>>,
     open_inputs: BTreeMap<NodeId, BTreeSet<DataId>>,
+    /// Outputs of all local nodes that were not closed yet.
+    open_outputs: BTreeMap<NodeId, BTreeSet<DataId>>,
     running_nodes: BTreeMap<NodeId, RunningNode>,
+    /// Local nodes that exited and are not restarted again.
+    exited_nodes: BTreeMap<NodeId, NodeExitStatus>,
+    /// Fill levels of all local `block_sender` input queues.
+    queue_gauges: BTreeMap<InputId, Arc<QueueGauge>>,
+    /// Queue configuration and message counters of all local inputs.
+    input_queues: BTreeMap<InputId, InputQueue>,
+    output_stats: BTreeMap<OutputId, OutputStats>,
+    /// Local nodes that have a restart policy.
+    restartable_nodes: BTreeMap<NodeId, RestartableNode>,
+    /// Local nodes that wait for their `depends_on` nodes to become ready.
+    deferred_nodes: Vec<ResolvedNode>,
+    /// Nodes that are ready (see `depends_on`), including nodes on other
+    /// machines.
+    ready_nodes: BTreeSet<NodeId>,
+    /// Local nodes that are only ready once they signal it explicitly.
+    ready_signal_nodes: BTreeSet<NodeId>,
+    /// Local nodes that nodes on other machines depend on.
+    remote_dependencies: BTreeSet<NodeId>,
 
     open_external_mappings: HashMap<OutputId, BTreeMap<String, BTreeSet<InputId>>>,
 
     pending_drop_tokens: HashMap<DropToken, DropTokenInformation>,
+    /// Large outputs of remote nodes that are still being received.
+    incoming_transfers: HashMap<Uuid, IncomingTransfer>,
+    /// Shared memory regions of received remote outputs, which are freed once
+    /// their drop token is released by all local receivers.
+    received_outputs: HashMap<DropToken, ShmemHandle>,
 
     /// Keep handles to all timer tasks of this dataflow to cancel them on drop.
     _timer_handles: Vec<futures::future::RemoteHandle<()>>,
@@ -1360,18 +2552,32 @@ pub struct RunningDataflow {
 }
 
 impl RunningDataflow {
-    fn new(dataflow_id: Uuid, machine_id: String) -> RunningDataflow {
+    fn new(dataflow_id: Uuid, machine_id: String, descriptor: Descriptor) -> RunningDataflow {
         Self {
             id: dataflow_id,
+            machine_id: machine_id.clone(),
+            descriptor,
             pending_nodes: PendingNodes::new(dataflow_id, machine_id),
             subscribe_channels: HashMap::new(),
             drop_channels: HashMap::new(),
             mappings: HashMap::new(),
             timers: BTreeMap::new(),
             open_inputs: BTreeMap::new(),
+            open_outputs: BTreeMap::new(),
             running_nodes: BTreeMap::new(),
+            exited_nodes: BTreeMap::new(),
+            queue_gauges: BTreeMap::new(),
+            input_queues: BTreeMap::new(),
+            output_stats: BTreeMap::new(),
+            restartable_nodes: BTreeMap::new(),
+            deferred_nodes: Vec::new(),
+            ready_nodes: BTreeSet::new(),
+            ready_signal_nodes: BTreeSet::new(),
+            remote_dependencies: BTreeSet::new(),
             open_external_mappings: HashMap::new(),
             pending_drop_tokens: HashMap::new(),
+            incoming_transfers: HashMap::new(),
+            received_outputs: HashMap::new(),
             _timer_handles: Vec::new(),
             stop_sent: false,
             empty_set: BTreeSet::new(),
@@ -1383,15 +2589,26 @@ impl RunningDataflow {
         events_tx: &mpsc::Sender<Timestamped<Event>>,
         clock: &Arc<HLC>,
     ) -> eyre::Result<()> {
-        for interval in self.timers.keys().copied() {
+        for timer in self.timers.keys().copied() {
             let events_tx = events_tx.clone();
             let dataflow_id = self.id;
             let clock = clock.clone();
             let task = async move {
-                let mut interval_stream = tokio::time::interval(interval);
+                tokio::time::sleep(timer.phase).await;
+                let mut interval_stream =
+                    (!timer.once).then(|| tokio::time::interval(timer.interval));
                 let hlc = HLC::default();
                 loop {
-                    interval_stream.tick().await;
+                    match &mut interval_stream {
+                        Some(interval_stream) => {
+                            interval_stream.tick().await;
+                        }
+                        None => tokio::time::sleep(timer.interval).await,
+                    }
+                    if !timer.jitter.is_zero() {
+                        let jitter = rand::thread_rng().gen_range(Duration::ZERO..=timer.jitter);
+                        tokio::time::sleep(jitter).await;
+                    }
 
                     let span = tracing::span!(tracing::Level::TRACE, "tick");
                     let _ = span.enter();
@@ -1412,13 +2629,13 @@ impl RunningDataflow {
                     let event = Timestamped {
                         inner: DoraEvent::Timer {
                             dataflow_id,
-                            interval,
+                            timer,
                             metadata,
                         }
                         .into(),
                         timestamp: clock.new_timestamp(),
                     };
-                    if events_tx.send(event).await.is_err() {
+                    if events_tx.send(event).await.is_err() || timer.once {
                         break;
                     }
                 }
@@ -1456,15 +2673,229 @@ impl RunningDataflow {
         self.stop_sent = true;
     }
 
+    /// Returns the gauges of all full `block_sender` queues that receive the given output.
+    fn full_queues(&self, node_id: &NodeId, output_id: &DataId) -> Vec<Arc<QueueGauge>> {
+        let output_id = OutputId(node_id.clone(), output_id.clone());
+        self.mappings
+            .get(&output_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|input| self.queue_gauges.get(input))
+            .filter(|gauge| gauge.is_full())
+            .cloned()
+            .collect()
+    }
+
+    /// Reports the status of all local nodes.
+    fn inspect(&self, machine_id: &str) -> Vec<NodeInfo> {
+        self.open_outputs
+            .iter()
+            .map(|(node_id, open_outputs)| {
+                let restarting = self
+                    .restartable_nodes
+                    .get(node_id)
+                    .is_some_and(|n| n.restarting);
+                // the exited process is only replaced after the backoff
+                let running = self.running_nodes.get(node_id).filter(|_| !restarting);
+                let state = if let Some(status) = self.exited_nodes.get(node_id) {
+                    NodeState::Exited(status.clone())
+                } else if restarting {
+                    NodeState::Restarting
+                } else if running.is_some() {
+                    NodeState::Running
+                } else {
+                    NodeState::Pending
+                };
+                NodeInfo {
+                    id: node_id.clone(),
+                    machine: machine_id.to_owned(),
+                    pid: running.map(|n| n.pid),
+                    uptime: running.map(|n| n.started.elapsed()),
+                    restarts: self
+                        .restartable_nodes
+                        .get(node_id)
+                        .map(|n| n.restarts)
+                        .unwrap_or_default(),
+                    // the inputs of exited nodes are only closed once their
+                    // sources finish too
+                    open_inputs: match state {
+                        NodeState::Exited(_) => BTreeSet::new(),
+                        _ => self.open_inputs(node_id).clone(),
+                    },
+                    open_outputs: open_outputs.clone(),
+                    state,
+                }
+            })
+            .collect()
+    }
+
+    fn stats(&self, machine_id: &str) -> DataflowStats {
+        DataflowStats {
+            outputs: self
+                .output_stats
+                .iter()
+                .map(|(OutputId(node, output), stats)| {
+                    stats.snapshot(node.clone(), output.clone(), machine_id.to_owned())
+                })
+                .collect(),
+            inputs: self
+                .input_queues
+                .iter()
+                .map(|((node, input), queue)| {
+                    queue
+                        .stats
+                        .snapshot(node.clone(), input.clone(), machine_id.to_owned())
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the outputs of the given node that are received by local
+    /// `block_sender` inputs.
+    fn blocking_outputs(&self, node_id: &NodeId) -> BTreeSet<DataId> {
+        self.mappings
+            .iter()
+            .filter(|(OutputId(source, _), receivers)| {
+                source == node_id
+                    && receivers
+                        .iter()
+                        .any(|input| self.queue_gauges.contains_key(input))
+            })
+            .map(|(OutputId(_, output_id), _)| output_id.clone())
+            .collect()
+    }
+
+    /// Returns the queue configuration of all inputs of the given local node,
+    /// by input ID.
+    fn node_input_queues(&self, node_id: &NodeId) -> BTreeMap<DataId, InputQueue> {
+        self.input_queues
+            .iter()
+            .filter(|((receiver_id, _), _)| receiver_id == node_id)
+            .map(|((_, input_id), queue)| (input_id.clone(), queue.clone()))
+            .collect()
+    }
+
+    /// Removes all deferred nodes that depend on the given node, directly or
+    /// transitively. Returns the IDs of the removed nodes.
+    fn remove_dependents(&mut self, node_id: &NodeId) -> Vec<NodeId> {
+        let mut removed = vec![node_id.clone()];
+        let mut i = 0;
+        while i < removed.len() {
+            let (dependents, others): (Vec<_>, Vec<_>) = std::mem::take(&mut self.deferred_nodes)
+                .into_iter()
+                .partition(|node| node.depends_on.contains(&removed[i]));
+            self.deferred_nodes = others;
+            removed.extend(dependents.into_iter().map(|node| node.id));
+            i += 1;
+        }
+        removed.remove(0);
+        removed
+    }
+
+    /// Whether the given node would be restarted if it exited now.
+    fn may_restart(&self, node_id: &NodeId) -> bool {
+        !self.stop_sent
+            && self
+                .restartable_nodes
+                .get(node_id)
+                .map(|n| n.node.restart.should_restart(true, n.restarts))
+                .unwrap_or(false)
+    }
+
+    /// Whether the given node was restarted after the dataflow was started.
+    fn is_restarted(&self, node_id: &NodeId) -> bool {
+        let restarted = self
+            .restartable_nodes
+            .get(node_id)
+            .map(|n| n.restarts > 0)
+            .unwrap_or(false);
+        restarted && !self.pending_nodes.is_pending(node_id)
+    }
+
+    /// Checks the restart policy of an exited node and prepares the restart.
+    ///
+    /// Returns the delay after which the node should be restarted, or `None` if
+    /// the node should not be restarted.
+    fn prepare_restart(&mut self, node_id: &NodeId, failed: bool) -> Option<Duration> {
+        if self.stop_sent {
+            return None;
+        }
+        let restartable = self.restartable_nodes.get_mut(node_id)?;
+        let policy = &restartable.node.restart;
+        if !policy.should_restart(failed, restartable.restarts) {
+            if policy.mode != RestartMode::Never && failed {
+                tracing::warn!("node `{node_id}` exceeded its maximum number of restarts");
+            }
+            return None;
+        }
+        let backoff = policy.backoff(restartable.restarts);
+        restartable.restarts += 1;
+        restartable.restarting = true;
+
+        // the restarted node registers new channels
+        self.subscribe_channels.remove(node_id);
+        self.drop_channels.remove(node_id);
+        for ((receiver_id, _), gauge) in &self.queue_gauges {
+            if receiver_id == node_id {
+                gauge.reset();
+            }
+        }
+        Some(backoff)
+    }
+
+    /// Discards incomplete transfers of large remote outputs that received no
+    /// chunk for a while, e.g. because the sending daemon was lost.
+    fn discard_stale_transfers(&mut self) {
+        self.incoming_transfers.retain(|id, transfer| {
+            let stale = transfer.is_stale();
+            if stale {
+                tracing::warn!("discarding stalled remote output transfer `{id}`");
+            }
+            !stale
+        });
+    }
+
+    /// Unblocks all senders that wait for queues of the given node.
+    fn close_queues(&mut self, node_id: &NodeId) {
+        for ((receiver_id, _), gauge) in &self.queue_gauges {
+            if receiver_id == node_id {
+                gauge.close();
+            }
+        }
+    }
+
     fn open_inputs(&self, node_id: &NodeId) -> &BTreeSet<DataId> {
         self.open_inputs.get(node_id).unwrap_or(&self.empty_set)
     }
 
+    /// Called once an output was sent to all receivers.
+    async fn release_drop_token(
+        &mut self,
+        token: DropToken,
+        owner: NodeId,
+        clock: &HLC,
+    ) -> eyre::Result<()> {
+        // insert token into `pending_drop_tokens` even if there are no local subscribers
+        self.pending_drop_tokens
+            .entry(token)
+            .or_insert_with(|| DropTokenInformation {
+                owner,
+                pending_nodes: Default::default(),
+                pending_transfers: 0,
+            });
+        // check if all local subscribers are finished with the token
+        self.check_drop_token(token, clock).await
+    }
+
     async fn check_drop_token(&mut self, token: DropToken, clock: &HLC) -> eyre::Result<()> {
         match self.pending_drop_tokens.entry(token) {
             std::collections::hash_map::Entry::Occupied(entry) => {
-                if entry.get().pending_nodes.is_empty() {
+                if entry.get().pending_nodes.is_empty() && entry.get().pending_transfers == 0 {
                     let (drop_token, info) = entry.remove_entry();
+                    if self.received_outputs.remove(&drop_token).is_some() {
+                        // region of a remote output, which is unmapped on drop
+                        return Ok(());
+                    }
                     let result = match self.drop_channels.get_mut(&info.owner) {
                         Some(channel) => send_with_timestamp(
                             channel,
@@ -1493,7 +2924,7 @@ impl RunningDataflow {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct OutputId(NodeId, DataId);
 type InputId = (NodeId, DataId);
 
@@ -1503,6 +2934,8 @@ struct DropTokenInformation {
     /// Contains the set of pending nodes that still have access to the input
     /// associated with a drop token.
     pending_nodes: BTreeSet<NodeId>,
+    /// Number of transfers to other daemons that still read the output data.
+    pending_transfers: usize,
 }
 
 #[derive(Debug)]
@@ -1514,6 +2947,14 @@ pub enum Event {
     },
     Coordinator(CoordinatorEvent),
     Daemon(InterDaemonEvent),
+    Peer(PeerEvent),
+    /// The registered connection to the coordinator was closed.
+    CoordinatorDisconnected,
+    /// New connections to the coordinator after a reconnect.
+    CoordinatorReconnected {
+        register: MaybeTlsStream,
+        events: MaybeTlsStream,
+    },
     Dora(DoraEvent),
     HeartbeatInterval,
     CtrlC,
@@ -1530,6 +2971,10 @@ pub enum DaemonNodeEvent {
     OutputsDone {
         reply_sender: oneshot::Sender<DaemonReply>,
     },
+    /// The node signaled that it is ready, see `Node::ready_signal`.
+    Ready {
+        reply_sender: oneshot::Sender<DaemonReply>,
+    },
     Subscribe {
         event_sender: UnboundedSender<Timestamped<daemon_messages::NodeEvent>>,
         reply_sender: oneshot::Sender<DaemonReply>,
@@ -1546,6 +2991,10 @@ pub enum DaemonNodeEvent {
         output_id: DataId,
         metadata: dora_core::message::Metadata,
         data: Option<DataMessage>,
+        /// Only set if the node waits for a reply, see `NodeConfig::blocking_outputs`.
+        ///
+        /// Delayed while a receiving `block_sender` input queue is full.
+        reply_sender: Option<oneshot::Sender<DaemonReply>>,
     },
     ReportDrop {
         tokens: Vec<DropToken>,
@@ -1559,7 +3008,7 @@ pub enum DaemonNodeEvent {
 pub enum DoraEvent {
     Timer {
         dataflow_id: DataflowId,
-        interval: Duration,
+        timer: TimerSpec,
         metadata: dora_core::message::Metadata,
     },
     Logs {
@@ -1573,39 +3022,28 @@ pub enum DoraEvent {
         node_id: NodeId,
         exit_status: NodeExitStatus,
     },
-}
-
-#[derive(Debug)]
-pub enum NodeExitStatus {
-    Success,
-    IoError(io::Error),
-    ExitCode(i32),
-    Signal(i32),
-    Unknown,
-}
-
-impl From<Result<std::process::ExitStatus, io::Error>> for NodeExitStatus {
-    fn from(result: Result<std::process::ExitStatus, io::Error>) -> Self {
-        match result {
-            Ok(status) => {
-                if status.success() {
-                    NodeExitStatus::Success
-                } else if let Some(code) = status.code() {
-                    Self::ExitCode(code)
-                } else {
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::process::ExitStatusExt;
-                        if let Some(signal) = status.signal() {
-                            return Self::Signal(signal);
-                        }
-                    }
-                    Self::Unknown
-                }
-            }
-            Err(err) => Self::IoError(err),
-        }
-    }
+    RestartNode {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    },
+    /// New output of a log subscription of the coordinator.
+    LogOutput {
+        dataflow_id: DataflowId,
+        subscription: Uuid,
+        output: eyre::Result<Vec<LogChunk>>,
+        finished: bool,
+    },
+    /// The connections to the other machines of the dataflow are set up.
+    RemoteLinksReady {
+        dataflow_id: DataflowId,
+        result: eyre::Result<()>,
+    },
+    /// An output was sent to other daemons in chunks and its data is no
+    /// longer needed.
+    RemoteTransferFinished {
+        dataflow_id: DataflowId,
+        drop_token: DropToken,
+    },
 }
 
 #[must_use]
@@ -1654,3 +3092,198 @@ fn set_up_ctrlc_handler(
 
     Ok(ReceiverStream::new(ctrlc_rx))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id<T: From<String>>(s: &str) -> T {
+        s.to_owned().into()
+    }
+
+    fn dataflow(yaml: &str) -> RunningDataflow {
+        let descriptor: Descriptor = serde_yaml::from_str(yaml).unwrap();
+        let mut dataflow = RunningDataflow::new(Uuid::new_v4(), String::new(), descriptor.clone());
+        for node in descriptor.resolve_aliases_and_set_defaults().unwrap() {
+            dataflow
+                .open_outputs
+                .insert(node.id.clone(), node_outputs(&node));
+            if node.restart.mode != RestartMode::Never {
+                dataflow.restartable_nodes.insert(
+                    node.id.clone(),
+                    RestartableNode {
+                        node: node.clone(),
+                        restarts: 0,
+                        restarting: false,
+                    },
+                );
+            }
+        }
+        dataflow
+    }
+
+    #[test]
+    fn outputs_with_block_sender_receivers_are_blocking() {
+        let mut dataflow = dataflow("nodes: []");
+        dataflow.mappings.insert(
+            OutputId(id("source"), id("blocking")),
+            [(id("sink"), id("a")), (id("sink"), id("b"))].into(),
+        );
+        dataflow.mappings.insert(
+            OutputId(id("source"), id("dropping")),
+            [(id("sink"), id("c"))].into(),
+        );
+        let gauge = Arc::new(QueueGauge::new(1));
+        dataflow
+            .queue_gauges
+            .insert((id("sink"), id("b")), gauge.clone());
+
+        assert_eq!(
+            dataflow.blocking_outputs(&id("source")),
+            [id("blocking")].into()
+        );
+        assert!(dataflow.blocking_outputs(&id("sink")).is_empty());
+
+        assert!(dataflow
+            .full_queues(&id("source"), &id("blocking"))
+            .is_empty());
+        gauge.push();
+        assert_eq!(
+            dataflow.full_queues(&id("source"), &id("blocking")).len(),
+            1
+        );
+        assert!(dataflow
+            .full_queues(&id("source"), &id("dropping"))
+            .is_empty());
+    }
+
+    #[test]
+    fn restart_according_to_policy() {
+        let mut dataflow = dataflow(
+            r#"
+            nodes:
+              - id: flaky
+                path: flaky
+                restart:
+                  mode: on-failure
+                  max_retries: 2
+                  backoff_ms: 100
+              - id: plain
+                path: plain
+            "#,
+        );
+        let flaky: NodeId = id("flaky");
+
+        assert_eq!(dataflow.prepare_restart(&id("plain"), true), None);
+        assert_eq!(dataflow.prepare_restart(&flaky, false), None);
+        assert!(dataflow.may_restart(&flaky));
+
+        assert_eq!(
+            dataflow.prepare_restart(&flaky, true),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            dataflow.prepare_restart(&flaky, true),
+            Some(Duration::from_millis(200))
+        );
+        assert!(!dataflow.may_restart(&flaky));
+        assert_eq!(dataflow.prepare_restart(&flaky, true), None);
+        assert_eq!(dataflow.restartable_nodes[&flaky].restarts, 2);
+    }
+
+    #[test]
+    fn restart_reopens_block_sender_queues() {
+        let mut dataflow = dataflow(
+            r#"
+            nodes:
+              - id: sink
+                path: sink
+                restart: always
+            "#,
+        );
+        let sink: NodeId = id("sink");
+        let gauge = Arc::new(QueueGauge::new(1));
+        dataflow
+            .queue_gauges
+            .insert((sink.clone(), id("input")), gauge.clone());
+        gauge.push();
+        dataflow.close_queues(&sink);
+
+        assert!(dataflow.prepare_restart(&sink, false).is_some());
+        assert!(!gauge.is_full());
+        // senders must block again once the restarted node's queue is full
+        gauge.push();
+        assert!(gauge.wait_for_space().now_or_never().is_none());
+    }
+
+    #[test]
+    fn no_restart_after_stop() {
+        let mut dataflow = dataflow(
+            r#"
+            nodes:
+              - id: node
+                path: node
+                restart: always
+            "#,
+        );
+        dataflow.stop_sent = true;
+        assert!(!dataflow.may_restart(&id("node")));
+        assert_eq!(dataflow.prepare_restart(&id("node"), true), None);
+    }
+
+    #[test]
+    fn inspect_node_states() {
+        let mut dataflow = dataflow(
+            r#"
+            nodes:
+              - id: pending
+                path: pending
+              - id: running
+                path: running
+                outputs: [out]
+              - id: finished
+                path: finished
+              - id: failed
+                path: failed
+              - id: flaky
+                path: flaky
+                restart: on-failure
+            "#,
+        );
+        for node in ["running", "flaky"] {
+            dataflow
+                .running_nodes
+                .insert(id(node), RunningNode::new(42));
+        }
+        dataflow
+            .exited_nodes
+            .insert(id("finished"), NodeExitStatus::Success);
+        dataflow
+            .exited_nodes
+            .insert(id("failed"), NodeExitStatus::ExitCode(1));
+        assert!(dataflow.prepare_restart(&id("flaky"), true).is_some());
+
+        let nodes: BTreeMap<String, NodeInfo> = dataflow
+            .inspect("A")
+            .into_iter()
+            .map(|node| (node.id.to_string(), node))
+            .collect();
+        assert!(matches!(nodes["pending"].state, NodeState::Pending));
+        assert!(matches!(nodes["running"].state, NodeState::Running));
+        assert_eq!(nodes["running"].pid, Some(42));
+        assert_eq!(nodes["running"].open_outputs, [id("out")].into());
+        assert!(matches!(
+            nodes["finished"].state,
+            NodeState::Exited(NodeExitStatus::Success)
+        ));
+        assert!(matches!(
+            nodes["failed"].state,
+            NodeState::Exited(NodeExitStatus::ExitCode(1))
+        ));
+        // the exited process of a restarting node is not reported
+        assert!(matches!(nodes["flaky"].state, NodeState::Restarting));
+        assert_eq!(nodes["flaky"].pid, None);
+        assert_eq!(nodes["flaky"].restarts, 1);
+        assert!(nodes.values().all(|node| node.machine == "A"));
+    }
+}
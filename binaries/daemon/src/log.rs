@@ -1,9 +1,1037 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use dora_core::config::NodeId;
+use dora_core::{
+    config::NodeId,
+    logs::{LogFilter, LogRecord},
+    topics::{LogChunk, LogPosition},
+};
+use eyre::Context;
+use flate2::{write::GzEncoder, Compression};
+use futures::future;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::watch,
+    task::JoinHandle,
+};
 use uuid::Uuid;
 
-pub fn log_path(working_dir: &Path, dataflow_id: &Uuid, node_id: &NodeId) -> PathBuf {
+/// Maximum number of bytes that are returned by a single [`read_log`] call.
+const MAX_CHUNK_LEN: u64 = 1024 * 1024;
+
+/// Minimum time between two entries of a [`LogIndex`].
+const INDEX_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Rotation of node log files and retention of the `out/` directories of
+/// finished dataflows.
+#[derive(Debug, Clone, Copy)]
+pub struct LogConfig {
+    /// Start a new log segment once the current one exceeds this size in bytes.
+    pub max_segment_size: Option<u64>,
+    /// Start a new log segment once the current one is older than this.
+    pub max_segment_age: Option<Duration>,
+    /// Number of rotated segments that are kept per node.
+    pub max_segments: usize,
+    /// Compress rotated segments with gzip.
+    pub compress: bool,
+    /// Maximum time between writing log output and syncing it to disk.
+    pub sync_interval: Duration,
+    pub format: LogFormat,
+    pub retention: RetentionPolicy,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            max_segment_size: Some(64 * 1024 * 1024),
+            max_segment_age: None,
+            max_segments: 5,
+            compress: true,
+            sync_interval: Duration::from_secs(1),
+            format: LogFormat::default(),
+            retention: RetentionPolicy::default(),
+        }
+    }
+}
+
+/// Which `out/<dataflow>` directories of finished dataflows to keep.
+///
+/// Only directories with a finish marker of the daemon's machine are
+/// considered (see [`mark_finished`]), so daemons that share a working
+/// directory don't remove the output of each other's dataflows. Everything is
+/// kept by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetentionPolicy {
+    /// Remove directories that were not modified for this duration.
+    pub max_age: Option<Duration>,
+    /// Only keep the given number of most recently modified directories.
+    pub max_count: Option<usize>,
+}
+
+/// Format of the node log files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The output as printed by the node, in `log_<node>.txt`.
+    #[default]
+    Text,
+    /// One JSON [`LogRecord`] per line, in `log_<node>.jsonl`. Required for
+    /// filtering by level or target.
+    Json,
+}
+
+impl LogFormat {
+    /// Encodes the given record as one or more complete lines.
+    pub fn encode(&self, record: &LogRecord) -> eyre::Result<Vec<u8>> {
+        let mut line = match self {
+            LogFormat::Text => record.to_string().into_bytes(),
+            LogFormat::Json => {
+                serde_json::to_vec(record).wrap_err("failed to serialize log record")?
+            }
+        };
+        if !line.ends_with(b"\n") {
+            line.push(b'\n');
+        }
+        Ok(line)
+    }
+
+    /// Checks that log files of this format can be filtered.
+    pub fn check_filter(&self, filter: &LogFilter) -> eyre::Result<()> {
+        if *self == LogFormat::Text && !filter.is_empty() {
+            eyre::bail!(
+                "filtering logs by level or target requires structured logs, \
+                start the daemon with `--structured-logs`"
+            );
+        }
+        Ok(())
+    }
+}
+
+pub fn log_path(
+    working_dir: &Path,
+    dataflow_id: &Uuid,
+    node_id: &NodeId,
+    format: LogFormat,
+) -> PathBuf {
+    let dataflow_dir = working_dir.join("out").join(dataflow_id.to_string());
+    let extension = match format {
+        LogFormat::Text => "txt",
+        LogFormat::Json => "jsonl",
+    };
+    dataflow_dir.join(format!("log_{node_id}.{extension}"))
+}
+
+/// Path of the rotated log segment with the given number.
+///
+/// Segments are numbered in the order they were rotated, so the segment with
+/// the highest number contains the most recent output.
+fn segment_path(log_path: &Path, segment: u64) -> PathBuf {
+    let mut path = OsString::from(log_path);
+    path.push(format!(".{segment}"));
+    path.into()
+}
+
+/// Returns the numbers and paths of all rotated segments of the given log
+/// file, ordered from oldest to newest.
+fn rotated_segments(log_path: &Path) -> eyre::Result<Vec<(u64, PathBuf)>> {
+    let (Some(dir), Some(file_name)) = (log_path.parent(), log_path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let prefix = format!("{}.", file_name.to_string_lossy());
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(dir).wrap_err("failed to read dataflow dir")? {
+        let path = entry.wrap_err("failed to read dataflow dir entry")?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let segment = name
+            .strip_prefix(&prefix)
+            .map(|rest| rest.strip_suffix(".gz").unwrap_or(rest))
+            .and_then(|number| number.parse().ok());
+        if let Some(segment) = segment {
+            segments.push((segment, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Compresses rotated segments and removes segments that exceed the
+/// configured number.
+///
+/// The most recent segment is kept uncompressed for readers that did not
+/// reach its end yet.
+fn finish_rotation(log_path: &Path, config: &LogConfig) -> eyre::Result<()> {
+    let segments = rotated_segments(log_path)?;
+    let outdated = segments.len().saturating_sub(config.max_segments);
+    for (_, path) in &segments[..outdated] {
+        std::fs::remove_file(path)
+            .wrap_err_with(|| format!("failed to remove log segment `{}`", path.display()))?;
+    }
+    if !config.compress {
+        return Ok(());
+    }
+    let kept = &segments[outdated..];
+    for (_, path) in kept.iter().take(kept.len().saturating_sub(1)) {
+        if path.extension().is_some_and(|ext| ext == "gz") {
+            continue;
+        }
+        let mut compressed = OsString::from(path);
+        compressed.push(".gz");
+        let mut input = std::fs::File::open(path).wrap_err("failed to open log segment")?;
+        let output =
+            std::fs::File::create(&compressed).wrap_err("failed to create compressed segment")?;
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        std::io::copy(&mut input, &mut encoder).wrap_err("failed to compress log segment")?;
+        encoder
+            .finish()
+            .and_then(|file| file.sync_all())
+            .wrap_err("failed to write compressed segment")?;
+        std::fs::remove_file(path).wrap_err("failed to remove uncompressed segment")?;
+    }
+    Ok(())
+}
+
+/// Appends log records to the log file of a node, rotating it according to
+/// the [`LogConfig`].
+///
+/// Offsets continue across segments, so readers can detect that their
+/// offset refers to an earlier segment.
+pub struct LogWriter {
+    path: PathBuf,
+    file: File,
+    config: LogConfig,
+    index: Arc<LogIndex>,
+    offset: u64,
+    segment_len: u64,
+    segment_created: SystemTime,
+    /// Compression and pruning of the previously rotated segments.
+    finishing_rotation: Option<JoinHandle<()>>,
+}
+
+impl LogWriter {
+    /// Opens the log file at the given path, appending to existing output of
+    /// restarted nodes.
+    pub async fn open(
+        path: PathBuf,
+        config: LogConfig,
+        index: Arc<LogIndex>,
+    ) -> eyre::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .wrap_err_with(|| format!("failed to open log file `{}`", path.display()))?;
+        let metadata = file
+            .metadata()
+            .await
+            .wrap_err("failed to read log file metadata")?;
+        let segment_len = metadata.len();
+        let segment_created = match segment_len {
+            0 => SystemTime::now(),
+            _ => metadata.created().unwrap_or_else(|_| SystemTime::now()),
+        };
+        Ok(Self {
+            path,
+            file,
+            config,
+            offset: index.segments().current_start + segment_len,
+            index,
+            segment_len,
+            segment_created,
+            finishing_rotation: None,
+        })
+    }
+
+    /// Writes the given line, starting a new segment before if necessary.
+    pub async fn write(&mut self, line: &[u8]) -> eyre::Result<()> {
+        if self.rotation_due() {
+            self.rotate().await.wrap_err("failed to rotate log file")?;
+        }
+        self.index.record(SystemTime::now(), self.offset);
+        self.file
+            .write_all(line)
+            .await
+            .wrap_err("failed to write to log file")?;
+        // make the line visible to readers
+        self.file
+            .flush()
+            .await
+            .wrap_err("failed to flush log file")?;
+        self.offset += line.len() as u64;
+        self.segment_len += line.len() as u64;
+        self.index.written.send_replace(self.offset);
+        Ok(())
+    }
+
+    pub async fn sync(&mut self) -> eyre::Result<()> {
+        self.file
+            .sync_data()
+            .await
+            .wrap_err("failed to sync log file")
+    }
+
+    fn rotation_due(&self) -> bool {
+        if self.segment_len == 0 {
+            return false;
+        }
+        let too_large = self
+            .config
+            .max_segment_size
+            .is_some_and(|max| self.segment_len >= max);
+        let too_old = self.config.max_segment_age.is_some_and(|max| {
+            self.segment_created
+                .elapsed()
+                .is_ok_and(|elapsed| elapsed >= max)
+        });
+        too_large || too_old
+    }
+
+    async fn rotate(&mut self) -> eyre::Result<()> {
+        self.sync().await?;
+        // the segments must not be modified concurrently
+        self.wait_for_rotation().await;
+        let path = self.path.clone();
+        let next_segment = tokio::task::spawn_blocking(move || rotated_segments(&path))
+            .await??
+            .last()
+            .map_or(1, |(segment, _)| segment + 1);
+        let rotated = segment_path(&self.path, next_segment);
+        tokio::fs::rename(&self.path, &rotated)
+            .await
+            .wrap_err("failed to rename log file")?;
+        self.file = File::create(&self.path)
+            .await
+            .wrap_err("failed to create new log file")?;
+        self.index.start_segment(self.offset, rotated);
+        self.segment_len = 0;
+        self.segment_created = SystemTime::now();
+
+        let path = self.path.clone();
+        let config = self.config;
+        self.finishing_rotation = Some(tokio::task::spawn_blocking(move || {
+            if let Err(err) = finish_rotation(&path, &config) {
+                tracing::warn!("{err:?}");
+            }
+        }));
+        Ok(())
+    }
+
+    /// Waits until the compression and pruning of the previously rotated
+    /// segments is done.
+    async fn wait_for_rotation(&mut self) {
+        if let Some(task) = self.finishing_rotation.take() {
+            if let Err(err) = task.await {
+                tracing::warn!("failed to finish log rotation: {err}");
+            }
+        }
+    }
+}
+
+/// Name of the file that marks the `out/<dataflow>` directory of a dataflow
+/// that finished on the given machine.
+fn finish_marker(machine_id: &str) -> String {
+    if machine_id.is_empty() {
+        ".finished".into()
+    } else {
+        format!(".finished-{machine_id}")
+    }
+}
+
+/// Records that the given dataflow finished on this machine, which makes its
+/// output directory subject to the [`RetentionPolicy`].
+pub fn mark_finished(working_dir: &Path, dataflow_id: &Uuid, machine_id: &str) -> eyre::Result<()> {
     let dataflow_dir = working_dir.join("out").join(dataflow_id.to_string());
-    dataflow_dir.join(format!("log_{node_id}.txt"))
+    if !dataflow_dir.is_dir() {
+        // no local output
+        return Ok(());
+    }
+    std::fs::write(dataflow_dir.join(finish_marker(machine_id)), [])
+        .wrap_err("failed to write finish marker")
+}
+
+/// Returns the most recent modification time of the files in the given
+/// directory, i.e. the time the dataflow last wrote output or finished.
+fn last_modified(dir: &Path) -> eyre::Result<SystemTime> {
+    let mut last = SystemTime::UNIX_EPOCH;
+    for entry in std::fs::read_dir(dir).wrap_err("failed to read dataflow dir")? {
+        let modified = entry
+            .and_then(|e| e.metadata())
+            .and_then(|m| m.modified())
+            .wrap_err("failed to read modification time")?;
+        last = last.max(modified);
+    }
+    Ok(last)
+}
+
+/// Removes the `out/<dataflow>` directories in the given working directory
+/// that exceed the retention policy.
+///
+/// Only directories of dataflows that finished on the given machine and are
+/// not `running` are considered.
+pub fn apply_retention(
+    working_dir: &Path,
+    machine_id: &str,
+    policy: &RetentionPolicy,
+    running: &HashSet<Uuid>,
+) -> eyre::Result<()> {
+    if policy.max_age.is_none() && policy.max_count.is_none() {
+        return Ok(());
+    }
+    let out_dir = working_dir.join("out");
+    let marker = finish_marker(machine_id);
+    let entries = match std::fs::read_dir(out_dir) {
+        Ok(entries) => entries,
+        // e.g. removed by the user
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).wrap_err("failed to read out dir"),
+    };
+    let mut finished = Vec::new();
+    for entry in entries {
+        let entry = entry.wrap_err("failed to read out dir entry")?;
+        let is_finished_dataflow = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| Uuid::parse_str(name).ok())
+            .is_some_and(|id| !running.contains(&id));
+        if !is_finished_dataflow || !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        let path = entry.path();
+        if !path.join(&marker).exists() {
+            // still running or run by another daemon
+            continue;
+        }
+        finished.push((last_modified(&path)?, path));
+    }
+    // most recently modified first
+    finished.sort_by(|a, b| b.0.cmp(&a.0));
+
+    for (i, (modified, path)) in finished.into_iter().enumerate() {
+        let too_many = policy.max_count.is_some_and(|max| i >= max);
+        let too_old = policy
+            .max_age
+            .is_some_and(|max| modified.elapsed().is_ok_and(|elapsed| elapsed > max));
+        if too_many || too_old {
+            tracing::info!("removing dataflow output `{}`", path.display());
+            std::fs::remove_dir_all(&path)
+                .wrap_err_with(|| format!("failed to remove `{}`", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Maps write times to offsets in the log file of a node.
+///
+/// Used to look up [`LogPosition::Since`] positions. Entries are recorded at
+/// most once per [`INDEX_INTERVAL`] to keep the index small, so lookups are
+/// only accurate up to that interval.
+#[derive(Debug)]
+pub struct LogIndex {
+    entries: Mutex<Vec<(SystemTime, u64)>>,
+    segments: Mutex<Segments>,
+    /// Offset up to which output is readable, used to wake [`LogFollower`]s.
+    written: watch::Sender<u64>,
+}
+
+impl Default for LogIndex {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+            segments: Default::default(),
+            written: watch::channel(0).0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Segments {
+    /// Offset at which the current segment of the log file starts.
+    current_start: u64,
+    /// Start offset and path of the most recently rotated segment.
+    previous: Option<(u64, PathBuf)>,
+}
+
+impl LogIndex {
+    fn segments(&self) -> Segments {
+        self.segments.lock().unwrap().clone()
+    }
+
+    /// Records that a new segment was started at the given offset, after the
+    /// previous one was moved to `rotated`.
+    ///
+    /// Entries of earlier segments are removed.
+    fn start_segment(&self, offset: u64, rotated: PathBuf) {
+        let mut entries = self.entries.lock().unwrap();
+        let mut segments = self.segments.lock().unwrap();
+        entries.retain(|(_, entry_offset)| *entry_offset >= segments.current_start);
+        segments.previous = Some((segments.current_start, rotated));
+        segments.current_start = offset;
+    }
+
+    /// Records that the given offset was written at the given time.
+    pub fn record(&self, time: SystemTime, offset: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        let recent = entries.last().is_some_and(|(last, _)| {
+            time.duration_since(*last)
+                .is_ok_and(|elapsed| elapsed < INDEX_INTERVAL)
+        });
+        if !recent {
+            entries.push((time, offset));
+        }
+    }
+
+    /// Returns the offset of the first output that was written at or after
+    /// the given time, or `None` if nothing was written since.
+    fn offset_since(&self, since: SystemTime) -> Option<u64> {
+        let entries = self.entries.lock().unwrap();
+        let next = entries.partition_point(|(time, _)| *time <= since);
+        // output of the previous entry might continue for up to `INDEX_INTERVAL`
+        if let Some((time, offset)) = next.checked_sub(1).map(|i| entries[i]) {
+            if since.duration_since(time).unwrap_or_default() < INDEX_INTERVAL {
+                return Some(offset);
+            }
+        }
+        entries.get(next).map(|(_, offset)| *offset)
+    }
+}
+
+/// Reads the log file at the given path, starting at the given position.
+///
+/// Readers that did not reach the end of the most recently rotated segment
+/// continue in it, earlier segments are skipped. Returns the read data and
+/// the offset to continue reading from. If
+/// `complete_lines` is set, a trailing incomplete line is not returned
+/// because the node might still be writing to it.
+///
+/// Only lines that match the given `filter` are returned. The returned offset
+/// still points past all lines that were read, including filtered ones.
+pub async fn read_log(
+    path: &Path,
+    position: LogPosition,
+    index: Option<&LogIndex>,
+    complete_lines: bool,
+    filter: &LogFilter,
+) -> eyre::Result<(Vec<u8>, u64)> {
+    // offsets continue across segments
+    let segments = index.map(|index| index.segments()).unwrap_or_default();
+    let (path, segment_start) = match (position, &segments.previous) {
+        (LogPosition::Offset(offset), Some((start, previous)))
+            if (*start..segments.current_start).contains(&offset) =>
+        {
+            (previous.as_path(), *start)
+        }
+        _ => (path, segments.current_start),
+    };
+
+    let mut file = match File::open(path).await {
+        Ok(file) => file,
+        // the node did not write any output yet
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok((Vec::new(), segments.current_start))
+        }
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("failed to open log file {path:?}"));
+        }
+    };
+    let len = file
+        .metadata()
+        .await
+        .wrap_err("failed to read log file metadata")?
+        .len();
+
+    let start = match position {
+        LogPosition::Offset(offset) => match offset.checked_sub(segment_start) {
+            Some(offset) if offset <= len => offset,
+            // the offset is in an earlier segment or the file was replaced in
+            // the meantime -> start again from the beginning
+            _ => 0,
+        },
+        LogPosition::Tail(lines) => tail_offset(&mut file, len, lines, filter).await?,
+        LogPosition::Since(age) => match index {
+            Some(index) => index
+                .offset_since(SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH))
+                .map_or(len, |offset| offset.saturating_sub(segment_start)),
+            // no index available, e.g. because the daemon was restarted
+            None => 0,
+        },
+    };
+
+    file.seek(SeekFrom::Start(start))
+        .await
+        .wrap_err("failed to seek in log file")?;
+    let mut data = Vec::new();
+    (&mut file)
+        .take(MAX_CHUNK_LEN)
+        .read_to_end(&mut data)
+        .await
+        .wrap_err("failed to read log file")?;
+    // filtering requires complete records
+    if complete_lines || !filter.is_empty() {
+        match data.iter().rposition(|&b| b == b'\n') {
+            Some(last_newline) => data.truncate(last_newline + 1),
+            // a single line longer than the chunk size is returned as is
+            None if data.len() as u64 == MAX_CHUNK_LEN => {}
+            None => data.clear(),
+        }
+    }
+
+    let offset = segment_start + start + data.len() as u64;
+    if !filter.is_empty() {
+        data = data
+            .split_inclusive(|&b| b == b'\n')
+            .filter(|line| line_matches(line, filter))
+            .flatten()
+            .copied()
+            .collect();
+    }
+    Ok((data, offset))
+}
+
+/// Reads the log output of a set of nodes as it is written.
+pub struct LogFollower {
+    working_dir: PathBuf,
+    dataflow_id: Uuid,
+    format: LogFormat,
+    logs: Vec<FollowedLog>,
+    filter: LogFilter,
+}
+
+struct FollowedLog {
+    node: NodeId,
+    position: LogPosition,
+    index: Arc<LogIndex>,
+    written: watch::Receiver<u64>,
+}
+
+impl LogFollower {
+    pub fn new(
+        working_dir: PathBuf,
+        dataflow_id: Uuid,
+        format: LogFormat,
+        nodes: Vec<(NodeId, LogPosition, Arc<LogIndex>)>,
+        filter: LogFilter,
+    ) -> Self {
+        let logs = nodes
+            .into_iter()
+            .map(|(node, position, index)| FollowedLog {
+                node,
+                position,
+                written: index.written.subscribe(),
+                index,
+            })
+            .collect();
+        Self {
+            working_dir,
+            dataflow_id,
+            format,
+            logs,
+            filter,
+        }
+    }
+
+    /// Reads new output of the followed logs, at most one chunk per log.
+    ///
+    /// Returns no chunks once all written output was read. If
+    /// `complete_lines` is set, a trailing incomplete line is left for the
+    /// next call.
+    pub async fn read(&mut self, complete_lines: bool) -> eyre::Result<Vec<LogChunk>> {
+        let mut chunks = Vec::new();
+        for log in &mut self.logs {
+            // output written after this point wakes up `changed`
+            log.written.borrow_and_update();
+            let path = log_path(&self.working_dir, &self.dataflow_id, &log.node, self.format);
+            loop {
+                let (data, offset) = read_log(
+                    &path,
+                    log.position,
+                    Some(&log.index),
+                    complete_lines,
+                    &self.filter,
+                )
+                .await
+                .wrap_err_with(|| format!("failed to read logs of `{}`", log.node))?;
+                let progressed = log.position != LogPosition::Offset(offset);
+                log.position = LogPosition::Offset(offset);
+                if !data.is_empty() {
+                    chunks.push(LogChunk {
+                        node: log.node.clone(),
+                        data,
+                        offset,
+                    });
+                    break;
+                }
+                // skip over filtered lines
+                if !progressed {
+                    break;
+                }
+            }
+        }
+        Ok(chunks)
+    }
+
+    /// Waits until new output is written to one of the followed logs.
+    pub async fn changed(&mut self) {
+        if self.logs.is_empty() {
+            return future::pending().await;
+        }
+        let changed = self
+            .logs
+            .iter_mut()
+            .map(|log| Box::pin(log.written.changed()));
+        // the senders are kept alive by the log indexes
+        let _ = future::select_all(changed).await;
+    }
+}
+
+fn line_matches(line: &[u8], filter: &LogFilter) -> bool {
+    if filter.is_empty() {
+        return true;
+    }
+    serde_json::from_slice::<LogRecord>(line).is_ok_and(|record| filter.matches(&record))
+}
+
+/// Returns the offset of the start of the last `lines` lines of the file that
+/// match the given filter.
+async fn tail_offset(
+    file: &mut File,
+    len: u64,
+    lines: usize,
+    filter: &LogFilter,
+) -> eyre::Result<u64> {
+    const BLOCK_LEN: u64 = 8 * 1024;
+
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut end = len;
+    let mut remaining = lines;
+    let mut block = Vec::new();
+    // start of the line that continues at `end`
+    let mut partial_line = Vec::new();
+    // ignore the newline that terminates the last line
+    let mut last_line = true;
+    while end > 0 {
+        let start = end.saturating_sub(BLOCK_LEN);
+        file.seek(SeekFrom::Start(start))
+            .await
+            .wrap_err("failed to seek in log file")?;
+        block.resize((end - start) as usize, 0);
+        file.read_exact(&mut block)
+            .await
+            .wrap_err("failed to read log file")?;
+        let block_len = block.len();
+        block.append(&mut partial_line);
+
+        let mut line_end = block.len();
+        for i in (0..block_len).rev() {
+            if block[i] != b'\n' {
+                continue;
+            }
+            let line = &block[i + 1..line_end];
+            let skip = last_line && line.is_empty();
+            last_line = false;
+            if !skip && line_matches(line, filter) {
+                remaining -= 1;
+                if remaining == 0 {
+                    return Ok(start + i as u64 + 1);
+                }
+            }
+            line_end = i;
+        }
+        block.truncate(line_end);
+        std::mem::swap(&mut block, &mut partial_line);
+        end = start;
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dora_core::logs::LogLevel;
+    use std::io::BufRead;
+
+    #[tokio::test]
+    async fn read_log_tail() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join(format!("dora-log-test-{}.txt", Uuid::new_v4()));
+        tokio::fs::write(&path, "a\nb\nc\nincomplete").await?;
+
+        let (data, offset) = read_log(
+            &path,
+            LogPosition::Tail(3),
+            None,
+            true,
+            &LogFilter::default(),
+        )
+        .await?;
+        assert_eq!(data, b"b\nc\n");
+        assert_eq!(offset, 6);
+        let (data, offset) = read_log(
+            &path,
+            LogPosition::Offset(offset),
+            None,
+            false,
+            &LogFilter::default(),
+        )
+        .await?;
+        assert_eq!(data, b"incomplete");
+        assert_eq!(offset, 16);
+
+        tokio::fs::remove_file(&path).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_log_filtered() -> eyre::Result<()> {
+        let clock = dora_core::message::uhlc::HLC::default();
+        let record = |level: Option<LogLevel>, message: &str| LogRecord {
+            timestamp: clock.new_timestamp(),
+            node_id: "node".to_owned().into(),
+            level,
+            target: "node".into(),
+            message: message.into(),
+            fields: Default::default(),
+        };
+        let records = [
+            record(Some(LogLevel::Error), "first"),
+            record(None, "plain"),
+            record(Some(LogLevel::Info), "info"),
+            record(Some(LogLevel::Warn), "second"),
+            record(Some(LogLevel::Debug), "debug"),
+        ];
+        let mut content = String::new();
+        for record in &records {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        let path = std::env::temp_dir().join(format!("dora-log-test-{}.jsonl", Uuid::new_v4()));
+        tokio::fs::write(&path, &content).await?;
+
+        let filter = LogFilter {
+            level: Some(LogLevel::Warn),
+            target: None,
+        };
+        let messages = |data: Vec<u8>| -> Vec<String> {
+            data.lines()
+                .map(|line| {
+                    serde_json::from_str::<LogRecord>(&line.unwrap())
+                        .unwrap()
+                        .message
+                })
+                .collect()
+        };
+        let (data, offset) = read_log(&path, LogPosition::Offset(0), None, true, &filter).await?;
+        assert_eq!(messages(data), ["first", "second"]);
+        assert_eq!(offset, content.len() as u64);
+        let (data, _) = read_log(&path, LogPosition::Tail(1), None, true, &filter).await?;
+        assert_eq!(messages(data), ["second"]);
+        let (data, _) = read_log(&path, LogPosition::Tail(2), None, true, &filter).await?;
+        assert_eq!(messages(data), ["first", "second"]);
+
+        tokio::fs::remove_file(&path).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rotate_segments() -> eyre::Result<()> {
+        let dir = std::env::temp_dir().join(format!("dora-log-test-{}", Uuid::new_v4()));
+        tokio::fs::create_dir_all(&dir).await?;
+        let path = dir.join("log_node.txt");
+        let config = LogConfig {
+            max_segment_size: Some(4),
+            max_segments: 2,
+            compress: false,
+            ..Default::default()
+        };
+        let index = Arc::new(LogIndex::default());
+        let mut writer = LogWriter::open(path.clone(), config, index.clone()).await?;
+        let filter = LogFilter::default();
+
+        writer.write(b"a\n").await?;
+        writer.write(b"b\n").await?;
+        let (data, offset) =
+            read_log(&path, LogPosition::Offset(0), Some(&index), true, &filter).await?;
+        assert_eq!(data, b"a\nb\n");
+        for line in [b"c\n", b"d\n", b"e\n"] {
+            writer.write(line).await?;
+        }
+        // readers continue in the most recently rotated segment
+        let (data, offset) = read_log(
+            &path,
+            LogPosition::Offset(offset),
+            Some(&index),
+            true,
+            &filter,
+        )
+        .await?;
+        assert_eq!(data, b"c\nd\n");
+        assert_eq!(offset, 8);
+        let (data, offset) = read_log(
+            &path,
+            LogPosition::Offset(offset),
+            Some(&index),
+            true,
+            &filter,
+        )
+        .await?;
+        assert_eq!(data, b"e\n");
+        assert_eq!(offset, 10);
+
+        writer.write(b"f\n").await?;
+        writer.write(b"g\n").await?;
+        writer.wait_for_rotation().await;
+        let segments: Vec<_> = rotated_segments(&path)?
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(segments, [2, 3]);
+
+        tokio::fs::remove_dir_all(&dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn follow_written_output() -> eyre::Result<()> {
+        let working_dir = std::env::temp_dir().join(format!("dora-log-test-{}", Uuid::new_v4()));
+        let dataflow_id = Uuid::new_v4();
+        let node: NodeId = "node".to_owned().into();
+        let path = log_path(&working_dir, &dataflow_id, &node, LogFormat::Text);
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        let index = Arc::new(LogIndex::default());
+        let mut writer = LogWriter::open(path, LogConfig::default(), index.clone()).await?;
+        writer.write(b"a\n").await?;
+
+        let nodes = vec![(node, LogPosition::Offset(0), index)];
+        let mut follower = LogFollower::new(
+            working_dir.clone(),
+            dataflow_id,
+            LogFormat::Text,
+            nodes,
+            LogFilter::default(),
+        );
+        let chunks = follower.read(true).await?;
+        assert_eq!(chunks[0].data, b"a\n");
+        assert!(follower.read(true).await?.is_empty());
+        assert!(futures::FutureExt::now_or_never(follower.changed()).is_none());
+
+        writer.write(b"b\n").await?;
+        tokio::time::timeout(Duration::from_secs(1), follower.changed()).await?;
+        let chunks = follower.read(true).await?;
+        assert_eq!(chunks[0].data, b"b\n");
+        assert_eq!(chunks[0].offset, 4);
+
+        tokio::fs::remove_dir_all(&working_dir).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn retention_of_finished_dataflows() -> eyre::Result<()> {
+        let working_dir = std::env::temp_dir().join(format!("dora-log-test-{}", Uuid::new_v4()));
+        let now = SystemTime::now();
+        let dataflow = |finished_by: Option<&str>, age: Duration| -> eyre::Result<Uuid> {
+            let id = Uuid::new_v4();
+            let dir = working_dir.join("out").join(id.to_string());
+            std::fs::create_dir_all(&dir)?;
+            let log = std::fs::File::create(dir.join("log_node.txt"))?;
+            log.set_modified(now - age)?;
+            if let Some(machine_id) = finished_by {
+                let marker = std::fs::File::create(dir.join(finish_marker(machine_id)))?;
+                marker.set_modified(now - age)?;
+            }
+            Ok(id)
+        };
+        let exists = |id: &Uuid| working_dir.join("out").join(id.to_string()).exists();
+
+        let old = dataflow(Some("A"), Duration::from_secs(3600))?;
+        let other_machine = dataflow(Some("B"), Duration::from_secs(3600))?;
+        let unfinished = dataflow(None, Duration::from_secs(3600))?;
+        let recent = dataflow(Some("A"), Duration::from_secs(60))?;
+        // created last, but finished before `recent`
+        let older = dataflow(Some("A"), Duration::from_secs(120))?;
+
+        let policy = RetentionPolicy {
+            max_age: Some(Duration::from_secs(1800)),
+            max_count: None,
+        };
+        apply_retention(&working_dir, "A", &policy, &HashSet::new())?;
+        assert!(!exists(&old));
+        assert!(exists(&other_machine) && exists(&unfinished));
+        assert!(exists(&recent) && exists(&older));
+
+        let policy = RetentionPolicy {
+            max_age: None,
+            max_count: Some(1),
+        };
+        // running dataflows are neither removed nor counted
+        apply_retention(&working_dir, "A", &policy, &[recent].into())?;
+        assert!(exists(&recent) && exists(&older));
+        apply_retention(&working_dir, "A", &policy, &HashSet::new())?;
+        assert!(exists(&recent));
+        assert!(!exists(&older));
+        assert!(exists(&other_machine) && exists(&unfinished));
+
+        std::fs::remove_dir_all(&working_dir)?;
+        // the periodic retention also sees working dirs without output
+        apply_retention(&working_dir, "A", &policy, &HashSet::new())?;
+        Ok(())
+    }
+
+    #[test]
+    fn encode_records() -> eyre::Result<()> {
+        let record = LogRecord {
+            timestamp: dora_core::message::uhlc::HLC::default().new_timestamp(),
+            node_id: "node".to_owned().into(),
+            level: None,
+            target: "stdout".into(),
+            message: "plain\n".into(),
+            fields: Default::default(),
+        };
+        assert_eq!(LogFormat::Text.encode(&record)?, b"plain\n");
+        let line = LogFormat::Json.encode(&record)?;
+        assert_eq!(serde_json::from_slice::<LogRecord>(&line)?, record);
+        assert_eq!(line.iter().filter(|&&b| b == b'\n').count(), 1);
+
+        let event = LogRecord {
+            level: Some(LogLevel::Warn),
+            target: "node::camera".into(),
+            message: "frame dropped".into(),
+            ..record
+        };
+        let text = String::from_utf8(LogFormat::Text.encode(&event)?)?;
+        assert!(text.ends_with(" WARN node::camera: frame dropped\n"));
+
+        let filter = LogFilter {
+            level: Some(LogLevel::Info),
+            target: None,
+        };
+        assert!(LogFormat::Text.check_filter(&filter).is_err());
+        assert!(LogFormat::Text.check_filter(&LogFilter::default()).is_ok());
+        assert!(LogFormat::Json.check_filter(&filter).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn log_index_lookup() {
+        let start = SystemTime::now();
+        let index = LogIndex::default();
+        index.record(start, 0);
+        index.record(start + Duration::from_millis(500), 10);
+        index.record(start + Duration::from_secs(5), 20);
+
+        assert_eq!(index.offset_since(start - Duration::from_secs(1)), Some(0));
+        assert_eq!(
+            index.offset_since(start + Duration::from_millis(800)),
+            Some(0)
+        );
+        assert_eq!(index.offset_since(start + Duration::from_secs(2)), Some(20));
+        assert_eq!(index.offset_since(start + Duration::from_secs(7)), None);
+    }
 }
@@ -1,10 +1,11 @@
-use crate::{DaemonNodeEvent, Event};
+use crate::{stats::InputStats, DaemonNodeEvent, Event};
 use dora_core::{
-    config::{DataId, LocalCommunicationConfig, NodeId},
+    config::{DataId, LocalCommunicationConfig, NodeId, QueuePolicy},
     daemon_messages::{
         DaemonCommunication, DaemonReply, DaemonRequest, DataflowId, NodeDropEvent, NodeEvent,
         Timestamped,
     },
+    logs::LogRecord,
     message::uhlc,
 };
 use eyre::{eyre, Context};
@@ -14,27 +15,111 @@ use std::{
     collections::{BTreeMap, VecDeque},
     mem,
     net::Ipv4Addr,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     task::Poll,
 };
 use tokio::{
     net::TcpListener,
     sync::{
         mpsc::{self, UnboundedReceiver},
-        oneshot,
+        oneshot, Notify,
     },
 };
 
 // TODO unify and avoid duplication;
 pub mod shmem;
 pub mod tcp;
+#[cfg(unix)]
+pub mod unix_domain;
+
+pub const DEFAULT_QUEUE_SIZE: usize = 10;
+
+/// Size of the shared memory region for log records, which are larger than
+/// the other messages. Nodes truncate their records to fit.
+const LOGS_REGION_SIZE: usize = 64 * 1024;
+
+/// Queue configuration of a node input.
+#[derive(Debug, Clone)]
+pub struct InputQueue {
+    pub size: usize,
+    pub policy: QueuePolicy,
+    /// Tracks the queue length for `block_sender` inputs.
+    pub gauge: Option<Arc<QueueGauge>>,
+    pub stats: Arc<InputStats>,
+}
+
+/// Number of queued events of a `block_sender` input, shared between the
+/// daemon (which delays senders) and the listener of the receiving node.
+#[derive(Debug)]
+pub struct QueueGauge {
+    queued: AtomicUsize,
+    capacity: usize,
+    closed: AtomicBool,
+    notify: Notify,
+}
+
+impl QueueGauge {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queued: AtomicUsize::new(0),
+            capacity,
+            closed: AtomicBool::new(false),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn push(&self) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queued.load(Ordering::SeqCst) >= self.capacity
+    }
+
+    fn pop(&self) {
+        let _ = self
+            .queued
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        self.notify.notify_waiters();
+    }
+
+    /// Clears the queue, e.g. because the receiver is restarted.
+    pub fn reset(&self) {
+        self.queued.store(0, Ordering::SeqCst);
+        self.closed.store(false, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Wakes up all waiting senders, e.g. because the receiver exited.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Waits until the queue is no longer full.
+    pub async fn wait_for_space(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.closed.load(Ordering::SeqCst)
+                || self.queued.load(Ordering::SeqCst) < self.capacity
+            {
+                break;
+            }
+            notified.await;
+        }
+    }
+}
 
 pub async fn spawn_listener_loop(
     dataflow_id: &DataflowId,
     node_id: &NodeId,
     daemon_tx: &mpsc::Sender<Timestamped<Event>>,
     config: LocalCommunicationConfig,
-    queue_sizes: BTreeMap<DataId, usize>,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    log_tx: mpsc::WeakSender<LogRecord>,
     clock: Arc<uhlc::HLC>,
 ) -> eyre::Result<DaemonCommunication> {
     match config {
@@ -55,7 +140,7 @@ pub async fn spawn_listener_loop(
             let event_loop_node_id = format!("{dataflow_id}/{node_id}");
             let daemon_tx = daemon_tx.clone();
             tokio::spawn(async move {
-                tcp::listener_loop(socket, daemon_tx, queue_sizes, clock).await;
+                tcp::listener_loop(socket, daemon_tx, input_queues, log_tx, clock).await;
                 tracing::debug!("event listener loop finished for `{event_loop_node_id}`");
             });
 
@@ -78,18 +163,30 @@ pub async fn spawn_listener_loop(
                 .size(4096)
                 .create()
                 .wrap_err("failed to allocate daemon_drop_region")?;
+            let daemon_logs_region = ShmemConf::new()
+                .size(LOGS_REGION_SIZE)
+                .create()
+                .wrap_err("failed to allocate daemon_logs_region")?;
             let daemon_control_region_id = daemon_control_region.get_os_id().to_owned();
             let daemon_events_region_id = daemon_events_region.get_os_id().to_owned();
             let daemon_drop_region_id = daemon_drop_region.get_os_id().to_owned();
             let daemon_events_close_region_id = daemon_events_close_region.get_os_id().to_owned();
+            let daemon_logs_region_id = daemon_logs_region.get_os_id().to_owned();
 
             {
                 let server = unsafe { ShmemServer::new(daemon_control_region) }
                     .wrap_err("failed to create control server")?;
                 let daemon_tx = daemon_tx.clone();
-                let queue_sizes = queue_sizes.clone();
+                let input_queues = input_queues.clone();
+                let log_tx = log_tx.clone();
                 let clock = clock.clone();
-                tokio::spawn(shmem::listener_loop(server, daemon_tx, queue_sizes, clock));
+                tokio::spawn(shmem::listener_loop(
+                    server,
+                    daemon_tx,
+                    input_queues,
+                    log_tx,
+                    clock,
+                ));
             }
 
             {
@@ -97,10 +194,11 @@ pub async fn spawn_listener_loop(
                     .wrap_err("failed to create events server")?;
                 let event_loop_node_id = format!("{dataflow_id}/{node_id}");
                 let daemon_tx = daemon_tx.clone();
-                let queue_sizes = queue_sizes.clone();
+                let input_queues = input_queues.clone();
+                let log_tx = log_tx.clone();
                 let clock = clock.clone();
                 tokio::task::spawn(async move {
-                    shmem::listener_loop(server, daemon_tx, queue_sizes, clock).await;
+                    shmem::listener_loop(server, daemon_tx, input_queues, log_tx, clock).await;
                     tracing::debug!("event listener loop finished for `{event_loop_node_id}`");
                 });
             }
@@ -110,10 +208,11 @@ pub async fn spawn_listener_loop(
                     .wrap_err("failed to create drop server")?;
                 let drop_loop_node_id = format!("{dataflow_id}/{node_id}");
                 let daemon_tx = daemon_tx.clone();
-                let queue_sizes = queue_sizes.clone();
+                let input_queues = input_queues.clone();
+                let log_tx = log_tx.clone();
                 let clock = clock.clone();
                 tokio::task::spawn(async move {
-                    shmem::listener_loop(server, daemon_tx, queue_sizes, clock).await;
+                    shmem::listener_loop(server, daemon_tx, input_queues, log_tx, clock).await;
                     tracing::debug!("drop listener loop finished for `{drop_loop_node_id}`");
                 });
             }
@@ -123,22 +222,53 @@ pub async fn spawn_listener_loop(
                     .wrap_err("failed to create events close server")?;
                 let drop_loop_node_id = format!("{dataflow_id}/{node_id}");
                 let daemon_tx = daemon_tx.clone();
+                let input_queues = input_queues.clone();
+                let log_tx = log_tx.clone();
                 let clock = clock.clone();
                 tokio::task::spawn(async move {
-                    shmem::listener_loop(server, daemon_tx, queue_sizes, clock).await;
+                    shmem::listener_loop(server, daemon_tx, input_queues, log_tx, clock).await;
                     tracing::debug!(
                         "events close listener loop finished for `{drop_loop_node_id}`"
                     );
                 });
             }
 
+            {
+                let server = unsafe { ShmemServer::new(daemon_logs_region) }
+                    .wrap_err("failed to create logs server")?;
+                let logs_loop_node_id = format!("{dataflow_id}/{node_id}");
+                let daemon_tx = daemon_tx.clone();
+                tokio::task::spawn(async move {
+                    shmem::listener_loop(server, daemon_tx, input_queues, log_tx, clock).await;
+                    tracing::debug!("logs listener loop finished for `{logs_loop_node_id}`");
+                });
+            }
+
             Ok(DaemonCommunication::Shmem {
                 daemon_control_region_id,
                 daemon_events_region_id,
                 daemon_drop_region_id,
                 daemon_events_close_region_id,
+                daemon_logs_region_id,
             })
         }
+        #[cfg(unix)]
+        LocalCommunicationConfig::UnixDomain => {
+            let (listener, socket_file) = unix_domain::bind(dataflow_id, node_id)?;
+
+            let event_loop_node_id = format!("{dataflow_id}/{node_id}");
+            let daemon_tx = daemon_tx.clone();
+            tokio::spawn(async move {
+                unix_domain::listener_loop(listener, daemon_tx, input_queues, log_tx, clock).await;
+                tracing::debug!("event listener loop finished for `{event_loop_node_id}`");
+            });
+
+            Ok(DaemonCommunication::UnixDomain { socket_file })
+        }
+        #[cfg(not(unix))]
+        LocalCommunicationConfig::UnixDomain => {
+            eyre::bail!("unix domain sockets are only supported on Unix platforms")
+        }
     }
 }
 
@@ -149,7 +279,9 @@ struct Listener {
     subscribed_events: Option<UnboundedReceiver<Timestamped<NodeEvent>>>,
     subscribed_drop_events: Option<UnboundedReceiver<Timestamped<NodeDropEvent>>>,
     queue: VecDeque<Box<Option<Timestamped<NodeEvent>>>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    dropped: BTreeMap<DataId, u64>,
+    log_tx: mpsc::WeakSender<LogRecord>,
     clock: Arc<uhlc::HLC>,
 }
 
@@ -157,7 +289,8 @@ impl Listener {
     pub(crate) async fn run<C: Connection>(
         mut connection: C,
         daemon_tx: mpsc::Sender<Timestamped<Event>>,
-        queue_sizes: BTreeMap<DataId, usize>,
+        input_queues: BTreeMap<DataId, InputQueue>,
+        log_tx: mpsc::WeakSender<LogRecord>,
         hlc: Arc<uhlc::HLC>,
     ) {
         // receive the first message
@@ -208,8 +341,10 @@ impl Listener {
                             daemon_tx,
                             subscribed_events: None,
                             subscribed_drop_events: None,
-                            queue_sizes,
+                            input_queues,
+                            dropped: BTreeMap::new(),
                             queue: VecDeque::new(),
+                            log_tx,
                             clock: hlc.clone(),
                         };
                         match listener
@@ -285,43 +420,16 @@ impl Listener {
                 self.queue.push_back(Box::new(Some(event)));
             }
 
-            // drop oldest input events to maintain max queue length queue
-            self.drop_oldest_inputs().await?;
+            // drop input events according to the queue policies to maintain max queue length
+            self.drop_inputs().await?;
         }
         Ok(())
     }
 
     #[tracing::instrument(skip(self), fields(%self.node_id), level = "trace")]
-    async fn drop_oldest_inputs(&mut self) -> Result<(), eyre::ErrReport> {
-        let mut queue_size_remaining = self.queue_sizes.clone();
-        let mut dropped = 0;
-        let mut drop_tokens = Vec::new();
-
-        // iterate over queued events, newest first
-        for event in self.queue.iter_mut().rev() {
-            let Some(Timestamped {
-                inner: NodeEvent::Input { id, data, .. },
-                ..
-            }) = event.as_mut()
-            else {
-                continue;
-            };
-            match queue_size_remaining.get_mut(id) {
-                Some(0) => {
-                    dropped += 1;
-                    if let Some(drop_token) = data.as_ref().and_then(|d| d.drop_token()) {
-                        drop_tokens.push(drop_token);
-                    }
-                    *event.as_mut() = None;
-                }
-                Some(size_remaining) => {
-                    *size_remaining = size_remaining.saturating_sub(1);
-                }
-                None => {
-                    tracing::warn!("no queue size known for received input `{id}`");
-                }
-            }
-        }
+    async fn drop_inputs(&mut self) -> Result<(), eyre::ErrReport> {
+        let (dropped, drop_tokens) =
+            drop_excess_inputs(&mut self.queue, &self.input_queues, &mut self.dropped);
         self.report_drop_tokens(drop_tokens).await?;
 
         if dropped > 0 {
@@ -330,6 +438,20 @@ impl Listener {
         Ok(())
     }
 
+    /// Prepares the given event for sending it to the node.
+    fn deliver(&mut self, mut event: Timestamped<NodeEvent>) -> Timestamped<NodeEvent> {
+        if let NodeEvent::Input { id, metadata, .. } = &mut event.inner {
+            if let Some(queue) = self.input_queues.get(id) {
+                if let Some(gauge) = &queue.gauge {
+                    gauge.pop();
+                }
+                queue.stats.record_delivered(metadata.timestamp());
+            }
+            metadata.dropped = self.dropped.get(id).copied().unwrap_or_default();
+        }
+        event
+    }
+
     #[tracing::instrument(skip(self, connection), fields(%self.dataflow_id, %self.node_id), level = "trace")]
     async fn handle_message<C: Connection>(
         &mut self,
@@ -347,6 +469,15 @@ impl Listener {
                     .await
                     .wrap_err("failed to send register reply")?;
             }
+            DaemonRequest::Ready => {
+                let (reply_sender, reply) = oneshot::channel();
+                self.process_daemon_event(
+                    DaemonNodeEvent::Ready { reply_sender },
+                    Some(reply),
+                    connection,
+                )
+                .await?
+            }
             DaemonRequest::OutputsDone => {
                 let (reply_sender, reply) = oneshot::channel();
                 self.process_daemon_event(
@@ -372,13 +503,21 @@ impl Listener {
                 output_id,
                 metadata,
                 data,
+                wait_for_reply,
             } => {
+                let (reply_sender, reply) = if wait_for_reply {
+                    let (reply_sender, reply) = oneshot::channel();
+                    (Some(reply_sender), Some(reply))
+                } else {
+                    (None, None)
+                };
                 let event = crate::DaemonNodeEvent::SendOut {
                     output_id,
                     metadata,
                     data,
+                    reply_sender,
                 };
-                self.process_daemon_event(event, None, connection).await?;
+                self.process_daemon_event(event, reply, connection).await?;
             }
             DaemonRequest::Subscribe => {
                 let (tx, rx) = mpsc::unbounded_channel();
@@ -415,12 +554,13 @@ impl Listener {
                 let queued_events: Vec<_> = mem::take(&mut self.queue)
                     .into_iter()
                     .filter_map(|e| *e)
+                    .map(|e| self.deliver(e))
                     .collect();
                 let reply = if queued_events.is_empty() {
                     match self.subscribed_events.as_mut() {
                         // wait for next event
                         Some(events) => match events.recv().await {
-                            Some(event) => DaemonReply::NextEvents(vec![event]),
+                            Some(event) => DaemonReply::NextEvents(vec![self.deliver(event)]),
                             None => DaemonReply::NextEvents(vec![]),
                         },
                         None => {
@@ -462,6 +602,25 @@ impl Listener {
                         format!("failed to send NextFinishedDropTokens reply: {reply:?}")
                     })?;
             }
+            DaemonRequest::Logs(records) => {
+                // the log file is closed once the node's stdout and stderr are closed
+                let result = async {
+                    let log_tx = self.log_tx.upgrade().ok_or("log file is already closed")?;
+                    for mut record in records {
+                        record.node_id = self.node_id.clone();
+                        log_tx
+                            .send(record)
+                            .await
+                            .map_err(|_| "log file is already closed")?;
+                    }
+                    Ok(())
+                }
+                .await
+                .map_err(|err: &str| err.to_owned());
+                self.send_reply(DaemonReply::Result(result), connection)
+                    .await
+                    .wrap_err("failed to send Logs reply")?;
+            }
             DaemonRequest::EventStreamDropped => {
                 let (reply_sender, reply) = oneshot::channel();
                 self.process_daemon_event(
@@ -566,3 +725,212 @@ trait Connection {
     async fn receive_message(&mut self) -> eyre::Result<Option<Timestamped<DaemonRequest>>>;
     async fn send_reply(&mut self, message: DaemonReply) -> eyre::Result<()>;
 }
+
+/// Drops queued input events according to the queue policies of the inputs.
+///
+/// Returns the number of dropped events and their drop tokens.
+fn drop_excess_inputs(
+    queue: &mut VecDeque<Box<Option<Timestamped<NodeEvent>>>>,
+    input_queues: &BTreeMap<DataId, InputQueue>,
+    dropped_counts: &mut BTreeMap<DataId, u64>,
+) -> (usize, Vec<dora_core::daemon_messages::DropToken>) {
+    let mut queue_size_remaining: BTreeMap<_, _> = input_queues
+        .iter()
+        .map(|(id, queue)| {
+            let size = match queue.policy {
+                QueuePolicy::LatestOnly => 1,
+                _ => queue.size,
+            };
+            (id.clone(), size)
+        })
+        .collect();
+    let mut dropped = 0;
+    let mut drop_tokens = Vec::new();
+
+    // iterate over queued events, newest first, except for `drop_newest` inputs
+    let (drop_newest, others): (Vec<_>, Vec<_>) =
+        queue.iter_mut().partition(|event| match event.as_ref() {
+            Some(Timestamped {
+                inner: NodeEvent::Input { id, .. },
+                ..
+            }) => input_queues
+                .get(id)
+                .map(|q| q.policy == QueuePolicy::DropNewest)
+                .unwrap_or(false),
+            _ => false,
+        });
+    for event in drop_newest.into_iter().chain(others.into_iter().rev()) {
+        let Some(Timestamped {
+            inner: NodeEvent::Input { id, data, .. },
+            ..
+        }) = event.as_mut()
+        else {
+            continue;
+        };
+        match queue_size_remaining.get_mut(id) {
+            Some(0) => {
+                dropped += 1;
+                *dropped_counts.entry(id.clone()).or_default() += 1;
+                if let Some(queue) = input_queues.get(id) {
+                    if let Some(gauge) = &queue.gauge {
+                        gauge.pop();
+                    }
+                    queue.stats.record_dropped();
+                }
+                if let Some(drop_token) = data.as_ref().and_then(|d| d.drop_token()) {
+                    drop_tokens.push(drop_token);
+                }
+                *event.as_mut() = None;
+            }
+            Some(size_remaining) => {
+                *size_remaining = size_remaining.saturating_sub(1);
+            }
+            None => {
+                tracing::warn!("no queue size known for received input `{id}`");
+            }
+        }
+    }
+    (dropped, drop_tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dora_core::message::{uhlc::HLC, ArrowTypeInfo, Metadata};
+    use std::time::Duration;
+
+    fn input_queue(size: usize, policy: QueuePolicy) -> InputQueue {
+        let gauge = (policy == QueuePolicy::BlockSender).then(|| Arc::new(QueueGauge::new(size)));
+        InputQueue {
+            size,
+            policy,
+            gauge,
+            stats: Default::default(),
+        }
+    }
+
+    fn input(clock: &HLC, id: &str) -> Box<Option<Timestamped<NodeEvent>>> {
+        Box::new(Some(Timestamped {
+            inner: NodeEvent::Input {
+                id: id.to_owned().into(),
+                metadata: Metadata::new(clock.new_timestamp(), ArrowTypeInfo::empty()),
+                data: None,
+            },
+            timestamp: clock.new_timestamp(),
+        }))
+    }
+
+    /// Returns the positions of the events that are still queued.
+    fn remaining(queue: &VecDeque<Box<Option<Timestamped<NodeEvent>>>>) -> Vec<usize> {
+        queue
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| event.is_some())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn drop_inputs_by_queue_policy() {
+        let clock = HLC::default();
+        let input_queues: BTreeMap<DataId, _> = [
+            ("oldest", input_queue(2, QueuePolicy::DropOldest)),
+            ("newest", input_queue(2, QueuePolicy::DropNewest)),
+            ("latest", input_queue(5, QueuePolicy::LatestOnly)),
+        ]
+        .into_iter()
+        .map(|(id, queue)| (id.to_owned().into(), queue))
+        .collect();
+        let mut queue: VecDeque<_> = (0..3)
+            .flat_map(|_| ["oldest", "newest", "latest"])
+            .map(|id| input(&clock, id))
+            .collect();
+        let mut dropped_counts = BTreeMap::new();
+
+        let (dropped, drop_tokens) =
+            drop_excess_inputs(&mut queue, &input_queues, &mut dropped_counts);
+
+        assert_eq!(dropped, 4);
+        assert!(drop_tokens.is_empty());
+        // `drop_oldest` keeps the newest two, `drop_newest` keeps the oldest
+        // two and `latest_only` keeps only the newest input
+        assert_eq!(remaining(&queue), vec![1, 3, 4, 6, 8]);
+        let counts: Vec<_> = dropped_counts
+            .iter()
+            .map(|(id, count)| (id.to_string(), *count))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("latest".to_owned(), 2),
+                ("newest".to_owned(), 1),
+                ("oldest".to_owned(), 1)
+            ]
+        );
+        let latest = &input_queues[&DataId::from("latest".to_owned())];
+        let stats = latest.stats.snapshot(
+            "node".to_owned().into(),
+            "latest".to_owned().into(),
+            String::new(),
+        );
+        assert_eq!(stats.dropped, 2);
+    }
+
+    #[test]
+    fn dropped_block_sender_inputs_free_queue_space() {
+        let clock = HLC::default();
+        let queue_config = input_queue(1, QueuePolicy::BlockSender);
+        let gauge = queue_config.gauge.clone().unwrap();
+        let input_queues = [("blocking".to_owned().into(), queue_config)].into();
+        let mut queue: VecDeque<_> = (0..3).map(|_| input(&clock, "blocking")).collect();
+        for _ in 0..3 {
+            gauge.push();
+        }
+
+        let (dropped, _) = drop_excess_inputs(&mut queue, &input_queues, &mut BTreeMap::new());
+
+        assert_eq!(dropped, 2);
+        assert_eq!(remaining(&queue), vec![2]);
+        assert_eq!(gauge.queued.load(Ordering::SeqCst), 1);
+        assert!(gauge.is_full());
+    }
+
+    #[tokio::test]
+    async fn block_sender_waits_until_queue_has_space() {
+        let gauge = Arc::new(QueueGauge::new(1));
+        gauge.push();
+        assert!(gauge.is_full());
+
+        let waiting = tokio::spawn({
+            let gauge = gauge.clone();
+            async move { gauge.wait_for_space().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        gauge.pop();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("sender should be woken up once the receiver pops an input")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn closing_block_sender_queue_wakes_senders() {
+        let gauge = Arc::new(QueueGauge::new(1));
+        gauge.push();
+
+        let waiting = tokio::spawn({
+            let gauge = gauge.clone();
+            async move { gauge.wait_for_space().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        gauge.close();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("sender should be woken up once the receiver exits")
+            .unwrap();
+    }
+}
@@ -1,21 +1,23 @@
 use std::{collections::BTreeMap, sync::Arc};
 
-use super::{Connection, Listener};
+use super::{Connection, InputQueue, Listener};
 use crate::Event;
 use dora_core::{
     config::DataId,
     daemon_messages::{DaemonReply, DaemonRequest, Timestamped},
+    logs::LogRecord,
     message::uhlc::HLC,
 };
 use eyre::eyre;
 use shared_memory_server::ShmemServer;
 use tokio::sync::{mpsc, oneshot};
 
-#[tracing::instrument(skip(server, daemon_tx, clock), level = "trace")]
+#[tracing::instrument(skip(server, daemon_tx, log_tx, clock), level = "trace")]
 pub async fn listener_loop(
     mut server: ShmemServer<Timestamped<DaemonRequest>, DaemonReply>,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    log_tx: mpsc::WeakSender<LogRecord>,
     clock: Arc<HLC>,
 ) {
     let (tx, rx) = flume::bounded(0);
@@ -40,7 +42,7 @@ pub async fn listener_loop(
         }
     });
     let connection = ShmemConnection(tx);
-    Listener::run(connection, daemon_tx, queue_sizes, clock).await
+    Listener::run(connection, daemon_tx, input_queues, log_tx, clock).await
 }
 
 enum Operation {
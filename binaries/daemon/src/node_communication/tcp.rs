@@ -1,6 +1,6 @@
 use std::{collections::BTreeMap, io::ErrorKind, sync::Arc};
 
-use super::{Connection, Listener};
+use super::{Connection, InputQueue, Listener};
 use crate::{
     tcp_utils::{tcp_receive, tcp_send},
     Event,
@@ -8,19 +8,22 @@ use crate::{
 use dora_core::{
     config::DataId,
     daemon_messages::{DaemonReply, DaemonRequest, Timestamped},
+    logs::LogRecord,
     message::uhlc::HLC,
 };
 use eyre::Context;
 use tokio::{
+    io::{AsyncRead, AsyncWrite},
     net::{TcpListener, TcpStream},
     sync::mpsc,
 };
 
-#[tracing::instrument(skip(listener, daemon_tx, clock), level = "trace")]
+#[tracing::instrument(skip(listener, daemon_tx, log_tx, clock), level = "trace")]
 pub async fn listener_loop(
     listener: TcpListener,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    log_tx: mpsc::WeakSender<LogRecord>,
     clock: Arc<HLC>,
 ) {
     loop {
@@ -36,7 +39,8 @@ pub async fn listener_loop(
                 tokio::spawn(handle_connection_loop(
                     connection,
                     daemon_tx.clone(),
-                    queue_sizes.clone(),
+                    input_queues.clone(),
+                    log_tx.clone(),
                     clock.clone(),
                 ));
             }
@@ -44,24 +48,37 @@ pub async fn listener_loop(
     }
 }
 
-#[tracing::instrument(skip(connection, daemon_tx, clock), level = "trace")]
+#[tracing::instrument(skip(connection, daemon_tx, log_tx, clock), level = "trace")]
 async fn handle_connection_loop(
     connection: TcpStream,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
-    queue_sizes: BTreeMap<DataId, usize>,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    log_tx: mpsc::WeakSender<LogRecord>,
     clock: Arc<HLC>,
 ) {
     if let Err(err) = connection.set_nodelay(true) {
         tracing::warn!("failed to set nodelay for connection: {err}");
     }
 
-    Listener::run(TcpConnection(connection), daemon_tx, queue_sizes, clock).await
+    Listener::run(
+        StreamConnection(connection),
+        daemon_tx,
+        input_queues,
+        log_tx,
+        clock,
+    )
+    .await
 }
 
-struct TcpConnection(TcpStream);
+/// Connection over a byte stream, e.g. a TCP or Unix domain socket, using
+/// length-prefixed bincode messages.
+pub(super) struct StreamConnection<S>(pub S);
 
 #[async_trait::async_trait]
-impl Connection for TcpConnection {
+impl<S> Connection for StreamConnection<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
     async fn receive_message(&mut self) -> eyre::Result<Option<Timestamped<DaemonRequest>>> {
         let raw = match tcp_receive(&mut self.0).await {
             Ok(raw) => raw,
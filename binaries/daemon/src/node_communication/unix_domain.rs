@@ -0,0 +1,167 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use super::{tcp::StreamConnection, InputQueue, Listener};
+use crate::Event;
+use dora_core::{
+    config::{DataId, NodeId},
+    daemon_messages::{DataflowId, Timestamped},
+    logs::LogRecord,
+    message::uhlc::HLC,
+};
+use eyre::Context;
+use tokio::{net::UnixListener, sync::mpsc};
+
+/// Maximum length of a socket path, excluding the terminating null byte
+/// (`sun_path` of `sockaddr_un`).
+#[cfg(target_os = "linux")]
+const MAX_SOCKET_PATH_LEN: usize = 107;
+#[cfg(not(target_os = "linux"))]
+const MAX_SOCKET_PATH_LEN: usize = 103;
+
+/// Directory that contains the sockets of all nodes of the given dataflow.
+///
+/// Every dataflow gets its own directory, so that daemons of different users
+/// don't share a parent directory with restricted permissions.
+pub fn socket_dir(dataflow_id: &DataflowId) -> PathBuf {
+    std::env::temp_dir().join(format!("dora-{dataflow_id}"))
+}
+
+/// Creates a socket that is only accessible by the current user.
+pub fn bind(dataflow_id: &DataflowId, node_id: &NodeId) -> eyre::Result<(UnixListener, PathBuf)> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let dir = socket_dir(dataflow_id);
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .wrap_err_with(|| format!("failed to create socket directory `{}`", dir.display()))?;
+    // the directory might exist already, e.g. when a node is restarted
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))
+        .wrap_err_with(|| format!("failed to restrict access to `{}`", dir.display()))?;
+    let socket_file = socket_file(&dir, node_id)?;
+    // remove stale socket of a previous run of the node (e.g. on restart)
+    if socket_file.exists() {
+        std::fs::remove_file(&socket_file)
+            .wrap_err_with(|| format!("failed to remove old socket `{}`", socket_file.display()))?;
+    }
+    let listener = UnixListener::bind(&socket_file)
+        .wrap_err_with(|| format!("failed to bind socket `{}`", socket_file.display()))?;
+    std::fs::set_permissions(&socket_file, std::fs::Permissions::from_mode(0o600))
+        .wrap_err("failed to set socket permissions")?;
+    Ok((listener, socket_file))
+}
+
+#[tracing::instrument(skip(listener, daemon_tx, log_tx, clock), level = "trace")]
+pub async fn listener_loop(
+    listener: UnixListener,
+    daemon_tx: mpsc::Sender<Timestamped<Event>>,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    log_tx: mpsc::WeakSender<LogRecord>,
+    clock: Arc<HLC>,
+) {
+    loop {
+        match listener
+            .accept()
+            .await
+            .wrap_err("failed to accept new connection")
+        {
+            Err(err) => {
+                tracing::info!("{err}");
+            }
+            Ok((connection, _)) => {
+                tokio::spawn(Listener::run(
+                    StreamConnection(connection),
+                    daemon_tx.clone(),
+                    input_queues.clone(),
+                    log_tx.clone(),
+                    clock.clone(),
+                ));
+            }
+        }
+    }
+}
+
+/// Path of the socket of the given node.
+///
+/// Falls back to a hash of the node ID if the path would exceed the
+/// maximum socket path length otherwise.
+fn socket_file(dir: &Path, node_id: &NodeId) -> eyre::Result<PathBuf> {
+    let socket_file = dir.join(format!("{node_id}.sock"));
+    if socket_file.as_os_str().len() <= MAX_SOCKET_PATH_LEN {
+        return Ok(socket_file);
+    }
+    let mut hasher = DefaultHasher::new();
+    node_id.hash(&mut hasher);
+    let socket_file = dir.join(format!("{:016x}.sock", hasher.finish()));
+    if socket_file.as_os_str().len() > MAX_SOCKET_PATH_LEN {
+        eyre::bail!(
+            "socket path `{}` exceeds the maximum length of {MAX_SOCKET_PATH_LEN} bytes, \
+            set `TMPDIR` to a shorter directory",
+            socket_file.display()
+        );
+    }
+    Ok(socket_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        node_communication::Connection,
+        tcp_utils::{tcp_receive, tcp_send},
+    };
+    use dora_core::daemon_messages::{DaemonReply, DaemonRequest};
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::UnixStream;
+
+    #[test]
+    fn shorten_long_socket_paths() {
+        let dir = Path::new("/tmp/dora-01a13ff5-9855-7462-bd44-36237dc730c9");
+        let short = socket_file(dir, &NodeId::from("camera".to_owned())).unwrap();
+        assert_eq!(short, dir.join("camera.sock"));
+
+        let long = socket_file(dir, &NodeId::from("a".repeat(100))).unwrap();
+        assert!(long.as_os_str().len() <= MAX_SOCKET_PATH_LEN);
+        assert_eq!(long.parent(), Some(dir));
+
+        let long_dir = Path::new("/tmp").join("d".repeat(100));
+        assert!(socket_file(&long_dir, &NodeId::from("camera".to_owned())).is_err());
+    }
+
+    #[tokio::test]
+    async fn bind_private_socket() -> eyre::Result<()> {
+        let dataflow_id = DataflowId::new_v4();
+        let (listener, socket_file) = bind(&dataflow_id, &NodeId::from("node".to_owned()))?;
+        let mode = |path: &Path| std::fs::metadata(path).map(|m| m.permissions().mode() & 0o777);
+        assert_eq!(mode(&socket_dir(&dataflow_id))?, 0o700);
+        assert_eq!(mode(&socket_file)?, 0o600);
+
+        let mut client = UnixStream::connect(&socket_file).await?;
+        let (server, _) = listener.accept().await?;
+        let mut server = StreamConnection(server);
+        let request = Timestamped {
+            inner: DaemonRequest::OutputsDone,
+            timestamp: HLC::default().new_timestamp(),
+        };
+        tcp_send(&mut client, &bincode::serialize(&request)?).await?;
+        let received = server.receive_message().await?;
+        assert!(matches!(
+            received.map(|r| r.inner),
+            Some(DaemonRequest::OutputsDone)
+        ));
+        server.send_reply(DaemonReply::Result(Ok(()))).await?;
+        let reply: DaemonReply = bincode::deserialize(&tcp_receive(&mut client).await?)?;
+        assert!(matches!(reply, DaemonReply::Result(Ok(()))));
+
+        drop(client);
+        assert!(server.receive_message().await?.is_none());
+        std::fs::remove_dir_all(socket_dir(&dataflow_id))?;
+        Ok(())
+    }
+}
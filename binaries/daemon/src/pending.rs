@@ -6,10 +6,10 @@ use dora_core::{
     daemon_messages::{DaemonReply, DataflowId, Timestamped},
     message::uhlc::{Timestamp, HLC},
 };
-use eyre::{bail, Context};
-use tokio::{net::TcpStream, sync::oneshot};
+use eyre::bail;
+use tokio::sync::oneshot;
 
-use crate::tcp_utils::tcp_send;
+use crate::coordinator::CoordinatorConnection;
 
 pub struct PendingNodes {
     dataflow_id: DataflowId,
@@ -30,6 +30,13 @@ pub struct PendingNodes {
     /// we report an error to the other nodes.
     exited_before_subscribe: HashSet<NodeId>,
 
+    /// Whether the connections to the other machines are still being set up.
+    ///
+    /// The init result is not reported to the coordinator before.
+    remote_links_pending: bool,
+    /// Set if the connections to the other machines could not be set up.
+    remote_link_error: Option<String>,
+
     /// Whether the local init result was already reported to the coordinator.
     reported_init_to_coordinator: bool,
 }
@@ -43,6 +50,8 @@ impl PendingNodes {
             external_nodes: false,
             waiting_subscribers: HashMap::new(),
             exited_before_subscribe: HashSet::new(),
+            remote_links_pending: false,
+            remote_link_error: None,
             reported_init_to_coordinator: false,
         }
     }
@@ -51,19 +60,48 @@ impl PendingNodes {
         self.local_nodes.insert(node_id);
     }
 
+    /// Whether the given node has not been started yet.
+    pub fn is_pending(&self, node_id: &NodeId) -> bool {
+        self.local_nodes.contains(node_id) || self.waiting_subscribers.contains_key(node_id)
+    }
+
     pub fn set_external_nodes(&mut self, value: bool) {
         self.external_nodes = value;
     }
 
+    /// Delays the init report until [`Self::handle_remote_links_ready`] is
+    /// called.
+    pub fn set_remote_links_pending(&mut self) {
+        self.remote_links_pending = true;
+    }
+
+    pub async fn handle_remote_links_ready(
+        &mut self,
+        result: eyre::Result<()>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
+        clock: &HLC,
+    ) -> eyre::Result<DataflowStatus> {
+        self.remote_links_pending = false;
+        if let Err(err) = result {
+            self.remote_link_error = Some(format!("{err:?}"));
+        }
+        self.update_dataflow_status(coordinator_connection, clock)
+            .await
+    }
+
+    /// The `reply_sender` is `None` if the subscribe request was already
+    /// answered, e.g. for nodes with a `ready_signal`.
     pub async fn handle_node_subscription(
         &mut self,
         node_id: NodeId,
-        reply_sender: oneshot::Sender<DaemonReply>,
-        coordinator_connection: &mut Option<TcpStream>,
+        reply_sender: Option<oneshot::Sender<DaemonReply>>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
     ) -> eyre::Result<DataflowStatus> {
-        self.waiting_subscribers
-            .insert(node_id.clone(), reply_sender);
+        if let Some(reply_sender) = reply_sender {
+            self.waiting_subscribers
+                .insert(node_id.clone(), reply_sender);
+        }
         self.local_nodes.remove(&node_id);
 
         self.update_dataflow_status(coordinator_connection, clock)
@@ -73,7 +111,7 @@ impl PendingNodes {
     pub async fn handle_node_stop(
         &mut self,
         node_id: &NodeId,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
     ) -> eyre::Result<()> {
         if self.local_nodes.remove(node_id) {
@@ -101,10 +139,10 @@ impl PendingNodes {
 
     async fn update_dataflow_status(
         &mut self,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         clock: &HLC,
     ) -> eyre::Result<DataflowStatus> {
-        if self.local_nodes.is_empty() {
+        if self.local_nodes.is_empty() && !self.remote_links_pending {
             if self.external_nodes {
                 if !self.reported_init_to_coordinator {
                     self.report_nodes_ready(coordinator_connection, clock.new_timestamp())
@@ -154,29 +192,31 @@ impl PendingNodes {
 
     async fn report_nodes_ready(
         &self,
-        coordinator_connection: &mut Option<TcpStream>,
+        coordinator_connection: &mut Option<CoordinatorConnection>,
         timestamp: Timestamp,
     ) -> eyre::Result<()> {
         let Some(connection) = coordinator_connection else {
             bail!("no coordinator connection to send AllNodesReady");
         };
 
-        let success = self.exited_before_subscribe.is_empty();
+        if let Some(err) = &self.remote_link_error {
+            tracing::error!("failed to connect to remote machines: {err}");
+        }
+        let success = self.exited_before_subscribe.is_empty() && self.remote_link_error.is_none();
         tracing::info!("all local nodes are ready (success = {success}), waiting for remote nodes");
 
-        let msg = serde_json::to_vec(&Timestamped {
-            inner: CoordinatorRequest::Event {
-                machine_id: self.machine_id.clone(),
-                event: DaemonEvent::AllNodesReady {
-                    dataflow_id: self.dataflow_id,
-                    success,
+        connection
+            .send(Timestamped {
+                inner: CoordinatorRequest::Event {
+                    machine_id: self.machine_id.clone(),
+                    event: DaemonEvent::AllNodesReady {
+                        dataflow_id: self.dataflow_id,
+                        success,
+                    },
                 },
-            },
-            timestamp,
-        })?;
-        tcp_send(connection, &msg)
-            .await
-            .wrap_err("failed to send AllNodesReady message to dora-coordinator")?;
+                timestamp,
+            })
+            .await?;
         Ok(())
     }
 }
@@ -1,17 +1,20 @@
 use crate::{
-    log, node_communication::spawn_listener_loop, node_inputs, runtime_node_inputs,
-    runtime_node_outputs, DoraEvent, Event, NodeExitStatus, OutputId,
+    log::{self, LogConfig, LogIndex, LogWriter},
+    node_communication::{spawn_listener_loop, InputQueue},
+    runtime_node_inputs, runtime_node_output_types, runtime_node_outputs, DoraEvent, Event,
+    NodeExitStatus, OutputId,
 };
 use aligned_vec::{AVec, ConstAlign};
 use dora_arrow_convert::IntoArrow;
 use dora_core::{
-    config::{DataId, NodeRunConfig},
+    config::{DataId, NodeId, NodeRunConfig},
     daemon_messages::{DataMessage, DataflowId, NodeConfig, RuntimeConfig, Timestamped},
     descriptor::{
         resolve_path, source_is_url, Descriptor, OperatorDefinition, OperatorSource, PythonSource,
         ResolvedNode, SHELL_SOURCE,
     },
     get_python_path,
+    logs::LogRecord,
     message::uhlc::HLC,
 };
 use dora_download::download_file;
@@ -22,40 +25,45 @@ use dora_node_api::{
 };
 use eyre::{ContextCompat, WrapErr};
 use std::{
+    collections::{BTreeMap, BTreeSet},
     env::consts::EXE_EXTENSION,
     path::{Path, PathBuf},
     process::Stdio,
     sync::Arc,
 };
 use tokio::{
-    fs::File,
-    io::{AsyncBufReadExt, AsyncWriteExt},
+    io::AsyncBufReadExt,
     sync::{mpsc, oneshot},
+    time::Instant,
 };
 use tracing::{debug, error};
 
 /// clock is required for generating timestamps when dropping messages early because queue is full
+#[allow(clippy::too_many_arguments)]
 pub async fn spawn_node(
     dataflow_id: DataflowId,
     working_dir: &Path,
     node: ResolvedNode,
     daemon_tx: mpsc::Sender<Timestamped<Event>>,
     dataflow_descriptor: Descriptor,
+    input_queues: BTreeMap<DataId, InputQueue>,
+    blocking_outputs: BTreeSet<DataId>,
+    log_index: Arc<LogIndex>,
+    log_config: LogConfig,
     clock: Arc<HLC>,
 ) -> eyre::Result<u32> {
     let node_id = node.id.clone();
     tracing::debug!("Spawning node `{dataflow_id}/{node_id}`");
 
-    let queue_sizes = node_inputs(&node)
-        .into_iter()
-        .map(|(k, v)| (k, v.queue_size.unwrap_or(10)))
-        .collect();
+    // log records of the node, from stdout, stderr, and the log channel
+    let (tx, mut rx) = mpsc::channel(10);
     let daemon_communication = spawn_listener_loop(
         &dataflow_id,
         &node_id,
         &daemon_tx,
         dataflow_descriptor.communication.local,
-        queue_sizes,
+        input_queues,
+        tx.downgrade(),
         clock.clone(),
     )
     .await?;
@@ -123,6 +131,7 @@ pub async fn spawn_node(
                 run_config: n.run_config.clone(),
                 daemon_communication,
                 dataflow_descriptor,
+                blocking_outputs,
             };
 
             command.env(
@@ -229,9 +238,11 @@ pub async fn spawn_node(
                     run_config: NodeRunConfig {
                         inputs: runtime_node_inputs(&n),
                         outputs: runtime_node_outputs(&n),
+                        output_types: runtime_node_output_types(&n),
                     },
                     daemon_communication,
                     dataflow_descriptor,
+                    blocking_outputs,
                 },
                 operators: n.operators,
             };
@@ -264,14 +275,17 @@ pub async fn spawn_node(
     if !dataflow_dir.exists() {
         std::fs::create_dir_all(&dataflow_dir).context("could not create dataflow_dir")?;
     }
-    let (tx, mut rx) = mpsc::channel(10);
-    let mut file = File::create(log::log_path(working_dir, &dataflow_id, &node_id))
-        .await
-        .expect("Failed to create log file");
+    let mut log_writer = LogWriter::open(
+        log::log_path(working_dir, &dataflow_id, &node_id, log_config.format),
+        log_config,
+        log_index,
+    )
+    .await?;
     let mut child_stdout =
         tokio::io::BufReader::new(child.stdout.take().expect("failed to take stdout"));
     let pid = child.id().unwrap();
     let stdout_tx = tx.clone();
+    let stdout_clock = clock.clone();
 
     // Stdout listener stream
     tokio::spawn(async move {
@@ -319,9 +333,12 @@ pub async fn spawn_node(
 
             // send the buffered lines
             let lines = std::mem::take(&mut buffer);
-            let sent = stdout_tx.send(lines.clone()).await;
-            if sent.is_err() {
-                println!("Could not log: {lines}");
+            if lines.is_empty() {
+                continue;
+            }
+            let record = output_record(&stdout_clock, &node_id, "stdout", lines);
+            if let Err(err) = stdout_tx.send(record).await {
+                println!("Could not log: {}", err.0.message);
             }
         }
     });
@@ -332,6 +349,7 @@ pub async fn spawn_node(
     // Stderr listener stream
     let stderr_tx = tx.clone();
     let node_id = node.id.clone();
+    let stderr_clock = clock.clone();
     let uhlc = clock.clone();
     let daemon_tx_log = daemon_tx.clone();
     tokio::spawn(async move {
@@ -374,9 +392,12 @@ pub async fn spawn_node(
 
             // send the buffered lines
             let lines = std::mem::take(&mut buffer);
-            let sent = stderr_tx.send(lines.clone()).await;
-            if sent.is_err() {
-                println!("Could not log: {lines}");
+            if lines.is_empty() {
+                continue;
+            }
+            let record = output_record(&stderr_clock, &node_id, "stderr", lines);
+            if let Err(err) = stderr_tx.send(record).await {
+                println!("Could not log: {}", err.0.message);
             }
         }
     });
@@ -402,11 +423,28 @@ pub async fn spawn_node(
     let node_id = node.id.clone();
     // Log to file stream.
     tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
+        // sync to disk in batches instead of after every record
+        let mut sync_deadline = None;
+        loop {
+            let record: LogRecord = tokio::select! {
+                record = rx.recv() => match record {
+                    Some(record) => record,
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(sync_deadline.unwrap_or_else(Instant::now)),
+                    if sync_deadline.is_some() =>
+                {
+                    sync_deadline = None;
+                    if let Err(err) = log_writer.sync().await {
+                        error!("Could not sync logs to file due to {err:?}");
+                    }
+                    continue;
+                }
+            };
             // If log is an output, we're sending the logs to the dataflow
             if let Some(stdout_output_name) = &send_stdout_to {
                 // Convert logs to DataMessage
-                let array = message.into_arrow();
+                let array = record.message.clone().into_arrow();
 
                 let array: ArrayData = array.into();
                 let total_len = required_data_size(&array);
@@ -433,22 +471,31 @@ pub async fn spawn_node(
                 let _ = daemon_tx_log.send(event).await;
             }
 
-            let _ = file
-                .write_all(message.as_bytes())
-                .await
-                .map_err(|err| error!("Could not log {message} to file due to {err}"));
-            let formatted = message.lines().fold(String::default(), |mut output, line| {
-                output.push_str("      ");
-                output.push_str(line);
-                output.push('\n');
-                output
-            });
+            let line = match log_config.format.encode(&record) {
+                Ok(line) => line,
+                Err(err) => {
+                    error!("Could not encode log record {record:?}: {err:?}");
+                    continue;
+                }
+            };
+            if let Err(err) = log_writer.write(&line).await {
+                error!("Could not log {} to file due to {err:?}", record.message);
+            }
+            sync_deadline.get_or_insert_with(|| Instant::now() + log_config.sync_interval);
+            let formatted = record
+                .message
+                .lines()
+                .fold(String::default(), |mut output, line| {
+                    output.push_str("      ");
+                    output.push_str(line);
+                    output.push('\n');
+                    output
+                });
             debug!("{dataflow_id}/{} logged:\n{formatted}", node.id.clone());
-            // Make sure that all data has been synced to disk.
-            let _ = file
-                .sync_all()
-                .await
-                .map_err(|err| error!("Could not sync logs to file due to {err}"));
+        }
+        // Make sure that all data has been synced to disk.
+        if let Err(err) = log_writer.sync().await {
+            error!("Could not sync logs to file due to {err:?}");
         }
         let _ = log_finish_tx
             .send(())
@@ -456,3 +503,15 @@ pub async fn spawn_node(
     });
     Ok(pid)
 }
+
+/// Wraps output that the node printed to stdout or stderr into a log record.
+fn output_record(clock: &HLC, node_id: &NodeId, target: &str, message: String) -> LogRecord {
+    LogRecord {
+        timestamp: clock.new_timestamp(),
+        node_id: node_id.clone(),
+        level: None,
+        target: target.to_owned(),
+        message,
+        fields: BTreeMap::new(),
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use dora_core::{
+    config::{DataId, NodeId},
+    daemon_messages::DataMessage,
+    message::uhlc,
+    topics,
+};
+
+/// Message counters of a local node input.
+///
+/// Shared between the daemon, which counts the forwarded messages, and the
+/// listener of the receiving node, which counts dropped and delivered
+/// messages.
+#[derive(Debug, Default)]
+pub struct InputStats {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+    total_latency_us: AtomicU64,
+}
+
+impl InputStats {
+    pub fn record_message(&self, data: Option<&DataMessage>) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(data_len(data), Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records the delivery of a message that was sent at the given time.
+    pub fn record_delivered(&self, sent: uhlc::Timestamp) {
+        let sent = sent.get_time().to_system_time();
+        let latency = SystemTime::now()
+            .duration_since(sent)
+            .unwrap_or(Duration::ZERO);
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.total_latency_us.fetch_add(
+            latency.as_micros().try_into().unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn snapshot(&self, node: NodeId, input: DataId, machine: String) -> topics::InputStats {
+        topics::InputStats {
+            node,
+            input,
+            machine,
+            messages: self.messages.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            delivered: self.delivered.load(Ordering::Relaxed),
+            total_latency: Duration::from_micros(self.total_latency_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Message counters of a local node output.
+#[derive(Debug, Default)]
+pub struct OutputStats {
+    messages: u64,
+    bytes: u64,
+}
+
+impl OutputStats {
+    pub fn record_message(&mut self, data: Option<&DataMessage>) {
+        self.messages += 1;
+        self.bytes += data_len(data);
+    }
+
+    pub fn snapshot(&self, node: NodeId, output: DataId, machine: String) -> topics::OutputStats {
+        topics::OutputStats {
+            node,
+            output,
+            machine,
+            messages: self.messages,
+            bytes: self.bytes,
+        }
+    }
+}
+
+fn data_len(data: Option<&DataMessage>) -> u64 {
+    let len = match data {
+        Some(DataMessage::Vec(v)) => v.len(),
+        Some(DataMessage::SharedMemory { len, .. }) => *len,
+        None => 0,
+    };
+    len as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aligned_vec::AVec;
+
+    #[test]
+    fn count_input_messages() {
+        let stats = InputStats::default();
+        let data = DataMessage::Vec(AVec::from_slice(128, &[0; 100]));
+        stats.record_message(Some(&data));
+        stats.record_message(None);
+        stats.record_dropped();
+        let clock = uhlc::HLC::default();
+        stats.record_delivered(clock.new_timestamp());
+
+        let snapshot = stats.snapshot(
+            "node".to_owned().into(),
+            "input".to_owned().into(),
+            "A".into(),
+        );
+        assert_eq!(snapshot.messages, 2);
+        assert_eq!(snapshot.bytes, 100);
+        assert_eq!(snapshot.dropped, 1);
+        assert_eq!(snapshot.delivered, 1);
+        assert!(snapshot.total_latency < Duration::from_secs(1));
+    }
+}
@@ -6,6 +6,7 @@ fn main() -> eyre::Result<()> {
     let output = DataId::from("random".to_owned());
 
     let (mut node, mut events) = DoraNode::init_from_env()?;
+    node.mark_ready()?;
 
     for i in 0..100 {
         let event = match events.recv() {
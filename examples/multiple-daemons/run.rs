@@ -1,3 +1,4 @@
+use communication_layer_request_reply::security::Security;
 use dora_coordinator::{ControlEvent, Event};
 use dora_core::{
     descriptor::Descriptor,
@@ -38,9 +39,15 @@ async fn main() -> eyre::Result<()> {
         IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
         DORA_COORDINATOR_PORT_DEFAULT,
     );
-    let (coordinator_port, coordinator) =
-        dora_coordinator::start(coordinator_bind, ReceiverStream::new(coordinator_events_rx))
-            .await?;
+    let (coordinator_port, coordinator) = dora_coordinator::start(
+        coordinator_bind,
+        ReceiverStream::new(coordinator_events_rx),
+        Security::default(),
+        None,
+        Default::default(),
+        None,
+    )
+    .await?;
     let coordinator_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), coordinator_port);
     let daemon_a = run_daemon(coordinator_addr.to_string(), "A");
     let daemon_b = run_daemon(coordinator_addr.to_string(), "B");
@@ -75,18 +82,19 @@ async fn main() -> eyre::Result<()> {
     }
 
     tracing::info!("waiting for dataflow `{uuid}` to finish");
-    let mut retries = 0;
-    loop {
-        let running = running_dataflows(&coordinator_events_tx).await?;
-        if running.is_empty() {
-            break;
-        } else if retries > 100 {
-            bail!("dataflow not finished after {retries} retries");
-        } else {
-            tracing::debug!("not done yet");
-            std::thread::sleep(Duration::from_millis(500));
-            retries += 1
-        }
+    wait_until_finished(&coordinator_events_tx, 100).await?;
+    tracing::info!("dataflow `{uuid}` finished");
+
+    // the failure of a node must reach dependent nodes on other machines
+    let failed_dependency = Path::new("failed-dependency.yml");
+    let uuid = start_dataflow(failed_dependency, &coordinator_events_tx).await?;
+    tracing::info!("started dataflow with failing dependency under ID `{uuid}`");
+    wait_until_finished(&coordinator_events_tx, 20).await?;
+    let dependent_log = Path::new("out")
+        .join(uuid.to_string())
+        .join("log_dependent.txt");
+    if dependent_log.exists() {
+        bail!("node `dependent` was started although its dependency failed");
     }
     tracing::info!("dataflow `{uuid}` finished, destroying coordinator");
     destroy(&coordinator_events_tx).await?;
@@ -100,6 +108,25 @@ async fn main() -> eyre::Result<()> {
     Ok(())
 }
 
+async fn wait_until_finished(
+    coordinator_events_tx: &Sender<Event>,
+    max_retries: u32,
+) -> eyre::Result<()> {
+    let mut retries = 0;
+    loop {
+        let running = running_dataflows(coordinator_events_tx).await?;
+        if running.is_empty() {
+            return Ok(());
+        } else if retries > max_retries {
+            bail!("dataflow not finished after {retries} retries");
+        } else {
+            tracing::debug!("not done yet");
+            std::thread::sleep(Duration::from_millis(500));
+            retries += 1
+        }
+    }
+}
+
 async fn start_dataflow(
     dataflow: &Path,
     coordinator_events_tx: &Sender<Event>,
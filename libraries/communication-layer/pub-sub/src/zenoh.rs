@@ -31,6 +31,15 @@ impl ZenohCommunicationLayer {
         })
     }
 
+    /// Returns the underlying `zenoh` session, e.g. to declare subscribers
+    /// that are received asynchronously.
+    ///
+    /// Note that the topic prefix is not applied to key expressions that are
+    /// used with the session directly.
+    pub fn session(&self) -> &Arc<zenoh::Session> {
+        &self.zenoh
+    }
+
     fn prefixed(&self, topic: &str) -> String {
         format!("{}/{topic}", self.topic_prefix)
     }
@@ -9,6 +9,7 @@
 
 pub use tcp::*;
 
+pub mod security;
 mod tcp;
 
 /// Abstraction trait for different publisher/subscriber implementations.
@@ -0,0 +1,603 @@
+//! Optional TLS and token authentication for TCP connections.
+//!
+//! Both sides of a connection need the same settings. With TLS, both sides
+//! present a certificate signed by the configured CA (mutual TLS). With a
+//! token, the connecting side sends the shared token first, which the
+//! accepting side checks before handling any messages. The token is sent in
+//! plain text if TLS is not enabled.
+
+use eyre::{bail, Context};
+use std::{
+    io::{self, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream as StdTcpStream},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{self, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    client,
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+        server::WebPkiClientVerifier,
+        ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+    },
+    server, TlsAcceptor, TlsConnector,
+};
+
+/// Tokens longer than this are rejected without reading them.
+const MAX_TOKEN_LEN: usize = 4096;
+
+/// Maximum duration of the TLS handshake and token check of an incoming
+/// connection, so that stalled clients don't block the listener.
+#[cfg(not(test))]
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+#[cfg(test)]
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(500);
+const TOKEN_ACCEPTED: u8 = 1;
+const TOKEN_REJECTED: u8 = 0;
+
+/// Security settings, e.g. read from a YAML file:
+///
+/// ```yaml
+/// tls:
+///   ca: ca.pem
+///   cert: machine.pem
+///   key: machine.key
+/// token_file: token
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecurityConfig {
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// File that contains the shared token.
+    #[serde(default)]
+    pub token_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file of the CA that signed the certificates of all parties.
+    pub ca: PathBuf,
+    /// PEM file of the certificate (chain) of this party.
+    pub cert: PathBuf,
+    /// PEM file of the private key of `cert`.
+    pub key: PathBuf,
+    /// Name that server certificates are checked against.
+    ///
+    /// Defaults to the IP address of the server, which requires IP subject
+    /// alternative names in all certificates.
+    #[serde(default)]
+    pub server_name: Option<String>,
+}
+
+impl SecurityConfig {
+    /// Reads the config file. Relative paths are resolved relative to the
+    /// directory of the file.
+    pub fn read(path: &Path) -> eyre::Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read security config `{}`", path.display()))?;
+        let mut config: Self = serde_yaml::from_str(&raw)
+            .wrap_err_with(|| format!("failed to parse security config `{}`", path.display()))?;
+        if let Some(base) = path.parent() {
+            if let Some(tls) = &mut config.tls {
+                tls.ca = base.join(&tls.ca);
+                tls.cert = base.join(&tls.cert);
+                tls.key = base.join(&tls.key);
+            }
+            if let Some(token_file) = &mut config.token_file {
+                *token_file = base.join(&token_file);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Loads the referenced certificates, keys and tokens.
+    pub fn load(&self) -> eyre::Result<Security> {
+        let tls = self
+            .tls
+            .as_ref()
+            .map(TlsSetup::load)
+            .transpose()
+            .wrap_err("failed to set up TLS")?;
+        let token = match &self.token_file {
+            Some(path) => {
+                let token = std::fs::read_to_string(path)
+                    .wrap_err_with(|| format!("failed to read token file `{}`", path.display()))?;
+                let token = token.trim();
+                if token.is_empty() {
+                    bail!("token file `{}` is empty", path.display());
+                }
+                if token.len() > MAX_TOKEN_LEN {
+                    bail!("token in `{}` is too long", path.display());
+                }
+                Some(Arc::from(token))
+            }
+            None => None,
+        };
+        Ok(Security { tls, token })
+    }
+}
+
+/// Loaded [`SecurityConfig`], which secures new connections.
+///
+/// The default value leaves connections unencrypted and unauthenticated.
+#[derive(Clone, Default)]
+pub struct Security {
+    tls: Option<TlsSetup>,
+    token: Option<Arc<str>>,
+}
+
+impl std::fmt::Debug for Security {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Security")
+            .field("tls", &self.tls.is_some())
+            .field("token", &self.token.is_some())
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+struct TlsSetup {
+    server: Arc<ServerConfig>,
+    client: Arc<ClientConfig>,
+    server_name: Option<ServerName<'static>>,
+}
+
+impl TlsSetup {
+    fn load(config: &TlsConfig) -> eyre::Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in read_certs(&config.ca)? {
+            roots
+                .add(cert)
+                .wrap_err_with(|| format!("invalid CA certificate in `{}`", config.ca.display()))?;
+        }
+        let roots = Arc::new(roots);
+        let certs = read_certs(&config.cert)?;
+        let key = read_key(&config.key)?;
+
+        let client_verifier = WebPkiClientVerifier::builder(roots.clone())
+            .build()
+            .wrap_err("failed to create client certificate verifier")?;
+        let server = ServerConfig::builder()
+            .with_client_cert_verifier(client_verifier)
+            .with_single_cert(certs.clone(), key.clone_key())
+            .wrap_err("invalid certificate or key")?;
+        let client = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(certs, key)
+            .wrap_err("invalid certificate or key")?;
+        let server_name = config
+            .server_name
+            .clone()
+            .map(ServerName::try_from)
+            .transpose()
+            .wrap_err("invalid TLS server name")?;
+
+        Ok(Self {
+            server: Arc::new(server),
+            client: Arc::new(client),
+            server_name,
+        })
+    }
+
+    fn server_name(&self, peer: SocketAddr) -> ServerName<'static> {
+        self.server_name
+            .clone()
+            .unwrap_or_else(|| ServerName::IpAddress(peer.ip().into()))
+    }
+}
+
+fn read_certs(path: &Path) -> eyre::Result<Vec<CertificateDer<'static>>> {
+    let file = std::fs::File::open(path)
+        .wrap_err_with(|| format!("failed to open `{}`", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err_with(|| format!("failed to parse certificates in `{}`", path.display()))?;
+    if certs.is_empty() {
+        bail!("no certificates found in `{}`", path.display());
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &Path) -> eyre::Result<PrivateKeyDer<'static>> {
+    let file = std::fs::File::open(path)
+        .wrap_err_with(|| format!("failed to open `{}`", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .wrap_err_with(|| format!("failed to parse private key in `{}`", path.display()))?
+        .ok_or_else(|| eyre::eyre!("no private key found in `{}`", path.display()))
+}
+
+impl Security {
+    pub fn is_enabled(&self) -> bool {
+        self.tls.is_some() || self.token.is_some()
+    }
+
+    /// Checks a token that was received out of band, e.g. in an HTTP
+    /// `Authorization` header.
+    ///
+    /// Always succeeds if no token is configured.
+    pub fn check_token(&self, received: Option<&str>) -> bool {
+        match &self.token {
+            Some(token) => received.is_some_and(|r| tokens_match(token.as_bytes(), r.as_bytes())),
+            None => true,
+        }
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    pub fn token_enabled(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Secures a connection that was accepted by a listener.
+    pub async fn accept(&self, stream: TcpStream) -> io::Result<MaybeTlsStream> {
+        with_timeout(self.accept_inner(stream)).await
+    }
+
+    async fn accept_inner(&self, stream: TcpStream) -> io::Result<MaybeTlsStream> {
+        let mut stream = self.handshake(stream).await?;
+        if let Some(token) = &self.token {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len).await?;
+            let len = u64::from_le_bytes(len) as usize;
+            let valid = if len <= MAX_TOKEN_LEN {
+                let mut received = vec![0; len];
+                stream.read_exact(&mut received).await?;
+                tokens_match(token.as_bytes(), &received)
+            } else {
+                false
+            };
+            if !valid {
+                let _ = stream.write_all(&[TOKEN_REJECTED]).await;
+                return Err(rejected());
+            }
+            stream.write_all(&[TOKEN_ACCEPTED]).await?;
+            stream.flush().await?;
+        }
+        Ok(stream)
+    }
+
+    /// Performs only the TLS handshake of [`Self::accept`], without the token
+    /// exchange.
+    ///
+    /// For protocols that transmit the token themselves, e.g. HTTP.
+    pub async fn accept_tls(&self, stream: TcpStream) -> io::Result<MaybeTlsStream> {
+        with_timeout(self.handshake(stream)).await
+    }
+
+    async fn handshake(&self, stream: TcpStream) -> io::Result<MaybeTlsStream> {
+        match &self.tls {
+            Some(tls) => {
+                let stream = TlsAcceptor::from(tls.server.clone()).accept(stream).await?;
+                Ok(MaybeTlsStream::Server(Box::new(stream)))
+            }
+            None => Ok(MaybeTlsStream::Plain(stream)),
+        }
+    }
+
+    /// Secures an outgoing connection.
+    pub async fn connect(&self, stream: TcpStream) -> io::Result<MaybeTlsStream> {
+        let mut stream = match &self.tls {
+            Some(tls) => {
+                let server_name = tls.server_name(stream.peer_addr()?);
+                let stream = TlsConnector::from(tls.client.clone())
+                    .connect(server_name, stream)
+                    .await?;
+                MaybeTlsStream::Client(Box::new(stream))
+            }
+            None => MaybeTlsStream::Plain(stream),
+        };
+        if let Some(token) = &self.token {
+            stream
+                .write_all(&(token.len() as u64).to_le_bytes())
+                .await?;
+            stream.write_all(token.as_bytes()).await?;
+            stream.flush().await?;
+            let mut reply = [0];
+            stream.read_exact(&mut reply).await?;
+            if reply[0] != TOKEN_ACCEPTED {
+                return Err(rejected());
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Blocking version of [`Self::accept`].
+    ///
+    /// The handshake is done with socket timeouts, which are removed again
+    /// afterwards.
+    pub(crate) fn accept_blocking(&self, stream: StdTcpStream) -> io::Result<BlockingStream> {
+        let socket = stream.try_clone()?;
+        socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        socket.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let stream = self.accept_blocking_inner(stream).map_err(|err| {
+            if err.kind() == io::ErrorKind::WouldBlock {
+                timed_out()
+            } else {
+                err
+            }
+        })?;
+        socket.set_read_timeout(None)?;
+        socket.set_write_timeout(None)?;
+        Ok(stream)
+    }
+
+    fn accept_blocking_inner(&self, stream: StdTcpStream) -> io::Result<BlockingStream> {
+        let mut stream: BlockingStream = match &self.tls {
+            Some(tls) => {
+                let connection =
+                    ServerConnection::new(tls.server.clone()).map_err(io::Error::other)?;
+                let mut stream = StreamOwned::new(connection, stream);
+                // handshake now instead of on the first read, while the timeouts are set
+                while stream.conn.is_handshaking() {
+                    stream.conn.complete_io(&mut stream.sock)?;
+                }
+                Box::new(stream)
+            }
+            None => Box::new(stream),
+        };
+        if let Some(token) = &self.token {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len)?;
+            let len = u64::from_le_bytes(len) as usize;
+            let valid = if len <= MAX_TOKEN_LEN {
+                let mut received = vec![0; len];
+                stream.read_exact(&mut received)?;
+                tokens_match(token.as_bytes(), &received)
+            } else {
+                false
+            };
+            if !valid {
+                let _ = stream.write_all(&[TOKEN_REJECTED]);
+                return Err(rejected());
+            }
+            stream.write_all(&[TOKEN_ACCEPTED])?;
+            stream.flush()?;
+        }
+        Ok(stream)
+    }
+
+    pub(crate) fn connect_blocking(&self, stream: StdTcpStream) -> io::Result<BlockingStream> {
+        let mut stream: BlockingStream = match &self.tls {
+            Some(tls) => {
+                let server_name = tls.server_name(stream.peer_addr()?);
+                let connection = ClientConnection::new(tls.client.clone(), server_name)
+                    .map_err(io::Error::other)?;
+                Box::new(StreamOwned::new(connection, stream))
+            }
+            None => Box::new(stream),
+        };
+        if let Some(token) = &self.token {
+            stream.write_all(&(token.len() as u64).to_le_bytes())?;
+            stream.write_all(token.as_bytes())?;
+            stream.flush()?;
+            let mut reply = [0];
+            stream.read_exact(&mut reply)?;
+            if reply[0] != TOKEN_ACCEPTED {
+                return Err(rejected());
+            }
+        }
+        Ok(stream)
+    }
+}
+
+async fn with_timeout<T>(
+    future: impl std::future::Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, future)
+        .await
+        .unwrap_or_else(|_| Err(timed_out()))
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "connection handshake did not finish in time",
+    )
+}
+
+/// Compares in constant time for tokens of the same length.
+fn tokens_match(expected: &[u8], received: &[u8]) -> bool {
+    expected.len() == received.len()
+        && expected
+            .iter()
+            .zip(received)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn rejected() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "invalid auth token")
+}
+
+pub(crate) trait BlockingReadWrite: Read + Write + Send + Sync {}
+
+impl<T: Read + Write + Send + Sync> BlockingReadWrite for T {}
+
+pub(crate) type BlockingStream = Box<dyn BlockingReadWrite>;
+
+/// A TCP stream that is encrypted if TLS is enabled.
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Client(Box<client::TlsStream<TcpStream>>),
+    Server(Box<server::TlsStream<TcpStream>>),
+}
+
+impl std::fmt::Debug for MaybeTlsStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            MaybeTlsStream::Plain(_) => "Plain",
+            MaybeTlsStream::Client(_) => "Client",
+            MaybeTlsStream::Server(_) => "Server",
+        };
+        f.debug_tuple(kind).field(self.tcp_stream()).finish()
+    }
+}
+
+impl MaybeTlsStream {
+    pub fn tcp_stream(&self) -> &TcpStream {
+        match self {
+            MaybeTlsStream::Plain(stream) => stream,
+            MaybeTlsStream::Client(stream) => stream.get_ref().0,
+            MaybeTlsStream::Server(stream) => stream.get_ref().0,
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp_stream().peer_addr()
+    }
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTlsStream::Client(stream) => Pin::new(stream).poll_read(cx, buf),
+            MaybeTlsStream::Server(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTlsStream::Client(stream) => Pin::new(stream).poll_write(cx, buf),
+            MaybeTlsStream::Server(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTlsStream::Client(stream) => Pin::new(stream).poll_flush(cx),
+            MaybeTlsStream::Server(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            MaybeTlsStream::Client(stream) => Pin::new(stream).poll_shutdown(cx),
+            MaybeTlsStream::Server(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RequestReplyLayer, TcpLayer};
+    use tokio::net::TcpListener;
+
+    fn security(tls: bool, token: Option<&str>) -> Security {
+        let certs = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/certs");
+        let config = SecurityConfig {
+            tls: tls.then(|| TlsConfig {
+                ca: certs.join("ca.pem"),
+                cert: certs.join("machine.pem"),
+                key: certs.join("machine.key"),
+                server_name: None,
+            }),
+            token_file: None,
+        };
+        Security {
+            token: token.map(Arc::from),
+            ..config.load().unwrap()
+        }
+    }
+
+    /// Accepts a single connection and echoes the first message.
+    async fn echo_server(
+        security: Security,
+    ) -> (SocketAddr, tokio::task::JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let task = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = security.accept(stream).await?;
+            let mut len = [0; 8];
+            stream.read_exact(&mut len).await?;
+            let mut message = vec![0; u64::from_le_bytes(len) as usize];
+            stream.read_exact(&mut message).await?;
+            stream.write_all(&len).await?;
+            stream.write_all(&message).await?;
+            stream.flush().await
+        });
+        (addr, task)
+    }
+
+    #[tokio::test]
+    async fn mutual_tls_with_token() {
+        let security = security(true, Some("secret"));
+        let (addr, server) = echo_server(security.clone()).await;
+
+        let reply = tokio::task::spawn_blocking(move || {
+            TcpLayer::with_security(security)
+                .connect(addr)?
+                .request(&b"hello".to_vec())
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(reply, b"hello");
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn stalled_clients_time_out() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut incoming = TcpLayer::with_security(security(true, Some("secret")))
+            .listen(addr)
+            .unwrap();
+
+        // connects, but never starts the handshake
+        let _stalled = StdTcpStream::connect(addr).unwrap();
+        let result = incoming.next().unwrap();
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::TimedOut);
+
+        // the listener still accepts other connections afterwards
+        let client = std::thread::spawn(move || {
+            TcpLayer::with_security(security(true, Some("secret")))
+                .connect(addr)?
+                .request(&b"hello".to_vec())
+        });
+        let mut connection = incoming.next().unwrap().unwrap();
+        connection.handle_next(Box::new(Ok)).unwrap();
+        assert_eq!(client.join().unwrap().unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn reject_wrong_token() {
+        let (addr, server) = echo_server(security(false, Some("secret"))).await;
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let result = security(false, Some("wrong")).connect(stream).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            server.await.unwrap().unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+    }
+}
@@ -3,16 +3,26 @@ use std::{
     net::{SocketAddr, TcpListener, TcpStream},
 };
 
-use crate::{ListenConnection, RequestReplyConnection, RequestReplyLayer};
+use crate::{
+    security::{BlockingStream, Security},
+    ListenConnection, RequestReplyConnection, RequestReplyLayer,
+};
 
 pub type TcpRequestReplyConnection =
     dyn RequestReplyConnection<RequestData = Vec<u8>, ReplyData = Vec<u8>, Error = std::io::Error>;
 
-pub struct TcpLayer {}
+pub struct TcpLayer {
+    security: Security,
+}
 
 impl TcpLayer {
     pub fn new() -> Self {
-        Self {}
+        Self::with_security(Security::default())
+    }
+
+    /// Secures all connections, e.g. using TLS.
+    pub fn with_security(security: Security) -> Self {
+        Self { security }
     }
 }
 
@@ -48,21 +58,23 @@ impl RequestReplyLayer for TcpLayer {
         >,
         Self::Error,
     > {
+        let security = self.security.clone();
         let incoming: Box<dyn Iterator<Item = Result<_, _>>> = Box::new(
             IntoIncoming {
                 listener: TcpListener::bind(addr)?,
             }
-            .map(|r| {
-                r.map(|stream| {
-                    let connection: Box<
-                        dyn ListenConnection<
-                            RequestData = Self::RequestData,
-                            ReplyData = Self::ReplyData,
-                            Error = Self::Error,
-                        >,
-                    > = Box::new(TcpConnection { stream });
-                    connection
-                })
+            .map(move |r| {
+                r.and_then(|stream| security.accept_blocking(stream))
+                    .map(|stream| {
+                        let connection: Box<
+                            dyn ListenConnection<
+                                RequestData = Self::RequestData,
+                                ReplyData = Self::ReplyData,
+                                Error = Self::Error,
+                            >,
+                        > = Box::new(TcpConnection { stream });
+                        connection
+                    })
             }),
         );
         Ok(incoming)
@@ -81,7 +93,8 @@ impl RequestReplyLayer for TcpLayer {
         >,
         Self::Error,
     > {
-        TcpStream::connect(addr).map(|s| {
+        let stream = TcpStream::connect(addr)?;
+        self.security.connect_blocking(stream).map(|s| {
             let connection: Box<
                 dyn RequestReplyConnection<
                     RequestData = Self::RequestData,
@@ -95,7 +108,7 @@ impl RequestReplyLayer for TcpLayer {
 }
 
 struct TcpConnection {
-    stream: TcpStream,
+    stream: BlockingStream,
 }
 
 impl ListenConnection for TcpConnection {
@@ -1,3 +1,4 @@
+use arrow_schema::DataType;
 use once_cell::sync::OnceCell;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -6,6 +7,7 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     convert::Infallible,
     fmt,
+    path::PathBuf,
     str::FromStr,
     time::Duration,
 };
@@ -121,19 +123,36 @@ impl Borrow<str> for DataId {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, JsonSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum InputMapping {
-    Timer { interval: Duration },
+    Timer(TimerSpec),
     User(UserInputMapping),
 }
 
+// serialized as `<node>/<output>` or `dora/timer/...` string
+impl JsonSchema for InputMapping {
+    fn schema_name() -> String {
+        "InputMapping".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        let mut schema = String::json_schema(gen).into_object();
+        schema.metadata().description = Some(
+            "Source of the input, either `<node_id>/<output_id>` or a timer \
+            such as `dora/timer/millis/100`"
+                .into(),
+        );
+        schema.into()
+    }
+}
+
 impl InputMapping {
     pub fn source(&self) -> &NodeId {
         static DORA_NODE_ID: OnceCell<NodeId> = OnceCell::new();
 
         match self {
             InputMapping::User(mapping) => &mapping.source,
-            InputMapping::Timer { .. } => DORA_NODE_ID.get_or_init(|| NodeId("dora".to_string())),
+            InputMapping::Timer(_) => DORA_NODE_ID.get_or_init(|| NodeId("dora".to_string())),
         }
     }
 }
@@ -141,10 +160,7 @@ impl InputMapping {
 impl fmt::Display for InputMapping {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            InputMapping::Timer { interval } => {
-                let duration = format_duration(*interval);
-                write!(f, "dora/timer/{duration}")
-            }
+            InputMapping::Timer(timer) => write!(f, "dora/timer/{timer}"),
             InputMapping::User(mapping) => {
                 write!(f, "{}/{}", mapping.source, mapping.output)
             }
@@ -174,35 +190,7 @@ impl<'de> Deserialize<'de> for InputMapping {
         let deserialized = match source {
             "dora" => match output.split_once('/') {
                 Some(("timer", output)) => {
-                    let (unit, value) = output.split_once('/').ok_or_else(|| {
-                        serde::de::Error::custom(
-                            "timer input must specify unit and value (e.g. `secs/5` or `millis/100`)",
-                        )
-                    })?;
-                    let interval = match unit {
-                        "secs" => {
-                            let value = value.parse().map_err(|_| {
-                                serde::de::Error::custom(format!(
-                                    "secs must be an integer (got `{value}`)"
-                                ))
-                            })?;
-                            Duration::from_secs(value)
-                        }
-                        "millis" => {
-                            let value = value.parse().map_err(|_| {
-                                serde::de::Error::custom(format!(
-                                    "millis must be an integer (got `{value}`)"
-                                ))
-                            })?;
-                            Duration::from_millis(value)
-                        }
-                        other => {
-                            return Err(serde::de::Error::custom(format!(
-                                "timer unit must be either secs or millis (got `{other}`"
-                            )))
-                        }
-                    };
-                    Self::Timer { interval }
+                    Self::Timer(output.parse().map_err(serde::de::Error::custom)?)
                 }
                 Some((other, _)) => {
                     return Err(serde::de::Error::custom(format!(
@@ -227,14 +215,142 @@ pub struct UserInputMapping {
     pub output: DataId,
 }
 
+/// Timer input provided by dora, e.g. `dora/timer/millis/100`.
+///
+/// Supported forms are `secs/N`, `millis/N`, `micros/N`, `nanos/N`, `hz/N`, and
+/// `once` or `once/<duration>` for a single tick after the dataflow start.
+/// A `?phase=<duration>&jitter=<duration>` suffix delays the first tick and adds
+/// a random delay of up to `jitter` to each tick. For periodic timers, `jitter`
+/// must be shorter than the interval. Calendar (cron) schedules are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema)]
+pub struct TimerSpec {
+    /// Tick interval, or the delay of the single tick for one-shot timers.
+    pub interval: Duration,
+    pub once: bool,
+    pub phase: Duration,
+    pub jitter: Duration,
+}
+
+impl TimerSpec {
+    pub fn interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            once: false,
+            phase: Duration::ZERO,
+            jitter: Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Display for TimerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.once, self.interval.is_zero()) {
+            (true, true) => write!(f, "once")?,
+            (true, false) => write!(f, "once/{}", format_duration(self.interval))?,
+            (false, _) => write!(f, "{}", format_duration(self.interval))?,
+        }
+        let mut separator = '?';
+        for (name, value) in [("phase", self.phase), ("jitter", self.jitter)] {
+            if !value.is_zero() {
+                write!(f, "{separator}{name}={}", format_duration(value))?;
+                separator = '&';
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TimerSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (spec, options) = match s.split_once('?') {
+            Some((spec, options)) => (spec, Some(options)),
+            None => (s, None),
+        };
+        let mut timer = match spec.strip_prefix("once") {
+            Some("") => Self {
+                once: true,
+                ..Self::interval(Duration::ZERO)
+            },
+            Some(delay) => Self {
+                once: true,
+                ..Self::interval(parse_duration(delay.strip_prefix('/').unwrap_or(delay))?)
+            },
+            None => {
+                let interval = parse_duration(spec)?;
+                if interval.is_zero() {
+                    return Err("timer interval must not be zero".into());
+                }
+                Self::interval(interval)
+            }
+        };
+        for option in options.into_iter().flat_map(|o| o.split('&')) {
+            match option.split_once('=') {
+                Some(("phase", value)) => timer.phase = parse_duration(value)?,
+                Some(("jitter", value)) => timer.jitter = parse_duration(value)?,
+                _ => {
+                    return Err(format!(
+                        "unknown timer option `{option}` (expected `phase=<duration>` \
+                        or `jitter=<duration>`)"
+                    ))
+                }
+            }
+        }
+        if !timer.once && timer.jitter >= timer.interval {
+            return Err(format!(
+                "timer jitter ({}) must be shorter than the interval ({})",
+                format_duration(timer.jitter),
+                format_duration(timer.interval)
+            ));
+        }
+        Ok(timer)
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (unit, value) = s.split_once('/').ok_or_else(|| {
+        format!("timer must specify unit and value (e.g. `secs/5` or `millis/100`, got `{s}`)")
+    })?;
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("{unit} must be an integer (got `{value}`)"))?;
+    let duration = match unit {
+        "secs" => Duration::from_secs(value),
+        "millis" => Duration::from_millis(value),
+        "micros" => Duration::from_micros(value),
+        "nanos" => Duration::from_nanos(value),
+        "hz" => {
+            if value == 0 {
+                return Err("hz must be greater than zero".into());
+            }
+            Duration::from_nanos(1_000_000_000 / value)
+        }
+        other => {
+            return Err(format!(
+                "timer unit must be one of secs, millis, micros, nanos, or hz (got `{other}`)"
+            ))
+        }
+    };
+    Ok(duration)
+}
+
 pub struct FormattedDuration(pub Duration);
 
 impl fmt::Display for FormattedDuration {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0.subsec_millis() == 0 {
+        let nanos = self.0.as_nanos();
+        if self.0.subsec_nanos() == 0 {
             write!(f, "secs/{}", self.0.as_secs())
-        } else {
+        } else if nanos % 1_000_000 == 0 {
             write!(f, "millis/{}", self.0.as_millis())
+        } else if nanos % 1_000 == 0 {
+            write!(f, "micros/{}", self.0.as_micros())
+        } else if nanos < 1_000_000_000 && 1_000_000_000 / (1_000_000_000 / nanos) == nanos {
+            // rate that was specified as `hz/N`
+            write!(f, "hz/{}", 1_000_000_000 / nanos)
+        } else {
+            write!(f, "nanos/{nanos}")
         }
     }
 }
@@ -266,22 +382,68 @@ pub struct NodeRunConfig {
     ///  - output_2
     #[serde(default)]
     pub outputs: BTreeSet<DataId>,
+    /// Optional Arrow data types of the outputs, e.g. `UInt8` or `Float32`.
+    ///
+    /// Sending data of a different type on a typed output results in an error.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(with = "BTreeMap<String, String>")]
+    pub output_types: BTreeMap<DataId, DataType>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields, from = "InputDef", into = "InputDef")]
 pub struct Input {
     pub mapping: InputMapping,
     pub queue_size: Option<usize>,
+    /// Expected Arrow data type, checked against the type declared by the source output.
+    pub data_type: Option<DataType>,
+    /// What to do when the input queue is full.
+    pub queue_policy: QueuePolicy,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+// schemars does not support `serde(from)`, so we use the schema of the serialized form
+impl JsonSchema for Input {
+    fn schema_name() -> String {
+        "Input".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        InputDef::json_schema(gen)
+    }
+}
+
+/// Behavior of an input when `queue_size` events are already queued.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuePolicy {
+    /// Drop the oldest queued event to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Drop new events until the receiver catches up.
+    DropNewest,
+    /// Delay the sender until there is room in the queue.
+    ///
+    /// Only senders on the same machine are delayed. Events from remote
+    /// senders are dropped like `drop_oldest` when the queue is full.
+    BlockSender,
+    /// Only keep the latest event, regardless of `queue_size`.
+    LatestOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum InputDef {
     MappingOnly(InputMapping),
     WithOptions {
         source: InputMapping,
+        /// Maximum number of queued events, defaults to 10.
         queue_size: Option<usize>,
+        /// Expected Arrow data type, checked against the type declared by the source output.
+        #[schemars(with = "Option<String>")]
+        data_type: Option<DataType>,
+        /// What to do when the input queue is full.
+        #[serde(default)]
+        queue_policy: QueuePolicy,
     },
 }
 
@@ -291,13 +453,19 @@ impl From<Input> for InputDef {
             Input {
                 mapping,
                 queue_size: None,
+                data_type: None,
+                queue_policy: QueuePolicy::DropOldest,
             } => Self::MappingOnly(mapping),
             Input {
                 mapping,
                 queue_size,
+                data_type,
+                queue_policy,
             } => Self::WithOptions {
                 source: mapping,
                 queue_size,
+                data_type,
+                queue_policy,
             },
         }
     }
@@ -309,10 +477,19 @@ impl From<InputDef> for Input {
             InputDef::MappingOnly(mapping) => Self {
                 mapping,
                 queue_size: None,
+                data_type: None,
+                queue_policy: QueuePolicy::DropOldest,
             },
-            InputDef::WithOptions { source, queue_size } => Self {
+            InputDef::WithOptions {
+                source,
+                queue_size,
+                data_type,
+                queue_policy,
+            } => Self {
                 mapping: source,
                 queue_size,
+                data_type,
+                queue_policy,
             },
         }
     }
@@ -327,21 +504,24 @@ pub struct CommunicationConfig {
         with = "serde_yaml::with::singleton_map",
         rename = "_unstable_local"
     )]
-    #[schemars(with = "String")]
+    #[schemars(with = "LocalCommunicationConfig")]
     pub local: LocalCommunicationConfig,
     #[serde(
         default,
         with = "serde_yaml::with::singleton_map",
         rename = "_unstable_remote"
     )]
-    #[schemars(with = "String")]
+    #[schemars(with = "RemoteCommunicationConfig")]
     pub remote: RemoteCommunicationConfig,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, JsonSchema)]
 pub enum LocalCommunicationConfig {
     Tcp,
     Shmem,
+    /// Unix domain sockets, which are only accessible by the user that runs
+    /// the daemon. Only supported on Unix platforms.
+    UnixDomain,
 }
 
 impl Default for LocalCommunicationConfig {
@@ -350,15 +530,78 @@ impl Default for LocalCommunicationConfig {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[serde(deny_unknown_fields, rename_all = "lowercase")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "RemoteCommunicationConfigDef", rename_all = "lowercase")]
 pub enum RemoteCommunicationConfig {
+    /// Direct TCP connections between all daemons of a dataflow.
     Tcp,
-    // TODO:a
-    // Zenoh {
-    //     config: Option<serde_yaml::Value>,
-    //     prefix: String,
-    // },
+    /// Publish messages through zenoh, which routes them between the daemons.
+    Zenoh(ZenohConfig),
+}
+
+// schemars does not support `serde(from)`, so we use the schema of the serialized form
+impl JsonSchema for RemoteCommunicationConfig {
+    fn schema_name() -> String {
+        "RemoteCommunicationConfig".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        RemoteCommunicationConfigDef::json_schema(gen)
+    }
+}
+
+/// Allows to write `zenoh` instead of `zenoh: {}` for the default zenoh configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum RemoteCommunicationConfigDef {
+    Kind(RemoteCommunicationKind),
+    Zenoh { zenoh: ZenohConfig },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RemoteCommunicationKind {
+    Tcp,
+    Zenoh,
+}
+
+impl From<RemoteCommunicationConfigDef> for RemoteCommunicationConfig {
+    fn from(value: RemoteCommunicationConfigDef) -> Self {
+        match value {
+            RemoteCommunicationConfigDef::Kind(RemoteCommunicationKind::Tcp) => Self::Tcp,
+            RemoteCommunicationConfigDef::Kind(RemoteCommunicationKind::Zenoh) => {
+                Self::Zenoh(ZenohConfig::default())
+            }
+            RemoteCommunicationConfigDef::Zenoh { zenoh } => Self::Zenoh(zenoh),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ZenohConfig {
+    /// Path to a zenoh configuration file, relative to the dataflow.
+    ///
+    /// By default, zenoh runs in peer-to-peer mode and discovers the other
+    /// daemons through multicast scouting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<PathBuf>,
+    /// Prefix of the zenoh key expressions used for inter-daemon messages.
+    #[serde(default = "default_zenoh_prefix")]
+    pub prefix: String,
+}
+
+impl Default for ZenohConfig {
+    fn default() -> Self {
+        Self {
+            config: None,
+            prefix: default_zenoh_prefix(),
+        }
+    }
+}
+
+fn default_zenoh_prefix() -> String {
+    "dora".into()
 }
 
 impl Default for RemoteCommunicationConfig {
@@ -366,3 +609,61 @@ impl Default for RemoteCommunicationConfig {
         Self::Tcp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_communication_config() {
+        let parse = |yaml: &str| -> CommunicationConfig { serde_yaml::from_str(yaml).unwrap() };
+
+        assert_eq!(
+            parse("_unstable_remote: tcp").remote,
+            RemoteCommunicationConfig::Tcp
+        );
+        assert_eq!(
+            parse("_unstable_remote: zenoh").remote,
+            RemoteCommunicationConfig::Zenoh(ZenohConfig::default())
+        );
+        let config = parse("_unstable_remote:\n  zenoh:\n    prefix: test");
+        assert_eq!(
+            config.remote,
+            RemoteCommunicationConfig::Zenoh(ZenohConfig {
+                config: None,
+                prefix: "test".into()
+            })
+        );
+
+        // the daemons receive the config as JSON
+        let json = serde_json::to_string(&config).unwrap();
+        let parsed: CommunicationConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.remote, config.remote);
+    }
+
+    #[test]
+    fn timer_roundtrip() {
+        for (input, interval) in [
+            ("secs/2", Duration::from_secs(2)),
+            ("micros/250", Duration::from_micros(250)),
+            ("hz/30", Duration::from_nanos(33_333_333)),
+            ("once", Duration::ZERO),
+            (
+                "once/millis/500?phase=millis/10",
+                Duration::from_millis(500),
+            ),
+            (
+                "millis/100?phase=millis/50&jitter=millis/5",
+                Duration::from_millis(100),
+            ),
+        ] {
+            let timer: TimerSpec = input.parse().unwrap();
+            assert_eq!(timer.interval, interval);
+            assert_eq!(timer.to_string(), input);
+        }
+        assert!("hz/0".parse::<TimerSpec>().is_err());
+        assert!("millis/10?offset=millis/5".parse::<TimerSpec>().is_err());
+        assert!("millis/10?jitter=millis/10".parse::<TimerSpec>().is_err());
+        assert!("once?jitter=millis/10".parse::<TimerSpec>().is_ok());
+    }
+}
@@ -1,5 +1,6 @@
-use crate::daemon_messages::DataflowId;
+use crate::{config::NodeId, daemon_messages::DataflowId, topics::LogChunk};
 use eyre::eyre;
+use uuid::Uuid;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum CoordinatorRequest {
@@ -7,6 +8,9 @@ pub enum CoordinatorRequest {
         dora_version: String,
         machine_id: String,
         listen_port: u16,
+        /// Dataflows that are already running on the daemon, e.g. because
+        /// it registers again after a coordinator restart.
+        running_dataflows: Vec<DataflowId>,
     },
     Event {
         machine_id: String,
@@ -20,11 +24,51 @@ pub enum DaemonEvent {
         dataflow_id: DataflowId,
         success: bool,
     },
+    /// A node that nodes on other machines depend on is ready.
+    NodeReady {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    },
+    /// A node that nodes on other machines depend on exited before it was
+    /// ready.
+    NodeFailed {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    },
     AllNodesFinished {
         dataflow_id: DataflowId,
         result: Result<(), String>,
     },
     Heartbeat,
+    /// New log output of a subscription that was started with a
+    /// `FollowLogs` event.
+    ///
+    /// `finished` is set on the last event of the subscription, an error
+    /// also ends it.
+    LogOutput {
+        dataflow_id: DataflowId,
+        subscription: Uuid,
+        output: Result<Vec<LogChunk>, String>,
+        finished: bool,
+    },
+    /// The connection to the daemon of another machine failed. The daemon
+    /// keeps trying to reconnect.
+    PeerDisconnected {
+        peer_machine_id: String,
+        error: String,
+    },
+    /// The connection to the daemon of another machine was re-established.
+    PeerReconnected {
+        peer_machine_id: String,
+    },
+}
+
+/// Sent by the coordinator on the events connection of a daemon to
+/// acknowledge the messages that it received on that connection.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct EventsAck {
+    /// Number of messages received since the connection was opened.
+    pub received: u64,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
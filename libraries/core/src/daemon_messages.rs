@@ -9,6 +9,8 @@ use std::{
 use crate::{
     config::{DataId, NodeId, NodeRunConfig, OperatorId},
     descriptor::{Descriptor, OperatorDefinition, ResolvedNode},
+    logs::{LogFilter, LogRecord},
+    topics::{DataflowStats, LogChunk, LogPosition, NodeInfo},
 };
 use aligned_vec::{AVec, ConstAlign};
 use dora_message::{uhlc, Metadata};
@@ -21,6 +23,12 @@ pub struct NodeConfig {
     pub run_config: NodeRunConfig,
     pub daemon_communication: DaemonCommunication,
     pub dataflow_descriptor: Descriptor,
+    /// Outputs that are received by `block_sender` inputs on the same machine.
+    ///
+    /// Sending on these outputs waits for a reply of the daemon, which is
+    /// delayed while a receiving queue is full.
+    #[serde(default)]
+    pub blocking_outputs: BTreeSet<DataId>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -30,10 +38,14 @@ pub enum DaemonCommunication {
         daemon_drop_region_id: SharedMemoryId,
         daemon_events_region_id: SharedMemoryId,
         daemon_events_close_region_id: SharedMemoryId,
+        daemon_logs_region_id: SharedMemoryId,
     },
     Tcp {
         socket_addr: SocketAddr,
     },
+    UnixDomain {
+        socket_file: PathBuf,
+    },
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -54,6 +66,8 @@ pub enum DaemonRequest {
         output_id: DataId,
         metadata: Metadata,
         data: Option<DataMessage>,
+        /// Wait for a reply of the daemon, see [`NodeConfig::blocking_outputs`].
+        wait_for_reply: bool,
     },
     CloseOutputs(Vec<DataId>),
     /// Signals that the node is finished sending outputs and that it received all
@@ -68,13 +82,19 @@ pub enum DaemonRequest {
     SubscribeDrop,
     NextFinishedDropTokens,
     EventStreamDropped,
+    /// Structured log output of the node, e.g. from `tracing`, sent in
+    /// batches.
+    Logs(Vec<LogRecord>),
+    /// Signals that the node is ready, see `Node::ready_signal`.
+    Ready,
 }
 
 impl DaemonRequest {
     pub fn expects_tcp_reply(&self) -> bool {
         #[allow(clippy::match_like_matches_macro)]
         match self {
-            DaemonRequest::SendMessage { .. } | DaemonRequest::ReportDropTokens { .. } => false,
+            DaemonRequest::SendMessage { wait_for_reply, .. } => *wait_for_reply,
+            DaemonRequest::ReportDropTokens { .. } => false,
             DaemonRequest::Register { .. }
             | DaemonRequest::Subscribe
             | DaemonRequest::CloseOutputs(_)
@@ -82,7 +102,9 @@ impl DaemonRequest {
             | DaemonRequest::NextEvent { .. }
             | DaemonRequest::SubscribeDrop
             | DaemonRequest::NextFinishedDropTokens
-            | DaemonRequest::EventStreamDropped => true,
+            | DaemonRequest::EventStreamDropped
+            | DaemonRequest::Logs(_)
+            | DaemonRequest::Ready => true,
         }
     }
 }
@@ -212,6 +234,17 @@ pub enum DaemonCoordinatorEvent {
         dataflow_id: DataflowId,
         success: bool,
     },
+    /// A node on another machine is ready (see `depends_on`).
+    NodeReady {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    },
+    /// A node on another machine exited before it was ready (see
+    /// `depends_on`).
+    NodeFailed {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+    },
     StopDataflow {
         dataflow_id: DataflowId,
         grace_duration: Option<Duration>,
@@ -225,6 +258,39 @@ pub enum DaemonCoordinatorEvent {
         dataflow_id: DataflowId,
         node_id: NodeId,
     },
+    /// Read the log output of the given local nodes.
+    ReadLogs {
+        dataflow_id: DataflowId,
+        nodes: BTreeMap<NodeId, LogPosition>,
+        filter: LogFilter,
+    },
+    /// Push the log output of the given local nodes to the coordinator as
+    /// [`DaemonEvent::LogOutput`](crate::coordinator_messages::DaemonEvent::LogOutput)
+    /// events until the dataflow finished.
+    FollowLogs {
+        dataflow_id: DataflowId,
+        subscription: Uuid,
+        nodes: BTreeMap<NodeId, LogPosition>,
+        filter: LogFilter,
+    },
+    /// Stop pushing the log output of the given subscription.
+    UnfollowLogs {
+        subscription: Uuid,
+    },
+    /// The given nodes ran on a machine that the coordinator lost, so the
+    /// inputs that they feed should be closed.
+    NodesLost {
+        dataflow_id: DataflowId,
+        nodes: BTreeSet<NodeId>,
+    },
+    /// Query the status of the local nodes of the given dataflow.
+    Inspect {
+        dataflow_id: DataflowId,
+    },
+    /// Query the message statistics of the local edges of the given dataflow.
+    Stats {
+        dataflow_id: DataflowId,
+    },
     Destroy,
     Heartbeat,
 }
@@ -242,6 +308,52 @@ pub enum InterDaemonEvent {
         dataflow_id: DataflowId,
         inputs: BTreeSet<(NodeId, DataId)>,
     },
+    /// Header of an output that is too large to be sent as a single message.
+    ///
+    /// The data follows in [`OutputChunk`](Self::OutputChunk) events with the
+    /// same `transfer_id`.
+    LargeOutput {
+        dataflow_id: DataflowId,
+        node_id: NodeId,
+        output_id: DataId,
+        metadata: Metadata,
+        transfer_id: Uuid,
+        len: usize,
+    },
+    OutputChunk {
+        dataflow_id: DataflowId,
+        transfer_id: Uuid,
+        offset: usize,
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+    },
+}
+
+/// Borrowed version of [`InterDaemonEvent::OutputChunk`], which serializes
+/// to the same format without copying the chunk data first.
+pub struct OutputChunkRef<'a> {
+    pub dataflow_id: DataflowId,
+    pub transfer_id: Uuid,
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+impl serde::Serialize for OutputChunkRef<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeStructVariant;
+
+        // must match the variant index of `InterDaemonEvent::OutputChunk`
+        let mut event =
+            serializer.serialize_struct_variant("InterDaemonEvent", 3, "OutputChunk", 4)?;
+        event.serialize_field("dataflow_id", &self.dataflow_id)?;
+        event.serialize_field("transfer_id", &self.transfer_id)?;
+        event.serialize_field("offset", &self.offset)?;
+        event.serialize_field("data", serde_bytes::Bytes::new(self.data))?;
+        event.end()
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -255,6 +367,9 @@ pub enum DaemonCoordinatorReply {
         notify: Option<tokio::sync::oneshot::Sender<()>>,
     },
     Logs(Result<Vec<u8>, String>),
+    LogChunks(Result<Vec<LogChunk>, String>),
+    InspectResult(Result<Vec<NodeInfo>, String>),
+    StatsResult(Result<DataflowStats, String>),
 }
 
 pub type DataflowId = Uuid;
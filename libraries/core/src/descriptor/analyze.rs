@@ -0,0 +1,198 @@
+use super::{
+    validate::{Problems, Severity},
+    CoreNodeKind, Descriptor, ResolvedNode, ValidationError,
+};
+use crate::config::{DataId, Input, InputMapping, NodeId, QueuePolicy, UserInputMapping};
+use eyre::eyre;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub(super) fn analyze_graph(dataflow: &Descriptor, nodes: &[ResolvedNode]) -> Vec<ValidationError> {
+    let mut problems = Problems::new(&dataflow.source_map, Severity::Warning);
+    check_unused_outputs(dataflow, nodes, &mut problems);
+    check_nodes_without_inputs(nodes, &mut problems);
+    check_blocking_cycles(nodes, &mut problems);
+    problems.into_vec()
+}
+
+/// All inputs of the given node, including the inputs of its operators.
+fn inputs(node: &ResolvedNode) -> Vec<(&DataId, &Input)> {
+    match &node.kind {
+        CoreNodeKind::Custom(custom) => custom.run_config.inputs.iter().collect(),
+        CoreNodeKind::Runtime(runtime) => runtime
+            .operators
+            .iter()
+            .flat_map(|operator| &operator.config.inputs)
+            .collect(),
+    }
+}
+
+/// Warns about outputs that are not mapped to any input.
+///
+/// Outputs that are exposed by a sub-graph are considered used.
+fn check_unused_outputs(dataflow: &Descriptor, nodes: &[ResolvedNode], problems: &mut Problems) {
+    let mut used: BTreeSet<String> = dataflow.outputs.values().cloned().collect();
+    for node in nodes {
+        for (_, input) in inputs(node) {
+            if let InputMapping::User(UserInputMapping { source, output }) = &input.mapping {
+                used.insert(format!("{source}/{output}"));
+            }
+        }
+    }
+
+    for node in nodes {
+        let outputs: Vec<String> = match &node.kind {
+            CoreNodeKind::Custom(custom) => custom
+                .run_config
+                .outputs
+                .iter()
+                .map(|output| format!("{}/{output}", node.id))
+                .collect(),
+            CoreNodeKind::Runtime(runtime) => runtime
+                .operators
+                .iter()
+                .flat_map(|operator| {
+                    operator
+                        .config
+                        .outputs
+                        .iter()
+                        .map(move |output| format!("{}/{}/{output}", node.id, operator.id))
+                })
+                .collect(),
+        };
+        for output in outputs.into_iter().filter(|o| !used.contains(o)) {
+            problems.node(
+                &node.id,
+                eyre!("output `{output}` is not used as input by any node"),
+            );
+        }
+    }
+}
+
+/// Warns about nodes that receive no inputs, not even timer ticks.
+///
+/// Such nodes only get the stop event, which is often a sign of a missing
+/// input mapping.
+fn check_nodes_without_inputs(nodes: &[ResolvedNode], problems: &mut Problems) {
+    for node in nodes {
+        if inputs(node).is_empty() {
+            problems.node(
+                &node.id,
+                eyre!("node `{}` has no inputs and no timers", node.id),
+            );
+        }
+    }
+}
+
+/// Warns about feedback cycles in which all inputs use the `block_sender` queue
+/// policy.
+///
+/// Once all queues of such a cycle are full, every node waits for the next
+/// one and the dataflow deadlocks.
+fn check_blocking_cycles(nodes: &[ResolvedNode], problems: &mut Problems) {
+    let mut senders: BTreeMap<&NodeId, BTreeSet<&NodeId>> =
+        nodes.iter().map(|n| (&n.id, BTreeSet::new())).collect();
+    for node in nodes {
+        for (_, input) in inputs(node) {
+            if let InputMapping::User(UserInputMapping { source, .. }) = &input.mapping {
+                if input.queue_policy == QueuePolicy::BlockSender {
+                    if let Some(receivers) = senders.get_mut(source) {
+                        receivers.insert(&node.id);
+                    }
+                }
+            }
+        }
+    }
+
+    // depth-first search, keeping track of the current path to report cycles
+    fn visit<'a>(
+        node_id: &'a NodeId,
+        senders: &BTreeMap<&'a NodeId, BTreeSet<&'a NodeId>>,
+        path: &mut Vec<&'a NodeId>,
+        done: &mut BTreeSet<&'a NodeId>,
+    ) -> Option<Vec<&'a NodeId>> {
+        if done.contains(node_id) {
+            return None;
+        }
+        if let Some(start) = path.iter().position(|id| *id == node_id) {
+            return Some(path[start..].to_vec());
+        }
+        path.push(node_id);
+        for receiver in &senders[node_id] {
+            if let Some(cycle) = visit(receiver, senders, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        done.insert(node_id);
+        None
+    }
+
+    let mut done = BTreeSet::new();
+    for node in nodes {
+        if let Some(cycle) = visit(&node.id, &senders, &mut Vec::new(), &mut done) {
+            let ids: Vec<_> = cycle
+                .iter()
+                .chain(cycle.first())
+                .map(|id| id.to_string())
+                .collect();
+            problems.node(
+                cycle[0],
+                eyre!(
+                    "feedback cycle {} only uses `block_sender` inputs and can deadlock",
+                    ids.join(" -> ")
+                ),
+            );
+            // report every cycle only once
+            done.extend(cycle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graph_warnings() {
+        let descriptor = Descriptor::parse(
+            br#"
+nodes:
+  - id: source
+    path: source
+    outputs: [data, debug]
+  - id: a
+    path: a
+    inputs:
+      data: source/data
+      feedback:
+        source: b/out
+        queue_policy: block_sender
+    outputs: [out]
+  - id: b
+    path: b
+    inputs:
+      data:
+        source: a/out
+        queue_policy: block_sender
+    outputs: [out]
+"#
+            .to_vec(),
+        )
+        .unwrap();
+
+        let warnings: Vec<_> = descriptor
+            .analyze()
+            .unwrap()
+            .into_iter()
+            .map(|w| w.message)
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "output `source/debug` is not used as input by any node",
+                "node `source` has no inputs and no timers",
+                "feedback cycle a -> b -> a only uses `block_sender` inputs and can deadlock",
+            ]
+        );
+    }
+}
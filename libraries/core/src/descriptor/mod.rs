@@ -1,19 +1,32 @@
 use crate::config::{
     CommunicationConfig, DataId, Input, InputMapping, NodeId, NodeRunConfig, OperatorId,
 };
+use arrow_schema::DataType;
 use eyre::{bail, eyre, Context, OptionExt, Result};
+pub use replicas::REPLICA_INDEX_PLACEHOLDER;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with_expand_env::with_expand_envs;
+pub use source_map::{SourceLocation, SourceMap};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     env::consts::EXE_EXTENSION,
     fmt,
     path::{Path, PathBuf},
+    time::Duration,
 };
+pub use subgraph::NAMESPACE_SEPARATOR;
 use tracing::warn;
+use validate::Problems;
+pub use validate::{Severity, ValidationError, ValidationErrors};
+pub use variables::VariableValue;
 pub use visualize::collect_dora_timers;
+mod analyze;
+mod replicas;
+mod source_map;
+mod subgraph;
 mod validate;
+mod variables;
 mod visualize;
 pub const SHELL_SOURCE: &str = "shell";
 
@@ -22,23 +35,51 @@ pub const SHELL_SOURCE: &str = "shell";
 #[serde(deny_unknown_fields)]
 #[schemars(title = "dora-rs specification")]
 pub struct Descriptor {
-    #[schemars(skip)]
     #[serde(default)]
     pub communication: CommunicationConfig,
-    #[schemars(skip)]
     #[serde(default, rename = "_unstable_deploy")]
     pub deploy: Deploy,
+    /// Typed variables that can be referenced as `${{ name }}` in the dataflow.
+    ///
+    /// The default values can be overridden using `dora start --set name=value`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, VariableValue>,
     pub nodes: Vec<Node>,
+    /// Inputs that are exposed when this dataflow is included as a sub-graph.
+    ///
+    /// Maps each exposed input ID to the list of `node_id/input_id` inputs
+    /// that it is forwarded to.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<DataId, Vec<String>>,
+    /// Outputs that are exposed when this dataflow is included as a sub-graph.
+    ///
+    /// Maps each exposed output ID to the `node_id/output_id` that provides it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<DataId, String>,
+    /// Locations of the parsed YAML source, used for error messages.
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub source_map: SourceMap,
 }
 
 pub const SINGLE_OPERATOR_DEFAULT_ID: &str = "op";
 
 impl Descriptor {
     pub fn resolve_aliases_and_set_defaults(&self) -> eyre::Result<Vec<ResolvedNode>> {
+        let mut problems = Problems::new(&self.source_map, Severity::Error);
+        let resolved = self.resolve_nodes(&mut problems);
+        problems.into_result()?;
+        Ok(resolved)
+    }
+
+    /// Resolves the nodes of the dataflow, reporting the nodes that cannot be
+    /// resolved to `problems`.
+    fn resolve_nodes(&self, problems: &mut Problems) -> Vec<ResolvedNode> {
         let default_op_id = OperatorId::from(SINGLE_OPERATOR_DEFAULT_ID.to_string());
 
-        let single_operator_nodes: HashMap<_, _> = self
-            .nodes
+        let nodes = self.expand_nodes(problems);
+
+        let single_operator_nodes: HashMap<_, _> = nodes
             .iter()
             .filter_map(|n| {
                 n.operator
@@ -48,9 +89,15 @@ impl Descriptor {
             .collect();
 
         let mut resolved = vec![];
-        for mut node in self.nodes.clone() {
+        for mut node in nodes.clone() {
             // adjust input mappings
-            let mut node_kind = node.kind_mut()?;
+            let mut node_kind = match node.kind_mut() {
+                Ok(kind) => kind,
+                Err(err) => {
+                    problems.node(&node.id, err);
+                    continue;
+                }
+            };
             let input_mappings: Vec<_> = match &mut node_kind {
                 NodeKindMut::Standard { path: _, inputs } => inputs.values_mut().collect(),
                 NodeKindMut::Runtime(node) => node
@@ -64,7 +111,7 @@ impl Descriptor {
             for mapping in input_mappings
                 .into_iter()
                 .filter_map(|i| match &mut i.mapping {
-                    InputMapping::Timer { .. } => None,
+                    InputMapping::Timer(_) => None,
                     InputMapping::User(m) => Some(m),
                 })
             {
@@ -83,6 +130,7 @@ impl Descriptor {
                     run_config: NodeRunConfig {
                         inputs: node.inputs,
                         outputs: node.outputs,
+                        output_types: node.output_types,
                     },
                     envs: None,
                 }),
@@ -102,11 +150,39 @@ impl Descriptor {
                 description: node.description,
                 env: node.env,
                 deploy: ResolvedDeploy::new(node.deploy, self),
+                restart: node.restart.unwrap_or_default(),
+                depends_on: node.depends_on,
+                ready_signal: node.ready_signal,
                 kind,
             });
         }
 
-        Ok(resolved)
+        resolved
+    }
+
+    /// Expands replicas and wildcard inputs, skipping and reporting invalid nodes.
+    fn expand_nodes(&self, problems: &mut Problems) -> Vec<Node> {
+        let mut nodes = replicas::expand_replicas(&self.nodes, problems);
+        nodes.retain(|node| match node.kind() {
+            Ok(_) => true,
+            Err(err) => {
+                problems.node(&node.id, err);
+                false
+            }
+        });
+        replicas::expand_wildcard_inputs(&mut nodes, problems);
+
+        let mut ids = BTreeSet::new();
+        for node in &nodes {
+            if !ids.insert(&node.id) {
+                problems.node(
+                    &node.id,
+                    eyre!("there are multiple nodes with ID `{}`", node.id),
+                );
+            }
+        }
+
+        nodes
     }
 
     pub fn visualize_as_mermaid(&self) -> eyre::Result<String> {
@@ -120,21 +196,116 @@ impl Descriptor {
         let buf = tokio::fs::read(path)
             .await
             .context("failed to open given file")?;
-        Descriptor::parse(buf)
+        let mut descriptor = Descriptor::parse(buf)?;
+        subgraph::load_includes(&mut descriptor, path, &BTreeMap::new())
+            .context("failed to load included sub-graphs")?;
+        Ok(descriptor)
     }
 
     pub fn blocking_read(path: &Path) -> eyre::Result<Descriptor> {
+        Self::blocking_read_with_variables(path, &BTreeMap::new())
+    }
+
+    /// Reads the descriptor, overriding the default values of the given variables.
+    ///
+    /// The overrides also apply to included sub-graphs that define variables with
+    /// the same name.
+    pub fn blocking_read_with_variables(
+        path: &Path,
+        variables: &BTreeMap<String, String>,
+    ) -> eyre::Result<Descriptor> {
         let buf = std::fs::read(path).context("failed to open given file")?;
-        Descriptor::parse(buf)
+        let mut descriptor = Descriptor::substitute_and_parse(buf, variables)?;
+        let subgraph_variables = subgraph::load_includes(&mut descriptor, path, variables)
+            .context("failed to load included sub-graphs")?;
+        variables::check_overrides(
+            variables,
+            descriptor.variables.keys().chain(&subgraph_variables),
+        )?;
+        Ok(descriptor)
     }
 
     pub fn parse(buf: Vec<u8>) -> eyre::Result<Descriptor> {
-        serde_yaml::from_slice(&buf).context("failed to parse given descriptor")
+        Self::parse_with_variables(buf, &BTreeMap::new())
+    }
+
+    pub fn parse_with_variables(
+        buf: Vec<u8>,
+        variables: &BTreeMap<String, String>,
+    ) -> eyre::Result<Descriptor> {
+        let descriptor = Self::substitute_and_parse(buf, variables)?;
+        variables::check_overrides(variables, descriptor.variables.keys())?;
+        Ok(descriptor)
+    }
+
+    /// Parses the descriptor, ignoring overrides of variables that it doesn't define.
+    fn substitute_and_parse(
+        buf: Vec<u8>,
+        variables: &BTreeMap<String, String>,
+    ) -> eyre::Result<Descriptor> {
+        let mut document: serde_yaml::Value =
+            serde_yaml::from_slice(&buf).context("failed to parse given descriptor")?;
+        variables::substitute(&mut document, variables)
+            .context("failed to substitute dataflow variables")?;
+        let mut descriptor = match Descriptor::deserialize(&document) {
+            Ok(descriptor) => descriptor,
+            Err(err) => {
+                let source_map = std::str::from_utf8(&buf)
+                    .map(SourceMap::parse)
+                    .unwrap_or_default();
+                let errors = validate::check_document(&document, &source_map);
+                if errors.len() > 1 {
+                    return Err(ValidationErrors(errors))
+                        .context("failed to parse given descriptor");
+                }
+                // values lose their location on substitution -> parse the original
+                // source again to report where the error occurred
+                let located = (!variables::contains_references(&buf))
+                    .then(|| serde_yaml::from_slice::<Descriptor>(&buf).err())
+                    .flatten()
+                    .filter(|err| err.location().is_some());
+                return Err(located.unwrap_or(err)).context("failed to parse given descriptor");
+            }
+        };
+        if let Ok(source) = std::str::from_utf8(&buf) {
+            descriptor.source_map = SourceMap::parse(source);
+        }
+        Ok(descriptor)
     }
 
     pub fn check(&self, working_dir: &Path) -> eyre::Result<()> {
         validate::check_dataflow(self, working_dir).wrap_err("Dataflow could not be validated.")
     }
+
+    /// Looks for parts of the dataflow graph that are valid, but likely not
+    /// intended, such as unused outputs or feedback cycles that can deadlock.
+    ///
+    /// The found problems are returned as warnings.
+    pub fn analyze(&self) -> eyre::Result<Vec<ValidationError>> {
+        let nodes = self.resolve_aliases_and_set_defaults()?;
+        Ok(analyze::analyze_graph(self, &nodes))
+    }
+
+    /// Returns the JSON schema of the dataflow descriptor format, e.g. for
+    /// validation and auto-completion in editors.
+    pub fn json_schema() -> String {
+        let mut schema = schemars::schema_for!(Descriptor);
+        schema
+            .schema
+            .extensions
+            .insert("version".into(), env!("CARGO_PKG_VERSION").into());
+        let raw_schema =
+            serde_json::to_string_pretty(&schema).expect("Could not serialize schema to json");
+
+        // Add additional properties to True, as #[derive(transparent)] of enums are not well handled.
+        //
+        // 'OneOf' such as Custom Nodes, Operators and Single Operators overwrite property values of the initial struct `Nodes`.`
+        // which make the original properties such as `id` and `name` not validated by IDE extensions.
+        raw_schema.replace(
+            "\"additionalProperties\": false",
+            "\"additionalProperties\": true",
+        )
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
@@ -143,6 +314,147 @@ pub struct Deploy {
     pub machine: Option<String>,
 }
 
+/// Whether the daemon restarts a node after it exited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartMode {
+    #[default]
+    Never,
+    /// Restart the node if it exited with an error code or signal.
+    OnFailure,
+    /// Restart the node whenever it exits, until the dataflow is stopped.
+    Always,
+}
+
+/// Restart policy of a node.
+///
+/// Can be given as a plain mode (e.g. `restart: on-failure`) or with options:
+///
+/// ```yaml
+/// restart:
+///   mode: on-failure
+///   max_retries: 3
+///   backoff_ms: 500
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RestartPolicyDef", into = "RestartPolicyDef")]
+pub struct RestartPolicy {
+    pub mode: RestartMode,
+    /// Maximum number of restarts. Unlimited if not set.
+    pub max_retries: Option<u32>,
+    /// Delay before the first restart, doubled for every further restart (up
+    /// to [`RestartPolicy::MAX_BACKOFF`]).
+    pub backoff: Duration,
+}
+
+impl RestartPolicy {
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+    pub const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+    /// Returns whether the node should be restarted after it exited.
+    ///
+    /// `restarts` is the number of times the node was already restarted.
+    pub fn should_restart(&self, failed: bool, restarts: u32) -> bool {
+        let mode_matches = match self.mode {
+            RestartMode::Never => false,
+            RestartMode::OnFailure => failed,
+            RestartMode::Always => true,
+        };
+        mode_matches && self.max_retries.map(|max| restarts < max).unwrap_or(true)
+    }
+
+    /// Delay before the next restart.
+    pub fn backoff(&self, restarts: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(restarts))
+            .unwrap_or(Self::MAX_BACKOFF)
+            .min(Self::MAX_BACKOFF)
+    }
+}
+
+impl JsonSchema for RestartPolicy {
+    fn schema_name() -> String {
+        "RestartPolicy".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        RestartPolicyDef::json_schema(gen)
+    }
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartMode::default().into()
+    }
+}
+
+impl From<RestartMode> for RestartPolicy {
+    fn from(mode: RestartMode) -> Self {
+        Self {
+            mode,
+            max_retries: None,
+            backoff: Self::DEFAULT_BACKOFF,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum RestartPolicyDef {
+    Mode(RestartMode),
+    WithOptions(RestartOptions),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct RestartOptions {
+    mode: RestartMode,
+    #[serde(default)]
+    max_retries: Option<u32>,
+    #[serde(default)]
+    backoff_ms: Option<u64>,
+}
+
+impl From<RestartPolicyDef> for RestartPolicy {
+    fn from(value: RestartPolicyDef) -> Self {
+        match value {
+            RestartPolicyDef::Mode(mode) => mode.into(),
+            RestartPolicyDef::WithOptions(RestartOptions {
+                mode,
+                max_retries,
+                backoff_ms,
+            }) => Self {
+                mode,
+                max_retries,
+                backoff: backoff_ms
+                    .map(Duration::from_millis)
+                    .unwrap_or(Self::DEFAULT_BACKOFF),
+            },
+        }
+    }
+}
+
+impl From<RestartPolicy> for RestartPolicyDef {
+    fn from(value: RestartPolicy) -> Self {
+        match value {
+            RestartPolicy {
+                mode,
+                max_retries: None,
+                backoff: RestartPolicy::DEFAULT_BACKOFF,
+            } => Self::Mode(mode),
+            RestartPolicy {
+                mode,
+                max_retries,
+                backoff,
+            } => Self::WithOptions(RestartOptions {
+                mode,
+                max_retries,
+                backoff_ms: Some(backoff.as_millis() as u64),
+            }),
+        }
+    }
+}
+
 /// Dora Node
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -157,7 +469,6 @@ pub struct Node {
     pub env: Option<BTreeMap<String, EnvValue>>,
 
     /// Unstable machine deployment configuration
-    #[schemars(skip)]
     #[serde(default, rename = "_unstable_deploy")]
     pub deploy: Deploy,
 
@@ -167,6 +478,55 @@ pub struct Node {
     custom: Option<CustomNode>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     operator: Option<SingleOperatorDefinition>,
+    /// Path to another dataflow descriptor that should be included as a sub-graph.
+    ///
+    /// The path is relative to the directory of this descriptor file. The nodes of
+    /// the sub-graph are added to the dataflow with `<id>.<sub_node_id>` IDs. Use
+    /// `inputs` to connect the inputs that the sub-graph exposes and `<id>/<output>`
+    /// to subscribe to its exposed outputs.
+    ///
+    /// Relative node and operator paths in the sub-graph are resolved relative to the
+    /// directory of the sub-graph file. Build commands run in the working directory
+    /// of the dataflow, like for all other nodes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<String>,
+    /// The sub-graph descriptor loaded from the `include` path.
+    ///
+    /// Only set while loading, `Descriptor::read` replaces `include` nodes by
+    /// the nodes of their sub-graph.
+    #[schemars(skip)]
+    #[serde(skip)]
+    pub included: Option<Box<Descriptor>>,
+    /// Number of instances of this node that should be started.
+    ///
+    /// Each replica gets the ID `<id>_<index>`, unless the ID contains an `{index}`
+    /// placeholder. The placeholder is also replaced in the `name`, `args`, `env`
+    /// values, and input sources of each replica. Other nodes can subscribe to all
+    /// replicas at once using a wildcard source such as `camera_*/image`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<usize>,
+    /// Whether the daemon should restart this node after it exited (`never`,
+    /// `on-failure`, or `always`). Defaults to `never`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartPolicy>,
+    /// Nodes that must be ready before this node is started.
+    ///
+    /// By default, a node is ready once it has subscribed to its event stream,
+    /// i.e. right after it initialized its dora connection. Nodes that set
+    /// `ready_signal` are only ready once they signal it explicitly. This can
+    /// be used to delay nodes until e.g. a slow model server has finished
+    /// loading.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub depends_on: BTreeSet<NodeId>,
+    /// Whether the node signals its readiness explicitly, e.g. through
+    /// `DoraNode::mark_ready` of the Rust node API.
+    ///
+    /// Nodes that depend on this node (see `depends_on`) are only started
+    /// once it signals that it is ready. To make this possible, the node
+    /// does not wait for the other nodes of the dataflow when it initializes
+    /// its dora connection. Only supported for custom nodes.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ready_signal: bool,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
@@ -180,24 +540,37 @@ pub struct Node {
     pub inputs: BTreeMap<DataId, Input>,
     #[serde(default)]
     pub outputs: BTreeSet<DataId>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(with = "BTreeMap<String, String>")]
+    pub output_types: BTreeMap<DataId, DataType>,
 }
 
 impl Node {
     pub fn kind(&self) -> eyre::Result<NodeKind> {
-        match (&self.path, &self.operators, &self.custom, &self.operator) {
-            (None, None, None, None) => {
+        match (
+            &self.path,
+            &self.operators,
+            &self.custom,
+            &self.operator,
+            &self.include,
+        ) {
+            (None, None, None, None, None) => {
                 eyre::bail!(
-                    "node `{}` requires a `path`, `custom`, or `operators` field",
+                    "node `{}` requires a `path`, `custom`, `operators`, or `include` field",
                     self.id
                 )
             }
-            (None, None, None, Some(operator)) => Ok(NodeKind::Operator(operator)),
-            (None, None, Some(custom), None) => Ok(NodeKind::Custom(custom)),
-            (None, Some(runtime), None, None) => Ok(NodeKind::Runtime(runtime)),
-            (Some(path), None, None, None) => Ok(NodeKind::Standard(path)),
+            (None, None, None, Some(operator), None) => Ok(NodeKind::Operator(operator)),
+            (None, None, Some(custom), None, None) => Ok(NodeKind::Custom(custom)),
+            (None, Some(runtime), None, None, None) => Ok(NodeKind::Runtime(runtime)),
+            (Some(path), None, None, None, None) => Ok(NodeKind::Standard(path)),
+            (None, None, None, None, Some(include)) => match &self.included {
+                Some(subgraph) => Ok(NodeKind::Subgraph(subgraph)),
+                None => eyre::bail!("sub-graph `{include}` of node `{}` was not loaded", self.id),
+            },
             _ => {
                 eyre::bail!(
-                    "node `{}` has multiple exclusive fields set, only one of `path`, `custom`, `operators` and `operator` is allowed",
+                    "node `{}` has multiple exclusive fields set, only one of `path`, `custom`, `operators`, `operator` and `include` is allowed",
                     self.id
                 )
             }
@@ -229,8 +602,53 @@ impl Node {
                 .as_mut()
                 .map(NodeKindMut::Operator)
                 .ok_or_eyre("no operator"),
+            NodeKind::Subgraph(_) => bail!("sub-graph node `{}` was not resolved", self.id),
         }
     }
+
+    fn inputs_mut(&mut self) -> eyre::Result<Vec<&mut Input>> {
+        let inputs = match self.kind_mut()? {
+            NodeKindMut::Standard { path: _, inputs } => inputs.values_mut().collect(),
+            NodeKindMut::Runtime(node) => node
+                .operators
+                .iter_mut()
+                .flat_map(|op| op.config.inputs.values_mut())
+                .collect(),
+            NodeKindMut::Custom(node) => node.run_config.inputs.values_mut().collect(),
+            NodeKindMut::Operator(operator) => operator.config.inputs.values_mut().collect(),
+        };
+        Ok(inputs)
+    }
+
+    /// Adds the given input, using `<operator_id>/<input_id>` for runtime nodes.
+    fn insert_input(&mut self, input_id: &str, input: Input) -> eyre::Result<()> {
+        let node_id = self.id.clone();
+        let (inputs, input_id) = match self.kind_mut()? {
+            NodeKindMut::Standard { path: _, inputs } => (inputs, input_id),
+            NodeKindMut::Custom(node) => (&mut node.run_config.inputs, input_id),
+            NodeKindMut::Operator(operator) => (&mut operator.config.inputs, input_id),
+            NodeKindMut::Runtime(node) => {
+                let (operator_id, input_id) = input_id.split_once('/').ok_or_else(|| {
+                    eyre!("inputs of runtime node `{node_id}` must have the form `<operator>/<input>`")
+                })?;
+                let operator = node
+                    .operators
+                    .iter_mut()
+                    .find(|op| op.id.as_ref() == operator_id)
+                    .ok_or_else(|| {
+                        eyre!("runtime node `{node_id}` has no operator `{operator_id}`")
+                    })?;
+                (&mut operator.config.inputs, input_id)
+            }
+        };
+        if inputs
+            .insert(DataId::from(input_id.to_owned()), input)
+            .is_some()
+        {
+            bail!("input `{node_id}/{input_id}` is defined multiple times");
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -240,6 +658,8 @@ pub enum NodeKind<'a> {
     Runtime(&'a RuntimeNode),
     Custom(&'a CustomNode),
     Operator(&'a SingleOperatorDefinition),
+    /// Included sub-graph
+    Subgraph(&'a Descriptor),
 }
 
 #[derive(Debug)]
@@ -264,6 +684,14 @@ pub struct ResolvedNode {
     #[serde(default)]
     pub deploy: ResolvedDeploy,
 
+    #[serde(default)]
+    pub restart: RestartPolicy,
+
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub depends_on: BTreeSet<NodeId>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ready_signal: bool,
+
     #[serde(flatten)]
     pub kind: CoreNodeKind,
 }
@@ -349,6 +777,10 @@ pub struct OperatorConfig {
     pub inputs: BTreeMap<DataId, Input>,
     #[serde(default)]
     pub outputs: BTreeSet<DataId>,
+    /// Optional Arrow data types of the outputs, e.g. `UInt8` or `Float32`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[schemars(with = "BTreeMap<String, String>")]
+    pub output_types: BTreeMap<DataId, DataType>,
 
     #[serde(flatten)]
     pub source: OperatorSource,
@@ -364,10 +796,9 @@ pub struct OperatorConfig {
 pub enum OperatorSource {
     SharedLibrary(String),
     Python(PythonSource),
-    #[schemars(skip)]
     Wasm(String),
 }
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(
     deny_unknown_fields,
     from = "PythonSourceDef",
@@ -378,6 +809,16 @@ pub struct PythonSource {
     pub conda_env: Option<String>,
 }
 
+impl JsonSchema for PythonSource {
+    fn schema_name() -> String {
+        "PythonSource".into()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        PythonSourceDef::json_schema(gen)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum PythonSourceDef {
@@ -495,3 +936,52 @@ impl fmt::Display for EnvValue {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_restart_policy() {
+        let policy: RestartPolicy = serde_yaml::from_str("on-failure").unwrap();
+        assert_eq!(policy, RestartMode::OnFailure.into());
+
+        let policy: RestartPolicy =
+            serde_yaml::from_str("{mode: always, max_retries: 3, backoff_ms: 500}").unwrap();
+        assert_eq!(
+            policy,
+            RestartPolicy {
+                mode: RestartMode::Always,
+                max_retries: Some(3),
+                backoff: Duration::from_millis(500),
+            }
+        );
+        let roundtrip: RestartPolicy =
+            serde_yaml::from_str(&serde_yaml::to_string(&policy).unwrap()).unwrap();
+        assert_eq!(roundtrip, policy);
+
+        // misspelled options must not be ignored silently
+        assert!(serde_yaml::from_str::<RestartPolicy>("{mode: always, max_retry: 3}").is_err());
+    }
+
+    #[test]
+    fn restart_limits_and_backoff() {
+        let policy = RestartPolicy {
+            mode: RestartMode::OnFailure,
+            max_retries: Some(2),
+            backoff: Duration::from_secs(1),
+        };
+        assert!(policy.should_restart(true, 0));
+        assert!(policy.should_restart(true, 1));
+        assert!(!policy.should_restart(true, 2));
+        assert!(!policy.should_restart(false, 0));
+
+        assert!(RestartPolicy::from(RestartMode::Always).should_restart(false, 1000));
+        assert!(!RestartPolicy::from(RestartMode::Never).should_restart(true, 0));
+
+        assert_eq!(policy.backoff(0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(8));
+        assert_eq!(policy.backoff(5), RestartPolicy::MAX_BACKOFF);
+        assert_eq!(policy.backoff(u32::MAX), RestartPolicy::MAX_BACKOFF);
+    }
+}
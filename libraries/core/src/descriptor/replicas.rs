@@ -0,0 +1,216 @@
+use super::{validate::Problems, EnvValue, Node, NodeKindMut};
+use crate::config::{DataId, Input, InputMapping, NodeId, UserInputMapping};
+use eyre::{bail, eyre};
+use std::collections::BTreeMap;
+
+/// Placeholder that is replaced by the replica index.
+pub const REPLICA_INDEX_PLACEHOLDER: &str = "{index}";
+
+/// Wildcard that can be used in input sources to subscribe to multiple nodes.
+const SOURCE_WILDCARD: char = '*';
+
+/// Expands all nodes with a `replicas` count into one node per replica.
+///
+/// Nodes that cannot be expanded are reported to `problems` and skipped.
+pub(super) fn expand_replicas(nodes: &[Node], problems: &mut Problems) -> Vec<Node> {
+    let mut expanded = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let replicas = match node.replicas {
+            None => Ok(vec![node.clone()]),
+            Some(_) if node.include.is_some() => {
+                Err(eyre!("sub-graph node `{}` cannot have replicas", node.id))
+            }
+            Some(0) => Err(eyre!("node `{}` must have at least one replica", node.id)),
+            Some(replicas) => (0..replicas).map(|index| replica(node, index)).collect(),
+        };
+        match replicas {
+            Ok(replicas) => expanded.extend(replicas),
+            Err(err) => problems.node(&node.id, err),
+        }
+    }
+    expanded
+}
+
+fn replica(node: &Node, index: usize) -> eyre::Result<Node> {
+    let index = index.to_string();
+    let substitute = |s: &str| s.replace(REPLICA_INDEX_PLACEHOLDER, &index);
+
+    let mut replica = node.clone();
+    replica.replicas = None;
+    let id = node.id.to_string();
+    replica.id = if id.contains(REPLICA_INDEX_PLACEHOLDER) {
+        NodeId::from(substitute(&id))
+    } else {
+        NodeId::from(format!("{id}_{index}"))
+    };
+    replica.name = node.name.as_deref().map(substitute);
+    replica.args = node.args.as_deref().map(substitute);
+    for value in replica.env.iter_mut().flat_map(|env| env.values_mut()) {
+        if let EnvValue::String(s) = value {
+            *s = substitute(s);
+        }
+    }
+    replica.depends_on = node
+        .depends_on
+        .iter()
+        .map(|id| NodeId::from(substitute(&id.to_string())))
+        .collect();
+    if let Some(custom) = &mut replica.custom {
+        custom.args = custom.args.as_deref().map(substitute);
+    }
+    for input in replica.inputs_mut()? {
+        if let InputMapping::User(mapping) = &mut input.mapping {
+            *mapping = UserInputMapping {
+                source: NodeId::from(substitute(&mapping.source.to_string())),
+                output: DataId::from(substitute(&mapping.output)),
+            };
+        }
+    }
+    Ok(replica)
+}
+
+/// Expands inputs with a wildcard source (e.g. `camera_*/image`) into one input
+/// per matching node.
+///
+/// The expanded inputs are named `<input_id>/<source_node_id>`.
+pub(super) fn expand_wildcard_inputs(nodes: &mut [Node], problems: &mut Problems) {
+    let node_ids: Vec<_> = nodes.iter().map(|n| n.id.to_string()).collect();
+    for node in nodes.iter_mut() {
+        let node_id = node.id.clone();
+        let result = node.kind_mut().and_then(|kind| match kind {
+            NodeKindMut::Standard { path: _, inputs } => expand_inputs(inputs, &node_id, &node_ids),
+            NodeKindMut::Custom(custom) => {
+                expand_inputs(&mut custom.run_config.inputs, &node_id, &node_ids)
+            }
+            NodeKindMut::Operator(operator) => {
+                expand_inputs(&mut operator.config.inputs, &node_id, &node_ids)
+            }
+            NodeKindMut::Runtime(runtime) => runtime
+                .operators
+                .iter_mut()
+                .try_for_each(|op| expand_inputs(&mut op.config.inputs, &node_id, &node_ids)),
+        });
+        if let Err(err) = result {
+            problems.node(&node_id, err);
+        }
+    }
+}
+
+fn expand_inputs(
+    inputs: &mut BTreeMap<DataId, Input>,
+    node_id: &NodeId,
+    node_ids: &[String],
+) -> eyre::Result<()> {
+    let wildcard_inputs: Vec<_> = inputs
+        .iter()
+        .filter(|(_, input)| match &input.mapping {
+            InputMapping::User(mapping) => mapping.source.to_string().contains(SOURCE_WILDCARD),
+            InputMapping::Timer(_) => false,
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    for input_id in wildcard_inputs {
+        let input = inputs.remove(&input_id).expect("input was just found");
+        let InputMapping::User(mapping) = &input.mapping else {
+            unreachable!("only user inputs can have wildcards")
+        };
+        let pattern = mapping.source.to_string();
+        let sources: Vec<_> = node_ids
+            .iter()
+            .filter(|id| wildcard_match(&pattern, id))
+            .collect();
+        if sources.is_empty() {
+            bail!("input `{node_id}/{input_id}` with source `{pattern}` does not match any node");
+        }
+        for source in sources {
+            let mut expanded = input.clone();
+            expanded.mapping = InputMapping::User(UserInputMapping {
+                source: NodeId::from(source.clone()),
+                output: mapping.output.clone(),
+            });
+            let expanded_id = DataId::from(format!("{input_id}/{source}"));
+            if inputs.insert(expanded_id.clone(), expanded).is_some() {
+                bail!("input `{node_id}/{expanded_id}` is defined multiple times");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    let mut parts = pattern.split(SOURCE_WILDCARD);
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = s.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<_> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{Descriptor, Severity};
+
+    #[test]
+    fn replicas_with_wildcard_input() {
+        let descriptor = Descriptor::parse(
+            br#"
+nodes:
+  - id: camera
+    path: camera
+    replicas: 3
+    args: --device /dev/video{index}
+    outputs: [image]
+  - id: detector_{index}
+    path: detector
+    replicas: 2
+    inputs:
+      image: camera_{index}/image
+    outputs: [bbox]
+  - id: plot
+    path: plot
+    inputs:
+      bbox: detector_*/bbox
+"#
+            .to_vec(),
+        )
+        .unwrap();
+
+        let mut problems = Problems::new(&descriptor.source_map, Severity::Error);
+        let mut nodes = expand_replicas(&descriptor.nodes, &mut problems);
+        expand_wildcard_inputs(&mut nodes, &mut problems);
+        problems.into_result().unwrap();
+        let ids: Vec<_> = nodes.iter().map(|n| n.id.to_string()).collect();
+        assert_eq!(
+            ids,
+            [
+                "camera_0",
+                "camera_1",
+                "camera_2",
+                "detector_0",
+                "detector_1",
+                "plot"
+            ]
+        );
+        assert_eq!(nodes[2].args.as_deref(), Some("--device /dev/video2"));
+        assert_eq!(
+            nodes[4].inputs[&DataId::from("image".to_owned())]
+                .mapping
+                .to_string(),
+            "camera_1/image"
+        );
+        let plot_inputs: Vec<_> = nodes[5].inputs.keys().map(|id| id.to_string()).collect();
+        assert_eq!(plot_inputs, ["bbox/detector_0", "bbox/detector_1"]);
+    }
+}
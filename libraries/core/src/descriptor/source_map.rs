@@ -0,0 +1,224 @@
+use crate::config::{DataId, NodeId};
+use std::{collections::BTreeMap, fmt};
+use yaml_rust::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::Marker,
+};
+
+/// A position in the YAML source of a descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct SourceLocation {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub column: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+impl From<Marker> for SourceLocation {
+    fn from(marker: Marker) -> Self {
+        Self {
+            line: marker.line(),
+            column: marker.col() + 1,
+        }
+    }
+}
+
+/// Locations of the values in the YAML source of a descriptor, keyed by their
+/// path (e.g. `nodes[0].inputs.tick`).
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    locations: BTreeMap<String, SourceLocation>,
+    /// Index in the `nodes` list by node ID
+    node_indices: BTreeMap<String, usize>,
+}
+
+impl SourceMap {
+    /// Records the locations of the given YAML document.
+    ///
+    /// Syntax errors are ignored because they are already reported when the
+    /// document is deserialized.
+    pub fn parse(source: &str) -> Self {
+        let mut builder = Builder::default();
+        let _ = Parser::new(source.chars()).load(&mut builder, false);
+        builder.map
+    }
+
+    pub fn get(&self, path: &str) -> Option<SourceLocation> {
+        self.locations.get(path).copied()
+    }
+
+    /// Returns the location of the given node.
+    ///
+    /// Nodes of included sub-graphs are mapped to their `include` node and
+    /// replicas to the node that they were created from.
+    pub fn node(&self, node_id: &NodeId) -> Option<SourceLocation> {
+        let index = self.node_index(node_id)?;
+        self.get(&format!("nodes[{index}]"))
+    }
+
+    /// Returns the location of the given node input, falling back to the location
+    /// of the node.
+    pub fn input(&self, node_id: &NodeId, input_id: &DataId) -> Option<SourceLocation> {
+        let suffix = format!(".inputs.{input_id}");
+        let input = self.node_index(node_id).and_then(|index| {
+            let prefix = format!("nodes[{index}]");
+            self.locations
+                .range(prefix.clone()..)
+                .take_while(|(path, _)| path.starts_with(&prefix))
+                .find(|(path, _)| path.ends_with(&suffix))
+                .map(|(_, location)| *location)
+        });
+        input.or_else(|| self.node(node_id))
+    }
+
+    fn node_index(&self, node_id: &NodeId) -> Option<usize> {
+        let id = node_id.to_string();
+        if let Some(index) = self.node_indices.get(&id) {
+            return Some(*index);
+        }
+        // node of an included sub-graph
+        if let Some((include_id, _)) = id.split_once(super::NAMESPACE_SEPARATOR) {
+            if let Some(index) = self.node_indices.get(include_id) {
+                return Some(*index);
+            }
+        }
+        // replica, named `<id>_<index>` or using the `{index}` placeholder
+        self.node_indices
+            .iter()
+            .find(|(template, _)| is_replica_of(&id, template))
+            .map(|(_, index)| *index)
+    }
+}
+
+fn is_replica_of(id: &str, template: &str) -> bool {
+    let is_index = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    match template.split_once(super::REPLICA_INDEX_PLACEHOLDER) {
+        Some((prefix, suffix)) => id
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(suffix))
+            .map_or(false, is_index),
+        None => id
+            .strip_prefix(template)
+            .and_then(|rest| rest.strip_prefix('_'))
+            .map_or(false, is_index),
+    }
+}
+
+enum Frame {
+    Mapping { key: Option<String> },
+    Sequence { index: usize },
+}
+
+#[derive(Default)]
+struct Builder {
+    /// Path and state of the currently open mappings and sequences
+    stack: Vec<(String, Frame)>,
+    map: SourceMap,
+}
+
+impl Builder {
+    /// Returns the path of the value that starts at the given marker and
+    /// records its location.
+    fn start_value(&mut self, marker: Marker) -> String {
+        let path = match self.stack.last_mut() {
+            None => String::new(),
+            Some((parent, Frame::Mapping { key })) => join(parent, &key.take().unwrap_or_default()),
+            Some((parent, Frame::Sequence { index })) => {
+                let path = format!("{parent}[{index}]");
+                *index += 1;
+                path
+            }
+        };
+        self.map
+            .locations
+            .entry(path.clone())
+            .or_insert_with(|| marker.into());
+        path
+    }
+}
+
+impl MarkedEventReceiver for Builder {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        match event {
+            Event::Scalar(value, ..) => {
+                if let Some((parent, Frame::Mapping { key: key @ None })) = self.stack.last_mut() {
+                    // mapping key -> point to the key instead of the value
+                    let path = join(parent, &value);
+                    self.map.locations.insert(path, marker.into());
+                    *key = Some(value);
+                    return;
+                }
+                let path = self.start_value(marker);
+                if let Some(index) = path
+                    .strip_prefix("nodes[")
+                    .and_then(|rest| rest.strip_suffix("].id"))
+                    .and_then(|index| index.parse().ok())
+                {
+                    self.map.node_indices.insert(value, index);
+                }
+            }
+            Event::MappingStart(_) => {
+                let path = self.start_value(marker);
+                self.stack.push((path, Frame::Mapping { key: None }));
+            }
+            Event::SequenceStart(_) => {
+                let path = self.start_value(marker);
+                self.stack.push((path, Frame::Sequence { index: 0 }));
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            }
+            Event::Alias(_) => {
+                self.start_value(marker);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn join(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{parent}.{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_and_input_locations() {
+        let map = SourceMap::parse(
+            "nodes:
+  - id: camera
+    path: camera.py
+    outputs: [image]
+  - id: detector_{index}
+    replicas: 2
+    custom:
+      source: detector.py
+      inputs:
+        image: camera/image
+",
+        );
+        let location = |line, column| Some(SourceLocation { line, column });
+
+        assert_eq!(map.get("nodes[0].path"), location(3, 5));
+        assert_eq!(map.node(&NodeId::from("camera".to_owned())), location(2, 7));
+        assert_eq!(
+            map.input(
+                &NodeId::from("detector_1".to_owned()),
+                &DataId::from("image".to_owned())
+            ),
+            location(10, 9)
+        );
+    }
+}
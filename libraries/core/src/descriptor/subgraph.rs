@@ -0,0 +1,477 @@
+use super::{
+    source_is_url,
+    validate::{Problems, Severity},
+    Descriptor, Node, OperatorSource, SHELL_SOURCE,
+};
+use crate::config::{DataId, InputMapping, NodeId, UserInputMapping};
+use eyre::{bail, eyre, Context, ContextCompat};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Separator between the ID of an `include` node and the IDs of the nodes of
+/// the included sub-graph.
+///
+/// For example, node `detector` of a sub-graph included as `perception` gets
+/// the ID `perception.detector`.
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+/// Loads the descriptors referenced by `include` fields, recursively, and
+/// replaces the `include` nodes by the nodes of their sub-graphs.
+///
+/// Include paths are relative to the directory of the descriptor file that
+/// contains them.
+///
+/// The given variable overrides are applied to all sub-graphs that define a
+/// variable of the same name. Returns the names of the variables that are
+/// defined by the sub-graphs.
+pub(super) fn load_includes(
+    descriptor: &mut Descriptor,
+    path: &Path,
+    variables: &BTreeMap<String, String>,
+) -> eyre::Result<BTreeSet<String>> {
+    let mut defined = BTreeSet::new();
+    load_includes_inner(descriptor, path, variables, &mut defined, &mut Vec::new())?;
+    Ok(defined)
+}
+
+fn load_includes_inner(
+    descriptor: &mut Descriptor,
+    path: &Path,
+    variables: &BTreeMap<String, String>,
+    defined: &mut BTreeSet<String>,
+    stack: &mut Vec<PathBuf>,
+) -> eyre::Result<()> {
+    let path = path
+        .canonicalize()
+        .wrap_err_with(|| format!("failed to canonicalize `{}`", path.display()))?;
+    if stack.contains(&path) {
+        bail!("`{}` includes itself", path.display());
+    }
+    let dir = path
+        .parent()
+        .ok_or_else(|| eyre!("descriptor path has no parent dir"))?
+        .to_owned();
+    stack.push(path);
+
+    for node in &mut descriptor.nodes {
+        let Some(include) = &node.include else {
+            continue;
+        };
+        let include_path = dir.join(include);
+        let buf = std::fs::read(&include_path).wrap_err_with(|| {
+            format!(
+                "failed to read sub-graph `{}` included by node `{}`",
+                include_path.display(),
+                node.id
+            )
+        })?;
+        let mut subgraph = Descriptor::substitute_and_parse(buf, variables)
+            .wrap_err_with(|| format!("failed to parse sub-graph of node `{}`", node.id))?;
+        defined.extend(subgraph.variables.keys().cloned());
+        let include_path = include_path
+            .canonicalize()
+            .wrap_err_with(|| format!("failed to canonicalize `{}`", include_path.display()))?;
+        if let Some(subgraph_dir) = include_path.parent() {
+            rebase_paths(&mut subgraph, subgraph_dir);
+        }
+        load_includes_inner(&mut subgraph, &include_path, variables, defined, stack)?;
+        node.included = Some(Box::new(subgraph));
+    }
+
+    stack.pop();
+    inline_includes(descriptor)
+}
+
+/// Makes the relative node and operator paths of a sub-graph absolute, using the
+/// directory of the sub-graph file as base.
+///
+/// This way, the paths don't depend on the working directory of the dataflow
+/// that includes the sub-graph.
+fn rebase_paths(subgraph: &mut Descriptor, dir: &Path) {
+    for node in &mut subgraph.nodes {
+        if let Some(path) = &mut node.path {
+            rebase_path(path, dir);
+        }
+        if let Some(custom) = &mut node.custom {
+            rebase_path(&mut custom.source, dir);
+        }
+        let operators = node
+            .operators
+            .iter_mut()
+            .flat_map(|runtime| runtime.operators.iter_mut().map(|op| &mut op.config))
+            .chain(node.operator.iter_mut().map(|op| &mut op.config));
+        for config in operators {
+            match &mut config.source {
+                OperatorSource::SharedLibrary(path) | OperatorSource::Wasm(path) => {
+                    rebase_path(path, dir)
+                }
+                OperatorSource::Python(python) => rebase_path(&mut python.source, dir),
+            }
+        }
+    }
+}
+
+fn rebase_path(source: &mut String, dir: &Path) {
+    if source == SHELL_SOURCE || source_is_url(source) {
+        return;
+    }
+    let path = Path::new(source.as_str());
+    // keep bare names such as `python` that refer to executables in `PATH`
+    let is_file = path.components().count() > 1 || dir.join(path).exists();
+    if path.is_relative() && is_file {
+        *source = dir.join(path).to_string_lossy().into_owned();
+    }
+}
+
+/// Replaces all `include` nodes of the given descriptor by the nodes of their
+/// loaded sub-graphs.
+///
+/// The sub-graph nodes are namespaced using the ID of the `include` node. Inputs
+/// of the `include` node are forwarded to the sub-graph nodes that the
+/// sub-graph exposes them to. Inputs that refer to an exposed output of a
+/// sub-graph are redirected to the sub-graph node that provides it.
+fn inline_includes(descriptor: &mut Descriptor) -> eyre::Result<()> {
+    if descriptor.nodes.iter().all(|node| node.include.is_none()) {
+        return Ok(());
+    }
+
+    let mut problems = Problems::new(&descriptor.source_map, Severity::Error);
+    let mut flat = Vec::new();
+    let mut exposed_outputs: BTreeMap<NodeId, BTreeMap<DataId, UserInputMapping>> = BTreeMap::new();
+
+    for node in std::mem::take(&mut descriptor.nodes) {
+        if node.include.is_none() {
+            flat.push(node);
+            continue;
+        }
+        match flatten_include(&node) {
+            Ok((sub_nodes, outputs)) => {
+                exposed_outputs.insert(node.id.clone(), outputs);
+                flat.extend(sub_nodes);
+            }
+            Err(err) => problems.node(&node.id, err),
+        }
+    }
+
+    for node in &mut flat {
+        // invalid nodes are reported when the nodes are resolved
+        if node.kind().is_err() {
+            continue;
+        }
+        if let Err(err) = redirect_exposed_outputs(node, &exposed_outputs) {
+            problems.node(&node.id, err);
+        }
+    }
+
+    descriptor.nodes = flat;
+    problems.into_result()
+}
+
+/// Returns the namespaced nodes of the sub-graph included by the given node,
+/// together with the sub-graph nodes that provide its exposed outputs.
+fn flatten_include(node: &Node) -> eyre::Result<(Vec<Node>, BTreeMap<DataId, UserInputMapping>)> {
+    let include = node.include.as_deref().unwrap_or_default();
+    let subgraph = node.included.as_deref().ok_or_else(|| {
+        eyre!(
+            "sub-graph `{include}` of node `{}` was not loaded \
+            (use `Descriptor::read` to load included descriptors)",
+            node.id
+        )
+    })?;
+    if node.replicas.is_some() {
+        bail!("sub-graph node `{}` cannot have replicas", node.id);
+    }
+    if !node.outputs.is_empty() {
+        bail!(
+            "node `{}` must not specify `outputs` because the outputs of a \
+            sub-graph are defined by the included descriptor",
+            node.id
+        );
+    }
+
+    let mut sub_problems = Problems::new(&subgraph.source_map, Severity::Error);
+    let mut sub_nodes = subgraph.expand_nodes(&mut sub_problems);
+    sub_problems
+        .into_result()
+        .wrap_err_with(|| format!("failed to resolve sub-graph of node `{}`", node.id))?;
+    let sub_node_ids: BTreeSet<_> = sub_nodes.iter().map(|n| n.id.clone()).collect();
+    let namespaced = |id: &str| NodeId::from(format!("{}{NAMESPACE_SEPARATOR}{id}", node.id));
+
+    for sub_node in &mut sub_nodes {
+        for input in sub_node.inputs_mut()? {
+            if let InputMapping::User(mapping) = &mut input.mapping {
+                if sub_node_ids.contains(&mapping.source) {
+                    mapping.source = namespaced(&mapping.source.to_string());
+                }
+            }
+        }
+        sub_node.depends_on = sub_node
+            .depends_on
+            .iter()
+            .map(|id| {
+                if sub_node_ids.contains(id) {
+                    namespaced(&id.to_string())
+                } else {
+                    id.clone()
+                }
+            })
+            .collect();
+        sub_node.id = namespaced(&sub_node.id.to_string());
+        if sub_node.deploy.machine.is_none() {
+            sub_node.deploy.machine = node
+                .deploy
+                .machine
+                .clone()
+                .or_else(|| subgraph.deploy.machine.clone());
+        }
+    }
+
+    for (input_id, input) in &node.inputs {
+        let targets = subgraph.inputs.get(input_id).ok_or_else(|| {
+            eyre!(
+                "sub-graph of node `{}` does not expose an input named `{input_id}`",
+                node.id
+            )
+        })?;
+        for target in targets {
+            let (target_node, target_input) = target.split_once('/').ok_or_else(|| {
+                eyre!("exposed input target `{target}` must have the form `<node>/<input>`")
+            })?;
+            let target_node = namespaced(target_node);
+            let sub_node = sub_nodes
+                .iter_mut()
+                .find(|n| n.id == target_node)
+                .wrap_err_with(|| {
+                    format!(
+                        "target node of exposed input `{}/{input_id}` does not exist",
+                        node.id
+                    )
+                })?;
+            sub_node.insert_input(target_input, input.clone())?;
+        }
+    }
+
+    let outputs = subgraph
+        .outputs
+        .iter()
+        .map(|(output_id, source)| {
+            let (source_node, source_output) = source.split_once('/').ok_or_else(|| {
+                eyre!("exposed output source `{source}` must have the form `<node>/<output>`")
+            })?;
+            let mapping = UserInputMapping {
+                source: namespaced(source_node),
+                output: DataId::from(source_output.to_owned()),
+            };
+            Ok((output_id.clone(), mapping))
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok((sub_nodes, outputs))
+}
+
+/// Redirects inputs that refer to an exposed output of a sub-graph to the
+/// sub-graph node that provides it.
+fn redirect_exposed_outputs(
+    node: &mut Node,
+    exposed_outputs: &BTreeMap<NodeId, BTreeMap<DataId, UserInputMapping>>,
+) -> eyre::Result<()> {
+    let node_id = node.id.clone();
+    for input in node.inputs_mut()? {
+        let InputMapping::User(mapping) = &mut input.mapping else {
+            continue;
+        };
+        if let Some(outputs) = exposed_outputs.get(&mapping.source) {
+            *mapping = outputs.get(&mapping.output).cloned().ok_or_else(|| {
+                eyre!(
+                    "sub-graph `{}` does not expose an output named `{}` \
+                    (used by node `{node_id}`)",
+                    mapping.source,
+                    mapping.output
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Descriptor {
+        Descriptor::parse(yaml.as_bytes().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn flatten_include() {
+        let mut descriptor = parse(
+            r#"
+nodes:
+  - id: camera
+    path: camera
+    outputs: [image]
+  - id: perception
+    include: perception.yml
+    inputs:
+      image: camera/image
+  - id: plot
+    path: plot
+    inputs:
+      bbox: perception/bbox
+"#,
+        );
+        descriptor.nodes[1].included = Some(Box::new(parse(
+            r#"
+inputs:
+  image: [detector/image]
+outputs:
+  bbox: detector/bbox
+nodes:
+  - id: detector
+    path: detector
+    outputs: [bbox]
+"#,
+        )));
+
+        inline_includes(&mut descriptor).unwrap();
+        let nodes = descriptor.nodes;
+        let ids: Vec<_> = nodes.iter().map(|n| n.id.to_string()).collect();
+        assert_eq!(ids, ["camera", "perception.detector", "plot"]);
+
+        let detector_input = &nodes[1].inputs[&DataId::from("image".to_owned())];
+        assert_eq!(detector_input.mapping.to_string(), "camera/image");
+        let plot_input = &nodes[2].inputs[&DataId::from("bbox".to_owned())];
+        assert_eq!(plot_input.mapping.to_string(), "perception.detector/bbox");
+    }
+
+    #[test]
+    fn reject_included_field() {
+        let result = Descriptor::parse(
+            br#"
+nodes:
+  - id: perception
+    _included:
+      nodes: []
+"#
+            .to_vec(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rebase_subgraph_paths() {
+        let dir = std::env::temp_dir().join(format!("dora-subgraph-test-{}", uuid::Uuid::now_v7()));
+        let app_dir = dir.join("app");
+        let shared_dir = dir.join("shared");
+        std::fs::create_dir_all(&app_dir).unwrap();
+        std::fs::create_dir_all(&shared_dir).unwrap();
+        let dataflow = app_dir.join("dataflow.yml");
+        std::fs::write(
+            &dataflow,
+            "nodes:\n  - id: perception\n    include: ../shared/perception.yml\n",
+        )
+        .unwrap();
+        std::fs::write(shared_dir.join("detector.py"), "").unwrap();
+        std::fs::write(
+            shared_dir.join("perception.yml"),
+            r#"
+nodes:
+  - id: detector
+    path: detector.py
+  - id: tracker
+    path: build/tracker
+  - id: plot
+    custom:
+      source: python
+      args: plot.py
+"#,
+        )
+        .unwrap();
+
+        let descriptor = Descriptor::blocking_read(&dataflow).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let shared_dir = shared_dir.canonicalize().unwrap_or(shared_dir);
+        let paths: Vec<_> = descriptor
+            .nodes
+            .iter()
+            .map(|node| match (&node.path, &node.custom) {
+                (Some(path), _) => path.clone(),
+                (None, Some(custom)) => custom.source.clone(),
+                (None, None) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                shared_dir
+                    .join("detector.py")
+                    .to_string_lossy()
+                    .into_owned(),
+                shared_dir
+                    .join("build/tracker")
+                    .to_string_lossy()
+                    .into_owned(),
+                "python".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn set_subgraph_variables() {
+        let dir = std::env::temp_dir().join(format!("dora-subgraph-test-{}", uuid::Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dataflow = dir.join("dataflow.yml");
+        std::fs::write(
+            &dataflow,
+            r#"
+variables:
+  rate: 10
+nodes:
+  - id: camera
+    path: camera
+    args: --rate ${{ rate }}
+  - id: perception
+    include: perception.yml
+"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("perception.yml"),
+            r#"
+variables:
+  model: small
+nodes:
+  - id: detector
+    path: detector
+    args: --model ${{ model }}
+"#,
+        )
+        .unwrap();
+
+        let read = |overrides: &[(&str, &str)]| {
+            let overrides = overrides
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            Descriptor::blocking_read_with_variables(&dataflow, &overrides)
+        };
+        let descriptor = read(&[("rate", "30"), ("model", "large")]);
+        let unknown = read(&[("modle", "large")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let args: Vec<_> = descriptor
+            .unwrap()
+            .nodes
+            .iter()
+            .map(|node| node.args.clone().unwrap_or_default())
+            .collect();
+        assert_eq!(args, ["--rate 30", "--model large"]);
+        assert!(unknown
+            .unwrap_err()
+            .to_string()
+            .contains("cannot set unknown variable `modle`"));
+    }
+}
@@ -1,45 +1,61 @@
 use crate::{
     adjust_shared_library_path,
-    config::{DataId, Input, InputMapping, OperatorId, UserInputMapping},
+    config::{DataId, Input, InputMapping, NodeId, OperatorId, UserInputMapping},
     descriptor::{self, source_is_url, CoreNodeKind, OperatorSource},
     get_python_path,
 };
 
 use eyre::{bail, eyre, Context};
-use std::{path::Path, process::Command};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    path::Path,
+    process::Command,
+};
 use tracing::info;
 
-use super::{resolve_path, Descriptor, SHELL_SOURCE};
+use super::{resolve_path, Descriptor, Node, SourceLocation, SourceMap, SHELL_SOURCE};
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub fn check_dataflow(dataflow: &Descriptor, working_dir: &Path) -> eyre::Result<()> {
-    let nodes = dataflow.resolve_aliases_and_set_defaults()?;
+    let mut problems = Problems::new(&dataflow.source_map, Severity::Error);
+    let nodes = dataflow.resolve_nodes(&mut problems);
+    if !problems.is_empty() {
+        // the checks below would report follow-up errors for the skipped nodes
+        return problems.into_result();
+    }
     let mut has_python_operator = false;
 
     // check that nodes and operators exist
     for node in &nodes {
         match &node.kind {
-            descriptor::CoreNodeKind::Custom(node) => match node.source.as_str() {
+            descriptor::CoreNodeKind::Custom(custom) => match custom.source.as_str() {
                 SHELL_SOURCE => (),
                 source => {
                     if source_is_url(source) {
                         info!("{source} is a URL."); // TODO: Implement url check.
-                    } else {
-                        resolve_path(source, working_dir)
-                            .wrap_err_with(|| format!("Could not find source path `{}`", source))?;
+                    } else if let Err(err) = resolve_path(source, working_dir)
+                        .wrap_err_with(|| format!("Could not find source path `{}`", source))
+                    {
+                        problems.node(&node.id, err);
                     };
                 }
             },
-            descriptor::CoreNodeKind::Runtime(node) => {
-                for operator_definition in &node.operators {
+            descriptor::CoreNodeKind::Runtime(runtime) => {
+                for operator_definition in &runtime.operators {
                     match &operator_definition.config.source {
                         OperatorSource::SharedLibrary(path) => {
                             if source_is_url(path) {
                                 info!("{path} is a URL."); // TODO: Implement url check.
                             } else {
-                                let path = adjust_shared_library_path(Path::new(&path))?;
-                                if !working_dir.join(&path).exists() {
-                                    bail!("no shared library at `{}`", path.display());
+                                match adjust_shared_library_path(Path::new(&path)) {
+                                    Ok(path) if !working_dir.join(&path).exists() => problems.node(
+                                        &node.id,
+                                        eyre!("no shared library at `{}`", path.display()),
+                                    ),
+                                    Ok(_) => {}
+                                    Err(err) => problems.node(&node.id, err),
                                 }
                             }
                         }
@@ -49,14 +65,14 @@ pub fn check_dataflow(dataflow: &Descriptor, working_dir: &Path) -> eyre::Result
                             if source_is_url(path) {
                                 info!("{path} is a URL."); // TODO: Implement url check.
                             } else if !working_dir.join(path).exists() {
-                                bail!("no Python library at `{path}`");
+                                problems.node(&node.id, eyre!("no Python library at `{path}`"));
                             }
                         }
                         OperatorSource::Wasm(path) => {
                             if source_is_url(path) {
                                 info!("{path} is a URL."); // TODO: Implement url check.
                             } else if !working_dir.join(path).exists() {
-                                bail!("no WASM library at `{path}`");
+                                problems.node(&node.id, eyre!("no WASM library at `{path}`"));
                             }
                         }
                     }
@@ -70,34 +86,298 @@ pub fn check_dataflow(dataflow: &Descriptor, working_dir: &Path) -> eyre::Result
         match &node.kind {
             descriptor::CoreNodeKind::Custom(custom_node) => {
                 for (input_id, input) in &custom_node.run_config.inputs {
-                    check_input(input, &nodes, &format!("{}/{input_id}", node.id))?;
+                    if let Err(err) = check_input(input, &nodes, &format!("{}/{input_id}", node.id))
+                    {
+                        problems.input(&node.id, input_id, err);
+                    }
                 }
             }
             descriptor::CoreNodeKind::Runtime(runtime_node) => {
                 for operator_definition in &runtime_node.operators {
                     for (input_id, input) in &operator_definition.config.inputs {
-                        check_input(
+                        if let Err(err) = check_input(
                             input,
                             &nodes,
                             &format!("{}/{}/{input_id}", operator_definition.id, node.id),
-                        )?;
+                        ) {
+                            problems.input(&node.id, input_id, err);
+                        }
                     }
                 }
             }
         };
     }
 
+    check_output_types(&nodes, &mut problems);
+    check_dependencies(&nodes, &mut problems);
+
     // Check that nodes can resolve `send_stdout_as`
     for node in &nodes {
-        node.send_stdout_as()
-            .context("Could not resolve `send_stdout_as` configuration")?;
+        if let Err(err) = node
+            .send_stdout_as()
+            .context("Could not resolve `send_stdout_as` configuration")
+        {
+            problems.node(&node.id, err);
+        }
     }
 
     if has_python_operator {
-        check_python_runtime()?;
+        if let Err(err) = check_python_runtime() {
+            problems.global(err);
+        }
     }
 
-    Ok(())
+    problems.into_result()
+}
+
+/// Deserializes the top-level fields and each node of the given descriptor
+/// document separately, to report all parse errors at once.
+pub(super) fn check_document(
+    document: &serde_yaml::Value,
+    source_map: &SourceMap,
+) -> Vec<ValidationError> {
+    let mut problems = Problems::new(source_map, Severity::Error);
+    let mut top_level = document.clone();
+    let nodes = match top_level.get_mut("nodes") {
+        Some(nodes) if nodes.is_sequence() => {
+            std::mem::replace(nodes, serde_yaml::Value::Sequence(Vec::new()))
+        }
+        _ => serde_yaml::Value::Null,
+    };
+    if let Err(err) = Descriptor::deserialize(&top_level) {
+        problems.global(err.into());
+    }
+    for (index, node) in nodes.as_sequence().into_iter().flatten().enumerate() {
+        if let Err(err) = Node::deserialize(node) {
+            problems.path(&format!("nodes[{index}]"), err.into());
+        }
+    }
+    problems.into_vec()
+}
+
+/// A single problem found while validating a dataflow.
+#[derive(Debug, Clone)]
+pub struct ValidationError {
+    pub severity: Severity,
+    pub message: String,
+    /// Location of the offending node or input in the YAML source, if known.
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    /// Suspicious, but valid dataflow (e.g. unused outputs).
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => f.write_str("error"),
+            Severity::Warning => f.write_str("warning"),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{location}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// All problems found while validating a dataflow.
+#[derive(Debug, Clone)]
+pub struct ValidationErrors(pub Vec<ValidationError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [error] => write!(f, "{error}"),
+            errors => {
+                write!(f, "found {} problems:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  - {error}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Collects the problems of a dataflow so that they can be reported at once.
+pub(super) struct Problems<'a> {
+    source_map: &'a SourceMap,
+    severity: Severity,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> Problems<'a> {
+    pub(super) fn new(source_map: &'a SourceMap, severity: Severity) -> Self {
+        Self {
+            source_map,
+            severity,
+            errors: Vec::new(),
+        }
+    }
+
+    pub(super) fn node(&mut self, node_id: &NodeId, error: eyre::Report) {
+        let location = self.source_map.node(node_id);
+        self.push(error, location);
+    }
+
+    fn input(&mut self, node_id: &NodeId, input_id: &DataId, error: eyre::Report) {
+        let location = self.source_map.input(node_id, input_id);
+        self.push(error, location);
+    }
+
+    fn path(&mut self, path: &str, error: eyre::Report) {
+        let location = self.source_map.get(path);
+        self.push(error, location);
+    }
+
+    fn global(&mut self, error: eyre::Report) {
+        self.push(error, None);
+    }
+
+    fn push(&mut self, error: eyre::Report, location: Option<SourceLocation>) {
+        self.errors.push(ValidationError {
+            severity: self.severity,
+            message: format!("{error:#}"),
+            location,
+        });
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub(super) fn into_vec(self) -> Vec<ValidationError> {
+        self.errors
+    }
+
+    pub(super) fn into_result(self) -> eyre::Result<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationErrors(self.errors).into())
+        }
+    }
+}
+
+/// Checks that `output_types` only declares types of existing outputs.
+fn check_output_types(nodes: &[super::ResolvedNode], problems: &mut Problems) {
+    for node in nodes {
+        match &node.kind {
+            CoreNodeKind::Custom(custom_node) => {
+                let config = &custom_node.run_config;
+                for output in config.output_types.keys() {
+                    if !config.outputs.contains(output) {
+                        problems.node(
+                            &node.id,
+                            eyre!(
+                                "`output_types` declares a type for `{}/{output}`, \
+                                which is not listed in `outputs`",
+                                node.id
+                            ),
+                        );
+                    }
+                }
+            }
+            CoreNodeKind::Runtime(runtime_node) => {
+                for operator in &runtime_node.operators {
+                    for output in operator.config.output_types.keys() {
+                        if !operator.config.outputs.contains(output) {
+                            problems.node(
+                                &node.id,
+                                eyre!(
+                                    "`output_types` declares a type for `{}/{}/{output}`, \
+                                    which is not listed in `outputs`",
+                                    node.id,
+                                    operator.id
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Checks that all `depends_on` entries refer to existing nodes and that there are
+/// no dependency cycles.
+fn check_dependencies(nodes: &[super::ResolvedNode], problems: &mut Problems) {
+    let dependencies: BTreeMap<_, _> = nodes.iter().map(|n| (&n.id, &n.depends_on)).collect();
+    for node in nodes {
+        if node.ready_signal && !matches!(node.kind, CoreNodeKind::Custom(_)) {
+            problems.node(
+                &node.id,
+                eyre!(
+                    "node `{}` sets `ready_signal`, which is only supported for custom nodes",
+                    node.id
+                ),
+            );
+        }
+        for dependency in &node.depends_on {
+            if !dependencies.contains_key(dependency) {
+                problems.node(
+                    &node.id,
+                    eyre!(
+                        "node `{dependency}` listed in `depends_on` of node `{}` does not exist",
+                        node.id
+                    ),
+                );
+            }
+        }
+    }
+
+    // depth-first search, keeping track of the current path to report cycles
+    fn visit<'a>(
+        node_id: &'a NodeId,
+        dependencies: &BTreeMap<&'a NodeId, &'a BTreeSet<NodeId>>,
+        path: &mut Vec<&'a NodeId>,
+        done: &mut BTreeSet<&'a NodeId>,
+    ) -> eyre::Result<()> {
+        if done.contains(node_id) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|id| *id == node_id) {
+            let cycle: Vec<_> = path[start..]
+                .iter()
+                .chain([&node_id])
+                .map(|id| id.to_string())
+                .collect();
+            bail!("dependency cycle in `depends_on`: {}", cycle.join(" -> "));
+        }
+        path.push(node_id);
+        // unknown dependencies are reported above
+        for dependency in dependencies[node_id]
+            .iter()
+            .filter(|id| dependencies.contains_key(id))
+        {
+            visit(dependency, dependencies, path, done)?;
+        }
+        path.pop();
+        done.insert(node_id);
+        Ok(())
+    }
+
+    let mut done = BTreeSet::new();
+    for node in nodes {
+        let mut path = Vec::new();
+        if let Err(err) = visit(&node.id, &dependencies, &mut path, &mut done) {
+            problems.node(&node.id, err);
+            // skip the nodes of the cycle to report it only once
+            done.extend(path);
+        }
+    }
 }
 
 fn check_input(
@@ -106,12 +386,12 @@ fn check_input(
     input_id_str: &str,
 ) -> Result<(), eyre::ErrReport> {
     match &input.mapping {
-        InputMapping::Timer { interval: _ } => {}
+        InputMapping::Timer(_) => {}
         InputMapping::User(UserInputMapping { source, output }) => {
             let source_node = nodes.iter().find(|n| &n.id == source).ok_or_else(|| {
                 eyre!("source node `{source}` mapped to input `{input_id_str}` does not exist",)
             })?;
-            match &source_node.kind {
+            let output_type = match &source_node.kind {
                 CoreNodeKind::Custom(custom_node) => {
                     if !custom_node.run_config.outputs.contains(output) {
                         bail!(
@@ -119,6 +399,7 @@ fn check_input(
                             input `{input_id_str}` does not exist",
                         );
                     }
+                    custom_node.run_config.output_types.get(output)
                 }
                 CoreNodeKind::Runtime(runtime) => {
                     let (operator_id, output) = output.split_once('/').unwrap_or_default();
@@ -142,6 +423,15 @@ fn check_input(
                             input `{input_id_str}` does not exist",
                         );
                     }
+                    operator.config.output_types.get(&output)
+                }
+            };
+            if let (Some(expected), Some(declared)) = (&input.data_type, output_type) {
+                if expected != declared {
+                    bail!(
+                        "input `{input_id_str}` expects data type `{expected}`, but \
+                        output `{source}/{output}` is declared as `{declared}`",
+                    );
                 }
             }
         }
@@ -176,3 +466,139 @@ assert dora.__version__=='{VERSION}',  'Python dora-rs should be {VERSION}, but
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(yaml: &str) -> Vec<String> {
+        let descriptor = Descriptor::parse(yaml.as_bytes().to_vec()).unwrap();
+        let err = check_dataflow(&descriptor, Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap_err();
+        let errors = err.downcast::<ValidationErrors>().unwrap();
+        errors.0.into_iter().map(|e| e.message).collect()
+    }
+
+    #[test]
+    fn undeclared_output_types() {
+        let problems = problems(
+            r#"
+nodes:
+  - id: camera
+    path: Cargo.toml
+    outputs: [image]
+    output_types:
+      image: UInt8
+      depth: Float32
+  - id: runtime
+    operators:
+      - id: op
+        wasm: Cargo.toml
+        outputs: [out]
+        output_types:
+          result: Utf8
+"#,
+        );
+        assert_eq!(
+            problems,
+            [
+                "`output_types` declares a type for `camera/depth`, \
+                which is not listed in `outputs`",
+                "`output_types` declares a type for `runtime/op/result`, \
+                which is not listed in `outputs`",
+            ]
+        );
+    }
+
+    #[test]
+    fn mismatching_input_type() {
+        let problems = problems(
+            r#"
+nodes:
+  - id: camera
+    path: Cargo.toml
+    outputs: [image]
+    output_types:
+      image: UInt8
+  - id: plot
+    path: Cargo.toml
+    inputs:
+      image:
+        source: camera/image
+        data_type: Float32
+"#,
+        );
+        assert_eq!(
+            problems,
+            ["input `plot/image` expects data type `Float32`, but \
+            output `camera/image` is declared as `UInt8`"]
+        );
+    }
+
+    #[test]
+    fn ready_signal_requires_custom_node() {
+        let problems = problems(
+            r#"
+nodes:
+  - id: runtime
+    ready_signal: true
+    operators:
+      - id: op
+        wasm: Cargo.toml
+"#,
+        );
+        assert_eq!(
+            problems,
+            ["node `runtime` sets `ready_signal`, which is only supported for custom nodes"]
+        );
+    }
+
+    #[test]
+    fn report_all_resolve_errors() {
+        let problems = problems(
+            r#"
+nodes:
+  - id: camera
+  - id: detector
+    path: Cargo.toml
+    replicas: 0
+  - id: plot
+    path: Cargo.toml
+    inputs:
+      bbox: tracker_*/bbox
+"#,
+        );
+        assert_eq!(
+            problems,
+            [
+                "node `detector` must have at least one replica",
+                "node `camera` requires a `path`, `custom`, `operators`, or `include` field",
+                "input `plot/bbox` with source `tracker_*` does not match any node",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_all_parse_errors() {
+        let err = Descriptor::parse(
+            br#"
+nodes:
+  - id: camera
+    pth: camera.py
+  - id: plot
+    path: plot.py
+    restart: sometimes
+"#
+            .to_vec(),
+        )
+        .unwrap_err();
+        let errors = err.downcast_ref::<ValidationErrors>().unwrap();
+        let locations: Vec<_> = errors.0.iter().map(|e| e.location).collect();
+        assert_eq!(
+            locations,
+            [
+                Some(SourceLocation { line: 3, column: 7 }),
+                Some(SourceLocation { line: 5, column: 7 }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use eyre::{bail, eyre, Context};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+const VARIABLES_KEY: &str = "variables";
+
+/// Value of a dataflow variable.
+///
+/// The type of a variable is given by its default value in the `variables`
+/// section. Overrides must have the same type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum VariableValue {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    List(Vec<VariableValue>),
+}
+
+impl VariableValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            VariableValue::Bool(_) => "bool",
+            VariableValue::Int(_) => "int",
+            VariableValue::String(_) => "string",
+            VariableValue::List(_) => "list",
+        }
+    }
+
+    /// Parses an override value (e.g. from `dora start --set`) with the same
+    /// type as `self`.
+    fn parse_override(&self, name: &str, value: &str) -> eyre::Result<Self> {
+        let parsed = match self {
+            VariableValue::Bool(_) => value.parse().map(VariableValue::Bool).ok(),
+            VariableValue::Int(_) => value.parse().map(VariableValue::Int).ok(),
+            VariableValue::String(_) => Some(VariableValue::String(value.to_owned())),
+            VariableValue::List(_) => serde_yaml::from_str(value).map(VariableValue::List).ok(),
+        };
+        parsed.ok_or_else(|| {
+            eyre!(
+                "invalid value `{value}` for variable `{name}`: expected {}",
+                self.type_name()
+            )
+        })
+    }
+
+    fn to_yaml(&self) -> Value {
+        match self {
+            VariableValue::Bool(v) => Value::Bool(*v),
+            VariableValue::Int(v) => Value::Number((*v).into()),
+            VariableValue::String(v) => Value::String(v.clone()),
+            VariableValue::List(v) => Value::Sequence(v.iter().map(Self::to_yaml).collect()),
+        }
+    }
+}
+
+impl fmt::Display for VariableValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VariableValue::Bool(v) => write!(f, "{v}"),
+            VariableValue::Int(v) => write!(f, "{v}"),
+            VariableValue::String(v) => write!(f, "{v}"),
+            VariableValue::List(v) => {
+                let items: Vec<_> = v.iter().map(|i| i.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+        }
+    }
+}
+
+/// Checks whether the given YAML source contains `${{ name }}` references.
+pub(super) fn contains_references(source: &[u8]) -> bool {
+    source.windows(3).any(|w| w == b"${{")
+}
+
+/// Replaces all `${{ name }}` references in the given YAML document by the
+/// values of the corresponding variables.
+///
+/// A string that consists of a single reference is replaced by the typed
+/// value of the variable, so that e.g. integer variables can be used as queue
+/// sizes. References inside longer strings are interpolated.
+pub(super) fn substitute(
+    document: &mut Value,
+    overrides: &BTreeMap<String, String>,
+) -> eyre::Result<()> {
+    let Some(root) = document.as_mapping_mut() else {
+        return Ok(());
+    };
+
+    let mut variables: BTreeMap<String, VariableValue> = match root.get(VARIABLES_KEY) {
+        Some(value) => {
+            serde_yaml::from_value(value.clone()).context("failed to parse `variables`")?
+        }
+        None => BTreeMap::new(),
+    };
+    // overrides of unknown variables are reported by `check_overrides` because
+    // they might refer to variables of included sub-graphs
+    for (name, value) in overrides {
+        if let Some(variable) = variables.get_mut(name) {
+            *variable = variable.parse_override(name, value)?;
+        }
+    }
+    if variables.is_empty() {
+        return Ok(());
+    }
+
+    for (key, value) in root.iter_mut() {
+        let key = key.as_str().unwrap_or_default();
+        if key != VARIABLES_KEY {
+            substitute_value(value, &variables, key)?;
+        }
+    }
+    // store the effective values
+    let effective = variables
+        .iter()
+        .map(|(name, value)| (Value::String(name.clone()), value.to_yaml()))
+        .collect();
+    root.insert(VARIABLES_KEY.into(), Value::Mapping(effective));
+
+    Ok(())
+}
+
+/// Checks that all overrides refer to one of the given defined variables.
+pub(super) fn check_overrides<'a>(
+    overrides: &BTreeMap<String, String>,
+    defined: impl IntoIterator<Item = &'a String>,
+) -> eyre::Result<()> {
+    let defined: BTreeSet<_> = defined.into_iter().collect();
+    match overrides.keys().find(|name| !defined.contains(name)) {
+        Some(name) => bail!("cannot set unknown variable `{name}`"),
+        None => Ok(()),
+    }
+}
+
+fn substitute_value(
+    value: &mut Value,
+    variables: &BTreeMap<String, VariableValue>,
+    path: &str,
+) -> eyre::Result<()> {
+    match value {
+        Value::String(s) => {
+            if let Some(name) = single_reference(s) {
+                let variable = lookup(variables, name, path)?;
+                *value = variable.to_yaml();
+            } else if s.contains("${{") {
+                *s = interpolate(s, variables, path)?;
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                substitute_value(item, variables, &format!("{path}[{i}]"))?;
+            }
+        }
+        Value::Mapping(mapping) => {
+            let mut substituted = Mapping::with_capacity(mapping.len());
+            for (key, mut value) in std::mem::take(mapping) {
+                let key = match key {
+                    Value::String(s) if s.contains("${{") => {
+                        Value::String(interpolate(&s, variables, path)?)
+                    }
+                    other => other,
+                };
+                let key_str = match &key {
+                    Value::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_owned(),
+                };
+                substitute_value(&mut value, variables, &format!("{path}.{key_str}"))?;
+                substituted.insert(key, value);
+            }
+            *mapping = substituted;
+        }
+        Value::Tagged(tagged) => substitute_value(&mut tagged.value, variables, path)?,
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+fn single_reference(s: &str) -> Option<&str> {
+    let name = s.trim().strip_prefix("${{")?.strip_suffix("}}")?.trim();
+    (!name.contains("}}")).then_some(name)
+}
+
+fn interpolate(
+    s: &str,
+    variables: &BTreeMap<String, VariableValue>,
+    path: &str,
+) -> eyre::Result<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 3..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| eyre!("unterminated variable reference in `{path}`"))?;
+        let name = after[..end].trim();
+        match lookup(variables, name, path)? {
+            VariableValue::List(_) => {
+                bail!("list variable `{name}` cannot be interpolated into a string at `{path}`")
+            }
+            value => result.push_str(&value.to_string()),
+        }
+        rest = &after[end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn lookup<'a>(
+    variables: &'a BTreeMap<String, VariableValue>,
+    name: &str,
+    path: &str,
+) -> eyre::Result<&'a VariableValue> {
+    variables
+        .get(name)
+        .ok_or_else(|| eyre!("unknown variable `{name}` referenced at `{path}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitute_typed_variables() {
+        let mut document: Value = serde_yaml::from_str(
+            r#"
+variables:
+  machine: A
+  queue: 10
+nodes:
+  - id: camera
+    path: ./camera-${{ machine }}
+    _unstable_deploy:
+      machine: ${{ machine }}
+    inputs:
+      tick:
+        source: dora/timer/millis/100
+        queue_size: ${{ queue }}
+"#,
+        )
+        .unwrap();
+        let overrides = [("machine".to_owned(), "B".to_owned())].into();
+        substitute(&mut document, &overrides).unwrap();
+
+        let node = &document["nodes"][0];
+        assert_eq!(node["path"].as_str(), Some("./camera-B"));
+        assert_eq!(node["_unstable_deploy"]["machine"].as_str(), Some("B"));
+        assert_eq!(node["inputs"]["tick"]["queue_size"].as_i64(), Some(10));
+
+        let overrides = [("queue".to_owned(), "many".to_owned())].into();
+        let err = substitute(&mut document, &overrides).unwrap_err();
+        assert!(err.to_string().contains("`queue`"));
+    }
+}
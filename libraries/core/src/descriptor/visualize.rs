@@ -1,27 +1,33 @@
-use super::{CoreNodeKind, CustomNode, OperatorDefinition, ResolvedNode, RuntimeNode};
-use crate::config::{format_duration, DataId, Input, InputMapping, NodeId, UserInputMapping};
+use super::{
+    CoreNodeKind, CustomNode, OperatorDefinition, ResolvedNode, RuntimeNode, NAMESPACE_SEPARATOR,
+};
+use crate::config::{DataId, Input, InputMapping, NodeId, TimerSpec, UserInputMapping};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     fmt::Write as _,
-    time::Duration,
 };
 
 pub fn visualize_nodes(nodes: &[ResolvedNode]) -> String {
     let mut flowchart = "flowchart TB\n".to_owned();
     let mut all_nodes = HashMap::new();
 
+    let mut namespace = Vec::new();
     for node in nodes {
+        enter_namespace(&node.id, &mut namespace, &mut flowchart);
         visualize_node(node, &mut flowchart);
         all_nodes.insert(&node.id, node);
     }
+    for _ in namespace {
+        flowchart.push_str("end\n");
+    }
 
     let dora_timers = collect_dora_timers(nodes);
     if !dora_timers.is_empty() {
         writeln!(flowchart, "subgraph ___dora___ [dora]").unwrap();
         writeln!(flowchart, "  subgraph ___timer_timer___ [timer]").unwrap();
-        for interval in dora_timers {
-            let duration = format_duration(interval);
-            writeln!(flowchart, "    dora/timer/{duration}[\\{duration}/]").unwrap();
+        for timer in dora_timers {
+            let id = timer_node_id(&timer);
+            writeln!(flowchart, "    {id}[\\{timer}/]").unwrap();
         }
         flowchart.push_str("  end\n");
         flowchart.push_str("end\n");
@@ -34,7 +40,7 @@ pub fn visualize_nodes(nodes: &[ResolvedNode]) -> String {
     flowchart
 }
 
-pub fn collect_dora_timers(nodes: &[ResolvedNode]) -> BTreeSet<Duration> {
+pub fn collect_dora_timers(nodes: &[ResolvedNode]) -> BTreeSet<TimerSpec> {
     let mut dora_timers = BTreeSet::new();
     for node in nodes {
         match &node.kind {
@@ -53,18 +59,45 @@ pub fn collect_dora_timers(nodes: &[ResolvedNode]) -> BTreeSet<Duration> {
 
 fn collect_dora_nodes(
     values: std::collections::btree_map::Values<DataId, Input>,
-    dora_timers: &mut BTreeSet<Duration>,
+    dora_timers: &mut BTreeSet<TimerSpec>,
 ) {
     for input in values {
         match &input.mapping {
             InputMapping::User(_) => {}
-            InputMapping::Timer { interval } => {
-                dora_timers.insert(*interval);
+            InputMapping::Timer(timer) => {
+                dora_timers.insert(*timer);
             }
         }
     }
 }
 
+/// Opens and closes mermaid subgraphs so that nodes of included sub-graphs are
+/// grouped by their `<include_id>.` prefixes.
+fn enter_namespace(node_id: &NodeId, namespace: &mut Vec<String>, flowchart: &mut String) {
+    let id = node_id.to_string();
+    let target: Vec<_> = match id.rsplit_once(NAMESPACE_SEPARATOR) {
+        Some((prefix, _)) => prefix
+            .split(NAMESPACE_SEPARATOR)
+            .map(String::from)
+            .collect(),
+        None => Vec::new(),
+    };
+    let common = namespace
+        .iter()
+        .zip(&target)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while namespace.len() > common {
+        namespace.pop();
+        flowchart.push_str("end\n");
+    }
+    for name in &target[common..] {
+        namespace.push(name.clone());
+        let prefix = namespace.join(&NAMESPACE_SEPARATOR.to_string());
+        writeln!(flowchart, "subgraph {prefix}[{name}]").unwrap();
+    }
+}
+
 fn visualize_node(node: &ResolvedNode, flowchart: &mut String) {
     let node_id = &node.id;
     match &node.kind {
@@ -159,8 +192,9 @@ fn visualize_inputs(
 ) {
     for (input_id, input) in inputs {
         match &input.mapping {
-            mapping @ InputMapping::Timer { .. } => {
-                writeln!(flowchart, "  {} -- {input_id} --> {target}", mapping).unwrap();
+            InputMapping::Timer(timer) => {
+                let id = timer_node_id(timer);
+                writeln!(flowchart, "  {id} -- {input_id} --> {target}").unwrap();
             }
             InputMapping::User(mapping) => {
                 visualize_user_mapping(mapping, target, nodes, input_id, flowchart)
@@ -169,6 +203,12 @@ fn visualize_inputs(
     }
 }
 
+/// Mermaid node ID of the given timer (timer options contain characters that
+/// are not allowed in IDs).
+fn timer_node_id(timer: &TimerSpec) -> String {
+    format!("dora/timer/{timer}").replace(['?', '&', '='], "/")
+}
+
 fn visualize_user_mapping(
     mapping: &UserInputMapping,
     target: &str,
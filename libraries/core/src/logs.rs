@@ -0,0 +1,161 @@
+//! Structured log output of nodes.
+//!
+//! The daemon stores the log output of each node as JSON lines, with one
+//! [`LogRecord`] per line.
+
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use crate::{config::NodeId, message::uhlc};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LogRecord {
+    pub timestamp: uhlc::Timestamp,
+    pub node_id: NodeId,
+    /// Not set for plain output that the node printed to stdout or stderr.
+    pub level: Option<LogLevel>,
+    /// The module path for `tracing` events, or `stdout`/`stderr` for plain
+    /// output.
+    pub target: String,
+    pub message: String,
+    /// Additional fields of `tracing` events.
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Formats `tracing` events as `<time> <LEVEL> <target>: <message> <fields>`
+/// and plain output as it is.
+impl Display for LogRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(level) = self.level else {
+            return f.write_str(&self.message);
+        };
+        write!(
+            f,
+            "{} {level:>5} {}: {}",
+            self.timestamp.get_time(),
+            self.target,
+            self.message
+        )?;
+        for (key, value) in &self.fields {
+            write!(f, " {key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Log levels, ordered from most to least severe.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Display for LogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let level = match self {
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Trace => "TRACE",
+        };
+        f.pad(level)
+    }
+}
+
+impl From<tracing::Level> for LogLevel {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::ERROR => LogLevel::Error,
+            tracing::Level::WARN => LogLevel::Warn,
+            tracing::Level::INFO => LogLevel::Info,
+            tracing::Level::DEBUG => LogLevel::Debug,
+            tracing::Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            other => Err(format!("unknown log level `{other}`")),
+        }
+    }
+}
+
+/// Selects the log records to show.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LogFilter {
+    /// Only show records of this level or more severe ones.
+    ///
+    /// Plain stdout/stderr output has no level, so it is not shown when a
+    /// level is set.
+    pub level: Option<LogLevel>,
+    /// Only show records whose target starts with the given string.
+    pub target: Option<String>,
+}
+
+impl LogFilter {
+    pub fn is_empty(&self) -> bool {
+        self.level.is_none() && self.target.is_none()
+    }
+
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        let level_matches = match (self.level, record.level) {
+            (None, _) => true,
+            (Some(max), Some(level)) => level <= max,
+            (Some(_), None) => false,
+        };
+        let target_matches = self
+            .target
+            .as_ref()
+            .map_or(true, |target| record.target.starts_with(target.as_str()));
+        level_matches && target_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_records() {
+        let record = LogRecord {
+            timestamp: uhlc::HLC::default().new_timestamp(),
+            node_id: "node".to_owned().into(),
+            level: Some(LogLevel::Warn),
+            target: "camera::driver".into(),
+            message: "frame dropped".into(),
+            fields: BTreeMap::new(),
+        };
+        let filter = |level: Option<&str>, target: Option<&str>| LogFilter {
+            level: level.map(|l| l.parse().unwrap()),
+            target: target.map(String::from),
+        };
+
+        assert!(filter(None, None).matches(&record));
+        assert!(filter(Some("info"), Some("camera")).matches(&record));
+        assert!(!filter(Some("error"), None).matches(&record));
+        assert!(!filter(None, Some("lidar")).matches(&record));
+
+        let plain = LogRecord {
+            level: None,
+            target: "stdout".into(),
+            ..record
+        };
+        assert!(!filter(Some("trace"), None).matches(&plain));
+        assert!(filter(None, Some("stdout")).matches(&plain));
+    }
+}
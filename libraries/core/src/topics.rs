@@ -1,5 +1,5 @@
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
     net::{Ipv4Addr, SocketAddr},
     path::PathBuf,
@@ -8,8 +8,9 @@ use std::{
 use uuid::Uuid;
 
 use crate::{
-    config::{NodeId, OperatorId},
+    config::{DataId, NodeId, OperatorId},
     descriptor::Descriptor,
+    logs::LogFilter,
 };
 
 pub const DORA_COORDINATOR_PORT_DEFAULT: u16 = 0xD02A;
@@ -54,6 +55,50 @@ pub enum ControlRequest {
         name: Option<String>,
         node: String,
     },
+    /// Read new log output of the given node, or of all nodes of the dataflow
+    /// if `node` is `None`.
+    ///
+    /// Nodes listed in `offsets` are read from the given byte offset, which
+    /// is taken from a previous [`ControlRequestReply::LogChunks`] reply. All
+    /// other nodes are read from `start`. Only log records that match the
+    /// `filter` are returned.
+    ReadLogs {
+        uuid: Option<Uuid>,
+        name: Option<String>,
+        node: Option<NodeId>,
+        offsets: BTreeMap<NodeId, u64>,
+        start: LogPosition,
+        filter: LogFilter,
+    },
+    /// Follow the log output of the given node, or of all nodes of the
+    /// dataflow if `node` is `None`, starting at `start`.
+    ///
+    /// The daemons push new output as it is written. It is retrieved through
+    /// [`ControlRequest::NextLogs`] requests with the subscription ID of the
+    /// [`ControlRequestReply::LogSubscription`] reply.
+    FollowLogs {
+        uuid: Option<Uuid>,
+        name: Option<String>,
+        node: Option<NodeId>,
+        start: LogPosition,
+        filter: LogFilter,
+    },
+    /// Wait for new output of the given log subscription.
+    ///
+    /// The subscription is dropped if no request is made for a while.
+    NextLogs {
+        subscription: Uuid,
+    },
+    /// Query the status of all nodes of a running dataflow.
+    Inspect {
+        uuid: Option<Uuid>,
+        name: Option<String>,
+    },
+    /// Query the message statistics of all edges of a running dataflow.
+    Stats {
+        uuid: Option<Uuid>,
+        name: Option<String>,
+    },
     Destroy,
     List,
     DaemonConnected,
@@ -74,6 +119,11 @@ pub enum ControlRequestReply {
         uuid: Uuid,
         result: Result<(), String>,
     },
+    /// The dataflow is still running, but some of its machines were lost.
+    DataflowDegraded {
+        uuid: Uuid,
+        lost_machines: BTreeSet<String>,
+    },
 
     DataflowList {
         dataflows: Vec<DataflowId>,
@@ -82,6 +132,27 @@ pub enum ControlRequestReply {
     DaemonConnected(bool),
     ConnectedMachines(BTreeSet<String>),
     Logs(Vec<u8>),
+    LogChunks {
+        uuid: Uuid,
+        chunks: Vec<LogChunk>,
+        /// Whether the dataflow is still running, i.e. whether more log
+        /// output might follow.
+        running: bool,
+    },
+    LogSubscription {
+        uuid: Uuid,
+        subscription: Uuid,
+    },
+    DataflowInfo {
+        uuid: Uuid,
+        name: Option<String>,
+        nodes: Vec<NodeInfo>,
+    },
+    DataflowStats {
+        uuid: Uuid,
+        name: Option<String>,
+        stats: DataflowStats,
+    },
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -99,3 +170,148 @@ impl Display for DataflowId {
         }
     }
 }
+
+/// Position in the log output of a node to start reading from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LogPosition {
+    /// Byte offset in the log file, e.g. `0` for the beginning.
+    Offset(u64),
+    /// Start of the last `n` lines.
+    Tail(usize),
+    /// First output that was written within the given duration before the
+    /// request.
+    ///
+    /// The start time is computed with the clock of the daemon that stores
+    /// the logs, so clock differences between machines don't matter.
+    Since(Duration),
+}
+
+/// New log output of a node.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LogChunk {
+    pub node: NodeId,
+    pub data: Vec<u8>,
+    /// Offset to continue reading from.
+    pub offset: u64,
+}
+
+/// Message counters of the edges of a dataflow, counted since the dataflow
+/// was started.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct DataflowStats {
+    pub outputs: Vec<OutputStats>,
+    pub inputs: Vec<InputStats>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct OutputStats {
+    pub node: NodeId,
+    pub output: DataId,
+    pub machine: String,
+    /// Number of sent messages.
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct InputStats {
+    pub node: NodeId,
+    pub input: DataId,
+    pub machine: String,
+    /// Number of messages that arrived at the daemon of the receiving node.
+    pub messages: u64,
+    pub bytes: u64,
+    /// Messages that were dropped because the input queue was full.
+    pub dropped: u64,
+    /// Messages that were passed on to the receiving node.
+    pub delivered: u64,
+    /// Sum of the end-to-end latencies of all delivered messages, measured
+    /// from the timestamp of the message metadata.
+    pub total_latency: Duration,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct NodeInfo {
+    pub id: NodeId,
+    pub machine: String,
+    pub state: NodeState,
+    pub pid: Option<u32>,
+    /// Time since the current process of the node was spawned.
+    pub uptime: Option<Duration>,
+    /// How often the node was restarted according to its restart policy.
+    pub restarts: u32,
+    pub open_inputs: BTreeSet<DataId>,
+    pub open_outputs: BTreeSet<DataId>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum NodeState {
+    /// The node is not spawned yet, e.g. because it waits for its
+    /// `depends_on` nodes.
+    Pending,
+    Running,
+    /// The node exited and is restarted after the backoff of its restart
+    /// policy.
+    Restarting,
+    Exited(NodeExitStatus),
+    /// The node was not reported by its daemon, e.g. because the dataflow
+    /// already finished on its machine or the machine was lost.
+    Unknown,
+}
+
+impl Display for NodeState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeState::Pending => write!(f, "pending"),
+            NodeState::Running => write!(f, "running"),
+            NodeState::Restarting => write!(f, "restarting"),
+            NodeState::Exited(status) => write!(f, "exited ({status})"),
+            NodeState::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum NodeExitStatus {
+    Success,
+    IoError(String),
+    ExitCode(i32),
+    Signal(i32),
+    Unknown,
+}
+
+impl From<Result<std::process::ExitStatus, std::io::Error>> for NodeExitStatus {
+    fn from(result: Result<std::process::ExitStatus, std::io::Error>) -> Self {
+        match result {
+            Ok(status) => {
+                if status.success() {
+                    NodeExitStatus::Success
+                } else if let Some(code) = status.code() {
+                    Self::ExitCode(code)
+                } else {
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::process::ExitStatusExt;
+                        if let Some(signal) = status.signal() {
+                            return Self::Signal(signal);
+                        }
+                    }
+                    Self::Unknown
+                }
+            }
+            Err(err) => Self::IoError(err.to_string()),
+        }
+    }
+}
+
+impl Display for NodeExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeExitStatus::Success => write!(f, "success"),
+            NodeExitStatus::IoError(err) => write!(f, "I/O error: {err}"),
+            NodeExitStatus::ExitCode(code) => write!(f, "exit code {code}"),
+            NodeExitStatus::Signal(signal) => write!(f, "signal {signal}"),
+            NodeExitStatus::Unknown => write!(f, "unknown exit status"),
+        }
+    }
+}
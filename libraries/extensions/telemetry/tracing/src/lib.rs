@@ -4,23 +4,46 @@
 //! able to serialize and deserialize context that has been sent via the middleware.
 
 use eyre::Context as EyreContext;
-use tracing::metadata::LevelFilter;
+use sink::{LogEvent, SinkLayer};
+use tracing::{metadata::LevelFilter, Subscriber};
 use tracing_subscriber::{
-    filter::FilterExt, prelude::__tracing_subscriber_SubscriberExt, EnvFilter, Layer,
+    filter::FilterExt, prelude::__tracing_subscriber_SubscriberExt, registry::LookupSpan,
+    EnvFilter, Layer,
 };
 
 use eyre::ContextCompat;
 use tracing_subscriber::Registry;
+pub mod sink;
 pub mod telemetry;
 
 pub fn set_up_tracing(name: &str) -> eyre::Result<()> {
-    // Filter log using `RUST_LOG`. More useful for CLI.
-    let filter = EnvFilter::from_default_env().or(LevelFilter::WARN);
     let stdout_log = tracing_subscriber::fmt::layer()
         .pretty()
-        .with_filter(filter);
+        .with_filter(filter());
 
-    let registry = Registry::default().with(stdout_log);
+    set_up_subscriber(name, Registry::default().with(stdout_log))
+}
+
+/// Sets up tracing like [`set_up_tracing`], but passes the events to the
+/// given sink instead of printing them to stdout.
+pub fn set_up_tracing_with_sink(
+    name: &str,
+    sink: impl Fn(LogEvent) + Send + Sync + 'static,
+) -> eyre::Result<()> {
+    let sink_layer = SinkLayer::new(sink).with_filter(filter());
+
+    set_up_subscriber(name, Registry::default().with(sink_layer))
+}
+
+/// Filter log using `RUST_LOG`. More useful for CLI.
+fn filter() -> impl tracing_subscriber::layer::Filter<Registry> {
+    EnvFilter::from_default_env().or(LevelFilter::WARN)
+}
+
+fn set_up_subscriber<S>(name: &str, registry: S) -> eyre::Result<()>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync + 'static,
+{
     if let Some(endpoint) = std::env::var_os("DORA_JAEGER_TRACING") {
         let endpoint = endpoint
             .to_str()
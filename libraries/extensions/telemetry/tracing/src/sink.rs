@@ -0,0 +1,73 @@
+//! Forward `tracing` events to a callback instead of printing them.
+
+use std::{collections::BTreeMap, fmt};
+
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// A `tracing` event with its fields formatted as strings.
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, String>,
+}
+
+/// Layer that passes all events to the given sink.
+pub struct SinkLayer<F> {
+    sink: F,
+}
+
+impl<F> SinkLayer<F> {
+    pub fn new(sink: F) -> Self {
+        Self { sink }
+    }
+}
+
+impl<S, F> Layer<S> for SinkLayer<F>
+where
+    S: Subscriber,
+    F: Fn(LogEvent) + 'static,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        (self.sink)(LogEvent {
+            level: *metadata.level(),
+            target: metadata.target().to_owned(),
+            message: visitor.message,
+            fields: visitor.fields,
+        });
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: String,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_owned();
+        } else {
+            self.fields
+                .insert(field.name().to_owned(), value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            self.fields
+                .insert(field.name().to_owned(), format!("{value:?}"));
+        }
+    }
+}
@@ -9,12 +9,22 @@ use eyre::Context;
 use serde::{Deserialize, Serialize};
 pub use uhlc;
 
+/// Version of the `Metadata` format.
+///
+/// Metadata is serialized with `bincode`, which does not support adding
+/// fields, so any change to the fields of `Metadata` breaks the message
+/// format and must bump this version. Version 1 added `dropped`.
+pub const METADATA_VERSION: u16 = 1;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Metadata {
     metadata_version: u16,
     timestamp: uhlc::Timestamp,
     pub type_info: ArrowTypeInfo,
     pub parameters: MetadataParameters,
+    /// Number of events of this input that were dropped so far because the
+    /// input queue was full.
+    pub dropped: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -132,14 +142,26 @@ impl Metadata {
         parameters: MetadataParameters,
     ) -> Self {
         Self {
-            metadata_version: 0,
+            metadata_version: METADATA_VERSION,
             timestamp,
             parameters,
             type_info,
+            dropped: 0,
         }
     }
 
     pub fn timestamp(&self) -> uhlc::Timestamp {
         self.timestamp
     }
+
+    /// Errors if the metadata was created with an incompatible message format.
+    pub fn check_version(&self) -> eyre::Result<()> {
+        if self.metadata_version != METADATA_VERSION {
+            eyre::bail!(
+                "incompatible metadata version {} (expected {METADATA_VERSION})",
+                self.metadata_version
+            );
+        }
+        Ok(())
+    }
 }